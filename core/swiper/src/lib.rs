@@ -1,12 +1,39 @@
+/// Re-export of `swiper_stealing`, usable as `#[preemptible(crate = ::swiper::stealing)]`
+/// by crates that only depend on the facade.
+pub use swiper_stealing as stealing;
+
+pub use swiper_derive::{SplitBorrow, enforce_mutexing, preemptible};
+pub use swiper_stealing::{
+    closure::{StandardTask, task_with},
+    executor::{block_on, block_on_all},
+};
+
+pub mod nursery;
+pub mod scheduler;
+pub mod sim;
+pub mod trigger;
+
 #[cfg(test)]
 mod tests {
     use core::task;
-    use std::task::{Context, Poll};
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
 
     use futures_lite::future;
     use lite_async_test::async_test;
-    use swiper_derive::preemptible;
-    use swiper_stealing::requirement::{Requirement, RevocableCell};
+    use swiper_derive::{SplitBorrow, enforce_mutexing, preemptible};
+
+    use crate::{StandardTask, block_on, block_on_all};
+    use swiper_stealing::{
+        borrowed::RevocableRef,
+        checked::CheckedRevocableCell,
+        history::OwnershipChange,
+        mutex::RequirementMutex,
+        requirement::{RequirementInfoSource, RevocableCell},
+        value::RevocableValue,
+    };
 
     #[test]
     fn basic_preemption() {
@@ -47,32 +74,74 @@ mod tests {
             let res = pinned_increment.as_mut().poll(&mut cx_increment);
             assert!(res.is_pending());
             assert!(data.current_owner().is_some());
-            assert_eq!(unsafe { **data.data.get() }, i);
+            assert_eq!(data.peek_with(|x| **x), i);
         }
 
         // now poll decrement
         let res = pinned_decrement.as_mut().poll(&mut cx_decrement);
         assert!(res.is_pending());
-        assert_eq!(unsafe { **data.data.get() }, 4);
+        assert_eq!(data.peek_with(|x| **x), 4);
 
         // increment should now be cancelled, and should not affect the data value
         let res = pinned_increment.as_mut().poll(&mut cx_increment);
         // assert_eq!(res, Poll::Ready(Result::Err(PreemptionError {})));
         assert!(res.is_ready());
-        assert_eq!(unsafe { **data.data.get() }, 4);
+        assert_eq!(data.peek_with(|x| **x), 4);
 
         // decrement should be running fine
         for i in (0..4).rev() {
             let res = pinned_decrement.as_mut().poll(&mut cx_decrement);
             assert!(res.is_pending());
             assert!(data.current_owner().is_some());
-            assert_eq!(unsafe { **data.data.get() }, i);
+            assert_eq!(data.peek_with(|x| **x), i);
         }
 
         // decrement should stop when i reaches 0
         let res = pinned_decrement.as_mut().poll(&mut cx_decrement);
         assert_eq!(res, Poll::Ready(Result::Ok(())));
-        assert_eq!(unsafe { **data.data.get() }, 0);
+        assert_eq!(data.peek_with(|x| **x), 0);
+        assert!(data.current_owner().is_none());
+    }
+
+    #[test]
+    fn copy_value() {
+        #[preemptible(speed)]
+        async fn report(speed: i32, times: i32) -> i32 {
+            for _ in 0..times {
+                future::yield_now().await;
+            }
+            speed
+        }
+
+        // a value is read and written without unsafe, and preempts like any cell
+        let speed = RevocableValue::new(3, "speed");
+        assert_eq!(block_on(report(&speed, 0)), Ok(3));
+        speed.set(4);
+        let [slow, fast] = block_on_all([report(&speed, 2), report(&speed, 0)]);
+        assert!(slow.is_err());
+        assert_eq!(fast, Ok(4));
+        assert_eq!(speed.get(), 4);
+        assert!(speed.current_owner().is_none());
+    }
+
+    #[test]
+    fn borrowed_cell() {
+        #[preemptible(x)]
+        async fn add(x: &mut i32, amount: i32) -> i32 {
+            *x += amount;
+            *x
+        }
+
+        #[preemptible(x)]
+        async fn read(x: i32) -> i32 {
+            x
+        }
+
+        let mut inner = 1;
+        let data = RevocableRef::new(&mut inner, "borrowed");
+        assert_eq!(block_on(add(&data, 2)), Ok(3));
+        assert_eq!(block_on(read(&data)), Ok(3));
+        assert_eq!(data.peek(), 3);
         assert!(data.current_owner().is_none());
     }
 
@@ -123,4 +192,326 @@ mod tests {
         let res = future::try_zip(wait_5_then_reset(), increment_n_times(&data, 100)).await;
         assert!(res.is_err()); // a preempted b, cancelling both since they are joined
     }
+
+    #[test]
+    fn requirement_stealing_block_on_all() {
+        #[preemptible(data)]
+        async fn step(data: &mut i32, times: i32, reset: bool) {
+            for i in 0..times {
+                if reset {
+                    *data = 0;
+                } else {
+                    assert_eq!(*data, i);
+                    *data += 1;
+                }
+                future::yield_now().await;
+            }
+        }
+
+        type Task<'a> = Pin<Box<dyn Future<Output = swiper_stealing::Result<()>> + 'a>>;
+
+        let mut x = 0;
+        let data = RevocableCell::new(&mut x, "test data");
+
+        let wait_5_then_reset: Task = Box::pin(async {
+            for _ in 0..5 {
+                future::yield_now().await;
+            }
+            step(&data, 1, true).await
+        });
+        let increment: Task = Box::pin(step(&data, 100, false));
+        let [a, b] = block_on_all([wait_5_then_reset, increment]);
+        assert!(a.is_ok()); // a ran to completion
+        assert!(b.is_err()); // b got cancelled by the reset after 5 ticks of a
+
+        // data got reset to 0 in a
+        block_on(step(&data, 1, false)).unwrap();
+        assert_eq!(data.peek_with(|x| **x), 1);
+    }
+
+    #[cfg_attr(all(), preemptible(x))]
+    async fn cfg_attr_enabled(x: &mut i32) {
+        *x += 1;
+    }
+
+    #[cfg_attr(any(), preemptible(x))]
+    async fn cfg_attr_disabled(x: &mut i32) {
+        *x += 1;
+    }
+
+    #[test]
+    fn preemptible_under_cfg_attr() {
+        let mut x = 0;
+        let mut data = RevocableCell::new(&mut x, "cfg data");
+        // enabled attribute wraps the parameter in a `RevocableCell`
+        block_on(cfg_attr_enabled(&data)).unwrap();
+        assert_eq!(**data.get_mut(), 1);
+
+        // disabled attribute leaves the original function untouched
+        let mut y = 0;
+        block_on(cfg_attr_disabled(&mut y));
+        assert_eq!(y, 1);
+    }
+
+    #[test]
+    fn preemptible_with_crate_path() {
+        mod renamed {
+            #[crate::preemptible(x, crate = crate::stealing)]
+            pub async fn double(x: &mut i32) {
+                *x *= 2;
+            }
+        }
+
+        let mut x = 3;
+        let mut data = RevocableCell::new(&mut x, "renamed data");
+        block_on(renamed::double(&data)).unwrap();
+        assert_eq!(**data.get_mut(), 6);
+    }
+
+    #[async_test]
+    async fn per_parameter_requirement_names() {
+        #[preemptible(left, right)]
+        async fn drive(
+            #[swiper(name = "left_motor")] left: &mut i32,
+            #[allow(unused_variables)] right: &mut i32,
+            #[allow(unused_variables)] speed: i32,
+        ) {
+            loop {
+                *left += 1;
+                future::yield_now().await;
+            }
+        }
+
+        #[preemptible(motor)]
+        async fn stop(motor: &mut i32) {
+            *motor = 0;
+        }
+
+        let mut l = 0;
+        let mut r = 0;
+        let left = RevocableCell::new(&mut l, "left");
+        let right = RevocableCell::new(&mut r, "right");
+
+        let (driving, stopped) = future::zip(drive(&left, &right, 1), async {
+            future::yield_now().await;
+            stop(&left).await
+        })
+        .await;
+
+        assert!(stopped.is_ok());
+        let err = driving.expect_err("drive should be preempted");
+        assert_eq!(err.requirement().name, "left_motor");
+        assert_eq!(err.outgoing().name, "drive");
+        assert!(err.to_string().contains("left_motor"));
+    }
+
+    #[test]
+    fn preemptible_with_history_cell() {
+        #[preemptible(x)]
+        async fn bump(x: &mut i32) {
+            *x += 1;
+        }
+
+        let mut x = 0;
+        let mut data = RevocableCell::new(&mut x, "history data").with_history::<4>();
+        block_on(bump(&data)).unwrap();
+        block_on(bump(&data)).unwrap();
+        assert_eq!(**data.get_mut(), 2);
+
+        let kinds: Vec<_> = data.history().map(|record| record.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                OwnershipChange::Acquired,
+                OwnershipChange::Released,
+                OwnershipChange::Acquired,
+                OwnershipChange::Released,
+            ]
+        );
+    }
+
+    #[async_test]
+    async fn preemptible_with_mutex() {
+        struct Counter(i32);
+
+        impl Counter {
+            fn increment(&mut self) {
+                self.0 += 1;
+            }
+        }
+
+        #[preemptible(counter)]
+        async fn reset(counter: &mut Counter) {
+            counter.0 = 0;
+        }
+
+        let mut inner = Counter(0);
+        let counter = RequirementMutex::new(&mut inner, "counter");
+        let increment = counter.run("increment", async |mut counter| {
+            loop {
+                counter.increment();
+                future::yield_now().await;
+            }
+        });
+
+        let (incremented, reset) = future::zip(increment, async {
+            future::yield_now().await;
+            reset(&counter).await
+        })
+        .await;
+
+        assert!(reset.is_ok());
+        let err = incremented.expect_err("increment should be preempted");
+        assert_eq!(
+            err.incoming().map(|thief| thief.name.as_str()),
+            Some("reset")
+        );
+        assert_eq!(inner.0, 0);
+    }
+
+    #[async_test]
+    async fn enforce_mutexing_methods() {
+        struct Example {
+            value: i32,
+        }
+
+        #[enforce_mutexing]
+        impl RequirementMutex<Example> {
+            #[enforce_mutexing]
+            async fn increment(&mut self) {
+                loop {
+                    self.value += 1;
+                    future::yield_now().await;
+                }
+            }
+
+            #[enforce_mutexing]
+            async fn decrement(&mut self) {
+                loop {
+                    if self.value == 0 {
+                        return;
+                    }
+                    self.value -= 1;
+                    future::yield_now().await;
+                }
+            }
+
+            async fn set(&mut self, value: i32) -> i32 {
+                std::mem::replace(&mut self.value, value)
+            }
+        }
+
+        let example = RequirementMutex::new(Example { value: 0 }, "example");
+        let (incremented, decremented) = future::zip(example.increment(), async {
+            for _ in 0..3 {
+                future::yield_now().await;
+            }
+            example.decrement().await
+        })
+        .await;
+
+        let err = incremented.expect_err("increment should be preempted");
+        assert_eq!(err.outgoing().name, "increment");
+        assert_eq!(
+            err.incoming().map(|thief| thief.name.as_str()),
+            Some("decrement")
+        );
+        assert!(decremented.is_ok());
+        assert_eq!(example.set(5).await, Ok(0));
+        assert_eq!(example.into_inner().value, 5);
+    }
+
+    #[async_test]
+    async fn closure_tasks_steal() {
+        let mut x = 0;
+        let data = RevocableCell::new(&mut x, "closure data");
+
+        let increment = (async |x: &mut &mut i32| {
+            loop {
+                **x += 1;
+                future::yield_now().await;
+            }
+        })
+        .with_requirement(&data);
+        let reset = (async |x: &mut &mut i32| **x = 0).requiring("reset", &data);
+
+        let (incremented, reset) = future::zip(increment, async {
+            future::yield_now().await;
+            reset.await
+        })
+        .await;
+
+        assert!(reset.is_ok());
+        let err = incremented.expect_err("increment should be preempted");
+        assert!(err.outgoing().name.as_str().contains("closure"));
+        assert_eq!(
+            err.incoming().map(|thief| thief.name.as_str()),
+            Some("reset")
+        );
+        assert_eq!(x, 0);
+    }
+
+    #[async_test]
+    async fn split_guard_pieces() {
+        #[derive(SplitBorrow)]
+        struct Robot {
+            #[split(Drive)]
+            left: f64,
+            #[split(Drive)]
+            right: f64,
+            #[split(Arm)]
+            angle: f64,
+        }
+
+        async fn stop(drive: Drive<'_>) {
+            future::yield_now().await;
+            *drive.left = 0.0;
+            *drive.right = 0.0;
+        }
+
+        async fn raise(arm: Arm<'_>, target: f64) {
+            while *arm.angle < target {
+                *arm.angle += 30.0;
+                future::yield_now().await;
+            }
+        }
+
+        let robot = RequirementMutex::new(
+            Robot {
+                left: 1.0,
+                right: 1.0,
+                angle: 0.0,
+            },
+            "robot",
+        );
+        let res = robot
+            .run("stow", async |mut robot| {
+                let (drive, arm) = robot.split();
+                stop(drive).await;
+                raise(arm, 90.0).await;
+            })
+            .await;
+
+        assert!(res.is_ok());
+        let robot = robot.into_inner();
+        assert_eq!((robot.left, robot.right, robot.angle), (0.0, 0.0, 90.0));
+    }
+
+    #[test]
+    fn checked_cell_with_macro() {
+        #[preemptible(x)]
+        async fn count_to(x: &mut i32, target: i32) {
+            while *x < target {
+                *x += 1;
+                future::yield_now().await;
+            }
+        }
+
+        let mut inner = 0;
+        let data = CheckedRevocableCell::new(&mut inner, "checked");
+        assert_eq!(block_on(count_to(&data, 3)), Ok(()));
+        assert!(data.current_owner().is_none());
+        drop(data);
+        assert_eq!(inner, 3);
+    }
 }
@@ -0,0 +1,250 @@
+//! Structured concurrency where the requirements themselves belong to the scope.
+//!
+//! A [`Nursery`], created by [`scope()`], owns every cell created with [`cell()`](Nursery::cell) and every task spawned on it.
+//! Cells and tasks are branded with the nursery's invariant lifetime `'n`, so a task can only be spawned on the nursery that owns its requirements,
+//! and no task can outlive them. This avoids both `'static` cells and reference counting.
+//!
+//! When the scope ends, tasks are dropped first, then cells are released and dropped in reverse creation order.
+//!
+//! ```
+//! # use swiper::{block_on, nursery};
+//! let total = nursery::scope(|nursery| {
+//!     let drive = nursery.cell(0, "drive");
+//!     nursery.spawn(drive.run("forward", async |x| *x += 3));
+//!     block_on(nursery.run());
+//!     drive.peek()
+//! });
+//! assert_eq!(total, 3);
+//! ```
+//!
+//! Tasks using cells of another nursery are rejected, even if that nursery outlives this one:
+//!
+//! ```compile_fail,E0521
+//! # use swiper::nursery;
+//! nursery::scope(|outer| {
+//!     let arm = outer.cell(0, "arm");
+//!     nursery::scope(|inner| {
+//!         inner.spawn(arm.run("raise", async |x| *x += 1));
+//!     });
+//! });
+//! ```
+
+use std::{
+    cell::RefCell,
+    future::poll_fn,
+    marker::PhantomData,
+    mem,
+    ops::Deref,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use swiper_stealing::{
+    PreemptionError,
+    requirement::{Requirement, RevocableCell, TenureEnd},
+};
+
+/// Invariant lifetime brand, so different nurseries never unify
+type Brand<'n> = PhantomData<fn(&'n ()) -> &'n ()>;
+
+type BoxedTask<'n> = Pin<Box<dyn Future<Output = swiper_stealing::Result<()>> + 'n>>;
+
+/// A [`RevocableCell`] owned by a [`Nursery`]
+pub struct NurseryCell<'n, T> {
+    cell: RevocableCell<T>,
+    _brand: Brand<'n>,
+}
+
+impl<'n, T: 'n> NurseryCell<'n, T> {
+    /// Same as [`RevocableCell::run`], but the returned task can only be spawned on this cell's nursery.
+    pub fn run<Out: 'n>(
+        &'n self,
+        name: &'static str,
+        func: impl AsyncFnOnce(&mut T) -> Out + 'n,
+    ) -> NurseryTask<'n, impl Future<Output = swiper_stealing::Result<Out>> + 'n> {
+        NurseryTask {
+            future: self.cell.run(name, func),
+            _brand: PhantomData,
+        }
+    }
+}
+
+impl<T> Deref for NurseryCell<'_, T> {
+    type Target = RevocableCell<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.cell
+    }
+}
+
+/// A task built from the cells of one [`Nursery`], see [`NurseryCell::run()`]
+pub struct NurseryTask<'n, Fut> {
+    future: Fut,
+    _brand: Brand<'n>,
+}
+
+/// Type erased cell, so the nursery can release cells of any type
+trait OwnedCell {
+    fn release(&self);
+}
+
+impl<T> OwnedCell for NurseryCell<'_, T> {
+    fn release(&self) {
+        self.cell.end_ownership(TenureEnd::Aborted);
+    }
+}
+
+/// Storage behind a [`Nursery`], with the brand erased so it can be dropped after the scope's borrows end
+#[derive(Default)]
+struct Storage {
+    cells: RefCell<Vec<Box<dyn OwnedCell>>>,
+    tasks: RefCell<Vec<BoxedTask<'static>>>,
+}
+
+impl Drop for Storage {
+    fn drop(&mut self) {
+        // tasks borrow the cells, so they go first
+        self.tasks.get_mut().clear();
+        let cells = self.cells.get_mut();
+        while let Some(cell) = cells.pop() {
+            cell.release();
+            drop(cell);
+        }
+    }
+}
+
+/// A scope owning cells and the tasks using them, see the [module-level documentation](self).
+///
+/// `'env` is the lifetime of everything borrowed from outside the scope, and bounds the brand `'n`.
+pub struct Nursery<'n, 'env: 'n> {
+    storage: &'n Storage,
+    _brand: Brand<'n>,
+    _env: PhantomData<&'env mut &'env ()>,
+}
+
+/// Runs `f` with a new [`Nursery`], tearing it down once `f` returns.
+pub fn scope<'env, R>(f: impl for<'n> FnOnce(&'n Nursery<'n, 'env>) -> R) -> R {
+    let storage = Storage::default();
+    let nursery = Nursery {
+        storage: &storage,
+        _brand: PhantomData,
+        _env: PhantomData,
+    };
+    f(&nursery)
+}
+
+impl<'n> Nursery<'n, '_> {
+    /// Creates a cell owned by this nursery, living until the nursery is torn down.
+    pub fn cell<T: 'n>(&'n self, data: T, name: &'static str) -> &'n NurseryCell<'n, T> {
+        let cell = Box::new(NurseryCell {
+            cell: RevocableCell::new(data, name),
+            _brand: PhantomData,
+        });
+        let ptr: *const NurseryCell<'n, T> = &*cell;
+        let cell: Box<dyn OwnedCell + 'n> = cell;
+        // everything `T` borrows outlives the call to `scope`, and the storage is dropped before it returns
+        let cell = unsafe { mem::transmute::<Box<dyn OwnedCell + 'n>, Box<dyn OwnedCell>>(cell) };
+        self.storage.cells.borrow_mut().push(cell);
+        // the box is only dropped with the storage, after every borrow of the nursery has ended
+        unsafe { &*ptr }
+    }
+
+    /// Spawns `task` to be driven by [`run()`](Self::run).
+    ///
+    /// The task's output is discarded, only preemptions are reported.
+    pub fn spawn<Out: 'n>(
+        &self,
+        task: NurseryTask<'n, impl Future<Output = swiper_stealing::Result<Out>> + 'n>,
+    ) {
+        let future = task.future;
+        let task: BoxedTask<'n> = Box::pin(async move { future.await.map(|_| ()) });
+        // tasks only borrow cells of this nursery, which the storage drops after its tasks
+        let task = unsafe { mem::transmute::<BoxedTask<'n>, BoxedTask<'static>>(task) };
+        self.storage.tasks.borrow_mut().push(task);
+    }
+
+    /// Drives every spawned task, including tasks spawned while running, until all have finished.
+    ///
+    /// Resolves to the errors of preempted tasks, in the order they were preempted.
+    pub async fn run(&self) -> Vec<PreemptionError> {
+        let mut running: Vec<BoxedTask<'static>> = Vec::new();
+        let mut errors = Vec::new();
+        poll_fn(|cx: &mut Context<'_>| {
+            running.append(&mut self.storage.tasks.borrow_mut());
+            running.retain_mut(|task| match task.as_mut().poll(cx) {
+                Poll::Ready(Ok(())) => false,
+                Poll::Ready(Err(err)) => {
+                    errors.push(err);
+                    false
+                }
+                Poll::Pending => true,
+            });
+            if running.is_empty() && self.storage.tasks.borrow().is_empty() {
+                Poll::Ready(mem::take(&mut errors))
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use futures_lite::future;
+    use swiper_stealing::requirement::RequirementInfoSource;
+
+    use super::*;
+    use crate::block_on;
+
+    struct Logged<'a>(&'static str, &'a RefCell<Vec<&'static str>>);
+
+    impl Drop for Logged<'_> {
+        fn drop(&mut self) {
+            self.1.borrow_mut().push(self.0);
+        }
+    }
+
+    #[test]
+    fn teardown_order() {
+        let log = RefCell::new(Vec::new());
+        scope(|nursery| {
+            let drive = nursery.cell(Logged("drive", &log), "drive");
+            let arm = nursery.cell(Logged("arm", &log), "arm");
+            nursery.cell(Logged("intake", &log), "intake");
+
+            // arm is left owned by a task that never finishes
+            nursery.spawn(arm.run("hold", async |_| future::pending::<()>().await));
+            nursery.spawn(drive.run("forward", async |_| ()));
+            let mut run = Box::pin(nursery.run());
+            assert!(future::block_on(future::poll_once(run.as_mut())).is_none());
+            assert!(drive.current_owner().is_none());
+            assert_eq!(
+                arm.current_owner().map(|thief| thief.name.as_str()),
+                Some("hold")
+            );
+        });
+        assert_eq!(*log.borrow(), ["intake", "arm", "drive"]);
+    }
+
+    #[test]
+    fn run_reports_preemptions() {
+        let errors = scope(|nursery| {
+            let arm = nursery.cell(0, "arm");
+            nursery.spawn(arm.run("raise", async |x| {
+                *x += 1;
+                future::yield_now().await;
+            }));
+            nursery.spawn(arm.run("lower", async |x| *x -= 1));
+            block_on(nursery.run())
+        });
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].outgoing().name, "raise");
+        assert_eq!(
+            errors[0].incoming().map(|thief| thief.name.as_str()),
+            Some("lower")
+        );
+    }
+}
@@ -0,0 +1,458 @@
+//! A minimal scheduler that polls every spawned task once per call.
+//!
+//! By default tasks are polled in the order they were spawned.
+//! A scheduler created [`with_priorities()`](Scheduler::with_priorities) instead polls higher priority tasks first,
+//! so a newly spawned high priority task steals shared requirements before a lower priority owner is polled again in the same call.
+//!
+//! Tasks are boxed futures resolving to a [`stealing::Result`](swiper_stealing::Result), so preemptions are reported as [`TaskEvent::Preempted`] rather than lost.
+//! The scheduler does not use wakers; it is meant to be driven at a fixed rate, such as once per robot loop iteration.
+//!
+//! Tasks spawned before the same [`poll_all()`](Scheduler::poll_all) are admitted together, so in debug builds spawning a task
+//! that shares a requirement with another task that has not been polled yet panics, naming the requirement.
+//! Spawn the second task after the first has been polled instead, so the steal between them is deliberate.
+//! Futures that do not list their requirements, such as those returned by `#[preemptible]` functions, are spawned with
+//! [`spawn_unchecked()`](Scheduler::spawn_unchecked) and never take part in this check.
+
+use std::{
+    cmp::Reverse,
+    fmt::Display,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use swiper_stealing::{
+    PreemptionError,
+    requirement::{HasRequirements, Requirement, requirements_overlap},
+    task::PreemptibleTask,
+};
+
+/// Identifies a task spawned on a [`Scheduler`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TaskId(pub usize);
+
+impl Display for TaskId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "task {}", self.0)
+    }
+}
+
+/// Something that happened to a task while the [`Scheduler`] polled it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskEvent {
+    /// The task was polled for the first time
+    Started(TaskId),
+    /// The task ran to completion
+    Completed(TaskId),
+    /// The task was cancelled because one of its requirements was stolen
+    Preempted(TaskId, PreemptionError),
+}
+
+/// A spawned future whose output is discarded
+trait Spawned {
+    fn poll_spawned(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<swiper_stealing::Result<()>>;
+
+    /// Returns the requirements of the task, or `None` if it does not list them
+    fn requirements(&self) -> Option<&dyn HasRequirements>;
+}
+
+/// A task spawned with [`Scheduler::spawn()`], whose requirements are checked on admission
+struct Listed<F>(F);
+
+impl<T, F> Spawned for Listed<F>
+where
+    F: Future<Output = swiper_stealing::Result<T>> + HasRequirements,
+{
+    fn poll_spawned(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<swiper_stealing::Result<()>> {
+        // the future is structurally pinned
+        unsafe { self.map_unchecked_mut(|task| &mut task.0) }
+            .poll(cx)
+            .map(|out| out.map(|_| ()))
+    }
+
+    fn requirements(&self) -> Option<&dyn HasRequirements> {
+        Some(&self.0)
+    }
+}
+
+/// A task spawned with [`Scheduler::spawn_unchecked()`]
+struct Unlisted<F>(F);
+
+impl<T, F> Spawned for Unlisted<F>
+where
+    F: Future<Output = swiper_stealing::Result<T>>,
+{
+    fn poll_spawned(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<swiper_stealing::Result<()>> {
+        // the future is structurally pinned
+        unsafe { self.map_unchecked_mut(|task| &mut task.0) }
+            .poll(cx)
+            .map(|out| out.map(|_| ()))
+    }
+
+    fn requirements(&self) -> Option<&dyn HasRequirements> {
+        None
+    }
+}
+
+/// Adapts a type erased [`PreemptibleTask`] into a future
+struct ErasedTask<'a>(Pin<Box<dyn PreemptibleTask + 'a>>);
+
+impl Future for ErasedTask<'_> {
+    type Output = swiper_stealing::Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.0.as_mut().poll_task(cx)
+    }
+}
+
+impl HasRequirements for ErasedTask<'_> {
+    fn visit_requirements(&self, visit: &mut dyn FnMut(&dyn Requirement)) {
+        self.0.visit_requirements(visit);
+    }
+}
+
+type BoxedTask<'a> = Pin<Box<dyn Spawned + 'a>>;
+
+struct Task<'a> {
+    id: TaskId,
+    future: BoxedTask<'a>,
+    started: bool,
+    priority: u8,
+    spawned_at: u64,
+}
+
+/// Polls a set of tasks once per call, see the [module-level documentation](self).
+#[derive(Default)]
+pub struct Scheduler<'a> {
+    tasks: Vec<Task<'a>>,
+    next_id: usize,
+    /// `None` polls in spawn order, otherwise the number of calls to `poll_all` that raise a waiting task's priority by one
+    aging: Option<u64>,
+    polls: u64,
+}
+
+impl<'a> Scheduler<'a> {
+    /// Creates a new [`Scheduler`] without any tasks, polling them in spawn order.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new [`Scheduler`] polling tasks by priority, then by age.
+    ///
+    /// Every `aging` calls to [`poll_all()`](Self::poll_all) a task has been waiting for raise its effective priority by one,
+    /// which bounds how long older low priority tasks are ordered behind newer high priority ones.
+    /// An `aging` of 0 disables aging.
+    pub fn with_priorities(aging: u64) -> Self {
+        Self {
+            aging: Some(aging),
+            ..Self::default()
+        }
+    }
+
+    /// Adds `task` to the scheduler with the lowest priority, to be first polled on the next call to [`poll_all()`](Self::poll_all).
+    ///
+    /// The task's output is discarded, only whether it completed or was preempted is reported.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, panics if `task` shares a requirement with a task that has not been polled yet, see the [module-level documentation](self).
+    pub fn spawn<T: 'a>(
+        &mut self,
+        task: impl Future<Output = swiper_stealing::Result<T>> + HasRequirements + 'a,
+    ) -> TaskId {
+        self.spawn_with_priority(0, task)
+    }
+
+    /// Same as [`spawn()`](Self::spawn), but polled before tasks of lower priority if the scheduler was created [`with_priorities()`](Self::with_priorities).
+    pub fn spawn_with_priority<T: 'a>(
+        &mut self,
+        priority: u8,
+        task: impl Future<Output = swiper_stealing::Result<T>> + HasRequirements + 'a,
+    ) -> TaskId {
+        if cfg!(debug_assertions) {
+            let admitted = self
+                .tasks
+                .iter()
+                .filter(|admitted| !admitted.started)
+                .filter_map(|admitted| Some((admitted.id, admitted.future.requirements()?)));
+            for (id, requirements) in admitted {
+                if let Some(requirement) = requirements_overlap(&task, requirements) {
+                    panic!(
+                        "{} and {id} are admitted in the same tick but both require {requirement}",
+                        TaskId(self.next_id),
+                    );
+                }
+            }
+        }
+        self.push(priority, Box::pin(Listed(task)))
+    }
+
+    /// Same as [`spawn()`](Self::spawn), for futures that do not list their requirements, such as those returned by `#[preemptible]` functions.
+    ///
+    /// Overlaps with other tasks admitted in the same tick are not detected.
+    pub fn spawn_unchecked<T: 'a>(
+        &mut self,
+        task: impl Future<Output = swiper_stealing::Result<T>> + 'a,
+    ) -> TaskId {
+        self.push(0, Box::pin(Unlisted(task)))
+    }
+
+    fn push(&mut self, priority: u8, future: BoxedTask<'a>) -> TaskId {
+        let id = TaskId(self.next_id);
+        self.next_id += 1;
+        self.tasks.push(Task {
+            id,
+            future,
+            started: false,
+            priority,
+            spawned_at: self.polls,
+        });
+        id
+    }
+
+    /// Adds a type erased `task` to the scheduler, see [`spawn()`](Self::spawn).
+    pub fn spawn_task(&mut self, task: Pin<Box<dyn PreemptibleTask + 'a>>) -> TaskId {
+        self.spawn(ErasedTask(task))
+    }
+
+    /// Returns the number of tasks that have not finished yet.
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Returns `true` if every spawned task has finished.
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Polls every unfinished task once, passing each resulting [`TaskEvent`] to `on_event`.
+    ///
+    /// Finished tasks are removed from the scheduler.
+    pub fn poll_all(&mut self, mut on_event: impl FnMut(TaskEvent)) {
+        if let Some(aging) = self.aging {
+            let polls = self.polls;
+            let effective = |task: &Task<'_>| {
+                let age = polls - task.spawned_at;
+                u64::from(task.priority) + age.checked_div(aging).unwrap_or(0)
+            };
+            // stable, so equal priorities stay in spawn order
+            self.tasks
+                .sort_by_key(|task| (Reverse(effective(task)), task.id));
+        }
+        self.polls += 1;
+
+        let mut cx = Context::from_waker(Waker::noop());
+        self.tasks.retain_mut(|task| {
+            if !task.started {
+                task.started = true;
+                on_event(TaskEvent::Started(task.id));
+            }
+            match task.future.as_mut().poll_spawned(&mut cx) {
+                Poll::Ready(Ok(())) => {
+                    on_event(TaskEvent::Completed(task.id));
+                    false
+                }
+                Poll::Ready(Err(err)) => {
+                    on_event(TaskEvent::Preempted(task.id, err));
+                    false
+                }
+                Poll::Pending => true,
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_lite::future;
+    use swiper_stealing::{requirement::RevocableCell, thief::PreemptibleFuture};
+
+    use super::*;
+
+    #[test]
+    fn poll_all_events() {
+        let cell = RevocableCell::new(0, "cell");
+        let mut scheduler = Scheduler::new();
+
+        let slow = scheduler.spawn(cell.run("slow", async |x| {
+            loop {
+                *x += 1;
+                future::yield_now().await;
+            }
+        }));
+        let mut events = Vec::new();
+        scheduler.poll_all(|event| events.push(event));
+        assert_eq!(events, [TaskEvent::Started(slow)]);
+
+        let fast = scheduler.spawn(cell.run("fast", async |x| *x += 1));
+        events.clear();
+        scheduler.poll_all(|event| events.push(event));
+        assert_eq!(
+            events,
+            [TaskEvent::Started(fast), TaskEvent::Completed(fast)]
+        );
+        assert_eq!(scheduler.len(), 1);
+
+        events.clear();
+        scheduler.poll_all(|event| events.push(event));
+        assert!(matches!(
+            events.as_slice(),
+            [TaskEvent::Preempted(id, err)] if *id == slow && err.outgoing().name == "slow"
+        ));
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn spawn_boxed_tasks() {
+        let cell = RevocableCell::new(0, "cell");
+        let other = RevocableCell::new(1, "other");
+        let out = std::cell::Cell::new(0);
+        let tasks: Vec<Pin<Box<dyn PreemptibleTask + '_>>> = vec![
+            Box::pin(cell.run("first", async |x| *x += 1)),
+            Box::pin(
+                other
+                    .run("second", async |x| *x + 1)
+                    .into_task(|x| out.set(x)),
+            ),
+        ];
+
+        let mut scheduler = Scheduler::new();
+        let ids: Vec<_> = tasks
+            .into_iter()
+            .map(|task| scheduler.spawn_task(task))
+            .collect();
+        let mut events = Vec::new();
+        scheduler.poll_all(|event| events.push(event));
+        assert_eq!(
+            events,
+            [
+                TaskEvent::Started(ids[0]),
+                TaskEvent::Completed(ids[0]),
+                TaskEvent::Started(ids[1]),
+                TaskEvent::Completed(ids[1]),
+            ]
+        );
+        assert_eq!(out.get(), 2);
+        assert_eq!(cell.peek(), 1);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic = "task 2 and task 0 are admitted in the same tick"]
+    fn overlapping_admission() {
+        let drive = RevocableCell::new(0, "drive");
+        let intake = RevocableCell::new(0, "intake");
+        let mut scheduler = Scheduler::new();
+        scheduler.spawn(drive.run("forward", async |x| *x += 1));
+        scheduler.spawn(intake.run("feed", async |x| *x += 1));
+        // the overlap is found through the nested composition
+        scheduler.spawn(swiper_stealing::combinator::race(
+            drive.run("back", async |x| *x -= 1),
+            intake.run("eject", async |x| *x -= 1),
+        ));
+    }
+
+    #[test]
+    fn overlapping_after_poll() {
+        let cell = RevocableCell::new(0, "cell");
+        let mut scheduler = Scheduler::new();
+        let first = scheduler.spawn(cell.run("first", async |x| {
+            loop {
+                *x += 1;
+                future::yield_now().await;
+            }
+        }));
+        scheduler.poll_all(|_| ());
+        // an owner that was already polled is stolen from deliberately
+        let second = scheduler.spawn(cell.run("second", async |x| *x = 10));
+        let mut events = Vec::new();
+        scheduler.poll_all(|event| events.push(event));
+        assert_eq!(
+            events,
+            [TaskEvent::Started(second), TaskEvent::Completed(second)]
+        );
+        events.clear();
+        scheduler.poll_all(|event| events.push(event));
+        assert!(matches!(events.as_slice(), [TaskEvent::Preempted(id, _)] if *id == first));
+        assert_eq!(cell.peek(), 10);
+    }
+
+    #[test]
+    fn priority_poll_order() {
+        use std::cell::RefCell;
+
+        let cell = RevocableCell::new(0, "shared");
+        let log = RefCell::new(Vec::new());
+        let task = |name: &'static str, polls: usize| {
+            let log = &log;
+            cell.run(name, async move |_| {
+                for _ in 0..polls {
+                    log.borrow_mut().push(name);
+                    future::yield_now().await;
+                }
+            })
+        };
+
+        let mut scheduler = Scheduler::with_priorities(0);
+        let low = scheduler.spawn_with_priority(0, task("low", 3));
+        let mut events = Vec::new();
+        scheduler.poll_all(|event| events.push(event));
+        assert_eq!(*log.borrow(), ["low"]);
+
+        // mid is polled before low, stealing the cell before low is polled again
+        let mid = scheduler.spawn_with_priority(1, task("mid", 3));
+        log.borrow_mut().clear();
+        events.clear();
+        scheduler.poll_all(|event| events.push(event));
+        assert_eq!(*log.borrow(), ["mid"]);
+        assert!(matches!(
+            events.as_slice(),
+            [TaskEvent::Started(m), TaskEvent::Preempted(l, _)] if *m == mid && *l == low
+        ));
+
+        // likewise high preempts mid in the tick it is spawned
+        let high = scheduler.spawn_with_priority(2, task("high", 1));
+        log.borrow_mut().clear();
+        events.clear();
+        scheduler.poll_all(|event| events.push(event));
+        assert_eq!(*log.borrow(), ["high"]);
+        assert!(matches!(
+            events.as_slice(),
+            [TaskEvent::Started(h), TaskEvent::Preempted(m, err)]
+                if *h == high && *m == mid && err.incoming().is_some_and(|thief| thief.name == "high")
+        ));
+
+        events.clear();
+        scheduler.poll_all(|event| events.push(event));
+        assert_eq!(events, [TaskEvent::Completed(high)]);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn priority_aging() {
+        let mut scheduler = Scheduler::with_priorities(2);
+        let old = scheduler.spawn(PreemptibleFuture::new(future::pending::<()>(), "old", []));
+        scheduler.poll_all(|_| ());
+        scheduler.poll_all(|_| ());
+        scheduler.poll_all(|_| ());
+        scheduler.poll_all(|_| ());
+
+        // after four polls, the old task has aged to priority 2 and ties break by age
+        let new = scheduler.spawn_with_priority(
+            2,
+            PreemptibleFuture::new(future::pending::<()>(), "new", []),
+        );
+        let mut started = Vec::new();
+        scheduler.poll_all(|event| started.push(event));
+        assert_eq!(started, [TaskEvent::Started(new)]);
+        assert_eq!(scheduler.tasks[0].id, old);
+    }
+}
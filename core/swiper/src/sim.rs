@@ -0,0 +1,269 @@
+//! Deterministic, time-stepped driver for unit testing robot routines.
+//!
+//! A [`Sim`] owns a [`ManualClock`], a [`Scheduler`] and a set of [`Trigger`]s.
+//! Each call to [`tick()`](Sim::tick) advances the clock, schedules the tasks of any triggers that fired, then polls every task once and reports what happened.
+//! Subsystems created [`with_clock(sim.clock())`](swiper_stealing::requirement::RevocableCell::with_clock) timestamp their preemptions in sim time.
+
+use swiper_stealing::{
+    PreemptionError,
+    clock::{Clock, ManualClock, Tick},
+    requirement::{Requirement, RequirementInfo},
+    thief::ThiefInfo,
+};
+
+use crate::{
+    scheduler::{Scheduler, TaskEvent, TaskId},
+    trigger::Trigger,
+};
+
+/// Everything that happened during one [`Sim::tick()`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TickReport {
+    /// Time after the tick's clock advance
+    pub now: Tick,
+    /// Tasks polled for the first time this tick
+    pub started: Vec<TaskId>,
+    /// Tasks that ran to completion this tick
+    pub completed: Vec<TaskId>,
+    /// Tasks cancelled this tick because a requirement was stolen
+    pub preempted: Vec<(TaskId, PreemptionError)>,
+    /// Owner of each subsystem after the tick, in the order they were added
+    pub owners: Vec<(RequirementInfo, Option<ThiefInfo>)>,
+}
+
+type TaskFactory<'a> = Box<dyn FnMut(&mut Scheduler<'a>) -> TaskId + 'a>;
+
+/// A simulated robot, see the [module-level documentation](self).
+pub struct Sim<'a> {
+    clock: &'static ManualClock,
+    scheduler: Scheduler<'a>,
+    triggers: Vec<(Box<dyn Trigger + 'a>, TaskFactory<'a>)>,
+    subsystems: Vec<&'a dyn Requirement>,
+}
+
+impl<'a> Sim<'a> {
+    /// Creates a new [`Sim`] at tick 0.
+    ///
+    /// The clock is leaked so subsystems can borrow it for `'static`, which costs a few bytes per simulation.
+    pub fn new() -> Self {
+        Self::with_clock(Box::leak(Box::new(ManualClock::new())))
+    }
+
+    /// Creates a new [`Sim`] driven by `clock`, for subsystems that must be created before the simulation.
+    pub fn with_clock(clock: &'static ManualClock) -> Self {
+        Self {
+            clock,
+            scheduler: Scheduler::new(),
+            triggers: Vec::new(),
+            subsystems: Vec::new(),
+        }
+    }
+
+    /// Returns the clock driving this simulation.
+    pub fn clock(&self) -> &'static ManualClock {
+        self.clock
+    }
+
+    /// Returns the current simulation time.
+    pub fn now(&self) -> Tick {
+        self.clock.now()
+    }
+
+    /// Adds `subsystem` to the ownership summary of each [`TickReport`].
+    pub fn add_subsystem(&mut self, subsystem: &'a dyn Requirement) -> &mut Self {
+        self.subsystems.push(subsystem);
+        self
+    }
+
+    /// Schedules `task` to be first polled on the next tick.
+    pub fn schedule<T: 'a>(
+        &mut self,
+        task: impl Future<Output = swiper_stealing::Result<T>> + 'a,
+    ) -> TaskId {
+        self.scheduler.spawn_unchecked(task)
+    }
+
+    /// Schedules a task created by `task` every time `trigger` fires.
+    pub fn on<T, Fut>(
+        &mut self,
+        trigger: impl Trigger + 'a,
+        mut task: impl FnMut() -> Fut + 'a,
+    ) -> &mut Self
+    where
+        T: 'a,
+        Fut: Future<Output = swiper_stealing::Result<T>> + 'a,
+    {
+        self.triggers.push((
+            Box::new(trigger),
+            Box::new(move |scheduler: &mut Scheduler<'a>| scheduler.spawn_unchecked(task())),
+        ));
+        self
+    }
+
+    /// Returns `true` if no scheduled task is still running.
+    ///
+    /// Triggers that may fire later are not taken into account.
+    pub fn is_idle(&self) -> bool {
+        self.scheduler.is_empty()
+    }
+
+    /// Advances the clock by `dt_ticks`, evaluates triggers, and polls every task once.
+    pub fn tick(&mut self, dt_ticks: u64) -> TickReport {
+        self.clock.advance(dt_ticks);
+        let now = self.clock.now();
+        for (trigger, task) in &mut self.triggers {
+            if trigger.fired(now) {
+                task(&mut self.scheduler);
+            }
+        }
+
+        let mut report = TickReport {
+            now,
+            ..TickReport::default()
+        };
+        self.scheduler.poll_all(|event| match event {
+            TaskEvent::Started(id) => report.started.push(id),
+            TaskEvent::Completed(id) => report.completed.push(id),
+            TaskEvent::Preempted(id, err) => report.preempted.push((id, err)),
+        });
+        report.owners = self
+            .subsystems
+            .iter()
+            .map(|subsystem| (subsystem.info(), subsystem.current_owner()))
+            .collect();
+        report
+    }
+
+    /// Ticks one step at a time until every task has finished.
+    ///
+    /// Returns the time the simulation became idle, or `None` if it was still busy after `max_ticks` ticks.
+    pub fn run_until_idle(&mut self, max_ticks: u64) -> Option<Tick> {
+        self.run_until(|_| false, max_ticks);
+        self.is_idle().then(|| self.now())
+    }
+
+    /// Ticks one step at a time until `pred` holds for a tick's report, or every task has finished.
+    ///
+    /// Returns the report that satisfied `pred`, or `None` if the simulation became idle or ran for `max_ticks` ticks first.
+    pub fn run_until(
+        &mut self,
+        mut pred: impl FnMut(&TickReport) -> bool,
+        max_ticks: u64,
+    ) -> Option<TickReport> {
+        for _ in 0..max_ticks {
+            if self.is_idle() {
+                return None;
+            }
+            let report = self.tick(1);
+            if pred(&report) {
+                return Some(report);
+            }
+        }
+        None
+    }
+}
+
+impl Default for Sim<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_lite::future;
+    use swiper_stealing::requirement::{RequirementInfoSource, RevocableCell};
+
+    use super::*;
+    use crate::{preemptible, trigger};
+
+    async fn wait_ticks(ticks: u32) {
+        for _ in 0..ticks {
+            future::yield_now().await;
+        }
+    }
+
+    #[preemptible(drive)]
+    async fn drive_forward(drive: &mut i32, ticks: u32) {
+        for _ in 0..ticks {
+            *drive += 1;
+            wait_ticks(1).await;
+        }
+    }
+
+    #[preemptible(arm)]
+    async fn raise_arm(arm: &mut i32) {
+        *arm = 90;
+        wait_ticks(1).await;
+    }
+
+    #[preemptible(drive)]
+    async fn brake(drive: &mut i32) {
+        *drive = 0;
+    }
+
+    #[test]
+    fn two_subsystem_auto() {
+        let clock = Box::leak(Box::new(ManualClock::new()));
+        let (mut d, mut a) = (0, 0);
+        let drive = RevocableCell::new(&mut d, "drive").with_clock(clock);
+        let arm = RevocableCell::new(&mut a, "arm").with_clock(clock);
+
+        let mut sim = Sim::with_clock(clock);
+        sim.add_subsystem(&drive).add_subsystem(&arm);
+        let auto = sim.schedule(drive_forward(&drive, 5));
+        let raise = sim.schedule(raise_arm(&arm));
+        sim.on(trigger::at(Tick(3)), || brake(&drive));
+
+        let drive_info = drive.info();
+        let arm_info = arm.info();
+        let driving = Some("drive_forward");
+        let raising = Some("raise_arm");
+        // owners are compared by name, since each task creates its own thief
+        let owners = |report: &TickReport| {
+            report
+                .owners
+                .iter()
+                .map(|&(info, owner)| (info, owner.map(|owner| owner.name.as_str())))
+                .collect::<Vec<_>>()
+        };
+
+        // both routines start on the first tick
+        let report = sim.tick(1);
+        assert_eq!(report.now, Tick(1));
+        assert_eq!(report.started, [auto, raise]);
+        assert!(report.completed.is_empty() && report.preempted.is_empty());
+        assert_eq!(
+            owners(&report),
+            [(drive_info, driving), (arm_info, raising)]
+        );
+
+        // the arm finishes and is released
+        let report = sim.tick(1);
+        assert!(report.started.is_empty());
+        assert_eq!(report.completed, [raise]);
+        assert_eq!(owners(&report), [(drive_info, driving), (arm_info, None)]);
+
+        // the trigger schedules brake, which steals the drivetrain and completes immediately
+        let report = sim.tick(1);
+        let braked = TaskId(2);
+        assert_eq!(report.started, [braked]);
+        assert_eq!(report.completed, [braked]);
+        assert!(report.preempted.is_empty());
+
+        // drive_forward notices it was preempted on its next poll
+        let report = sim.tick(1);
+        let [(id, err)] = report.preempted.as_slice() else {
+            panic!("expected one preemption, got {:?}", report.preempted);
+        };
+        assert_eq!(*id, auto);
+        assert_eq!(err.outgoing().name, "drive_forward");
+        assert_eq!(err.requirement(), drive_info);
+        assert_eq!(err.acquired_at(), Some(Tick(1)));
+        assert_eq!(err.stolen_at(), Some(Tick(3)));
+        assert_eq!(owners(&report), [(drive_info, None), (arm_info, None)]);
+
+        assert!(sim.is_idle());
+        assert_eq!(sim.run_until_idle(10), Some(Tick(4)));
+    }
+}
@@ -0,0 +1,55 @@
+//! Conditions that schedule tasks when they fire.
+
+use swiper_stealing::clock::Tick;
+
+/// A condition checked once per tick, such as a button press or a timer.
+///
+/// Any `FnMut(Tick) -> bool` closure is a trigger.
+pub trait Trigger {
+    /// Returns `true` if the trigger fires at `now`.
+    fn fired(&mut self, now: Tick) -> bool;
+}
+
+impl<F: FnMut(Tick) -> bool> Trigger for F {
+    fn fired(&mut self, now: Tick) -> bool {
+        self(now)
+    }
+}
+
+/// Returns a [`Trigger`] that fires once, on the first check at or after `tick`.
+pub fn at(tick: Tick) -> impl Trigger {
+    let mut done = false;
+    move |now: Tick| {
+        let fire = !done && now >= tick;
+        done |= fire;
+        fire
+    }
+}
+
+/// Returns a [`Trigger`] that fires every `period` ticks, starting at tick `period`.
+pub fn every(period: u64) -> impl Trigger {
+    let mut next = period;
+    move |now: Tick| {
+        let fire = now.0 >= next;
+        if fire {
+            next = now.0 + period;
+        }
+        fire
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timed_triggers() {
+        let mut once = at(Tick(2));
+        let fired: Vec<_> = (0..5).map(|t| once.fired(Tick(t))).collect();
+        assert_eq!(fired, [false, false, true, false, false]);
+
+        let mut periodic = every(2);
+        let fired: Vec<_> = (0..5).map(|t| periodic.fired(Tick(t))).collect();
+        assert_eq!(fired, [false, false, true, false, true]);
+    }
+}
@@ -1,16 +1,25 @@
 extern crate proc_macro;
 
+mod split;
+
 use core::fmt;
 
 use quote::{ToTokens, format_ident};
 use syn::{
-    Error, Expr, FnArg, Ident, ItemFn, Pat, PatType, ReturnType, parse_macro_input, parse_quote,
+    Attribute, Error, Expr, FnArg, GenericArgument, Ident, ImplItem, ImplItemFn, ItemFn, ItemImpl,
+    LitStr, Pat, PatType, Path, PathArguments, ReturnType, Type,
+    parse::{Parse, ParseStream},
+    parse_macro_input, parse_quote,
     punctuated::Punctuated,
 };
 
 // two macros
 // #[preemptible] (for functions) replaces all function args T with RevocableCell<T>, doesn't touch receivers
-// #[impl_preemptible] (for impl blocks) generates an impl for Receiver<T> will all methods for receiver T
+// #[enforce_mutexing] (for impl blocks) generates a trait for RequirementMutex<T> with all `&mut self` methods of T
+// #[derive(SplitBorrow)] (for structs) generates disjoint borrows of field groups, see swiper_stealing::split
+
+/// panic message of the guards created by #[preemptible], which can only fail if the generated future is misused
+const OWNED: &str = "the preemptible future owns its requirements";
 
 #[proc_macro_attribute]
 pub fn preemptible(
@@ -18,10 +27,7 @@ pub fn preemptible(
     item: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
     let input = parse_macro_input!(item as ItemFn);
-    let macro_args: Vec<Ident> =
-        parse_macro_input!(attr with Punctuated::<Ident, syn::Token![,]>::parse_terminated)
-            .into_iter()
-            .collect();
+    let macro_args = parse_macro_input!(attr as MacroArgs);
 
     // ensure function is async
     if input.sig.asyncness.is_none() {
@@ -33,16 +39,112 @@ pub fn preemptible(
         .into();
     }
 
-    let ir = match single_fn_to_ir(&input, &macro_args) {
+    let ir = match single_fn_to_ir(&input, &macro_args.wrapped, &macro_args.crate_path) {
         Ok(ir) => ir,
         Err(e) => return e.into_compile_error().into(),
     };
 
-    generate_wrapped_function(&input, ir)
+    generate_wrapped_function(&input, ir, &macro_args.crate_path)
         .into_token_stream()
         .into()
 }
 
+#[proc_macro_derive(SplitBorrow, attributes(split))]
+pub fn split_borrow(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(item as syn::DeriveInput);
+    match split::derive_split_borrow(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.into_compile_error().into(),
+    }
+}
+
+#[proc_macro_attribute]
+pub fn enforce_mutexing(
+    attr: proc_macro::TokenStream,
+    item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    let Ok(input) = syn::parse::<ItemImpl>(item.clone()) else {
+        return Error::new(
+            proc_macro2::TokenStream::from(item)
+                .into_iter()
+                .next()
+                .map_or_else(proc_macro2::Span::call_site, |token| token.span()),
+            "`enforce_mutexing` must be applied to an `impl RequirementMutex<T>` block",
+        )
+        .into_compile_error()
+        .into();
+    };
+    let macro_args = parse_macro_input!(attr as MacroArgs);
+    if let Some(ident) = macro_args.wrapped.first() {
+        return Error::new_spanned(ident, "`enforce_mutexing` only accepts a `crate` argument")
+            .into_compile_error()
+            .into();
+    }
+
+    match generate_mutexing_impl(&input, &macro_args.crate_path) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.into_compile_error().into(),
+    }
+}
+
+/// Arguments accepted by `#[preemptible(...)]`
+///
+/// A comma separated list of parameter names to wrap, optionally containing
+/// `crate = path` to override where the generated code looks for `swiper_stealing`
+/// (e.g. `crate = ::swiper::stealing` through the facade, or `crate = crate` inside swiper_stealing itself).
+struct MacroArgs {
+    wrapped: Vec<Ident>,
+    crate_path: Path,
+}
+
+impl Default for MacroArgs {
+    fn default() -> Self {
+        Self {
+            wrapped: Vec::new(),
+            crate_path: parse_quote! { ::swiper_stealing },
+        }
+    }
+}
+
+impl Parse for MacroArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut args = Self::default();
+        let mut crate_path_set = false;
+
+        for arg in Punctuated::<MacroArg, syn::Token![,]>::parse_terminated(input)? {
+            match arg {
+                MacroArg::Wrapped(ident) => args.wrapped.push(ident),
+                MacroArg::CratePath(token, path) => {
+                    if crate_path_set {
+                        return Err(Error::new_spanned(token, "duplicate `crate` argument"));
+                    }
+                    args.crate_path = path;
+                    crate_path_set = true;
+                }
+            }
+        }
+
+        Ok(args)
+    }
+}
+
+enum MacroArg {
+    Wrapped(Ident),
+    CratePath(syn::Token![crate], Path),
+}
+
+impl Parse for MacroArg {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(syn::Token![crate]) {
+            let token = input.parse()?;
+            input.parse::<syn::Token![=]>()?;
+            Ok(Self::CratePath(token, Path::parse_mod_style(input)?))
+        } else {
+            Ok(Self::Wrapped(input.parse()?))
+        }
+    }
+}
+
 struct IntermediateRepr {
     outer_params: Vec<FnArg>,
     inner_params: Vec<FnArg>,
@@ -89,19 +191,75 @@ impl PartialEq for IntermediateRepr {
     }
 }
 
-fn method_to_ir() -> syn::Result<IntermediateRepr> {
-    todo!()
+// parses an `async fn(&mut self, ...)` method to create intermediate representation
+// the outer method takes `&self` on the mutex, and the guard is the only requirement
+fn method_to_ir(input: &ImplItemFn) -> syn::Result<IntermediateRepr> {
+    if input.sig.asyncness.is_none() {
+        return Err(Error::new_spanned(
+            input.sig.fn_token,
+            "method must be async to safetly be preempted",
+        ));
+    }
+
+    let mut outer_params: Vec<FnArg> = Vec::with_capacity(input.sig.inputs.len());
+    let mut inner_params: Vec<FnArg> = Vec::with_capacity(input.sig.inputs.len());
+    let mut inner_args: Vec<Expr> = Vec::with_capacity(input.sig.inputs.len());
+
+    for arg in &input.sig.inputs {
+        match arg {
+            FnArg::Receiver(recv) => {
+                if recv.reference.is_none() || recv.mutability.is_none() {
+                    return Err(Error::new_spanned(
+                        recv,
+                        "method must take `&mut self` to be guarded by the mutex",
+                    ));
+                }
+                outer_params.push(parse_quote! { &self });
+                inner_params.push(arg.clone());
+            }
+            FnArg::Typed(PatType { pat, .. }) => {
+                let Pat::Ident(ident) = &**pat else {
+                    return Err(Error::new_spanned(
+                        pat,
+                        "this macro does not yet support destructuring function arguments",
+                    ));
+                };
+                let ident = &ident.ident;
+                outer_params.push(arg.clone());
+                inner_params.push(arg.clone());
+                inner_args.push(parse_quote! { #ident });
+            }
+        }
+    }
+
+    if input.sig.receiver().is_none() {
+        return Err(Error::new_spanned(
+            &input.sig,
+            "method must take `&mut self` to be guarded by the mutex",
+        ));
+    }
+
+    Ok(IntermediateRepr {
+        outer_params,
+        inner_params,
+        inner_args,
+        requirements_arr: vec![parse_quote! { self }],
+    })
 }
 
 // parses input args to create intermediate representation
-fn single_fn_to_ir(input: &ItemFn, wrapped_names: &[Ident]) -> syn::Result<IntermediateRepr> {
+fn single_fn_to_ir(
+    input: &ItemFn,
+    wrapped_names: &[Ident],
+    crate_path: &Path,
+) -> syn::Result<IntermediateRepr> {
     // all mutex_args types wrapped with RevocableCell
     let mut outer_params: Vec<FnArg> = Vec::with_capacity(input.sig.inputs.len());
 
     // original params for inner fn definition
     let mut inner_params: Vec<FnArg> = Vec::with_capacity(input.sig.inputs.len());
 
-    // args to be fed to origin params, all mutex_args mapped x -> unsafe { *x.data.get() }
+    // args to be fed to origin params, all mutex_args mapped x -> *RevocableGuard::current(x).expect(..)
     let mut inner_args: Vec<Expr> = Vec::with_capacity(input.sig.inputs.len());
 
     // maps all RevocableCell inputs (a, d, e) -> [&a, &d, &e]
@@ -110,21 +268,46 @@ fn single_fn_to_ir(input: &ItemFn, wrapped_names: &[Ident]) -> syn::Result<Inter
     for arg in &input.sig.inputs {
         match &arg {
             FnArg::Typed(PatType { attrs, pat, ty, .. }) => {
+                let (swiper_attrs, attrs): (Vec<_>, Vec<_>) = attrs
+                    .iter()
+                    .partition(|attr| attr.path().is_ident("swiper"));
+                let requirement_name = parse_param_attrs(&swiper_attrs)?;
+
+                if let Some(cfg) = attrs.iter().find(|attr| attr.path().is_ident("cfg")) {
+                    return Err(Error::new_spanned(
+                        cfg,
+                        "`cfg` on parameters is not supported since the argument is forwarded unconditionally",
+                    ));
+                }
+
                 if let Pat::Ident(ident) = &**pat {
                     if wrapped_names.is_empty() || wrapped_names.contains(&ident.ident) {
                         outer_params.push(parse_quote! {
                             #(#attrs)*
-                            #pat: &swiper_stealing::requirement::RevocableCell<#ty>
+                            #pat: &impl #crate_path::requirement::RevocableData<#ty>
+                        });
+                        inner_params.push(parse_quote! { #(#attrs)* #pat: #ty });
+                        inner_args.push(parse_quote! {
+                            *#crate_path::guard::RevocableGuard::current(#pat).expect(#OWNED)
+                        });
+                        requirements_arr.push(match requirement_name {
+                            Some(name) => parse_quote! {
+                                &#crate_path::requirement::RenamedRequirement::new(#ident, #name)
+                            },
+                            None => parse_quote! { #ident },
                         });
-                        inner_params.push(parse_quote! { #pat: #ty });
-                        inner_args.push(parse_quote! { unsafe { *#pat.data.get() } });
-                        requirements_arr.push(parse_quote! { #ident });
                     } else {
+                        if let Some(attr) = swiper_attrs.first() {
+                            return Err(Error::new_spanned(
+                                attr,
+                                "`swiper` attributes can only be applied to requirement parameters",
+                            ));
+                        }
                         outer_params.push(parse_quote! {
                             #(#attrs)*
                             #pat: #ty
                         });
-                        inner_params.push(parse_quote! { #pat: #ty });
+                        inner_params.push(parse_quote! { #(#attrs)* #pat: #ty });
                         inner_args.push(parse_quote! { #pat });
                     }
                 } else {
@@ -150,6 +333,25 @@ fn single_fn_to_ir(input: &ItemFn, wrapped_names: &[Ident]) -> syn::Result<Inter
     })
 }
 
+/// parses `#[swiper(name = "...")]` parameter attributes, returning the overridden requirement name
+fn parse_param_attrs(attrs: &[&Attribute]) -> syn::Result<Option<LitStr>> {
+    let mut name = None;
+    for attr in attrs {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                if name.is_some() {
+                    return Err(meta.error("duplicate `name` argument"));
+                }
+                name = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("unsupported swiper parameter attribute"))
+            }
+        })?;
+    }
+    Ok(name)
+}
+
 /// original function + modified inputs -> rust code
 fn generate_wrapped_function(
     input: &ItemFn,
@@ -159,6 +361,7 @@ fn generate_wrapped_function(
         inner_args,
         requirements_arr,
     }: IntermediateRepr,
+    crate_path: &Path,
 ) -> ItemFn {
     // both inner and outer signatures are async because i don't know the type of the anonymous inner fn and don't want to parameterize the outer fn on its type
     let mut outer_sig = input.sig.clone();
@@ -171,7 +374,7 @@ fn generate_wrapped_function(
     };
     outer_sig.output = ReturnType::Type(
         syn::token::RArrow::default(),
-        Box::new(parse_quote! { swiper_stealing::Result<#prev_output> }),
+        Box::new(parse_quote! { #crate_path::Result<#prev_output> }),
     );
 
     let mut inner_sig = input.sig.clone();
@@ -180,6 +383,8 @@ fn generate_wrapped_function(
     inner_sig.inputs.extend(inner_params);
 
     let fn_block = &input.block;
+    // every attribute (including `cfg` and `cfg_attr`) stays on the generated outer item,
+    // the inner fn is nested inside it so it is stripped along with it
     let fn_attrs = &input.attrs;
     let fn_vis = &input.vis;
 
@@ -190,8 +395,9 @@ fn generate_wrapped_function(
         #fn_vis #outer_sig {
             #inner_sig #fn_block
 
-            swiper_stealing::thief::PreemptibleFuture::new(
-                __inner(#(#inner_args),*),
+            // the guards are created once the future owns its requirements, and released when the body completes
+            #crate_path::thief::PreemptibleFuture::new(
+                async move { __inner(#(#inner_args),*).await },
                 #name,
                 [#(#requirements_arr),*],
             ).await
@@ -199,6 +405,134 @@ fn generate_wrapped_function(
     }
 }
 
+/// returns `T` of `impl RequirementMutex<T>`
+fn mutex_data_type(self_ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = self_ty else {
+        return None;
+    };
+    let last = path.path.segments.last()?;
+    if last.ident != "RequirementMutex" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &last.arguments else {
+        return None;
+    };
+    match args.args.first() {
+        Some(GenericArgument::Type(ty)) if args.args.len() == 1 => Some(ty),
+        _ => None,
+    }
+}
+
+/// `impl RequirementMutex<T>` block -> trait for the mutex + hidden methods on `T` holding the original bodies
+fn generate_mutexing_impl(
+    input: &ItemImpl,
+    crate_path: &Path,
+) -> syn::Result<proc_macro2::TokenStream> {
+    if input.trait_.is_some() || !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            input.impl_token,
+            "`enforce_mutexing` only supports non-generic inherent impl blocks",
+        ));
+    }
+    let data_ty = mutex_data_type(&input.self_ty)
+        .ok_or_else(|| Error::new_spanned(&input.self_ty, "expected `RequirementMutex<T>`"))?;
+    let Some(data_ident) = (match data_ty {
+        Type::Path(path) => path.path.segments.last().map(|segment| &segment.ident),
+        _ => None,
+    }) else {
+        return Err(Error::new_spanned(data_ty, "expected a named type"));
+    };
+    let trait_ident = format_ident!("{}Mutexing", data_ident);
+    let trait_doc = format!(
+        "Methods of [`{data_ident}`] called through a `RequirementMutex`, generated by `#[enforce_mutexing]`"
+    );
+    let self_ty = &input.self_ty;
+
+    let mut trait_items = Vec::with_capacity(input.items.len());
+    let mut trait_impls = Vec::with_capacity(input.items.len());
+    let mut inner_methods = Vec::with_capacity(input.items.len());
+    let mut trait_vis = None;
+
+    for item in &input.items {
+        let ImplItem::Fn(method) = item else {
+            return Err(Error::new_spanned(
+                item,
+                "`enforce_mutexing` impl blocks can only contain methods",
+            ));
+        };
+        let IntermediateRepr {
+            outer_params,
+            inner_params,
+            inner_args,
+            requirements_arr: _,
+        } = method_to_ir(method)?;
+
+        // methods may also be marked individually, which is the same as marking the impl block
+        let attrs: Vec<_> = method
+            .attrs
+            .iter()
+            .filter(|attr| !attr.path().is_ident("enforce_mutexing"))
+            .collect();
+        trait_vis.get_or_insert(&method.vis);
+
+        let name = &method.sig.ident;
+        let name_str = name.to_string();
+        let inner_name = format_ident!("__enforce_mutexing_{}", name);
+        let generics = &method.sig.generics;
+        let where_clause = &method.sig.generics.where_clause;
+        let output = match &method.sig.output {
+            ReturnType::Default => parse_quote! { () },
+            ReturnType::Type(_, out) => (**out).clone(),
+        };
+        let outer_sig = quote::quote! {
+            fn #name #generics (#(#outer_params),*)
+                -> impl ::core::future::Future<Output = #crate_path::Result<#output>>
+                #where_clause
+        };
+        let block = &method.block;
+
+        trait_items.push(quote::quote! {
+            #(#attrs)*
+            #outer_sig;
+        });
+        trait_impls.push(quote::quote! {
+            #(#attrs)*
+            #outer_sig {
+                #crate_path::mutex::RequirementMutex::run(
+                    self,
+                    #name_str,
+                    async move |mut __guard| __guard.#inner_name(#(#inner_args),*).await,
+                )
+            }
+        });
+        inner_methods.push(quote::quote! {
+            #[doc(hidden)]
+            #(#attrs)*
+            async fn #inner_name #generics (#(#inner_params),*) -> #output #where_clause #block
+        });
+    }
+
+    let trait_vis = trait_vis.cloned().unwrap_or(syn::Visibility::Inherited);
+    let impl_attrs = &input.attrs;
+    Ok(quote::quote! {
+        #(#impl_attrs)*
+        #[doc = #trait_doc]
+        #trait_vis trait #trait_ident {
+            #(#trait_items)*
+        }
+
+        #(#impl_attrs)*
+        impl #trait_ident for #self_ty {
+            #(#trait_impls)*
+        }
+
+        #(#impl_attrs)*
+        impl #data_ty {
+            #(#inner_methods)*
+        }
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -213,18 +547,19 @@ mod tests {
                 inner_args: vec![parse_quote! { x }],
                 requirements_arr: vec![],
             },
+            &MacroArgs::default().crate_path,
         )
         .into_token_stream()
         .to_string();
 
         let expected = quote::quote! {
-            async fn eg(x: i32) -> swiper_stealing::Result<i32> {
+            async fn eg(x: i32) -> ::swiper_stealing::Result<i32> {
                 async fn __inner(x: i32) -> i32 {
                     x
                 }
 
-                swiper_stealing::thief::PreemptibleFuture::new(
-                    __inner(x),
+                ::swiper_stealing::thief::PreemptibleFuture::new(
+                    async move { __inner(x).await },
                     "eg",
                     [],
                 ).await
@@ -241,28 +576,29 @@ mod tests {
             &parse_quote! { async fn eg(x: i32, y: i32) -> i32 { x + y } },
             IntermediateRepr {
                 outer_params: vec![
-                    parse_quote! { x: &swiper_stealing::requirement::RevocableCell<i32> },
+                    parse_quote! { x: &impl ::swiper_stealing::requirement::RevocableData<i32> },
                     parse_quote! { y: i32 },
                 ],
                 inner_params: vec![parse_quote! { x: i32 }, parse_quote! { y: i32 }],
                 inner_args: vec![
-                    parse_quote! { unsafe { *x.data.get() } },
+                    parse_quote! { *::swiper_stealing::guard::RevocableGuard::current(x).expect(#OWNED) },
                     parse_quote! { y },
                 ],
                 requirements_arr: vec![parse_quote! { &x }],
             },
+            &MacroArgs::default().crate_path,
         )
         .into_token_stream()
         .to_string();
 
         let expected = quote::quote! {
-            async fn eg(x: &swiper_stealing::requirement::RevocableCell<i32>, y: i32) -> swiper_stealing::Result<i32> {
+            async fn eg(x: &impl ::swiper_stealing::requirement::RevocableData<i32>, y: i32) -> ::swiper_stealing::Result<i32> {
                 async fn __inner(x: i32, y: i32) -> i32 {
                     x + y
                 }
 
-                swiper_stealing::thief::PreemptibleFuture::new(
-                    __inner( unsafe { *x.data.get() }, y),
+                ::swiper_stealing::thief::PreemptibleFuture::new(
+                    async move { __inner(*::swiper_stealing::guard::RevocableGuard::current(x).expect(#OWNED), y).await },
                     "eg",
                     [&x],
                 ).await
@@ -278,17 +614,18 @@ mod tests {
         let out = single_fn_to_ir(
             &parse_quote! { async fn eg(a: i32, b: i32) { a + b } },
             &[format_ident!("a")],
+            &MacroArgs::default().crate_path,
         )
         .expect("failed to parse IR");
 
         let expected = IntermediateRepr {
             outer_params: vec![
-                parse_quote! { a: &swiper_stealing::requirement::RevocableCell<i32>},
+                parse_quote! { a: &impl ::swiper_stealing::requirement::RevocableData<i32>},
                 parse_quote! { b: i32 },
             ],
             inner_params: vec![parse_quote! { a: i32 }, parse_quote! { b: i32 }],
             inner_args: vec![
-                parse_quote! { unsafe { *a.data.get() } },
+                parse_quote! { *::swiper_stealing::guard::RevocableGuard::current(a).expect(#OWNED) },
                 parse_quote! { b },
             ],
             requirements_arr: vec![parse_quote! {a}],
@@ -296,4 +633,128 @@ mod tests {
 
         assert_eq!(out, expected);
     }
+
+    #[test]
+    fn macro_args_crate_path() {
+        let args: MacroArgs = parse_quote! { a, crate = ::swiper::stealing, b };
+        assert_eq!(args.wrapped, vec![format_ident!("a"), format_ident!("b")]);
+        assert_eq!(
+            args.crate_path.to_token_stream().to_string(),
+            quote::quote! { ::swiper::stealing }.to_string()
+        );
+
+        let args: MacroArgs = parse_quote! { crate = crate };
+        assert!(args.wrapped.is_empty());
+        assert_eq!(args.crate_path.to_token_stream().to_string(), "crate");
+
+        assert!(syn::parse_str::<MacroArgs>("crate = a, crate = b").is_err());
+    }
+
+    #[test]
+    fn wrapped_fn_keeps_cfg_and_crate_path() {
+        let input: ItemFn = parse_quote! {
+            #[cfg(test)]
+            #[cfg_attr(test, allow(unused))]
+            async fn eg(x: &mut i32) {}
+        };
+        let crate_path: Path = parse_quote! { crate::stealing };
+        let ir = single_fn_to_ir(&input, &[], &crate_path).expect("failed to parse IR");
+        let out = generate_wrapped_function(&input, ir, &crate_path)
+            .into_token_stream()
+            .to_string();
+
+        let expected = quote::quote! {
+            #[cfg(test)]
+            #[cfg_attr(test, allow(unused))]
+            async fn eg(x: &impl crate::stealing::requirement::RevocableData<&mut i32>) -> crate::stealing::Result<()> {
+                async fn __inner(x: &mut i32) {}
+
+                crate::stealing::thief::PreemptibleFuture::new(
+                    async move { __inner(*crate::stealing::guard::RevocableGuard::current(x).expect(#OWNED)).await },
+                    "eg",
+                    [x],
+                ).await
+            }
+        }
+        .to_string();
+
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn fn_to_ir_param_attrs() {
+        let out = single_fn_to_ir(
+            &parse_quote! {
+                async fn eg(
+                    #[allow(unused)] #[swiper(name = "left_motor")] a: i32,
+                    #[allow(unused)] b: i32,
+                ) {}
+            },
+            &[format_ident!("a")],
+            &MacroArgs::default().crate_path,
+        )
+        .expect("failed to parse IR");
+
+        let expected = IntermediateRepr {
+            outer_params: vec![
+                parse_quote! { #[allow(unused)] a: &impl ::swiper_stealing::requirement::RevocableData<i32>},
+                parse_quote! { #[allow(unused)] b: i32 },
+            ],
+            inner_params: vec![
+                parse_quote! { #[allow(unused)] a: i32 },
+                parse_quote! { #[allow(unused)] b: i32 },
+            ],
+            inner_args: vec![
+                parse_quote! { *::swiper_stealing::guard::RevocableGuard::current(a).expect(#OWNED) },
+                parse_quote! { b },
+            ],
+            requirements_arr: vec![parse_quote! {
+                &::swiper_stealing::requirement::RenamedRequirement::new(a, "left_motor")
+            }],
+        };
+
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn method_to_ir_guarded_receiver() {
+        let out = method_to_ir(&parse_quote! {
+            async fn bump(&mut self, by: i32) { self.value += by; }
+        })
+        .expect("failed to parse IR");
+
+        let expected = IntermediateRepr {
+            outer_params: vec![parse_quote! { &self }, parse_quote! { by: i32 }],
+            inner_params: vec![parse_quote! { &mut self }, parse_quote! { by: i32 }],
+            inner_args: vec![parse_quote! { by }],
+            requirements_arr: vec![parse_quote! { self }],
+        };
+        assert_eq!(out, expected);
+
+        let invalid: [ImplItemFn; 3] = [
+            parse_quote! { fn bump(&mut self) {} },
+            parse_quote! { async fn bump(&self) {} },
+            parse_quote! { async fn bump(x: i32) {} },
+        ];
+        for input in &invalid {
+            assert!(method_to_ir(input).is_err());
+        }
+    }
+
+    #[test]
+    fn fn_to_ir_invalid_param_attrs() {
+        let crate_path = MacroArgs::default().crate_path;
+        let invalid: [ItemFn; 4] = [
+            parse_quote! { async fn eg(#[swiper(name = "x")] a: i32, b: i32) {} },
+            parse_quote! { async fn eg(#[swiper(rename = "x")] a: i32) {} },
+            parse_quote! { async fn eg(#[swiper(name = "x", name = "y")] a: i32) {} },
+            parse_quote! { async fn eg(#[cfg(test)] a: i32) {} },
+        ];
+        for input in &invalid[..1] {
+            assert!(single_fn_to_ir(input, &[format_ident!("b")], &crate_path).is_err());
+        }
+        for input in &invalid[1..] {
+            assert!(single_fn_to_ir(input, &[], &crate_path).is_err());
+        }
+    }
 }
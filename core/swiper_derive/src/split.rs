@@ -0,0 +1,174 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Error, Fields, Ident, Path, Type, parse_quote};
+
+/// fields grouped into one piece, in declaration order
+struct Piece<'a> {
+    ident: Ident,
+    fields: Vec<(&'a Ident, &'a Type)>,
+}
+
+/// struct with `#[split(Piece)]` fields -> one borrowing struct per piece + `SplitBorrow` impl returning them as a tuple
+pub(crate) fn derive_split_borrow(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let mut crate_path: Path = parse_quote! { ::swiper_stealing };
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("split"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("crate") {
+                crate_path = meta.value()?.parse()?;
+                Ok(())
+            } else {
+                Err(meta.error("unsupported split container attribute"))
+            }
+        })?;
+    }
+
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "`SplitBorrow` does not yet support generic structs",
+        ));
+    }
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "`SplitBorrow` can only be derived for structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(Error::new_spanned(
+            &data.fields,
+            "`SplitBorrow` requires named fields",
+        ));
+    };
+
+    let mut pieces: Vec<Piece> = Vec::new();
+    for field in &fields.named {
+        let Some(attr) = field
+            .attrs
+            .iter()
+            .find(|attr| attr.path().is_ident("split"))
+        else {
+            continue;
+        };
+        let piece: Ident = attr.parse_args()?;
+        let ident = field.ident.as_ref().expect("named fields have idents");
+        match pieces.iter_mut().find(|existing| existing.ident == piece) {
+            Some(existing) => existing.fields.push((ident, &field.ty)),
+            None => pieces.push(Piece {
+                ident: piece,
+                fields: vec![(ident, &field.ty)],
+            }),
+        }
+    }
+    if pieces.is_empty() {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "`SplitBorrow` needs at least one field marked `#[split(Piece)]`",
+        ));
+    }
+
+    let vis = &input.vis;
+    let ident = &input.ident;
+    let piece_structs = pieces.iter().map(|piece| {
+        let piece_ident = &piece.ident;
+        let doc = format!(
+            "Disjoint borrow of fields of [`{ident}`], generated by `#[derive(SplitBorrow)]`"
+        );
+        let fields = piece
+            .fields
+            .iter()
+            .map(|(field, ty)| quote! { pub #field: &'a mut #ty });
+        quote! {
+            #[doc = #doc]
+            #vis struct #piece_ident<'a> {
+                #(#fields),*
+            }
+        }
+    });
+    let piece_types = pieces.iter().map(|piece| {
+        let piece_ident = &piece.ident;
+        quote! { #piece_ident<'a> }
+    });
+    let piece_exprs = pieces.iter().map(|piece| {
+        let piece_ident = &piece.ident;
+        let fields = piece
+            .fields
+            .iter()
+            .map(|(field, _)| quote! { #field: &mut self.#field });
+        quote! { #piece_ident { #(#fields),* } }
+    });
+
+    Ok(quote! {
+        #(#piece_structs)*
+
+        impl #crate_path::split::SplitBorrow for #ident {
+            type Pieces<'a> = (#(#piece_types,)*);
+
+            fn split(&mut self) -> Self::Pieces<'_> {
+                (#(#piece_exprs,)*)
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_pieces() {
+        let out = derive_split_borrow(&parse_quote! {
+            struct Robot {
+                #[split(Drive)]
+                left: f64,
+                #[split(Arm)]
+                angle: f64,
+                #[split(Drive)]
+                right: f64,
+                name: &'static str,
+            }
+        })
+        .expect("failed to derive")
+        .to_string();
+
+        let expected = quote! {
+            #[doc = "Disjoint borrow of fields of [`Robot`], generated by `#[derive(SplitBorrow)]`"]
+            struct Drive<'a> {
+                pub left: &'a mut f64,
+                pub right: &'a mut f64
+            }
+            #[doc = "Disjoint borrow of fields of [`Robot`], generated by `#[derive(SplitBorrow)]`"]
+            struct Arm<'a> {
+                pub angle: &'a mut f64
+            }
+
+            impl ::swiper_stealing::split::SplitBorrow for Robot {
+                type Pieces<'a> = (Drive<'a>, Arm<'a>,);
+
+                fn split(&mut self) -> Self::Pieces<'_> {
+                    (Drive { left: &mut self.left, right: &mut self.right }, Arm { angle: &mut self.angle },)
+                }
+            }
+        }
+        .to_string();
+
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn split_invalid() {
+        let invalid: [DeriveInput; 4] = [
+            parse_quote! { struct Robot(#[split(Drive)] f64); },
+            parse_quote! { struct Robot { left: f64 } },
+            parse_quote! { struct Robot<T> { #[split(Drive)] left: T } },
+            parse_quote! { #[split(rename = "x")] struct Robot { #[split(Drive)] left: f64 } },
+        ];
+        for input in &invalid {
+            assert!(derive_split_borrow(input).is_err());
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! Development-time counting of the accesses each owner makes to a cell, for checking assumptions about who writes what.
+//!
+//! With the `debug-audit` feature, every [`RevocableCell`] counts the reads and writes made through guards,
+//! which includes the data handed to [`run()`](RevocableCell::run) bodies and `#[preemptible]` functions,
+//! and remembers the last task that wrote.
+//! The counts restart whenever a different thief accesses the cell, so they always describe a single owner.
+//! Access through raw pointers, such as [`data_ptr()`](RevocableCell::data_ptr), is not counted.
+//! Without the feature, neither the counters nor this module exist.
+
+use core::any::Any;
+
+use crate::{requirement::RevocableCell, thief::ThiefInfo};
+
+/// Accesses made to a cell by its most recent accessor, returned by [`RevocableCell::audit()`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AccessAudit {
+    /// The thief the counts belong to
+    pub owner: Option<ThiefInfo>,
+    /// Number of shared accesses by `owner`
+    pub reads: u32,
+    /// Number of mutable accesses by `owner`
+    pub writes: u32,
+    /// The most recent thief with a mutable access, which may be an earlier owner
+    pub last_writer: Option<ThiefInfo>,
+}
+
+impl<T: ?Sized, M: Any, const H: usize> RevocableCell<T, M, H> {
+    /// Returns the accesses counted for this cell, see the [module-level documentation](self).
+    pub fn audit(&self) -> AccessAudit {
+        self.audit_cell().get()
+    }
+
+    pub(crate) fn record_audit(&self, thief: &ThiefInfo, write: bool) {
+        let mut audit = self.audit();
+        if !audit.owner.is_some_and(|owner| owner.same_thief(thief)) {
+            audit = AccessAudit {
+                owner: Some(*thief),
+                last_writer: audit.last_writer,
+                ..AccessAudit::default()
+            };
+        }
+        if write {
+            audit.writes += 1;
+            audit.last_writer = Some(*thief);
+        } else {
+            audit.reads += 1;
+        }
+        self.audit_cell().set(audit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use futures_lite::future::yield_now;
+
+    use super::*;
+    use crate::mutex::RequirementMutex;
+
+    #[test]
+    fn counts_per_owner() {
+        let arm = RevocableCell::new(0, "arm");
+        let script = ThiefInfo::new("script");
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut guard = arm.claim(&script).unwrap();
+        let _ = *guard + *guard;
+        *guard += 1;
+        drop(guard);
+        assert_eq!(
+            arm.audit(),
+            AccessAudit {
+                owner: Some(script),
+                reads: 2,
+                writes: 1,
+                last_writer: Some(script),
+            }
+        );
+
+        // a run body receives its data once, however often it is polled
+        let mut hold = pin!(arm.run("hold", async |arm| {
+            yield_now().await;
+            *arm
+        }));
+        assert!(hold.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(hold.as_mut().poll(&mut cx), Poll::Ready(Ok(1)));
+        let audit = arm.audit();
+        assert_eq!(audit.owner.map(|owner| owner.name), Some("hold".into()));
+        assert_eq!((audit.reads, audit.writes), (0, 1));
+
+        // reading alone keeps the previous writer
+        let reader = ThiefInfo::new("reader");
+        let guard = arm.claim(&reader).unwrap();
+        assert_eq!(*guard, 1);
+        drop(guard);
+        let audit = arm.audit();
+        assert_eq!((audit.reads, audit.writes), (1, 0));
+        assert_eq!(
+            audit.last_writer.map(|owner| owner.name),
+            Some("hold".into())
+        );
+    }
+
+    #[test]
+    fn mutex_guards_are_counted() {
+        let counter = RequirementMutex::new(0, "counter");
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut task = pin!(counter.run("task", async |mut counter| {
+            *counter += 1;
+            *counter += 1;
+        }));
+        assert_eq!(task.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(counter.audit().writes, 2);
+    }
+}
@@ -0,0 +1,395 @@
+//! Synchronous access to a [`RevocableCell`] for quick scripts, stealing it from whichever task holds it.
+//!
+//! [`AutoSteal::guard_mut()`] steals the cell on behalf of a thief named after the caller's source location,
+//! and the returned [`AutoStealGuard`] releases it when dropped.
+//! The thief has the default priority, so the cell's [policy](crate::policy) may refuse the steal, in which case no guard is returned.
+//! Steals and releases go through the cell's usual [`Requirement`] bookkeeping,
+//! so a preempted task's [`PreemptionError`](crate::PreemptionError) names the line that took the cell.
+
+extern crate std;
+
+use core::{
+    cell::Cell,
+    ops::{Deref, DerefMut},
+    panic::Location,
+};
+use std::{boxed::Box, collections::HashMap, format};
+
+use crate::{
+    guard::{BorrowFlag, RevocableGuard},
+    history::StealRecord,
+    requirement::{
+        Claims, LastOwner, LastPreemption, Requirement, RequirementId, RequirementInfo,
+        RequirementInfoSource, RevocableCell, RevocableData, StealOutcome, TenureEnd,
+    },
+    thief::{PreemptibleFuture, ThiefInfo},
+};
+
+std::thread_local! {
+    static LOCATION_NAMES: core::cell::RefCell<HashMap<Location<'static>, &'static str>> =
+        core::cell::RefCell::new(HashMap::new());
+}
+
+/// Returns `file:line:column` of `location`, leaking each distinct location's name once.
+fn location_name(location: &'static Location<'static>) -> &'static str {
+    LOCATION_NAMES.with_borrow_mut(|names| {
+        *names.entry(*location).or_insert_with(|| {
+            Box::leak(
+                format!(
+                    "{}:{}:{}",
+                    location.file(),
+                    location.line(),
+                    location.column()
+                )
+                .into_boxed_str(),
+            )
+        })
+    })
+}
+
+/// A [`RevocableCell`] that can also be borrowed synchronously by stealing it, see the [module-level documentation](self)
+pub struct AutoSteal<T> {
+    cell: RevocableCell<T>,
+    thief: Cell<ThiefInfo>,
+    guarded: Cell<bool>,
+}
+
+impl<T> AutoSteal<T> {
+    /// Creates a new [`AutoSteal`] with ownership of `data`.
+    pub fn new(data: T, name: &'static str) -> Self {
+        Self {
+            cell: RevocableCell::new(data, name),
+            thief: Cell::new(ThiefInfo::new("auto_steal")),
+            guarded: Cell::new(false),
+        }
+    }
+
+    /// Consumes the cell, returning the guarded value.
+    pub fn into_inner(self) -> T {
+        self.cell.into_inner()
+    }
+
+    /// Steals the cell from any task holding it, returning a guard that releases it when dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a guard of this cell is still live or the cell refuses the steal, see [`try_guard_mut()`](Self::try_guard_mut).
+    #[track_caller]
+    pub fn guard_mut(&self) -> AutoStealGuard<'_, T> {
+        let location = Location::caller();
+        assert!(
+            !self.guarded.get(),
+            "{} is already guarded, requested again at {location}",
+            self.cell.info()
+        );
+        self.steal_at(location).unwrap_or_else(|| {
+            panic!(
+                "{} refused to be stolen from {:?} at {location}",
+                self.cell.info(),
+                self.cell.current_owner()
+            )
+        })
+    }
+
+    /// Same as [`guard_mut()`](Self::guard_mut), but returns `None` instead of panicking if a guard is still live or the cell refuses the steal.
+    #[track_caller]
+    pub fn try_guard_mut(&self) -> Option<AutoStealGuard<'_, T>> {
+        self.steal_at(Location::caller())
+    }
+
+    fn steal_at(&self, location: &'static Location<'static>) -> Option<AutoStealGuard<'_, T>> {
+        if self.guarded.replace(true) {
+            return None;
+        }
+        let thief = ThiefInfo::new(location_name(location));
+        if let StealOutcome::Refused { .. } = self.cell.try_steal_ownership(&thief) {
+            self.guarded.set(false);
+            return None;
+        }
+        self.thief.set(thief);
+        Some(AutoStealGuard {
+            cell: self,
+            generation: self.cell.generation(),
+        })
+    }
+
+    /// Same as [`RevocableCell::run`], so tasks can contend with synchronous accesses.
+    ///
+    /// # Errors
+    ///
+    /// If access to this cell has been stolen by a different future or a guard, the future returns `Err<PreemptionError>` when next polled.
+    pub fn run<'a, Out>(
+        &'a self,
+        name: &'static str,
+        func: impl AsyncFnOnce(&mut T) -> Out + 'a,
+    ) -> PreemptibleFuture<'a, impl Future<Output = Out>, Out, 1> {
+        let inner = async move {
+            let mut guard = RevocableGuard::current(self).expect("run owns its cell");
+            func(&mut guard).await
+        };
+        PreemptibleFuture::new(inner, name, [self]).lending_data()
+    }
+}
+
+impl<T> Requirement for AutoSteal<T> {
+    fn steal_ownership(&self, thief: &ThiefInfo) {
+        self.cell.steal_ownership(thief);
+    }
+
+    fn try_steal_ownership(&self, thief: &ThiefInfo) -> StealOutcome {
+        self.cell.try_steal_ownership(thief)
+    }
+
+    fn claim_shared(&self, thief: &ThiefInfo) -> StealOutcome {
+        self.cell.claim_shared(thief)
+    }
+
+    fn release_shared(&self, generation: u64) {
+        self.cell.release_shared(generation);
+    }
+
+    fn release_ownership(&self) {
+        self.cell.release_ownership();
+    }
+
+    fn end_ownership(&self, ended: TenureEnd) {
+        self.cell.end_ownership(ended);
+    }
+
+    fn revoke(&self, by: &ThiefInfo) {
+        self.cell.revoke(by);
+    }
+
+    fn poison(&self) {
+        self.cell.poison();
+    }
+
+    fn is_owned_by(&self, thief: &ThiefInfo) -> bool {
+        self.cell.is_owned_by(thief)
+    }
+
+    fn mark_polled(&self) {
+        self.cell.mark_polled();
+    }
+
+    fn register_owner_waker(&self, waker: &core::task::Waker) {
+        self.cell.register_owner_waker(waker);
+    }
+
+    fn register_release_waker(&self, waker: &core::task::Waker) {
+        self.cell.register_release_waker(waker);
+    }
+
+    fn meta_any(&self) -> Option<&dyn core::any::Any> {
+        self.cell.meta_any()
+    }
+}
+
+impl<T> RequirementInfoSource for AutoSteal<T> {
+    fn current_owner(&self) -> Option<ThiefInfo> {
+        self.cell.current_owner()
+    }
+
+    fn current_claims(&self) -> Claims {
+        self.cell.current_claims()
+    }
+
+    fn info(&self) -> RequirementInfo {
+        self.cell.info()
+    }
+
+    fn id(&self) -> RequirementId {
+        self.cell.id()
+    }
+
+    fn generation(&self) -> u64 {
+        self.cell.generation()
+    }
+
+    fn last_preemption(&self) -> Option<LastPreemption> {
+        self.cell.last_preemption()
+    }
+
+    fn last_owner(&self) -> Option<LastOwner> {
+        self.cell.last_owner()
+    }
+
+    fn last_steal(&self) -> Option<StealRecord> {
+        self.cell.last_steal()
+    }
+
+    fn yielded_by(&self) -> Option<ThiefInfo> {
+        self.cell.yielded_by()
+    }
+
+    fn is_poisoned(&self) -> bool {
+        self.cell.is_poisoned()
+    }
+
+    fn is_frozen(&self) -> bool {
+        self.cell.is_frozen()
+    }
+
+    fn min_steal_priority(&self) -> u8 {
+        self.cell.min_steal_priority()
+    }
+
+    fn may_steal(&self, incoming: &ThiefInfo) -> bool {
+        self.cell.may_steal(incoming)
+    }
+}
+
+impl<T> RevocableData<T> for AutoSteal<T> {
+    unsafe fn data_ptr(&self) -> *mut T {
+        unsafe { self.cell.data_ptr() }
+    }
+
+    fn borrow_flag(&self) -> Option<&BorrowFlag> {
+        self.cell.borrow_flag()
+    }
+
+    fn mark_changed(&self) {
+        self.cell.mark_changed();
+    }
+
+    #[cfg(feature = "debug-audit")]
+    fn record_access(&self, thief: &ThiefInfo, write: bool) {
+        self.cell.record_access(thief, write);
+    }
+}
+
+/// Synchronous access to an [`AutoSteal`] cell, created by [`AutoSteal::guard_mut()`]
+pub struct AutoStealGuard<'a, T> {
+    cell: &'a AutoSteal<T>,
+    generation: u64,
+}
+
+impl<T> AutoStealGuard<'_, T> {
+    fn is_owner(&self) -> bool {
+        self.cell.cell.generation() == self.generation
+    }
+
+    fn validate(&self) {
+        assert!(
+            self.is_owner(),
+            "{} was stolen by a task while {} still held a guard",
+            self.cell.cell.info(),
+            self.cell.thief.get(),
+        );
+    }
+}
+
+impl<T> Deref for AutoStealGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.validate();
+        // the guard's thief owns the cell, and there is only one guard
+        unsafe { &*self.cell.cell.data_ptr() }
+    }
+}
+
+impl<T> DerefMut for AutoStealGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.validate();
+        unsafe { &mut *self.cell.cell.data_ptr() }
+    }
+}
+
+impl<T> Drop for AutoStealGuard<'_, T> {
+    fn drop(&mut self) {
+        // a task that stole the cell while the guard was held keeps it
+        if self.is_owner() {
+            self.cell.cell.release_ownership();
+        }
+        self.cell.guarded.set(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use super::*;
+
+    #[test]
+    fn guard_preempts_task() {
+        let arm = AutoSteal::new(0, "arm");
+        let mut task = pin!(arm.run("hold", async |x| {
+            loop {
+                *x += 1;
+                futures_lite::future::yield_now().await;
+            }
+        }));
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(task.as_mut().poll(&mut cx).is_pending());
+
+        let line = line!() + 1;
+        let mut guard = arm.guard_mut();
+        *guard = 90;
+        let Poll::Ready(Err(err)) = task.as_mut().poll(&mut cx) else {
+            panic!("hold should be preempted by the guard");
+        };
+        let incoming = err.incoming().expect("the guard should still own the cell");
+        assert!(
+            incoming
+                .name
+                .as_str()
+                .contains(&format!("auto_steal.rs:{line}:"))
+        );
+        assert_eq!(err.outgoing().name, "hold");
+
+        drop(guard);
+        assert!(arm.current_owner().is_none());
+        let mut task = pin!(arm.run("read", async |x| *x));
+        assert_eq!(task.as_mut().poll(&mut cx), Poll::Ready(Ok(90)));
+    }
+
+    #[test]
+    fn guard_refused_by_owner() {
+        let arm = AutoSteal::new(0, "arm");
+        let mut task = pin!(
+            arm.run("hold", async |x| {
+                *x += 1;
+                futures_lite::future::yield_now().await;
+            })
+            .with_priority(2)
+        );
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(task.as_mut().poll(&mut cx).is_pending());
+
+        // guards have the default priority, so they cannot steal from the task
+        assert!(arm.try_guard_mut().is_none());
+        assert_eq!(
+            arm.current_owner().map(|owner| owner.name.as_str()),
+            Some("hold")
+        );
+        assert_eq!(task.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+
+        // the refusal does not leave the cell marked as guarded
+        assert_eq!(*arm.guard_mut(), 1);
+    }
+
+    #[test]
+    fn reentrant_guard() {
+        let arm = AutoSteal::new(0, "arm");
+        let guard = arm.guard_mut();
+        assert!(arm.try_guard_mut().is_none());
+        drop(guard);
+        assert!(arm.try_guard_mut().is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "still held a guard")]
+    fn guard_used_after_task_steal() {
+        let arm = AutoSteal::new(0, "arm");
+        let mut guard = arm.guard_mut();
+        let mut task = pin!(arm.run("thief", async |x| *x = 1));
+        let mut cx = Context::from_waker(Waker::noop());
+        assert_eq!(task.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        *guard += 1;
+    }
+}
@@ -0,0 +1,269 @@
+//! A cell over borrowed data, so the data can stay on the caller's stack.
+//!
+//! A [`RevocableRef`] wraps a `&mut T` and hands tasks that reference directly,
+//! where a `RevocableCell<&mut T>` would hand out a `&mut &mut T`.
+//! It is a [`RevocableData`] of both `T` and `&mut T`, so it can be passed to `#[preemptible]` functions
+//! taking either `x: T` or `x: &mut T`, in place of a `RevocableCell<T>` or a `RevocableCell<&mut T>`.
+
+use crate::{
+    guard::{BorrowFlag, RevocableGuard},
+    history::StealRecord,
+    name::Name,
+    requirement::{
+        Claims, LastOwner, LastPreemption, Requirement, RequirementId, RequirementInfo,
+        RequirementInfoSource, RevocableCell, RevocableData, StealOutcome, TenureEnd,
+    },
+    thief::{PreemptibleFuture, ThiefInfo},
+};
+
+/// A [`RevocableCell`] borrowing its data, see the [module-level documentation](self)
+pub struct RevocableRef<'a, T> {
+    cell: RevocableCell<&'a mut T>,
+}
+
+impl<'a, T> RevocableRef<'a, T> {
+    /// Creates a new [`RevocableRef`] with ownership of the borrowed `data`.
+    pub fn new(data: &'a mut T, name: impl Into<Name>) -> Self {
+        Self {
+            cell: RevocableCell::new(data, name),
+        }
+    }
+
+    /// Returns a mutable reference to the borrowed value, forgetting the current owner as [`RevocableCell::get_mut()`] does.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.cell.get_mut()
+    }
+
+    /// Consumes the cell, returning the borrow it was created with.
+    pub fn into_inner(self) -> &'a mut T {
+        self.cell.into_inner()
+    }
+
+    /// Returns a copy of the borrowed value without claiming the cell, see [`RevocableCell::peek()`].
+    ///
+    /// # Panics
+    ///
+    /// Same as [`RevocableCell::peek()`].
+    pub fn peek(&self) -> T
+    where
+        T: Copy,
+    {
+        self.cell.peek_with(|data| **data)
+    }
+
+    /// Returns a raw pointer to the borrowed value.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`RevocableCell::data_ptr()`].
+    pub unsafe fn data_ptr(&self) -> *mut T {
+        // the borrow itself is only replaced through `get_mut()`, which needs exclusive access
+        unsafe { &raw mut **self.cell.data_ptr() }
+    }
+
+    /// Same as [`RevocableCell::run()`], with `func` receiving the borrowed value directly.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`RevocableCell::run()`].
+    pub fn run<'b, Out>(
+        &'b self,
+        name: impl Into<Name>,
+        func: impl AsyncFnOnce(&mut T) -> Out + 'b,
+    ) -> PreemptibleFuture<'b, impl Future<Output = Out>, Out, 1> {
+        let inner = async move {
+            let mut guard: RevocableGuard<'_, T> =
+                RevocableGuard::current(self).expect("run owns its cell");
+            func(&mut guard).await
+        };
+        PreemptibleFuture::new(inner, name, [self]).lending_data()
+    }
+}
+
+impl<T> Requirement for RevocableRef<'_, T> {
+    fn steal_ownership(&self, thief: &ThiefInfo) {
+        self.cell.steal_ownership(thief);
+    }
+
+    fn try_steal_ownership(&self, thief: &ThiefInfo) -> StealOutcome {
+        self.cell.try_steal_ownership(thief)
+    }
+
+    fn claim_shared(&self, thief: &ThiefInfo) -> StealOutcome {
+        self.cell.claim_shared(thief)
+    }
+
+    fn release_shared(&self, generation: u64) {
+        self.cell.release_shared(generation);
+    }
+
+    fn release_ownership(&self) {
+        self.cell.release_ownership();
+    }
+
+    fn end_ownership(&self, ended: TenureEnd) {
+        self.cell.end_ownership(ended);
+    }
+
+    fn revoke(&self, by: &ThiefInfo) {
+        self.cell.revoke(by);
+    }
+
+    fn poison(&self) {
+        self.cell.poison();
+    }
+
+    fn is_owned_by(&self, thief: &ThiefInfo) -> bool {
+        self.cell.is_owned_by(thief)
+    }
+
+    fn mark_polled(&self) {
+        self.cell.mark_polled();
+    }
+
+    fn register_owner_waker(&self, waker: &core::task::Waker) {
+        self.cell.register_owner_waker(waker);
+    }
+
+    fn register_release_waker(&self, waker: &core::task::Waker) {
+        self.cell.register_release_waker(waker);
+    }
+
+    fn meta_any(&self) -> Option<&dyn core::any::Any> {
+        self.cell.meta_any()
+    }
+}
+
+impl<T> RequirementInfoSource for RevocableRef<'_, T> {
+    fn current_owner(&self) -> Option<ThiefInfo> {
+        self.cell.current_owner()
+    }
+
+    fn current_claims(&self) -> Claims {
+        self.cell.current_claims()
+    }
+
+    fn info(&self) -> RequirementInfo {
+        RequirementInfo {
+            type_name: core::any::type_name::<T>(),
+            ..self.cell.info()
+        }
+    }
+
+    fn id(&self) -> RequirementId {
+        self.cell.id()
+    }
+
+    fn generation(&self) -> u64 {
+        self.cell.generation()
+    }
+
+    fn last_preemption(&self) -> Option<LastPreemption> {
+        self.cell.last_preemption()
+    }
+
+    fn last_owner(&self) -> Option<LastOwner> {
+        self.cell.last_owner()
+    }
+
+    fn last_steal(&self) -> Option<StealRecord> {
+        self.cell.last_steal()
+    }
+
+    fn yielded_by(&self) -> Option<ThiefInfo> {
+        self.cell.yielded_by()
+    }
+
+    fn is_poisoned(&self) -> bool {
+        self.cell.is_poisoned()
+    }
+
+    fn is_frozen(&self) -> bool {
+        self.cell.is_frozen()
+    }
+
+    fn min_steal_priority(&self) -> u8 {
+        self.cell.min_steal_priority()
+    }
+
+    fn may_steal(&self, incoming: &ThiefInfo) -> bool {
+        self.cell.may_steal(incoming)
+    }
+}
+
+impl<T> RevocableData<T> for RevocableRef<'_, T> {
+    unsafe fn data_ptr(&self) -> *mut T {
+        unsafe { RevocableRef::data_ptr(self) }
+    }
+
+    fn borrow_flag(&self) -> Option<&BorrowFlag> {
+        self.cell.borrow_flag()
+    }
+
+    fn mark_changed(&self) {
+        self.cell.mark_changed();
+    }
+
+    #[cfg(feature = "debug-audit")]
+    fn record_access(&self, thief: &ThiefInfo, write: bool) {
+        self.cell.record_access(thief, write);
+    }
+}
+
+impl<'a, T> RevocableData<&'a mut T> for RevocableRef<'a, T> {
+    unsafe fn data_ptr(&self) -> *mut &'a mut T {
+        unsafe { self.cell.data_ptr() }
+    }
+
+    fn borrow_flag(&self) -> Option<&BorrowFlag> {
+        self.cell.borrow_flag()
+    }
+
+    fn mark_changed(&self) {
+        self.cell.mark_changed();
+    }
+
+    #[cfg(feature = "debug-audit")]
+    fn record_access(&self, thief: &ThiefInfo, write: bool) {
+        self.cell.record_access(thief, write);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::task::{Context, Poll, Waker};
+    use std::boxed::Box;
+
+    use futures_lite::future::yield_now;
+
+    use super::*;
+
+    #[test]
+    fn direct_access() {
+        let mut count = 0;
+        let cell = RevocableRef::new(&mut count, "count");
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut increment = Box::pin(cell.run("increment", async |count| {
+            loop {
+                *count += 1;
+                yield_now().await;
+            }
+        }));
+        assert!(increment.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(cell.peek(), 1);
+
+        let mut reset = Box::pin(cell.run("reset", async |count| *count = 0));
+        assert_eq!(reset.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(matches!(
+            increment.as_mut().poll(&mut cx),
+            Poll::Ready(Err(_))
+        ));
+        assert_eq!(cell.info().type_name, "i32");
+        drop((increment, reset));
+        *cell.into_inner() += 2;
+        assert_eq!(count, 2);
+    }
+}
@@ -0,0 +1,362 @@
+//! A drop-in replacement for [`RevocableCell`] that panics on misuse instead of risking undefined behavior.
+//!
+//! [`CheckedRevocableCell`] keeps its data in a [`RefCell`] and borrows it for every poll of a task's inner future,
+//! so a second access during that poll, such as a nested task stealing the same cell, panics instead of aliasing.
+//! Every poll also checks that the polled task owns the cell, and releasing a cell that has no owner panics as a double release.
+//! Panic messages name the requirement and the thieves involved.
+//!
+//! The checks cost a borrow flag and an ownership lookup per poll, so the cell is meant for tests and debug builds:
+//! swap the type parameter back to [`RevocableCell`] once the code is known to be correct.
+//! Like any [`RevocableData`], it can be passed to `#[preemptible]` functions,
+//! although such functions only go through [`data_ptr()`](CheckedRevocableCell::data_ptr) once, when they are called.
+
+use core::{
+    cell::{Cell, RefCell, RefMut},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{
+    current_thief,
+    guard::BorrowFlag,
+    history::StealRecord,
+    requirement::{
+        Claims, LastOwner, LastPreemption, Requirement, RequirementId, RequirementInfo,
+        RequirementInfoSource, RevocableCell, RevocableData, StealOutcome, TenureEnd,
+    },
+    thief::{PreemptibleFuture, ThiefInfo, owned_by_current},
+};
+
+fn name(thief: Option<ThiefInfo>) -> &'static str {
+    thief.map_or("code outside of any task", |thief| thief.name.as_str())
+}
+
+/// A [`RevocableCell`] that validates every access, see the [module-level documentation](self)
+pub struct CheckedRevocableCell<T> {
+    data: RefCell<T>,
+    /// Ownership bookkeeping, shared with the unchecked cell
+    cell: RevocableCell<()>,
+    /// Task whose inner future is being polled, holding the borrow of `data`
+    active: Cell<Option<ThiefInfo>>,
+}
+
+impl<T> CheckedRevocableCell<T> {
+    /// Creates a new [`CheckedRevocableCell`] with ownership of `data`.
+    ///
+    /// The cell will default having no owner.
+    pub fn new(data: T, name: &'static str) -> Self {
+        Self {
+            data: RefCell::new(data),
+            cell: RevocableCell::new((), name),
+            active: Cell::new(None),
+        }
+    }
+
+    /// Returns a mutable reference to the guarded value, forgetting the current owner as [`RevocableCell::get_mut()`] does.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.cell.get_mut();
+        self.data.get_mut()
+    }
+
+    /// Consumes the cell, returning the guarded value.
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+
+    /// Returns a raw pointer to the guarded value, see [`RevocableCell::data_ptr()`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data is borrowed by a task being polled.
+    ///
+    /// # Safety
+    ///
+    /// The pointer may only be dereferenced while no other reference to the value is in use.
+    pub unsafe fn data_ptr(&self) -> *mut T {
+        drop(self.borrow());
+        self.data.as_ptr()
+    }
+
+    /// Same as [`RevocableCell::run()`], validating ownership and exclusive access on every poll.
+    ///
+    /// # Errors
+    ///
+    /// If access to this cell has been stolen by a different future, the future returns `Err<PreemptionError>` when next polled.
+    pub fn run<'a, Out>(
+        &'a self,
+        name: &'static str,
+        func: impl AsyncFnOnce(&mut T) -> Out + 'a,
+    ) -> PreemptibleFuture<'a, Checked<'a, T, impl Future<Output = Out>>, Out, 1> {
+        let data = self.data.as_ptr();
+        // the reference is only created once the future has stolen the cell, and only used while `Checked` borrows the data
+        let inner = async move { func(unsafe { &mut *data }).await };
+        PreemptibleFuture::new(Checked { cell: self, inner }, name, [self]).lending_data()
+    }
+
+    /// Borrows the data, panicking if it is already borrowed by a task being polled.
+    fn borrow(&self) -> RefMut<'_, T> {
+        self.data.try_borrow_mut().unwrap_or_else(|_| {
+            panic!(
+                "{} was accessed by {} while {} was using it",
+                self.info(),
+                name(current_thief()),
+                name(self.active.get()),
+            )
+        })
+    }
+
+    fn check_inactive(&self, thief: &ThiefInfo) {
+        if let Some(active) = self.active.get() {
+            panic!(
+                "{} was stolen by {} while {} was using it",
+                self.info(),
+                thief.name,
+                active.name,
+            );
+        }
+    }
+
+    fn check_owned(&self, action: &str) {
+        if self.cell.current_owner().is_some() {
+            return;
+        }
+        match self.cell.last_owner() {
+            Some(last) => panic!(
+                "{} was {action} twice, last owned by {}",
+                self.info(),
+                last.thief.name
+            ),
+            None => panic!("{} was {action} without an owner", self.info()),
+        }
+    }
+}
+
+impl<T> Requirement for CheckedRevocableCell<T> {
+    fn steal_ownership(&self, thief: &ThiefInfo) {
+        self.try_steal_ownership(thief);
+    }
+
+    fn try_steal_ownership(&self, thief: &ThiefInfo) -> StealOutcome {
+        self.check_inactive(thief);
+        self.cell.try_steal_ownership(thief)
+    }
+
+    fn claim_shared(&self, thief: &ThiefInfo) -> StealOutcome {
+        self.cell.claim_shared(thief)
+    }
+
+    fn release_shared(&self, generation: u64) {
+        self.cell.release_shared(generation);
+    }
+
+    fn release_ownership(&self) {
+        self.end_ownership(TenureEnd::Completed);
+    }
+
+    fn end_ownership(&self, ended: TenureEnd) {
+        self.check_owned("released");
+        self.cell.end_ownership(ended);
+    }
+
+    fn revoke(&self, by: &ThiefInfo) {
+        self.check_inactive(by);
+        self.cell.revoke(by);
+    }
+
+    fn poison(&self) {
+        self.cell.poison();
+    }
+
+    fn is_owned_by(&self, thief: &ThiefInfo) -> bool {
+        self.cell.is_owned_by(thief)
+    }
+
+    fn mark_polled(&self) {
+        self.cell.mark_polled();
+    }
+
+    fn register_owner_waker(&self, waker: &core::task::Waker) {
+        self.cell.register_owner_waker(waker);
+    }
+
+    fn register_release_waker(&self, waker: &core::task::Waker) {
+        self.cell.register_release_waker(waker);
+    }
+
+    fn meta_any(&self) -> Option<&dyn core::any::Any> {
+        self.cell.meta_any()
+    }
+}
+
+impl<T> RequirementInfoSource for CheckedRevocableCell<T> {
+    fn current_owner(&self) -> Option<ThiefInfo> {
+        self.cell.current_owner()
+    }
+
+    fn current_claims(&self) -> Claims {
+        self.cell.current_claims()
+    }
+
+    fn info(&self) -> RequirementInfo {
+        RequirementInfo {
+            type_name: core::any::type_name::<T>(),
+            ..self.cell.info()
+        }
+    }
+
+    fn id(&self) -> RequirementId {
+        self.cell.id()
+    }
+
+    fn generation(&self) -> u64 {
+        self.cell.generation()
+    }
+
+    fn last_preemption(&self) -> Option<LastPreemption> {
+        self.cell.last_preemption()
+    }
+
+    fn last_owner(&self) -> Option<LastOwner> {
+        self.cell.last_owner()
+    }
+
+    fn last_steal(&self) -> Option<StealRecord> {
+        self.cell.last_steal()
+    }
+
+    fn yielded_by(&self) -> Option<ThiefInfo> {
+        self.cell.yielded_by()
+    }
+
+    fn is_poisoned(&self) -> bool {
+        self.cell.is_poisoned()
+    }
+
+    fn is_frozen(&self) -> bool {
+        self.cell.is_frozen()
+    }
+
+    fn min_steal_priority(&self) -> u8 {
+        self.cell.min_steal_priority()
+    }
+
+    fn may_steal(&self, incoming: &ThiefInfo) -> bool {
+        self.cell.may_steal(incoming)
+    }
+}
+
+impl<T> RevocableData<T> for CheckedRevocableCell<T> {
+    unsafe fn data_ptr(&self) -> *mut T {
+        unsafe { CheckedRevocableCell::data_ptr(self) }
+    }
+
+    fn borrow_flag(&self) -> Option<&BorrowFlag> {
+        self.cell.borrow_flag()
+    }
+}
+
+/// Inner future of [`CheckedRevocableCell::run()`], borrowing the cell's data for each poll
+pub struct Checked<'a, T, Fut> {
+    cell: &'a CheckedRevocableCell<T>,
+    inner: Fut,
+}
+
+impl<T, Fut: Future> Future for Checked<'_, T, Fut> {
+    type Output = Fut::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // inner is structurally pinned, the cell is not
+        let this = unsafe { self.get_unchecked_mut() };
+        let cell = this.cell;
+        assert!(
+            owned_by_current(&cell.cell),
+            "{} was accessed by {} while owned by {}",
+            cell.cell.info(),
+            name(current_thief()),
+            cell.cell
+                .current_owner()
+                .map_or("no task", |owner| owner.name.as_str()),
+        );
+        let borrow = cell.borrow();
+        let previous = cell.active.replace(current_thief());
+        let res = unsafe { Pin::new_unchecked(&mut this.inner) }.poll(cx);
+        cell.active.set(previous);
+        drop(borrow);
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::{pin::pin, task::Waker};
+
+    use futures_lite::future::yield_now;
+
+    use super::*;
+
+    #[test]
+    fn drop_in_preemption() {
+        let arm = CheckedRevocableCell::new(0, "arm");
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut hold = pin!(arm.run("hold", async |x| {
+            loop {
+                *x += 1;
+                yield_now().await;
+            }
+        }));
+        assert!(hold.as_mut().poll(&mut cx).is_pending());
+        let mut stow = pin!(arm.run("stow", async |x| *x = 0));
+        assert_eq!(stow.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        let Poll::Ready(Err(err)) = hold.as_mut().poll(&mut cx) else {
+            panic!("hold should be preempted by stow");
+        };
+        assert_eq!(
+            err.incoming().map(|thief| thief.name.as_str()),
+            Some("stow")
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Requirement { name: arm, type: i32 }  was stolen by inner while outer was using it"
+    )]
+    fn nested_steal_panics() {
+        let arm = CheckedRevocableCell::new(0, "arm");
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut outer = pin!(arm.run("outer", async |x| {
+            *x += 1;
+            drop(arm.run("inner", async |y| *y = 0).await);
+            *x += 1;
+        }));
+        drop(outer.as_mut().poll(&mut cx));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Requirement { name: arm, type: i32 }  was accessed by hold while hold was using it"
+    )]
+    fn aliased_access_panics() {
+        let arm = CheckedRevocableCell::new(0, "arm");
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut hold = pin!(arm.run("hold", async |x| {
+            let alias = unsafe { arm.data_ptr() };
+            *x += 1;
+            unsafe { *alias += 1 };
+        }));
+        drop(hold.as_mut().poll(&mut cx));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Requirement { name: arm, type: i32 }  was released twice, last owned by hold"
+    )]
+    fn double_release_panics() {
+        let arm = CheckedRevocableCell::new(0, "arm");
+        let thief = ThiefInfo::new("hold");
+        arm.steal_ownership(&thief);
+        arm.release_ownership();
+        arm.release_ownership();
+    }
+}
@@ -0,0 +1,64 @@
+use core::{cell::Cell, fmt::Display};
+
+/// A point in time measured by a [`Clock`], in clock-defined units
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Tick(pub u64);
+
+impl Display for Tick {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "tick {}", self.0)
+    }
+}
+
+/// Source of timestamps for ownership bookkeeping.
+///
+/// Requirements only read the clock when ownership changes hands, so implementations should be cheap but need not be monotonic across resets.
+pub trait Clock {
+    /// Returns the current time.
+    fn now(&self) -> Tick;
+}
+
+/// A [`Clock`] that only advances when told to, for deterministic tests and simulations.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: Cell<u64>,
+}
+
+impl ManualClock {
+    /// Creates a new [`ManualClock`] starting at tick 0.
+    pub const fn new() -> Self {
+        Self { now: Cell::new(0) }
+    }
+
+    /// Moves the clock forward by `ticks`.
+    pub fn advance(&self, ticks: u64) {
+        self.now.set(self.now.get() + ticks);
+    }
+
+    /// Sets the clock to `tick`.
+    pub fn set(&self, tick: Tick) {
+        self.now.set(tick.0);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Tick {
+        Tick(self.now.get())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock() {
+        let clock = ManualClock::new();
+        assert_eq!(clock.now(), Tick(0));
+        clock.advance(3);
+        clock.advance(2);
+        assert_eq!(clock.now(), Tick(5));
+        clock.set(Tick(1));
+        assert_eq!(clock.now(), Tick(1));
+    }
+}
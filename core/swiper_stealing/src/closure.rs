@@ -0,0 +1,320 @@
+//! Closures as preemptible tasks, for tasks too small to be worth a `#[preemptible]` function.
+//!
+//! Any `async |x: &mut T| -> Out` closure is a [`StandardTask`], turned into a [`PreemptibleFuture`]
+//! with [`requiring()`](StandardTask::requiring).
+//! The trait is generic over the cells it requires, so the returned future borrows them for exactly the call's lifetime,
+//! and the future type stays anonymous without naming the closure's own future.
+//!
+//! Closures over several cells, e.g. `async |a: &mut A, b: &mut B|`, take a tuple of cells and steal all of them together.
+//! The same future is created directly by [`run2()`], [`run3()`] and [`run4()`], which also name the task at runtime.
+//!
+//! Closures taking extra arguments after the guarded data are curried with [`task_with()`],
+//! which captures the arguments by value and passes them through when the task runs.
+
+use crate::{
+    guard::RevocableGuard,
+    name::Name,
+    requirement::{Requirement, RevocableCell},
+    thief::PreemptibleFuture,
+};
+
+/// An async closure that can run as a [`PreemptibleFuture`] requiring `Cells`, resolving to `Out`
+pub trait StandardTask<Cells, Out = ()> {
+    /// Turns this closure into a task named `name`, which steals `cells` when first polled.
+    ///
+    /// # Errors
+    ///
+    /// If access to any of `cells` has been stolen by a different future, the future returns `Err<PreemptionError>` when next polled.
+    fn requiring(
+        self,
+        name: &'static str,
+        cells: Cells,
+    ) -> impl Future<Output = crate::Result<Out>>;
+
+    /// Same as [`requiring()`](Self::requiring), with the task named after the closure's type.
+    ///
+    /// The returned future borrows `cells` for as long as it exists, and the closure only receives data once the cells have been stolen.
+    fn with_requirement(self, cells: Cells) -> impl Future<Output = crate::Result<Out>>
+    where
+        Self: Sized,
+    {
+        self.requiring(core::any::type_name::<Self>(), cells)
+    }
+}
+
+impl<'a, T, F, Out> StandardTask<&'a RevocableCell<T>, Out> for F
+where
+    F: AsyncFnOnce(&mut T) -> Out + 'a,
+{
+    fn requiring(
+        self,
+        name: &'static str,
+        cell: &'a RevocableCell<T>,
+    ) -> impl Future<Output = crate::Result<Out>> {
+        cell.run(name, self)
+    }
+}
+
+/// A closure with its extra arguments captured, created by [`task_with()`]
+pub struct TaskWith<F, Args> {
+    func: F,
+    args: Args,
+}
+
+/// Captures `args` for a closure taking them after the guarded data, e.g. `async |arm: &mut Arm, target: f64|` with `(target,)`.
+pub fn task_with<F, Args>(func: F, args: Args) -> TaskWith<F, Args> {
+    TaskWith { func, args }
+}
+
+macro_rules! impl_task_with {
+    ($($arg:ident),+) => {
+        impl<'a, T, F, Out, $($arg: 'a),+> StandardTask<&'a RevocableCell<T>, Out> for TaskWith<F, ($($arg,)+)>
+        where
+            F: AsyncFnOnce(&mut T, $($arg),+) -> Out + 'a,
+        {
+            #[allow(non_snake_case)]
+            fn requiring(
+                self,
+                name: &'static str,
+                cell: &'a RevocableCell<T>,
+            ) -> impl Future<Output = crate::Result<Out>> {
+                let Self { func, args: ($($arg,)+) } = self;
+                cell.run(name, async move |x| func(x, $($arg),+).await)
+            }
+        }
+    };
+}
+
+impl_task_with!(A1);
+impl_task_with!(A1, A2);
+impl_task_with!(A1, A2, A3);
+
+macro_rules! impl_multi_cell {
+    ($n:literal, $run:ident; $($data:ident $cell:ident),+) => {
+        #[doc = concat!("Runs `func` with mutable access to the data of ", stringify!($n), " cells, as a [`PreemptibleFuture`] requiring all of them.")]
+        ///
+        /// Like [`RevocableCell::run()`], the data is only handed to `func` once every cell has been stolen.
+        ///
+        /// # Errors
+        ///
+        /// If access to any of the cells has been stolen by a different future, the future returns `Err<PreemptionError>` when next polled.
+        ///
+        /// # Panics
+        ///
+        /// Panics if the same cell is passed more than once.
+        pub fn $run<'a, F, Out, $($data: 'a),+>(
+            name: impl Into<Name>,
+            $($cell: &'a RevocableCell<$data>,)+
+            func: F,
+        ) -> PreemptibleFuture<'a, impl Future<Output = Out>, Out, $n>
+        where
+            F: AsyncFnOnce($(&mut $data),+) -> Out + 'a,
+        {
+            let name = name.into();
+            let requirements = [$($cell as &dyn Requirement),+];
+            for (i, a) in requirements.iter().enumerate() {
+                assert!(
+                    requirements[..i].iter().all(|b| a.id() != b.id()),
+                    "{name} requires {} more than once",
+                    a.info(),
+                );
+            }
+            let inner = async move {
+                let ($(mut $cell,)+) = ($(
+                    RevocableGuard::current($cell).expect(concat!(stringify!($run), " owns its cells")),
+                )+);
+                func($(&mut $cell),+).await
+            };
+            PreemptibleFuture::new(inner, name, requirements).lending_data()
+        }
+
+        impl<'a, F, Out, $($data: 'a),+> StandardTask<($(&'a RevocableCell<$data>,)+), Out> for F
+        where
+            F: AsyncFnOnce($(&mut $data),+) -> Out + 'a,
+        {
+            fn requiring(
+                self,
+                name: &'static str,
+                ($($cell,)+): ($(&'a RevocableCell<$data>,)+),
+            ) -> impl Future<Output = crate::Result<Out>> {
+                $run(name, $($cell,)+ self)
+            }
+        }
+    };
+}
+
+impl_multi_cell!(2, run2; A a, B b);
+impl_multi_cell!(3, run3; A a, B b, C c);
+impl_multi_cell!(4, run4; A a, B b, C c, D d);
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::{
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use futures_lite::future::yield_now;
+
+    use super::*;
+    use crate::requirement::RequirementInfoSource;
+
+    #[test]
+    fn closure_with_arguments() {
+        let arm = RevocableCell::new(0.0, "arm");
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let step = async |arm: &mut f64, target: f64, rate: f64| {
+            while *arm < target {
+                *arm += rate;
+                yield_now().await;
+            }
+        };
+        let mut raise = pin!(task_with(step, (90.0, 30.0)).requiring("raise", &arm));
+        while raise.as_mut().poll(&mut cx).is_pending() {}
+        assert_eq!(arm.peek(), 90.0);
+
+        // preemption is unchanged by the captured arguments
+        let hold = async |arm: &mut f64, target: f64| {
+            loop {
+                *arm = target;
+                yield_now().await;
+            }
+        };
+        let mut hold = pin!(task_with(hold, (45.0,)).requiring("hold", &arm));
+        assert!(hold.as_mut().poll(&mut cx).is_pending());
+        let mut stow = pin!((async |arm: &mut f64| *arm = 0.0).requiring("stow", &arm));
+        assert_eq!(stow.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(matches!(
+            hold.as_mut().poll(&mut cx),
+            Poll::Ready(Err(err)) if err.incoming().map(|thief| thief.name.as_str()) == Some("stow")
+        ));
+        assert_eq!(arm.peek(), 0.0);
+    }
+
+    #[test]
+    fn two_cell_closure() {
+        let left = RevocableCell::new(0, "left");
+        let right = RevocableCell::new(0, "right");
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let drive = async |left: &mut i32, right: &mut i32| {
+            loop {
+                *left += 1;
+                *right -= 1;
+                yield_now().await;
+            }
+        };
+        let mut drive = pin!(drive.requiring("drive", (&left, &right)));
+        assert!(drive.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(
+            right.current_owner().map(|thief| thief.name.as_str()),
+            Some("drive")
+        );
+
+        // stealing only the second cell cancels the whole task
+        let mut brake = pin!((async |right: &mut i32| *right = 0).requiring("brake", &right));
+        assert_eq!(brake.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        let Poll::Ready(Err(err)) = drive.as_mut().poll(&mut cx) else {
+            panic!("drive should be preempted by brake");
+        };
+        assert_eq!(err.requirement(), right.info());
+        assert_eq!(left.peek(), 1);
+    }
+
+    #[test]
+    fn run_over_cells_holds_guards() {
+        let left = RevocableCell::new(0, "left");
+        let right = RevocableCell::new(0, "right");
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut drive = pin!(run2("drive", &left, &right, async |left, _| {
+            *left = 1;
+            RevocableGuard::current(&right).is_none()
+        }));
+        assert_eq!(drive.as_mut().poll(&mut cx), Poll::Ready(Ok(true)));
+        assert!(left.take_dirty());
+    }
+
+    #[test]
+    fn run_over_cells() {
+        let left = RevocableCell::new(0, "left");
+        let right = RevocableCell::new(0, "right");
+        let arm = RevocableCell::new(0, "arm");
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut drive = pin!(run2("drive", &left, &right, async |left, right| {
+            loop {
+                *left += 1;
+                *right += 1;
+                yield_now().await;
+            }
+        }));
+        assert!(drive.as_mut().poll(&mut cx).is_pending());
+
+        // overlaps with drive only on its second cell
+        let mut turn = pin!(run2("turn", &arm, &right, async |arm, right| *arm += *right));
+        assert_eq!(turn.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        let Poll::Ready(Err(err)) = drive.as_mut().poll(&mut cx) else {
+            panic!("drive should be preempted by turn");
+        };
+        assert_eq!(err.requirement(), right.info());
+
+        let mut reset = pin!(run3(
+            "reset",
+            &left,
+            &right,
+            &arm,
+            async |left, right, arm| {
+                *left = 0;
+                *right = 0;
+                *arm *= 10;
+            }
+        ));
+        assert_eq!(reset.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(reset.requirement_ids().count(), 3);
+        assert_eq!((left.peek(), right.peek(), arm.peek()), (0, 0, 10));
+    }
+
+    #[test]
+    #[should_panic(expected = "requires Requirement { name: left, type: i32 }  more than once")]
+    fn repeated_cell() {
+        let left = RevocableCell::new(0, "left");
+        drop((async |_: &mut i32, _: &mut i32| ()).requiring("both", (&left, &left)));
+    }
+
+    #[test]
+    fn closure_output() {
+        let sensor = RevocableCell::new(0.0, "sensor");
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let read = async |sensor: &mut f64| -> f64 {
+            yield_now().await;
+            *sensor + 1.5
+        };
+        let mut reading = pin!(read.requiring("read", &sensor));
+        assert!(reading.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(reading.as_mut().poll(&mut cx), Poll::Ready(Ok(1.5)));
+
+        // preempted mid-read, the reading is lost
+        let scaled = async |sensor: &mut f64, scale: f64| -> f64 {
+            yield_now().await;
+            *sensor * scale
+        };
+        let mut reading = pin!(task_with(scaled, (2.0,)).requiring("scaled", &sensor));
+        assert!(reading.as_mut().poll(&mut cx).is_pending());
+        let mut calibrate =
+            pin!((async |sensor: &mut f64| *sensor = 4.0).requiring("calibrate", &sensor));
+        assert_eq!(calibrate.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        let Poll::Ready(Err(err)) = reading.as_mut().poll(&mut cx) else {
+            panic!("scaled should be preempted by calibrate");
+        };
+        assert_eq!(err.outgoing().name, "scaled");
+        assert_eq!(
+            err.incoming().map(|thief| thief.name.as_str()),
+            Some("calibrate")
+        );
+    }
+}
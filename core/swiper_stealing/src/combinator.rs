@@ -0,0 +1,445 @@
+//! Combinators for composing preemptible futures.
+//!
+//! Futures running concurrently must not share requirements, otherwise they preempt each other as soon as both are polled.
+//! [`parallel()`] and [`race()`] check this with a debug assertion, while [`try_parallel()`] and [`try_race()`] always check and return an [`OverlapError`] instead.
+//! All combinators implement [`HasRequirements`], so overlaps are detected through nested compositions.
+//! [`retry()`] restarts a future that is safe to rerun from scratch whenever it is preempted.
+
+use core::{
+    fmt::Display,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{
+    Result,
+    requirement::{HasRequirements, Requirement, RequirementInfo, requirements_overlap},
+};
+
+/// Returned when futures composed to run concurrently share a requirement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OverlapError {
+    /// A requirement needed by both futures
+    pub requirement: RequirementInfo,
+}
+
+impl Display for OverlapError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "concurrently composed futures both require {}",
+            self.requirement
+        )
+    }
+}
+
+fn check_disjoint(
+    a: &impl HasRequirements,
+    b: &impl HasRequirements,
+) -> core::result::Result<(), OverlapError> {
+    match requirements_overlap(a, b) {
+        Some(requirement) => Err(OverlapError { requirement }),
+        None => Ok(()),
+    }
+}
+
+/// Future returned by [`parallel()`], completing once both futures complete
+pub struct Parallel<A: Future, B: Future> {
+    a: A,
+    b: B,
+    a_out: Option<A::Output>,
+    b_out: Option<B::Output>,
+}
+
+impl<A: Future, B: Future> Parallel<A, B> {
+    /// Composes `a` and `b` without checking their requirements.
+    pub(crate) fn new(a: A, b: B) -> Self {
+        Self {
+            a,
+            b,
+            a_out: None,
+            b_out: None,
+        }
+    }
+}
+
+impl<A: Future, B: Future> Future for Parallel<A, B> {
+    type Output = (A::Output, B::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // a and b are structurally pinned, the outputs are not
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.a_out.is_none() {
+            this.a_out = match unsafe { Pin::new_unchecked(&mut this.a) }.poll(cx) {
+                Poll::Ready(out) => Some(out),
+                Poll::Pending => None,
+            };
+        }
+        if this.b_out.is_none() {
+            this.b_out = match unsafe { Pin::new_unchecked(&mut this.b) }.poll(cx) {
+                Poll::Ready(out) => Some(out),
+                Poll::Pending => None,
+            };
+        }
+        match (this.a_out.take(), this.b_out.take()) {
+            (Some(a), Some(b)) => Poll::Ready((a, b)),
+            (a, b) => {
+                this.a_out = a;
+                this.b_out = b;
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<A, B> HasRequirements for Parallel<A, B>
+where
+    A: Future + HasRequirements,
+    B: Future + HasRequirements,
+{
+    fn visit_requirements(&self, visit: &mut dyn FnMut(&dyn Requirement)) {
+        self.a.visit_requirements(visit);
+        self.b.visit_requirements(visit);
+    }
+}
+
+/// Future returned by [`race()`], completing with the output of whichever future completes first
+pub struct Race<A, B> {
+    a: A,
+    b: B,
+}
+
+impl<A, B> Future for Race<A, B>
+where
+    A: Future,
+    B: Future<Output = A::Output>,
+{
+    type Output = A::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // a and b are structurally pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        if let Poll::Ready(out) = unsafe { Pin::new_unchecked(&mut this.a) }.poll(cx) {
+            return Poll::Ready(out);
+        }
+        unsafe { Pin::new_unchecked(&mut this.b) }.poll(cx)
+    }
+}
+
+impl<A: HasRequirements, B: HasRequirements> HasRequirements for Race<A, B> {
+    fn visit_requirements(&self, visit: &mut dyn FnMut(&dyn Requirement)) {
+        self.a.visit_requirements(visit);
+        self.b.visit_requirements(visit);
+    }
+}
+
+/// Future returned by [`sequence()`], running the second future after the first completes
+pub struct Sequence<A: Future, B> {
+    a: A,
+    b: B,
+    a_out: Option<A::Output>,
+}
+
+impl<A: Future, B: Future> Future for Sequence<A, B> {
+    type Output = (A::Output, B::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // a and b are structurally pinned, the output is not
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.a_out.is_none() {
+            match unsafe { Pin::new_unchecked(&mut this.a) }.poll(cx) {
+                Poll::Ready(out) => this.a_out = Some(out),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        unsafe { Pin::new_unchecked(&mut this.b) }
+            .poll(cx)
+            .map(|b| (this.a_out.take().expect("first future completed"), b))
+    }
+}
+
+impl<A, B> HasRequirements for Sequence<A, B>
+where
+    A: Future + HasRequirements,
+    B: HasRequirements,
+{
+    fn visit_requirements(&self, visit: &mut dyn FnMut(&dyn Requirement)) {
+        self.a.visit_requirements(visit);
+        self.b.visit_requirements(visit);
+    }
+}
+
+/// Future returned by [`retry()`], restarting a preempted future from scratch
+pub struct Retry<F, Fut> {
+    factory: F,
+    attempt: Fut,
+    retries: u32,
+    /// Whether the attempt failed and is replaced at the next poll
+    backing_off: bool,
+}
+
+impl<F, Fut, T> Future for Retry<F, Fut>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    type Output = Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // the attempt is structurally pinned, and replacing it drops the failed attempt in place
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.backing_off {
+            this.backing_off = false;
+            this.attempt = (this.factory)();
+        }
+        match unsafe { Pin::new_unchecked(&mut this.attempt) }.poll(cx) {
+            Poll::Ready(Err(_)) if this.retries > 0 => {
+                this.retries -= 1;
+                this.backing_off = true;
+                // yield once, so the task that preempted this one runs before it is stolen back
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+            poll => poll,
+        }
+    }
+}
+
+impl<F, Fut: HasRequirements> HasRequirements for Retry<F, Fut> {
+    fn visit_requirements(&self, visit: &mut dyn FnMut(&dyn Requirement)) {
+        self.attempt.visit_requirements(visit);
+    }
+}
+
+/// Runs the future created by `factory`, creating and running a fresh one whenever it fails, up to `retries` times.
+///
+/// The first attempt is created right away, so its requirements are visible to overlap checks.
+/// After a failure the retry yields once before restarting, so it does not steal straight back from the task that preempted it.
+///
+/// # Errors
+///
+/// Returns the [`PreemptionError`](crate::PreemptionError) of the last attempt if all `retries + 1` attempts fail.
+pub fn retry<F, Fut, T>(retries: u32, mut factory: F) -> Retry<F, Fut>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    Retry {
+        attempt: factory(),
+        factory,
+        retries,
+        backing_off: false,
+    }
+}
+
+/// Runs both futures concurrently, completing once both complete.
+///
+/// # Panics
+///
+/// In debug builds, panics if `a` and `b` share a requirement. See [`try_parallel()`] for a checked version.
+pub fn parallel<A, B>(a: A, b: B) -> Parallel<A, B>
+where
+    A: Future + HasRequirements,
+    B: Future + HasRequirements,
+{
+    if cfg!(debug_assertions) {
+        check_disjoint(&a, &b).unwrap_or_else(|err| panic!("{err}"));
+    }
+    Parallel::new(a, b)
+}
+
+/// Runs both futures concurrently, completing once both complete.
+///
+/// # Errors
+///
+/// Returns an [`OverlapError`] naming a shared requirement if `a` and `b` overlap.
+pub fn try_parallel<A, B>(a: A, b: B) -> core::result::Result<Parallel<A, B>, OverlapError>
+where
+    A: Future + HasRequirements,
+    B: Future + HasRequirements,
+{
+    check_disjoint(&a, &b)?;
+    Ok(Parallel::new(a, b))
+}
+
+/// Runs both futures concurrently, completing with the output of whichever completes first.
+///
+/// The other future is dropped without being polled again.
+///
+/// # Panics
+///
+/// In debug builds, panics if `a` and `b` share a requirement. See [`try_race()`] for a checked version.
+pub fn race<A, B>(a: A, b: B) -> Race<A, B>
+where
+    A: Future + HasRequirements,
+    B: Future<Output = A::Output> + HasRequirements,
+{
+    if cfg!(debug_assertions) {
+        check_disjoint(&a, &b).unwrap_or_else(|err| panic!("{err}"));
+    }
+    Race { a, b }
+}
+
+/// Runs both futures concurrently, completing with the output of whichever completes first.
+///
+/// # Errors
+///
+/// Returns an [`OverlapError`] naming a shared requirement if `a` and `b` overlap.
+pub fn try_race<A, B>(a: A, b: B) -> core::result::Result<Race<A, B>, OverlapError>
+where
+    A: Future + HasRequirements,
+    B: Future<Output = A::Output> + HasRequirements,
+{
+    check_disjoint(&a, &b)?;
+    Ok(Race { a, b })
+}
+
+/// Runs `b` after `a` completes.
+///
+/// Sequenced futures never run concurrently, so they may share requirements.
+pub fn sequence<A, B>(a: A, b: B) -> Sequence<A, B>
+where
+    A: Future + HasRequirements,
+    B: Future + HasRequirements,
+{
+    Sequence { a, b, a_out: None }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_lite::future;
+
+    use super::*;
+    use crate::requirement::{RequirementInfoSource, RevocableCell};
+
+    #[test]
+    fn nested_overlap() {
+        let drive = RevocableCell::new(0, "drive");
+        let arm = RevocableCell::new(0, "arm");
+        // same name as arm, but a different requirement
+        let other_arm = RevocableCell::new(0, "arm");
+
+        let seq = sequence(
+            drive.run("forward", async |x| *x += 1),
+            arm.run("raise", async |x| *x += 1),
+        );
+        let err = try_parallel(seq, arm.run("lower", async |x| *x -= 1))
+            .err()
+            .expect("sequence and lower both require arm");
+        assert_eq!(err.requirement, arm.info());
+
+        let seq = sequence(
+            drive.run("forward", async |x| *x += 1),
+            arm.run("raise", async |x| *x += 1),
+        );
+        assert!(try_parallel(other_arm.run("wiggle", async |_| ()), seq).is_ok());
+        assert!(
+            try_race(
+                drive.run("forward", async |_| ()),
+                arm.run("raise", async |_| ())
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, should_panic(expected = "both require"))]
+    fn parallel_debug_assert() {
+        let drive = RevocableCell::new(0, "drive");
+        drop(parallel(
+            drive.run("forward", async |x| *x += 1),
+            drive.run("back", async |x| *x -= 1),
+        ));
+    }
+
+    #[test]
+    fn retry_restarts_preempted() {
+        use core::{
+            cell::Cell,
+            pin::pin,
+            task::{Context, Waker},
+        };
+
+        let elevator = RevocableCell::new(0, "elevator");
+        let attempts = Cell::new(0);
+        let home = || {
+            attempts.set(attempts.get() + 1);
+            elevator.run("home", async |height| {
+                future::yield_now().await;
+                *height = 0;
+                "homed"
+            })
+        };
+        let mut cx = Context::from_waker(Waker::noop());
+        let preempt = || {
+            let mut lift = pin!(elevator.run("lift", async |height| *height = 5));
+            assert_eq!(
+                lift.as_mut().poll(&mut Context::from_waker(Waker::noop())),
+                Poll::Ready(Ok(()))
+            );
+        };
+
+        // preempted once, then left alone
+        let mut homing = pin!(retry(2, home));
+        assert_eq!(attempts.get(), 1);
+        assert!(homing.as_mut().poll(&mut cx).is_pending());
+        preempt();
+        assert!(homing.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(attempts.get(), 1);
+        assert!(homing.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(attempts.get(), 2);
+        assert_eq!(homing.as_mut().poll(&mut cx), Poll::Ready(Ok("homed")));
+        assert_eq!(elevator.peek(), 0);
+
+        // preempted more often than it may retry
+        attempts.set(0);
+        let mut homing = pin!(retry(1, home));
+        assert!(homing.as_mut().poll(&mut cx).is_pending());
+        preempt();
+        // one poll backs off, the next starts the second attempt
+        assert!(homing.as_mut().poll(&mut cx).is_pending());
+        assert!(homing.as_mut().poll(&mut cx).is_pending());
+        preempt();
+        let Poll::Ready(Err(err)) = homing.as_mut().poll(&mut cx) else {
+            panic!("the last attempt should be preempted");
+        };
+        assert_eq!(err.outgoing().name, "home");
+        assert_eq!(attempts.get(), 2);
+        assert_eq!(elevator.peek(), 5);
+    }
+
+    #[test]
+    fn combinator_outputs() {
+        let drive = RevocableCell::new(0, "drive");
+        let arm = RevocableCell::new(0, "arm");
+
+        let (seq, par) = future::block_on(sequence(
+            drive.run("forward", async |x| {
+                *x += 1;
+                *x
+            }),
+            parallel(
+                drive.run("back", async |x| {
+                    future::yield_now().await;
+                    *x -= 1;
+                    *x
+                }),
+                arm.run("raise", async |x| {
+                    *x += 1;
+                    *x
+                }),
+            ),
+        ));
+        assert_eq!(seq, Ok(1));
+        assert_eq!(par, (Ok(0), Ok(1)));
+
+        let first = future::block_on(race(
+            drive.run("slow", async |_| {
+                future::yield_now().await;
+                "slow"
+            }),
+            arm.run("fast", async |_| "fast"),
+        ));
+        assert_eq!(first, Ok("fast"));
+    }
+}
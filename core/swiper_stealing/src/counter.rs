@@ -0,0 +1,55 @@
+//! Global counters handing out the unique ids of thieves and requirements.
+
+use core::{num::NonZeroU64, sync::atomic::Ordering};
+
+/// A 64 bit counter shared by all threads, too wide to wrap
+#[cfg(target_has_atomic = "64")]
+pub(crate) struct IdCounter(core::sync::atomic::AtomicU64);
+
+#[cfg(target_has_atomic = "64")]
+impl IdCounter {
+    pub(crate) const fn new() -> Self {
+        Self(core::sync::atomic::AtomicU64::new(1))
+    }
+
+    /// Returns an id never returned before by this counter.
+    pub(crate) fn next(&self) -> NonZeroU64 {
+        NonZeroU64::new(self.0.fetch_add(1, Ordering::Relaxed)).expect("ids never wrap")
+    }
+}
+
+/// A 64 bit counter incremented under a lock, for targets without 64 bit atomics
+#[cfg(not(target_has_atomic = "64"))]
+pub(crate) struct IdCounter {
+    lock: core::sync::atomic::AtomicBool,
+    next: core::cell::Cell<u64>,
+}
+
+// `next` is only accessed while holding `lock`
+#[cfg(not(target_has_atomic = "64"))]
+unsafe impl Sync for IdCounter {}
+
+#[cfg(not(target_has_atomic = "64"))]
+impl IdCounter {
+    pub(crate) const fn new() -> Self {
+        Self {
+            lock: core::sync::atomic::AtomicBool::new(false),
+            next: core::cell::Cell::new(1),
+        }
+    }
+
+    /// Returns an id never returned before by this counter.
+    pub(crate) fn next(&self) -> NonZeroU64 {
+        while self
+            .lock
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        let id = self.next.get();
+        self.next.set(id + 1);
+        self.lock.store(false, Ordering::Release);
+        NonZeroU64::new(id).expect("ids never wrap")
+    }
+}
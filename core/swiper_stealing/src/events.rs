@@ -0,0 +1,310 @@
+//! Ownership changes of a [`RevocableCell`] as a [`Stream`], for telemetry tasks consuming ownership churn.
+//!
+//! Each [`EventStream`] shares a bounded buffer with its cell through an `Rc`, so the stream never borrows the cell
+//! and the cell keeps working while the stream is alive. When the buffer is full the oldest event is dropped,
+//! and the stream yields a [`PreemptionEvent::Lost`] marker counting the dropped events before the remaining ones.
+//! [`RevocableCell::on_preempted_of()`] subscribes the same way but only waits for one thief to be displaced.
+
+extern crate alloc;
+
+use alloc::{
+    collections::VecDeque,
+    rc::{Rc, Weak},
+    vec::Vec,
+};
+use core::{
+    any::Any,
+    cell::{Cell, RefCell},
+    future::poll_fn,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use futures_core::Stream;
+
+use crate::{history::OwnershipRecord, name::Name, requirement::RevocableCell, thief::ThiefInfo};
+
+/// Buffer capacity of streams created by [`RevocableCell::events()`]
+pub const DEFAULT_CAPACITY: usize = 16;
+
+/// An item of an [`EventStream`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreemptionEvent {
+    /// The cell changed owners, `displaced` is the previous owner of a [`Stolen`](crate::history::OwnershipChange::Stolen) cell
+    Ownership {
+        record: OwnershipRecord,
+        displaced: Option<ThiefInfo>,
+    },
+    /// `count` events were dropped because the stream was not polled quickly enough
+    Lost { count: usize },
+}
+
+struct Buffer {
+    events: RefCell<VecDeque<PreemptionEvent>>,
+    capacity: usize,
+    lost: Cell<usize>,
+    waker: Cell<Option<Waker>>,
+    /// Only keep the first event displacing this thief, for [`RevocableCell::on_preempted_of()`]
+    displacing: Option<Name>,
+}
+
+impl Buffer {
+    fn accepts(&self, event: &PreemptionEvent) -> bool {
+        let Some(name) = self.displacing else {
+            return true;
+        };
+        let displaced = match event {
+            PreemptionEvent::Ownership { displaced, .. } => displaced,
+            PreemptionEvent::Lost { .. } => &None,
+        };
+        displaced.is_some_and(|thief| thief.name == name) && self.events.borrow().is_empty()
+    }
+}
+
+/// Streams subscribed to a cell, stored in the cell
+#[derive(Default)]
+pub(crate) struct Subscribers {
+    buffers: RefCell<Vec<Weak<Buffer>>>,
+}
+
+impl Subscribers {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pushes `event` to every live stream, forgetting streams that were dropped.
+    pub(crate) fn publish(&self, event: PreemptionEvent) {
+        self.buffers.borrow_mut().retain(|buffer| {
+            let Some(buffer) = buffer.upgrade() else {
+                return false;
+            };
+            if !buffer.accepts(&event) {
+                return true;
+            }
+            let mut events = buffer.events.borrow_mut();
+            if events.len() == buffer.capacity {
+                events.pop_front();
+                buffer.lost.set(buffer.lost.get() + 1);
+            }
+            events.push_back(event);
+            if let Some(waker) = buffer.waker.take() {
+                waker.wake();
+            }
+            true
+        });
+    }
+
+    fn subscribe(&self, capacity: usize, displacing: Option<Name>) -> Rc<Buffer> {
+        let buffer = Rc::new(Buffer {
+            events: RefCell::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            lost: Cell::new(0),
+            waker: Cell::new(None),
+            displacing,
+        });
+        self.buffers.borrow_mut().push(Rc::downgrade(&buffer));
+        buffer
+    }
+}
+
+/// Stream of a cell's ownership changes, created by [`RevocableCell::events()`]
+///
+/// The stream never ends, it stays pending once its cell has been dropped.
+pub struct EventStream {
+    buffer: Rc<Buffer>,
+}
+
+impl Stream for EventStream {
+    type Item = PreemptionEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let buffer = &self.buffer;
+        let count = buffer.lost.replace(0);
+        if count > 0 {
+            return Poll::Ready(Some(PreemptionEvent::Lost { count }));
+        }
+        match buffer.events.borrow_mut().pop_front() {
+            Some(event) => Poll::Ready(Some(event)),
+            None => {
+                buffer.waker.set(Some(cx.waker().clone()));
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T, M: Any, const H: usize> RevocableCell<T, M, H> {
+    /// Returns a stream of this cell's ownership changes from now on, buffering up to [`DEFAULT_CAPACITY`] events.
+    pub fn events(&self) -> EventStream {
+        self.events_with_capacity(DEFAULT_CAPACITY)
+    }
+
+    /// Same as [`events()`](Self::events), buffering up to `capacity` events.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn events_with_capacity(&self, capacity: usize) -> EventStream {
+        assert!(
+            capacity > 0,
+            "event streams need room for at least one event"
+        );
+        EventStream {
+            buffer: self.subscribers().subscribe(capacity, None),
+        }
+    }
+
+    /// Returns a future resolving with the first event in which a steal displaces the thief named `name`.
+    ///
+    /// The future only observes the cell and never takes part in its ownership.
+    /// Dropping it before it resolves unsubscribes it.
+    pub fn on_preempted_of(&self, name: impl Into<Name>) -> impl Future<Output = PreemptionEvent> {
+        let buffer = self.subscribers().subscribe(1, Some(name.into()));
+        poll_fn(move |cx| match buffer.events.borrow_mut().pop_front() {
+            Some(event) => Poll::Ready(event),
+            None => {
+                buffer.waker.set(Some(cx.waker().clone()));
+                Poll::Pending
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use core::pin::pin;
+
+    use futures_lite::StreamExt;
+
+    use super::*;
+    use crate::{
+        history::OwnershipChange,
+        requirement::{Requirement, RequirementInfoSource},
+    };
+
+    fn change(event: Option<PreemptionEvent>) -> Option<(&'static str, OwnershipChange)> {
+        match event? {
+            PreemptionEvent::Ownership { record, .. } => {
+                Some((record.thief.name.as_str(), record.kind))
+            }
+            PreemptionEvent::Lost { .. } => None,
+        }
+    }
+
+    #[test]
+    fn consume_events() {
+        let cell = RevocableCell::new(0, "arm");
+        let thief1 = ThiefInfo::new("thief1");
+        let thief2 = ThiefInfo::new("thief2");
+        let mut events = pin!(cell.events());
+
+        cell.steal_ownership(&thief1);
+        cell.steal_ownership(&thief2);
+        cell.release_ownership();
+
+        futures_lite::future::block_on(async {
+            assert_eq!(
+                change(events.next().await),
+                Some(("thief1", OwnershipChange::Acquired))
+            );
+            assert_eq!(
+                events.next().await,
+                Some(PreemptionEvent::Ownership {
+                    record: OwnershipRecord {
+                        thief: thief2,
+                        kind: OwnershipChange::Stolen,
+                        at: None,
+                    },
+                    displaced: Some(thief1),
+                })
+            );
+            assert_eq!(
+                change(events.next().await),
+                Some(("thief2", OwnershipChange::Released))
+            );
+        });
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(events.as_mut().poll_next(&mut cx).is_pending());
+    }
+
+    #[test]
+    fn overflow_marker() {
+        let cell = RevocableCell::new(0, "arm");
+        let thief = ThiefInfo::new("thief");
+        let mut events = pin!(cell.events_with_capacity(2));
+
+        for _ in 0..3 {
+            cell.steal_ownership(&thief);
+            cell.release_ownership();
+        }
+
+        // 6 events were published into a buffer of 2, so the 4 oldest were dropped
+        futures_lite::future::block_on(async {
+            assert_eq!(
+                events.next().await,
+                Some(PreemptionEvent::Lost { count: 4 })
+            );
+            assert_eq!(
+                change(events.next().await),
+                Some(("thief", OwnershipChange::Acquired))
+            );
+            assert_eq!(
+                change(events.next().await),
+                Some(("thief", OwnershipChange::Released))
+            );
+        });
+    }
+
+    #[test]
+    fn preempted_of_owner() {
+        let cell = RevocableCell::new(0, "drivetrain");
+        let align = ThiefInfo::new("auto_align");
+        let drive = ThiefInfo::new("teleop");
+        let stow = ThiefInfo::new("stow");
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut lost = pin!(cell.on_preempted_of("auto_align"));
+
+        // steals displacing other owners are ignored
+        cell.steal_ownership(&drive);
+        cell.steal_ownership(&stow);
+        cell.steal_ownership(&align);
+        assert!(lost.as_mut().poll(&mut cx).is_pending());
+
+        cell.steal_ownership(&drive);
+        cell.steal_ownership(&align);
+        cell.steal_ownership(&stow);
+        assert_eq!(
+            lost.as_mut().poll(&mut cx),
+            Poll::Ready(PreemptionEvent::Ownership {
+                record: OwnershipRecord {
+                    thief: drive,
+                    kind: OwnershipChange::Stolen,
+                    at: None,
+                },
+                displaced: Some(align),
+            })
+        );
+    }
+
+    #[test]
+    fn preempted_of_dropped() {
+        let cell = RevocableCell::new(0, "drivetrain");
+        let align = ThiefInfo::new("auto_align");
+        let drive = ThiefInfo::new("teleop");
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut lost = Box::pin(cell.on_preempted_of("auto_align"));
+        cell.steal_ownership(&align);
+        assert!(lost.as_mut().poll(&mut cx).is_pending());
+        drop(lost);
+
+        // the next event forgets the dropped subscription and ownership is unaffected
+        cell.steal_ownership(&drive);
+        assert!(cell.subscribers().buffers.borrow().is_empty());
+        assert_eq!(
+            cell.current_owner().map(|thief| thief.name.as_str()),
+            Some("teleop")
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! Minimal executors for driving preemptible futures from synchronous code.
+//!
+//! With the `std` feature the current thread parks until a future wakes it.
+//! Without it, the executor spins, polling again after each [`spin_loop`](core::hint::spin_loop) hint.
+//! Either is enough for the futures in this crate, but neither is meant to replace a real async runtime.
+
+use core::{
+    array,
+    pin::{Pin, pin},
+    task::{Context, Poll},
+};
+
+#[cfg(feature = "std")]
+mod park {
+    extern crate std;
+
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use std::{
+        sync::Arc,
+        task::Wake,
+        thread::{self, Thread},
+    };
+
+    pub(super) struct Parker {
+        thread: Thread,
+        woken: AtomicBool,
+    }
+
+    impl Parker {
+        pub(super) fn new() -> Arc<Self> {
+            Arc::new(Self {
+                thread: thread::current(),
+                woken: AtomicBool::new(false),
+            })
+        }
+
+        /// Blocks the current thread until the waker has been used since the last call.
+        pub(super) fn wait(&self) {
+            while !self.woken.swap(false, Ordering::Acquire) {
+                thread::park();
+            }
+        }
+    }
+
+    impl Wake for Parker {
+        fn wake(self: Arc<Self>) {
+            self.wake_by_ref();
+        }
+
+        fn wake_by_ref(self: &Arc<Self>) {
+            self.woken.store(true, Ordering::Release);
+            self.thread.unpark();
+        }
+    }
+}
+
+/// Runs `f` with a context whose waker unblocks `wait`, the second argument.
+fn with_context<R>(f: impl FnOnce(&mut Context<'_>, &dyn Fn()) -> R) -> R {
+    #[cfg(feature = "std")]
+    {
+        extern crate std;
+
+        let parker = park::Parker::new();
+        let waker = std::task::Waker::from(parker.clone());
+        f(&mut Context::from_waker(&waker), &|| parker.wait())
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        f(
+            &mut Context::from_waker(core::task::Waker::noop()),
+            &core::hint::spin_loop,
+        )
+    }
+}
+
+/// Polls `fut` on the current thread until it completes.
+pub fn block_on<F: Future>(fut: F) -> F::Output {
+    let mut fut = pin!(fut);
+    with_context(|cx, wait| {
+        loop {
+            if let Poll::Ready(out) = fut.as_mut().poll(cx) {
+                return out;
+            }
+            wait();
+        }
+    })
+}
+
+/// Polls every future in `futs` on the current thread until all complete, returning their outputs in order.
+///
+/// Futures are polled round robin, once per pass, so futures sharing requirements preempt each other as they would on a scheduler.
+pub fn block_on_all<F: Future, const N: usize>(futs: [F; N]) -> [F::Output; N] {
+    let mut futs = pin!(futs);
+    let mut outs: [Option<F::Output>; N] = array::from_fn(|_| None);
+    with_context(|cx, wait| {
+        loop {
+            for (i, out) in outs.iter_mut().enumerate() {
+                if out.is_some() {
+                    continue;
+                }
+                // each element is pinned because the array is, and is never moved out of it
+                let fut = unsafe { Pin::new_unchecked(&mut futs.as_mut().get_unchecked_mut()[i]) };
+                if let Poll::Ready(value) = fut.poll(cx) {
+                    *out = Some(value);
+                }
+            }
+            if outs.iter().all(Option::is_some) {
+                return outs.map(|out| out.expect("all futures completed"));
+            }
+            wait();
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_lite::future;
+
+    use super::*;
+    use crate::requirement::RevocableCell;
+
+    #[test]
+    fn block_on_yielding() {
+        let out = block_on(async {
+            for _ in 0..3 {
+                future::yield_now().await;
+            }
+            7
+        });
+        assert_eq!(out, 7);
+    }
+
+    #[test]
+    fn block_on_all_interleaves() {
+        fn add(
+            cell: &RevocableCell<i32>,
+            name: &'static str,
+            n: i32,
+        ) -> impl Future<Output = crate::Result<i32>> {
+            cell.run(name, async move |x| {
+                *x += n;
+                future::yield_now().await;
+                *x
+            })
+        }
+
+        let cell = RevocableCell::new(0, "cell");
+        let [a, b] = block_on_all([add(&cell, "a", 1), add(&cell, "b", 10)]);
+        // b is first polled before a resumes, so it preempts a
+        assert!(a.is_err_and(|err| err.outgoing().name == "a"));
+        assert_eq!(b, Ok(11));
+    }
+}
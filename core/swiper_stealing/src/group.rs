@@ -0,0 +1,203 @@
+//! Borrowed cells of the same type claimed together or interchangeably, for tasks that need any or all of several identical resources.
+//!
+//! A [`RevocableCellGroup`] groups cells that are also used on their own, unlike a [`RevocablePool`](crate::pool::RevocablePool), which owns its cells.
+//! [`claim_any()`](RevocableCellGroup::claim_any) runs a task on whichever member is free,
+//! stealing the member acquired least recently through the group when all of them are claimed,
+//! and [`claim_all()`](RevocableCellGroup::claim_all) runs a task owning every member.
+//! Either task is an ordinary [`PreemptibleFuture`], which is cancelled as soon as any member it claimed is stolen.
+
+use core::cell::Cell;
+
+use crate::{
+    Result,
+    guard::RevocableGuard,
+    name::Name,
+    requirement::{Requirement, RequirementInfoSource, RevocableCell},
+    thief::PreemptibleFuture,
+};
+
+/// `N` cells claimed by one task or by several interchangeably, see the [module-level documentation](self)
+pub struct RevocableCellGroup<'a, T, const N: usize> {
+    cells: [&'a RevocableCell<T>; N],
+    /// Value of `acquisitions` when each member was last claimed through [`claim_any()`](Self::claim_any)
+    acquired: [Cell<u64>; N],
+    acquisitions: Cell<u64>,
+}
+
+impl<'a, T, const N: usize> RevocableCellGroup<'a, T, N> {
+    /// Groups `cells`, whose indices are passed to the tasks claiming them.
+    pub fn new(cells: [&'a RevocableCell<T>; N]) -> Self {
+        Self {
+            cells,
+            acquired: core::array::from_fn(|_| Cell::new(0)),
+            acquisitions: Cell::new(0),
+        }
+    }
+
+    /// Returns the cells of this group.
+    pub fn members(&self) -> &[&'a RevocableCell<T>; N] {
+        &self.cells
+    }
+
+    /// Returns the index of the member [`claim_any()`](Self::claim_any) would claim now.
+    ///
+    /// Frozen and poisoned members are skipped, since a task claiming them would never start.
+    /// If every member is skipped, the first one is returned so the task reports why it did not start.
+    fn pick(&self) -> usize {
+        let usable = |index: &usize| {
+            let cell = self.cells[*index];
+            !cell.is_frozen() && !cell.is_poisoned()
+        };
+        (0..N)
+            .filter(usable)
+            .find(|&index| self.cells[index].is_free())
+            .or_else(|| {
+                (0..N)
+                    .filter(usable)
+                    .min_by_key(|&index| self.acquired[index].get())
+            })
+            .unwrap_or(0)
+    }
+
+    /// Runs `func` on the first free member, or steals the member acquired least recently through this group if every member is claimed.
+    ///
+    /// The member is chosen on the first poll, and its index is passed to `func` along with its data.
+    ///
+    /// # Errors
+    ///
+    /// If the chosen member is stolen by a different future, the future returns `Err<PreemptionError>` when next polled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the group has no members.
+    pub async fn claim_any<Out>(
+        &self,
+        name: impl Into<Name>,
+        func: impl AsyncFnOnce(usize, &mut T) -> Out,
+    ) -> Result<Out> {
+        assert!(N > 0, "cannot claim a member of an empty group");
+        let index = self.pick();
+        let cell = self.cells[index];
+        self.acquisitions.set(self.acquisitions.get() + 1);
+        self.acquired[index].set(self.acquisitions.get());
+        let inner = async move {
+            let mut guard = RevocableGuard::current(cell).expect("claim_any owns its member");
+            func(index, &mut guard).await
+        };
+        PreemptibleFuture::new(inner, name, [cell]).await
+    }
+
+    /// Runs `func` on the data of every member, which are all stolen on the first poll before `func` starts.
+    ///
+    /// # Errors
+    ///
+    /// If any member is stolen by a different future, the future returns `Err<PreemptionError>` when next polled.
+    pub fn claim_all<Out>(
+        &self,
+        name: impl Into<Name>,
+        func: impl AsyncFnOnce([&mut T; N]) -> Out + 'a,
+    ) -> PreemptibleFuture<'a, impl Future<Output = Out> + 'a, Out, N> {
+        let cells = self.cells;
+        let inner = async move {
+            let mut guards = cells
+                .map(|cell| RevocableGuard::current(cell).expect("claim_all owns its members"));
+            func(guards.each_mut().map(|guard| &mut **guard)).await
+        };
+        PreemptibleFuture::new(inner, name, cells.map(|cell| cell as &dyn Requirement))
+            .lending_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use futures_lite::future::pending;
+
+    use super::*;
+
+    fn owner(requirement: &dyn Requirement) -> Option<&'static str> {
+        requirement.current_owner().map(|owner| owner.name.as_str())
+    }
+
+    #[test]
+    fn claim_any_free_member() {
+        let cameras = RevocableCell::array(|_| 0, "camera");
+        let [front, back] = &cameras;
+        let group = RevocableCellGroup::new([front, back]);
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut other = pin!(PreemptibleFuture::new(pending::<()>(), "other", [front]));
+        assert!(other.as_mut().poll(&mut cx).is_pending());
+        let mut detect = pin!(group.claim_any("detect", async |index, frames| {
+            *frames += 1;
+            index
+        }));
+        assert_eq!(detect.as_mut().poll(&mut cx), Poll::Ready(Ok(1)));
+        assert_eq!(owner(front), Some("other"));
+        assert!(other.as_mut().poll(&mut cx).is_pending());
+    }
+
+    #[test]
+    fn all_busy_steals_one() {
+        let cameras = RevocableCell::array(|_| 0, "camera");
+        let [a, b, c] = &cameras;
+        let group = RevocableCellGroup::new([a, b, c]);
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut first = pin!(group.claim_any("first", async |_, _| pending::<()>().await));
+        let mut second = pin!(group.claim_any("second", async |_, _| pending::<()>().await));
+        let mut third = pin!(group.claim_any("third", async |_, _| pending::<()>().await));
+        assert!(first.as_mut().poll(&mut cx).is_pending());
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+        assert!(third.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(
+            (owner(a), owner(b), owner(c)),
+            (Some("first"), Some("second"), Some("third"))
+        );
+
+        // every member is claimed, so the one claimed longest ago is stolen
+        let mut fourth = pin!(group.claim_any("fourth", async |index, _| {
+            pending::<()>().await;
+            index
+        }));
+        assert!(fourth.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(owner(a), Some("fourth"));
+        let Poll::Ready(Err(err)) = first.as_mut().poll(&mut cx) else {
+            panic!("first should be preempted by fourth");
+        };
+        assert_eq!(
+            err.incoming().map(|thief| thief.name.as_str()),
+            Some("fourth")
+        );
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+        assert!(third.as_mut().poll(&mut cx).is_pending());
+    }
+
+    #[test]
+    fn claim_all_members() {
+        let cameras = RevocableCell::array(|_| 0, "camera");
+        let [a, b] = &cameras;
+        let group = RevocableCellGroup::new([a, b]);
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut calibrate = pin!(group.claim_all("calibrate", async |[a, b]| {
+            *a = 1;
+            *b = 2;
+            pending::<()>().await;
+        }));
+        assert!(calibrate.as_mut().poll(&mut cx).is_pending());
+        assert_eq!((owner(a), owner(b)), (Some("calibrate"), Some("calibrate")));
+
+        // stealing a single member cancels the whole task
+        let mut detect = pin!(PreemptibleFuture::new(pending::<()>(), "detect", [b]));
+        assert!(detect.as_mut().poll(&mut cx).is_pending());
+        let Poll::Ready(Err(err)) = calibrate.as_mut().poll(&mut cx) else {
+            panic!("calibrate should be preempted by detect");
+        };
+        assert_eq!(err.requirement().index, Some(1));
+    }
+}
@@ -0,0 +1,587 @@
+//! Checked access to the data of a cell, for code that holds ownership without going through raw pointers.
+//!
+//! A [`RevocableGuard`] remembers the thief it was created for and checks on every dereference that this thief still owns the cell,
+//! panicking if the cell was stolen in the meantime.
+//! [`RevocableCell::claim()`] steals the cell for a given thief, failing if the cell refuses the steal, while [`RevocableGuard::current()`] borrows a cell
+//! already owned by the [`PreemptibleFuture`](crate::thief::PreemptibleFuture) being polled, which is how
+//! [`RevocableCell::run()`] and `#[preemptible]` functions hand data to their bodies.
+//!
+//! A thief holds at most one guard or [`RefMut`] of a cell at a time, so a second [`current()`](RevocableGuard::current) or [`claim()`](RevocableCell::claim)
+//! fails while the first guard is live, rather than handing out a second mutable reference.
+//! Dropping a guard releases the cell, unless it was stolen by another thief, which keeps it.
+//! A guard that handed out mutable access also marks the data as changed, waking [`changed()`](RevocableCell::changed) observers.
+//!
+//! A [`ClaimedToken`], created by [`RevocableCell::claim_sync()`], is the same for synchronous critical sections,
+//! except that a stolen token returns a [`PreemptionError`] from [`get_mut()`](ClaimedToken::get_mut) instead of panicking.
+//! [`RevocableCell::try_borrow_mut()`] is the shortest form of checked access: it neither claims nor releases the cell,
+//! and returns a [`RefMut`] only while the given thief owns it and no other borrow is live, which lets hand-written futures reach their data without `unsafe`.
+
+use core::{
+    any::Any,
+    cell::Cell,
+    fmt,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+use crate::{
+    PreemptionError, PreemptionKind, Result, current_thief,
+    name::Name,
+    requirement::{
+        LastPreemption, Requirement, RequirementInfoSource, RevocableCell, RevocableData,
+        StealOutcome, TenureEnd,
+    },
+    thief::{ThiefInfo, owned_by_current},
+};
+
+/// Records which thief holds a live guard of a requirement's data, see [`RevocableData::borrow_flag()`]
+#[derive(Debug, Default)]
+pub struct BorrowFlag(Cell<Option<ThiefInfo>>);
+
+impl BorrowFlag {
+    /// Creates a flag with no guard recorded.
+    pub const fn new() -> Self {
+        Self(Cell::new(None))
+    }
+
+    /// Records a guard held by `thief`, returning `false` if `thief` already holds one.
+    ///
+    /// A guard of another thief is forgotten, since that thief lost ownership and its guard fails on its next access.
+    pub fn begin(&self, thief: &ThiefInfo) -> bool {
+        if self.0.get().is_some_and(|holder| holder.same_thief(thief)) {
+            return false;
+        }
+        self.0.set(Some(*thief));
+        true
+    }
+
+    /// Forgets the guard of `thief`, if it is still the one recorded.
+    pub fn end(&self, thief: &ThiefInfo) {
+        if self.0.get().is_some_and(|holder| holder.same_thief(thief)) {
+            self.0.set(None);
+        }
+    }
+}
+
+/// Records a guard of `requirement` held by `thief`, returning `false` if `thief` already holds one.
+fn begin_borrow<T: ?Sized>(requirement: &dyn RevocableData<T>, thief: &ThiefInfo) -> bool {
+    requirement
+        .borrow_flag()
+        .is_none_or(|flag| flag.begin(thief))
+}
+
+fn end_borrow<T: ?Sized>(requirement: &dyn RevocableData<T>, thief: &ThiefInfo) {
+    if let Some(flag) = requirement.borrow_flag() {
+        flag.end(thief);
+    }
+}
+
+/// Access to the data of a requirement owned by a thief, see the [module-level documentation](self)
+pub struct RevocableGuard<'a, T: ?Sized> {
+    requirement: &'a dyn RevocableData<T>,
+    info: ThiefInfo,
+    /// Whether mutable access was handed out, so dropping the guard marks the data as changed
+    written: bool,
+}
+
+impl<'a, T: ?Sized> RevocableGuard<'a, T> {
+    /// Steals `requirement` for `thief`, returning a guard to its data.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`PreemptionError`] marked [`refused()`](PreemptionError::refused) or [`frozen()`](PreemptionError::frozen)
+    /// if the requirement refuses the steal, in which case its owner keeps it,
+    /// or one marked [`borrowed()`](PreemptionError::borrowed) if `thief` still holds another guard of the requirement.
+    pub fn claim(requirement: &'a impl RevocableData<T>, thief: &ThiefInfo) -> Result<Self> {
+        if !begin_borrow(requirement, thief) {
+            return Err(already_borrowed(requirement, *thief));
+        }
+        if let StealOutcome::Refused { owner } = requirement.try_steal_ownership(thief) {
+            end_borrow(requirement, thief);
+            return Err(refused(requirement, *thief, owner));
+        }
+        Ok(Self {
+            requirement,
+            info: *thief,
+            written: false,
+        })
+    }
+
+    /// Returns a guard to the data of `requirement` if it is owned by the [`PreemptibleFuture`](crate::thief::PreemptibleFuture) being polled,
+    /// and that future holds no other guard of it.
+    ///
+    /// The future keeps ownership, so the guard only releases the requirement early if it is dropped first.
+    pub fn current(requirement: &'a impl RevocableData<T>) -> Option<Self> {
+        let info = current_thief()?;
+        (owned_by_current(requirement) && begin_borrow(requirement, &info)).then(|| Self {
+            requirement,
+            info,
+            written: false,
+        })
+    }
+
+    /// Returns `true` if the guard's thief still owns the requirement.
+    pub fn is_owner(&self) -> bool {
+        self.requirement.is_owned_by(&self.info)
+    }
+
+    /// Returns the thief this guard was created for.
+    pub fn thief(&self) -> ThiefInfo {
+        self.info
+    }
+
+    fn validate(&self) {
+        assert!(
+            self.is_owner(),
+            "{} was stolen while {} still held a guard",
+            self.requirement.info(),
+            self.info,
+        );
+    }
+}
+
+impl<T: ?Sized> Deref for RevocableGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.validate();
+        #[cfg(feature = "debug-audit")]
+        self.requirement.record_access(&self.info, false);
+        // the guard's thief owns the requirement, which only hands out its data through guards and tasks of that thief
+        unsafe { &*self.requirement.data_ptr() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RevocableGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.validate();
+        self.written = true;
+        #[cfg(feature = "debug-audit")]
+        self.requirement.record_access(&self.info, true);
+        unsafe { &mut *self.requirement.data_ptr() }
+    }
+}
+
+impl<T: ?Sized> Drop for RevocableGuard<'_, T> {
+    fn drop(&mut self) {
+        end_borrow(self.requirement, &self.info);
+        if self.written {
+            self.requirement.mark_changed();
+        }
+        // a thief that stole the requirement keeps it
+        if self.is_owner() {
+            self.requirement.release_ownership();
+        }
+    }
+}
+
+/// Ownership of a requirement for a synchronous critical section, created by [`RevocableCell::claim_sync()`]
+///
+/// The requirement is released when the token is dropped, unless it was stolen in the meantime.
+pub struct ClaimedToken<'a, T> {
+    requirement: &'a dyn RevocableData<T>,
+    info: ThiefInfo,
+    /// Generation of the requirement when it was claimed
+    generation: u64,
+    written: bool,
+}
+
+impl<T> ClaimedToken<'_, T> {
+    /// Returns `true` if the token's thief still owns the requirement.
+    pub fn is_owner(&self) -> bool {
+        self.requirement.is_owned_by(&self.info)
+    }
+
+    /// Returns a mutable reference to the data if the token still owns the requirement.
+    ///
+    /// # Errors
+    ///
+    /// Returns the [`PreemptionError`] of the steal if another task stole the requirement since it was claimed.
+    pub fn get_mut(&mut self) -> Result<&mut T> {
+        if !self.is_owner() {
+            let preemption = self
+                .requirement
+                .last_preemption()
+                .filter(|preemption| preemption.generation == self.generation);
+            return Err(not_owned(self.requirement, self.info, preemption));
+        }
+        self.written = true;
+        #[cfg(feature = "debug-audit")]
+        self.requirement.record_access(&self.info, true);
+        // the token's thief owns the requirement, and only hands out its data through this token
+        Ok(unsafe { &mut *self.requirement.data_ptr() })
+    }
+}
+
+impl<T> Drop for ClaimedToken<'_, T> {
+    fn drop(&mut self) {
+        if self.written {
+            self.requirement.mark_changed();
+        }
+        // a thief that stole the requirement keeps it
+        if self.is_owner() {
+            self.requirement.release_ownership();
+        }
+    }
+}
+
+/// Returns the error for `outgoing` accessing `requirement` without owning it, described by `preemption` if that steal ended its ownership.
+fn not_owned<T>(
+    requirement: &dyn RevocableData<T>,
+    outgoing: ThiefInfo,
+    preemption: Option<LastPreemption>,
+) -> PreemptionError {
+    PreemptionError {
+        incoming: preemption
+            .map(|preemption| preemption.by)
+            .or_else(|| requirement.current_owner()),
+        acquired_at: preemption.and_then(|preemption| preemption.acquired_at),
+        stolen_at: preemption.and_then(|preemption| preemption.stolen_at),
+        last_steal: requirement.last_steal(),
+        ..PreemptionError::new(PreemptionKind::Stolen, outgoing, requirement.info())
+    }
+}
+
+/// Returns the error for `outgoing` being refused `requirement`, which `owner` keeps.
+fn refused<T: ?Sized>(
+    requirement: &dyn RevocableData<T>,
+    outgoing: ThiefInfo,
+    owner: Option<ThiefInfo>,
+) -> PreemptionError {
+    let kind = if requirement.is_frozen() {
+        PreemptionKind::Frozen
+    } else {
+        PreemptionKind::Refused
+    };
+    PreemptionError {
+        incoming: owner,
+        ..PreemptionError::new(kind, outgoing, requirement.info())
+    }
+}
+
+/// Returns the error for `outgoing` borrowing `requirement` while an earlier borrow is live.
+fn already_borrowed<T: ?Sized>(
+    requirement: &dyn RevocableData<T>,
+    outgoing: ThiefInfo,
+) -> PreemptionError {
+    PreemptionError::new(PreemptionKind::Borrowed, outgoing, requirement.info())
+}
+
+/// Mutable access to the data of a cell for as long as one call, created by [`RevocableCell::try_borrow_mut()`]
+///
+/// The borrow does not claim or release the cell, and cannot leave the thread or the task that created it.
+/// While it is live, further borrows of the cell fail.
+pub struct RefMut<'a, T> {
+    requirement: &'a dyn RevocableData<T>,
+    /// Owner the borrow was handed to, whose [`BorrowFlag`] entry is cleared when it is dropped
+    info: ThiefInfo,
+    data: &'a mut T,
+    _not_send: PhantomData<*mut T>,
+}
+
+impl<T> Deref for RefMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.data
+    }
+}
+
+impl<T> DerefMut for RefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.data
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for RefMut<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.data.fmt(f)
+    }
+}
+
+impl<T> Drop for RefMut<'_, T> {
+    fn drop(&mut self) {
+        end_borrow(self.requirement, &self.info);
+        self.requirement.mark_changed();
+    }
+}
+
+impl<T, M: Any, const H: usize> RevocableCell<T, M, H> {
+    /// Returns a mutable borrow of the data if `who` owns this cell and holds no other borrow of it.
+    ///
+    /// This is the checked replacement for dereferencing [`data_ptr()`](Self::data_ptr) in hand-written futures:
+    /// a borrow is only handed out to the current owner, and it is tied to `&self` so it cannot be kept across polls by accident.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`PreemptionError`] naming the current owner if `who` does not own the cell,
+    /// with the details of the steal if `who` was its last preempted owner,
+    /// or one marked [`borrowed()`](PreemptionError::borrowed) if `who` still holds an earlier borrow or a [`RevocableGuard`] of the cell.
+    pub fn try_borrow_mut(&self, who: &ThiefInfo) -> Result<RefMut<'_, T>> {
+        if !self.is_owned_by(who) {
+            let preempted = self.last_owner().is_some_and(|last| {
+                last.thief.same_thief(who) && matches!(last.ended, TenureEnd::Preempted { .. })
+            });
+            let preemption = self.last_preemption().filter(|_| preempted);
+            return Err(not_owned(self, *who, preemption));
+        }
+        if !begin_borrow(self, who) {
+            return Err(already_borrowed(self, *who));
+        }
+        #[cfg(feature = "debug-audit")]
+        self.record_audit(who, true);
+        Ok(RefMut {
+            requirement: self,
+            info: *who,
+            // `who` owns the cell, and the flag keeps this its only live borrow
+            data: unsafe { &mut *self.data_ptr() },
+            _not_send: PhantomData,
+        })
+    }
+
+    /// Steals this cell for `thief`, returning a guard that releases it when dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`PreemptionError`] if the cell refuses the steal, see [`RevocableGuard::claim()`].
+    pub fn claim<'a>(&'a self, thief: &'a ThiefInfo) -> Result<RevocableGuard<'a, T>> {
+        RevocableGuard::claim(self, thief)
+    }
+
+    /// Steals this cell for a new thief named `name`, returning a token for a synchronous critical section.
+    ///
+    /// The section has the same exclusivity as a [`PreemptibleFuture`](crate::thief::PreemptibleFuture),
+    /// and a future stealing the cell mid-section makes the next [`get_mut()`](ClaimedToken::get_mut) fail.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`PreemptionError`] if the cell refuses the steal, in which case its owner keeps it.
+    pub fn claim_sync(&self, name: impl Into<Name>) -> Result<ClaimedToken<'_, T>> {
+        let info = ThiefInfo::new(name);
+        if let StealOutcome::Refused { owner } = self.try_steal_ownership(&info) {
+            return Err(refused(self, info, owner));
+        }
+        Ok(ClaimedToken {
+            requirement: self,
+            info,
+            generation: self.generation(),
+            written: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::{
+        cell::Cell,
+        future::poll_fn,
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use super::*;
+    use crate::thief::PreemptibleFuture;
+
+    #[test]
+    fn claim_and_release() {
+        let arm = RevocableCell::new(0, "arm");
+        let thief = ThiefInfo::new("script");
+        let mut guard = arm.claim(&thief).unwrap();
+        *guard += 1;
+        assert!(guard.is_owner());
+        drop(guard);
+        assert!(arm.current_owner().is_none());
+        assert_eq!(arm.into_inner(), 1);
+    }
+
+    #[test]
+    fn stolen_guard_keeps_thief() {
+        let arm = RevocableCell::new(0, "arm");
+        let script = ThiefInfo::new("script");
+        let stow = ThiefInfo::new("stow");
+        let guard = arm.claim(&script).unwrap();
+        arm.steal_ownership(&stow);
+        assert!(!guard.is_owner());
+
+        // dropping the stolen guard leaves the cell with its thief
+        drop(guard);
+        assert!(
+            arm.current_owner()
+                .is_some_and(|owner| owner.same_thief(&stow))
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Requirement { name: arm, type: i32 }  was stolen while Thief { name: script }  still held a guard"
+    )]
+    fn stolen_guard_panics() {
+        let arm = RevocableCell::new(0, "arm");
+        let script = ThiefInfo::new("script");
+        let stow = ThiefInfo::new("stow");
+        let mut guard = arm.claim(&script).unwrap();
+        arm.steal_ownership(&stow);
+        *guard += 1;
+    }
+
+    #[test]
+    fn refused_claims() {
+        let arm = RevocableCell::new(0, "arm");
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut homing = pin!(
+            PreemptibleFuture::new(futures_lite::future::pending::<()>(), "homing", [&arm])
+                .with_priority(2)
+        );
+        assert!(homing.as_mut().poll(&mut cx).is_pending());
+
+        // neither form of claim hands out the data of a cell that refuses the steal
+        let script = ThiefInfo::new("script");
+        let Err(err) = arm.claim(&script) else {
+            panic!("homing has a higher priority than script");
+        };
+        assert!(err.refused());
+        assert_eq!(
+            err.incoming().map(|thief| thief.name.as_str()),
+            Some("homing")
+        );
+        assert!(arm.claim_sync("sync").is_err_and(|err| err.refused()));
+        assert!(homing.as_mut().poll(&mut cx).is_pending());
+    }
+
+    #[test]
+    fn claimed_token() {
+        let arm = RevocableCell::new(0, "arm");
+        let mut token = arm.claim_sync("zero").unwrap();
+        *token.get_mut().expect("zero owns arm") += 1;
+        assert_eq!(
+            arm.current_owner().map(|owner| owner.name),
+            Some("zero".into())
+        );
+
+        // a future stealing mid-section fails the next access instead of sharing the data
+        let mut cx = Context::from_waker(Waker::noop());
+        {
+            let mut stow = pin!(arm.run("stow", async |arm| *arm = 5));
+            assert_eq!(stow.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        }
+        let err = token.get_mut().expect_err("stow stole arm");
+        assert_eq!(
+            err.incoming().map(|thief| thief.name.as_str()),
+            Some("stow")
+        );
+        assert_eq!(err.outgoing().name, "zero");
+        drop(token);
+        assert!(arm.current_owner().is_none());
+        assert_eq!(arm.into_inner(), 5);
+    }
+
+    #[test]
+    fn one_guard_per_thief() {
+        let arm = RevocableCell::new(0, "arm");
+        let script = ThiefInfo::new("script");
+        let guard = arm.claim(&script).unwrap();
+        let Err(err) = arm.claim(&script) else {
+            panic!("script already holds a guard");
+        };
+        assert!(err.borrowed());
+        drop(guard);
+        assert!(arm.claim(&script).is_ok());
+
+        // inside a task, the second guard of the same poll is refused too
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut task = pin!(PreemptibleFuture::new(
+            poll_fn(|_| {
+                let mut first = RevocableGuard::current(&arm).expect("task owns arm");
+                assert!(RevocableGuard::current(&arm).is_none());
+                assert!(current_thief().is_some_and(|thief| arm.try_borrow_mut(&thief).is_err()));
+                *first += 1;
+                Poll::Ready(())
+            }),
+            "task",
+            [&arm],
+        ));
+        assert_eq!(task.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(arm.peek(), 1);
+    }
+
+    #[test]
+    fn one_borrow_at_a_time() {
+        let arm = RevocableCell::new(0, "arm");
+        let thief = ThiefInfo::new("script");
+        arm.steal_ownership(&thief);
+
+        // the thief info is `Copy`, so the flag rather than the borrow of `who` keeps the borrows apart
+        let mut first = arm.try_borrow_mut(&thief).expect("script owns arm");
+        let err = arm
+            .try_borrow_mut(&thief)
+            .expect_err("the first borrow is live");
+        assert!(err.borrowed());
+        assert_eq!(err.outgoing().name, "script");
+        *first += 1;
+        drop(first);
+        assert_eq!(*arm.try_borrow_mut(&thief).expect("script owns arm"), 1);
+    }
+
+    #[test]
+    fn checked_borrows_between_polls() {
+        let resource = &RevocableCell::new(0, "test");
+        let seen = &Cell::new(0);
+        // hand-written bodies borrow the data on each poll instead of holding a reference
+        let step = |delta: i32| {
+            poll_fn(move |_| {
+                let thief = current_thief().expect("polled by its preemptible future");
+                let mut x = resource.try_borrow_mut(&thief).expect("the task owns test");
+                *x += delta;
+                seen.set(*x);
+                Poll::<()>::Pending
+            })
+        };
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut plus_5 = pin!(PreemptibleFuture::new(step(5), "plus_5", [resource]));
+        assert!(plus_5.as_mut().poll(&mut cx).is_pending());
+        assert!(plus_5.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(seen.get(), 10);
+        let plus_5_thief = plus_5.info;
+
+        let mut minus_1 = pin!(PreemptibleFuture::new(step(-1), "minus_1", [resource]));
+        assert!(minus_1.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(seen.get(), 9);
+
+        // the preempted thief is refused a borrow, and told who took the cell
+        let err = resource
+            .try_borrow_mut(&plus_5_thief)
+            .expect_err("minus_1 stole test");
+        assert_eq!(
+            err.incoming().map(|thief| thief.name.as_str()),
+            Some("minus_1")
+        );
+        assert!(matches!(plus_5.as_mut().poll(&mut cx), Poll::Ready(Err(_))));
+        assert!(minus_1.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(seen.get(), 8);
+    }
+
+    #[test]
+    fn current_task_guard() {
+        let arm = RevocableCell::new(0, "arm");
+        assert!(RevocableGuard::current(&arm).is_none());
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut task = pin!(crate::thief::PreemptibleFuture::new(
+            async {
+                let mut guard = RevocableGuard::current(&arm).expect("the task owns arm");
+                *guard += 1;
+                guard.thief()
+            },
+            "task",
+            [&arm],
+        ));
+        let Poll::Ready(Ok(thief)) = task.as_mut().poll(&mut cx) else {
+            panic!("task should complete");
+        };
+        assert_eq!(thief.name, "task");
+        assert!(arm.current_owner().is_none());
+    }
+}
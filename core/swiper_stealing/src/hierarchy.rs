@@ -0,0 +1,305 @@
+//! A parent requirement with child requirements, for subsystems made of independently usable parts.
+//!
+//! A [`RequirementTree`] relates a parent, such as a whole superstructure, to its children, such as its elevator and wrist.
+//! Tasks list the tree's [`parent()`](RequirementTree::parent) or one of its [`child()`](RequirementTree::child) nodes as requirements:
+//! claiming the parent revokes the owners of every child, claiming a child revokes the owner of the parent,
+//! and tasks claiming different children coexist.
+//! Revoked requirements are left without an owner, as with [projections](crate::projection).
+//!
+//! The relationship only holds for tasks going through the nodes, a task listing a child cell directly does not revoke the parent.
+//! Children are stored inline, so trees work without an allocator.
+
+use crate::{
+    history::StealRecord,
+    requirement::{
+        Claims, LastOwner, LastPreemption, Requirement, RequirementId, RequirementInfo,
+        RequirementInfoSource, StealOutcome, TenureEnd,
+    },
+    thief::ThiefInfo,
+};
+
+/// A parent requirement and its `N` children, see the [module-level documentation](self)
+pub struct RequirementTree<'a, const N: usize> {
+    parent: ParentNode<'a, N>,
+    children: [ChildNode<'a>; N],
+}
+
+impl<'a, const N: usize> RequirementTree<'a, N> {
+    /// Relates `parent` to `children`.
+    pub fn new(parent: &'a dyn Requirement, children: [&'a dyn Requirement; N]) -> Self {
+        Self {
+            parent: ParentNode { parent, children },
+            children: children.map(|child| ChildNode { child, parent }),
+        }
+    }
+
+    /// Returns the node claiming the parent, which revokes the owners of every child.
+    pub fn parent(&self) -> &ParentNode<'a, N> {
+        &self.parent
+    }
+
+    /// Returns the node claiming the child at `index`, which revokes the owner of the parent.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn child(&self, index: usize) -> &ChildNode<'a> {
+        &self.children[index]
+    }
+}
+
+/// Revokes `requirement` unless `thief` already owns it, so a task listing several nodes of a tree does not preempt itself.
+fn revoke_other(requirement: &dyn Requirement, thief: &ThiefInfo) {
+    if !requirement
+        .current_owner()
+        .is_some_and(|owner| owner.same_thief(thief))
+    {
+        requirement.revoke(thief);
+    }
+}
+
+/// The parent of a [`RequirementTree`], created by [`RequirementTree::parent()`]
+pub struct ParentNode<'a, const N: usize> {
+    parent: &'a dyn Requirement,
+    children: [&'a dyn Requirement; N],
+}
+
+/// A child of a [`RequirementTree`], created by [`RequirementTree::child()`]
+pub struct ChildNode<'a> {
+    child: &'a dyn Requirement,
+    parent: &'a dyn Requirement,
+}
+
+/// Implements [`Requirement`] for a node by revoking its `$related` requirements before delegating to `$own`.
+macro_rules! impl_node {
+    ([$($generics:tt)*] $node:ty, $own:ident, |$this:ident| $related:expr) => {
+        impl<$($generics)*> Requirement for $node {
+            fn steal_ownership(&self, thief: &ThiefInfo) {
+                self.try_steal_ownership(thief);
+            }
+
+            fn try_steal_ownership(&self, thief: &ThiefInfo) -> StealOutcome {
+                let $this = self;
+                for related in $related {
+                    revoke_other(related, thief);
+                }
+                self.$own.try_steal_ownership(thief)
+            }
+
+            fn release_ownership(&self) {
+                self.$own.release_ownership();
+            }
+
+            fn end_ownership(&self, ended: TenureEnd) {
+                self.$own.end_ownership(ended);
+            }
+
+            fn revoke(&self, by: &ThiefInfo) {
+                self.$own.revoke(by);
+            }
+
+            fn poison(&self) {
+                self.$own.poison();
+            }
+
+            fn is_owned_by(&self, thief: &ThiefInfo) -> bool {
+                self.$own.is_owned_by(thief)
+            }
+
+            fn mark_polled(&self) {
+                self.$own.mark_polled();
+            }
+
+            fn register_owner_waker(&self, waker: &core::task::Waker) {
+                self.$own.register_owner_waker(waker);
+            }
+
+            fn register_release_waker(&self, waker: &core::task::Waker) {
+                self.$own.register_release_waker(waker);
+            }
+
+            fn meta_any(&self) -> Option<&dyn core::any::Any> {
+                self.$own.meta_any()
+            }
+        }
+
+        impl<$($generics)*> RequirementInfoSource for $node {
+            fn current_owner(&self) -> Option<ThiefInfo> {
+                self.$own.current_owner()
+            }
+
+            fn current_claims(&self) -> Claims {
+                self.$own.current_claims()
+            }
+
+            fn info(&self) -> RequirementInfo {
+                self.$own.info()
+            }
+
+            fn id(&self) -> RequirementId {
+                self.$own.id()
+            }
+
+            fn generation(&self) -> u64 {
+                self.$own.generation()
+            }
+
+            fn last_preemption(&self) -> Option<LastPreemption> {
+                self.$own.last_preemption()
+            }
+
+            fn last_owner(&self) -> Option<LastOwner> {
+                self.$own.last_owner()
+            }
+
+            fn last_steal(&self) -> Option<StealRecord> {
+                self.$own.last_steal()
+            }
+
+            fn is_poisoned(&self) -> bool {
+                self.$own.is_poisoned()
+            }
+
+            fn is_frozen(&self) -> bool {
+                self.$own.is_frozen()
+            }
+
+            fn min_steal_priority(&self) -> u8 {
+                self.$own.min_steal_priority()
+            }
+
+            fn may_steal(&self, incoming: &ThiefInfo) -> bool {
+                self.$own.may_steal(incoming)
+            }
+        }
+    };
+}
+
+impl_node!([const N: usize] ParentNode<'_, N>, parent, |node| node.children);
+impl_node!([] ChildNode<'_>, child, |node| [node.parent]);
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use futures_lite::future::pending;
+
+    use super::*;
+    use crate::{requirement::RevocableCell, thief::PreemptibleFuture};
+
+    fn owner(requirement: &dyn Requirement) -> Option<&'static str> {
+        requirement.current_owner().map(|owner| owner.name.as_str())
+    }
+
+    #[test]
+    fn children_coexist() {
+        let superstructure = RevocableCell::new((), "superstructure");
+        let elevator = RevocableCell::new(0, "elevator");
+        let wrist = RevocableCell::new(0, "wrist");
+        let tree = RequirementTree::new(&superstructure, [&elevator, &wrist]);
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut lift = pin!(PreemptibleFuture::new(
+            pending::<()>(),
+            "lift",
+            [tree.child(0)]
+        ));
+        let mut tilt = pin!(PreemptibleFuture::new(
+            pending::<()>(),
+            "tilt",
+            [tree.child(1)]
+        ));
+        assert!(lift.as_mut().poll(&mut cx).is_pending());
+        assert!(tilt.as_mut().poll(&mut cx).is_pending());
+        assert!(lift.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(
+            (owner(&elevator), owner(&wrist)),
+            (Some("lift"), Some("tilt"))
+        );
+    }
+
+    #[test]
+    fn parent_revokes_children() {
+        let superstructure = RevocableCell::new((), "superstructure");
+        let elevator = RevocableCell::new(0, "elevator");
+        let wrist = RevocableCell::new(0, "wrist");
+        let tree = RequirementTree::new(&superstructure, [&elevator, &wrist]);
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut lift = pin!(PreemptibleFuture::new(
+            pending::<()>(),
+            "lift",
+            [tree.child(0)]
+        ));
+        assert!(lift.as_mut().poll(&mut cx).is_pending());
+        let mut stow = pin!(PreemptibleFuture::new(
+            pending::<()>(),
+            "stow",
+            [tree.parent()]
+        ));
+        assert!(stow.as_mut().poll(&mut cx).is_pending());
+
+        let Poll::Ready(Err(err)) = lift.as_mut().poll(&mut cx) else {
+            panic!("lift should be preempted by stow");
+        };
+        assert_eq!(
+            err.incoming().map(|thief| thief.name.as_str()),
+            Some("stow")
+        );
+        assert_eq!(err.requirement().name, "elevator");
+        assert_eq!(owner(&elevator), None);
+        assert!(stow.as_mut().poll(&mut cx).is_pending());
+    }
+
+    #[test]
+    fn child_revokes_parent() {
+        let superstructure = RevocableCell::new((), "superstructure");
+        let elevator = RevocableCell::new(0, "elevator");
+        let wrist = RevocableCell::new(0, "wrist");
+        let tree = RequirementTree::new(&superstructure, [&elevator, &wrist]);
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut stow = pin!(PreemptibleFuture::new(
+            pending::<()>(),
+            "stow",
+            [tree.parent()]
+        ));
+        assert!(stow.as_mut().poll(&mut cx).is_pending());
+        let mut tilt = pin!(PreemptibleFuture::new(
+            pending::<()>(),
+            "tilt",
+            [tree.child(1)]
+        ));
+        assert!(tilt.as_mut().poll(&mut cx).is_pending());
+
+        let Poll::Ready(Err(err)) = stow.as_mut().poll(&mut cx) else {
+            panic!("stow should be preempted by tilt");
+        };
+        assert_eq!(
+            err.incoming().map(|thief| thief.name.as_str()),
+            Some("tilt")
+        );
+        assert_eq!(owner(&superstructure), None);
+        assert!(tilt.as_mut().poll(&mut cx).is_pending());
+    }
+
+    #[test]
+    fn parent_and_child_together() {
+        let superstructure = RevocableCell::new((), "superstructure");
+        let elevator = RevocableCell::new(0, "elevator");
+        let tree = RequirementTree::new(&superstructure, [&elevator]);
+        let mut cx = Context::from_waker(Waker::noop());
+
+        // a task claiming both nodes does not revoke itself
+        let mut home = pin!(PreemptibleFuture::new(
+            async {},
+            "home",
+            [tree.child(0), tree.parent()]
+        ));
+        assert_eq!(home.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!((owner(&superstructure), owner(&elevator)), (None, None));
+    }
+}
@@ -0,0 +1,94 @@
+use core::{cell::Cell, fmt::Display};
+
+use crate::{clock::Tick, thief::ThiefInfo};
+
+/// Kind of ownership transition stored in an [`OwnershipRecord`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OwnershipChange {
+    /// The thief took ownership of an unowned requirement
+    Acquired,
+    /// The thief took ownership from a previous owner
+    Stolen,
+    /// The thief released ownership
+    Released,
+}
+
+/// A single ownership transition of a requirement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OwnershipRecord {
+    /// The task that acquired, stole, or released the requirement
+    pub thief: ThiefInfo,
+    pub kind: OwnershipChange,
+    /// When the transition happened, if the requirement has a [`Clock`](crate::clock::Clock)
+    pub at: Option<Tick>,
+}
+
+/// A steal of a requirement from a live owner
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StealRecord {
+    /// The task that lost the requirement
+    pub outgoing: ThiefInfo,
+    /// The task that stole the requirement
+    pub incoming: ThiefInfo,
+    /// Value of [`poll_sequence()`](crate::thief::poll_sequence) when the requirement was stolen
+    pub sequence: u32,
+    /// When the requirement was stolen, if it has a [`Clock`](crate::clock::Clock)
+    pub at: Option<Tick>,
+}
+
+impl Display for StealRecord {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{} stole from {} at poll {}",
+            self.incoming.name, self.outgoing.name, self.sequence
+        )?;
+        if let Some(at) = self.at {
+            write!(f, " ({at})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Fixed capacity ring buffer of the last `K` records.
+///
+/// With `K == 0` nothing is recorded.
+pub(crate) struct History<const K: usize, R = OwnershipRecord> {
+    records: [Cell<Option<R>>; K],
+    next: Cell<usize>,
+}
+
+impl<const K: usize, R: Copy> History<K, R> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            records: [const { Cell::new(None) }; K],
+            next: Cell::new(0),
+        }
+    }
+
+    pub(crate) fn record(&self, record: R) {
+        if K == 0 {
+            return;
+        }
+        let next = self.next.get();
+        self.records[next].set(Some(record));
+        self.next.set((next + 1) % K);
+    }
+
+    /// Iterates over the retained records, oldest first.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = R> + '_ {
+        let next = self.next.get();
+        (0..K).filter_map(move |i| self.records[(next + i) % K].get())
+    }
+
+    /// Returns the most recent record.
+    pub(crate) fn last(&self) -> Option<R> {
+        let last = self.next.get().checked_sub(1).unwrap_or(K.checked_sub(1)?);
+        self.records[last].get()
+    }
+
+    pub(crate) fn clear(&self) {
+        self.records.iter().for_each(|record| record.set(None));
+        self.next.set(0);
+    }
+}
@@ -0,0 +1,348 @@
+//! Bridge letting interrupt handlers preempt tasks without touching non-`Sync` state.
+//!
+//! An [`AtomicRevocableCell`] is a [`RevocableCell`] with a lock-free pending steal flag.
+//! [`IsrSteal::register()`] hands out an [`IsrStealToken`] whose [`fire()`](IsrStealToken::fire) only sets that flag,
+//! so it is safe to call from interrupt context or another thread.
+//! The steal itself is applied by the main context the next time anything inspects the cell's ownership,
+//! which for a running [`PreemptibleFuture`] is its next poll, so the victim observes the preemption at a safe point between polls.
+//! A steal refused by the cell, for example because its [policy](crate::policy) protects the owner, stays pending and is retried at every inspection.
+//!
+//! For HALs that take `extern "C"` callbacks, [`IsrStealToken::as_raw()`] and [`isr_steal_callback`] pass a token through a `void *` context pointer.
+
+use core::{
+    cell::Cell,
+    ffi::c_void,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{
+    clock::Clock,
+    guard::{BorrowFlag, RevocableGuard},
+    requirement::{
+        LastOwner, LastPreemption, Requirement, RequirementId, RequirementInfo,
+        RequirementInfoSource, RevocableCell, RevocableData, StealOutcome, TenureEnd,
+    },
+    thief::{PreemptibleFuture, ThiefInfo},
+};
+
+/// A [`RevocableCell`] whose ownership can be stolen from interrupt context through an [`IsrStealToken`]
+pub struct AtomicRevocableCell<T> {
+    cell: RevocableCell<T>,
+    pending: AtomicBool,
+    isr_thief: Cell<ThiefInfo>,
+}
+
+impl<T> AtomicRevocableCell<T> {
+    /// Creates a new [`AtomicRevocableCell`] with ownership of `data`.
+    pub fn new(data: T, name: &'static str) -> Self {
+        Self {
+            cell: RevocableCell::new(data, name),
+            pending: AtomicBool::new(false),
+            isr_thief: Cell::new(ThiefInfo::new("isr")),
+        }
+    }
+
+    /// Timestamps ownership changes of this cell using `clock`.
+    pub fn with_clock(self, clock: &'static dyn Clock) -> Self {
+        Self {
+            cell: self.cell.with_clock(clock),
+            ..self
+        }
+    }
+
+    /// Consumes the cell, returning the guarded value.
+    pub fn into_inner(self) -> T {
+        self.cell.into_inner()
+    }
+
+    /// Returns `true` if a steal was fired but has not been applied yet.
+    pub fn is_steal_pending(&self) -> bool {
+        self.pending.load(Ordering::Acquire)
+    }
+
+    fn apply_pending(&self) {
+        if !self.pending.swap(false, Ordering::AcqRel) {
+            return;
+        }
+        if let StealOutcome::Refused { .. } = self.cell.try_steal_ownership(&self.isr_thief.get()) {
+            // the steal is retried once the cell allows it
+            self.pending.store(true, Ordering::Release);
+        }
+    }
+
+    /// Same as [`RevocableCell::run`], but the future also observes steals fired from interrupt context.
+    ///
+    /// # Errors
+    ///
+    /// If access to this cell has been stolen by a different future or an [`IsrStealToken`],
+    /// the future returns `Err<PreemptionError>` when next polled.
+    pub fn run<'a, Out>(
+        &'a self,
+        name: &'static str,
+        func: impl AsyncFnOnce(&mut T) -> Out + 'a,
+    ) -> PreemptibleFuture<'a, impl Future<Output = Out>, Out, 1> {
+        let inner = async move {
+            let mut guard = RevocableGuard::current(self).expect("run owns its cell");
+            func(&mut guard).await
+        };
+        PreemptibleFuture::new(inner, name, [self]).lending_data()
+    }
+}
+
+impl<T> Requirement for AtomicRevocableCell<T> {
+    fn steal_ownership(&self, thief: &ThiefInfo) {
+        self.apply_pending();
+        self.cell.steal_ownership(thief);
+    }
+
+    fn try_steal_ownership(&self, thief: &ThiefInfo) -> StealOutcome {
+        self.apply_pending();
+        self.cell.try_steal_ownership(thief)
+    }
+
+    fn release_ownership(&self) {
+        self.end_ownership(TenureEnd::Completed);
+    }
+
+    fn end_ownership(&self, ended: TenureEnd) {
+        self.apply_pending();
+        // a steal fired during the final poll already replaced the owner, so it keeps the cell
+        if self
+            .cell
+            .current_owner()
+            .is_some_and(|owner| owner.same_thief(&self.isr_thief.get()))
+        {
+            return;
+        }
+        self.cell.end_ownership(ended);
+    }
+
+    fn poison(&self) {
+        self.cell.poison();
+    }
+
+    fn is_owned_by(&self, thief: &ThiefInfo) -> bool {
+        self.apply_pending();
+        self.cell.is_owned_by(thief)
+    }
+
+    fn mark_polled(&self) {
+        self.cell.mark_polled();
+    }
+
+    fn register_owner_waker(&self, waker: &core::task::Waker) {
+        self.cell.register_owner_waker(waker);
+    }
+
+    fn register_release_waker(&self, waker: &core::task::Waker) {
+        self.cell.register_release_waker(waker);
+    }
+
+    fn meta_any(&self) -> Option<&dyn core::any::Any> {
+        self.cell.meta_any()
+    }
+}
+
+impl<T> RequirementInfoSource for AtomicRevocableCell<T> {
+    fn current_owner(&self) -> Option<ThiefInfo> {
+        self.apply_pending();
+        self.cell.current_owner()
+    }
+
+    fn info(&self) -> RequirementInfo {
+        self.cell.info()
+    }
+
+    fn id(&self) -> RequirementId {
+        self.cell.id()
+    }
+
+    fn generation(&self) -> u64 {
+        self.apply_pending();
+        self.cell.generation()
+    }
+
+    fn last_preemption(&self) -> Option<LastPreemption> {
+        self.apply_pending();
+        self.cell.last_preemption()
+    }
+
+    fn last_owner(&self) -> Option<LastOwner> {
+        self.apply_pending();
+        self.cell.last_owner()
+    }
+
+    fn is_poisoned(&self) -> bool {
+        self.cell.is_poisoned()
+    }
+
+    fn is_frozen(&self) -> bool {
+        self.cell.is_frozen()
+    }
+
+    fn min_steal_priority(&self) -> u8 {
+        self.cell.min_steal_priority()
+    }
+
+    fn may_steal(&self, incoming: &ThiefInfo) -> bool {
+        self.cell.may_steal(incoming)
+    }
+}
+
+impl<T> RevocableData<T> for AtomicRevocableCell<T> {
+    unsafe fn data_ptr(&self) -> *mut T {
+        unsafe { self.cell.data_ptr() }
+    }
+
+    fn borrow_flag(&self) -> Option<&BorrowFlag> {
+        self.cell.borrow_flag()
+    }
+
+    fn mark_changed(&self) {
+        self.cell.mark_changed();
+    }
+
+    #[cfg(feature = "debug-audit")]
+    fn record_access(&self, thief: &ThiefInfo, write: bool) {
+        self.cell.record_access(thief, write);
+    }
+}
+
+/// Registers interrupt handlers as synthetic thieves of an [`AtomicRevocableCell`]
+pub struct IsrSteal;
+
+impl IsrSteal {
+    /// Returns a token that steals `cell` on behalf of a thief named `name` when fired.
+    ///
+    /// Each cell has a single interrupt thief, so registering again renames it.
+    /// Registration itself must happen in the main context.
+    pub fn register<T>(cell: &'static AtomicRevocableCell<T>, name: &'static str) -> IsrStealToken {
+        cell.isr_thief.set(ThiefInfo::new(name));
+        IsrStealToken {
+            pending: &cell.pending,
+        }
+    }
+}
+
+/// Handle for firing a steal registered with [`IsrSteal::register()`], safe to use from interrupt context
+#[derive(Debug, Clone, Copy)]
+pub struct IsrStealToken {
+    pending: &'static AtomicBool,
+}
+
+impl IsrStealToken {
+    /// Records a pending steal, applied by the main context at its next safe point.
+    ///
+    /// This is lock-free and never panics. Firing again before the steal is applied has no additional effect.
+    pub fn fire(self) {
+        self.pending.store(true, Ordering::Release);
+    }
+
+    /// Converts the token into a context pointer for C callback registration, see [`isr_steal_callback`].
+    pub fn as_raw(self) -> *mut c_void {
+        core::ptr::from_ref(self.pending).cast_mut().cast()
+    }
+
+    /// Recreates a token from a pointer returned by [`as_raw()`](Self::as_raw).
+    ///
+    /// # Safety
+    ///
+    /// `raw` must have been returned by [`as_raw()`](Self::as_raw).
+    pub unsafe fn from_raw(raw: *mut c_void) -> Self {
+        Self {
+            pending: unsafe { &*raw.cast::<AtomicBool>() },
+        }
+    }
+}
+
+/// An `extern "C"` callback firing the [`IsrStealToken`] passed as its context pointer.
+///
+/// # Safety
+///
+/// `ctx` must have been returned by [`IsrStealToken::as_raw()`].
+pub unsafe extern "C" fn isr_steal_callback(ctx: *mut c_void) {
+    unsafe { IsrStealToken::from_raw(ctx) }.fire();
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::task::{Context, Poll, Waker};
+    use std::{boxed::Box, thread};
+
+    use super::*;
+
+    #[test]
+    fn isr_steal_from_thread() {
+        let motor: &'static AtomicRevocableCell<i32> =
+            Box::leak(Box::new(AtomicRevocableCell::new(0, "motor")));
+        let token = IsrSteal::register(motor, "limit_switch");
+
+        let mut victim = Box::pin(motor.run("drive", async |x| {
+            loop {
+                *x += 1;
+                futures_lite::future::yield_now().await;
+            }
+        }));
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(victim.as_mut().poll(&mut cx).is_pending());
+
+        // simulate the interrupt firing on another core
+        thread::spawn(move || token.fire()).join().unwrap();
+        assert!(motor.is_steal_pending());
+
+        let Poll::Ready(Err(err)) = victim.as_mut().poll(&mut cx) else {
+            panic!("drive should be preempted by the interrupt");
+        };
+        assert_eq!(
+            err.incoming().map(|thief| thief.name.as_str()),
+            Some("limit_switch")
+        );
+        assert_eq!(err.outgoing().name, "drive");
+        assert_eq!(err.requirement(), motor.info());
+        assert!(!motor.is_steal_pending());
+    }
+
+    #[test]
+    fn refused_steal_stays_pending() {
+        let motor: &'static AtomicRevocableCell<i32> =
+            Box::leak(Box::new(AtomicRevocableCell::new(0, "motor")));
+        let token = IsrSteal::register(motor, "limit_switch");
+        let mut homing = Box::pin(
+            motor
+                .run("homing", async |x| {
+                    *x = 1;
+                    futures_lite::future::yield_now().await;
+                })
+                .with_priority(2),
+        );
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(homing.as_mut().poll(&mut cx).is_pending());
+
+        // the interrupt cannot preempt a higher priority, so it steals the cell once homing releases it
+        token.fire();
+        assert!(motor.is_owned_by(&homing.info));
+        assert!(motor.is_steal_pending());
+        assert_eq!(homing.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(!motor.is_steal_pending());
+        assert_eq!(
+            motor.current_owner().map(|owner| owner.name.as_str()),
+            Some("limit_switch")
+        );
+    }
+
+    #[test]
+    fn c_callback_round_trip() {
+        let cell: &'static AtomicRevocableCell<()> =
+            Box::leak(Box::new(AtomicRevocableCell::new((), "cell")));
+        let ctx = IsrSteal::register(cell, "timer").as_raw();
+
+        let callback: unsafe extern "C" fn(*mut c_void) = isr_steal_callback;
+        unsafe { callback(ctx) };
+        assert_eq!(
+            cell.current_owner().map(|thief| thief.name.as_str()),
+            Some("timer")
+        );
+    }
+}
@@ -1,15 +1,177 @@
 #![no_std]
 #![doc = include_str!("../README.md")]
 
+#[cfg(feature = "debug-audit")]
+pub mod audit;
+#[cfg(feature = "std")]
+pub mod auto_steal;
+pub mod borrowed;
+pub mod checked;
+pub mod clock;
+pub mod closure;
+pub mod combinator;
+mod counter;
+#[cfg(feature = "futures")]
+pub mod events;
+pub mod executor;
+pub mod group;
+pub mod guard;
+pub mod hierarchy;
+pub mod history;
+#[cfg(feature = "sync")]
+pub mod isr;
+#[cfg(feature = "std")]
+pub mod model;
+pub mod mutex;
+pub mod name;
+pub mod notify;
+#[cfg(feature = "alloc")]
+pub mod owned;
+pub mod policy;
+pub mod pool;
+pub mod projection;
+#[cfg(feature = "introspection")]
+pub mod registry;
 pub mod requirement;
+pub mod semaphore;
+pub mod set;
+pub mod snapshot;
+pub mod split;
+pub mod stale;
+pub mod static_cell;
+pub mod swap;
+pub mod task;
 pub mod thief;
+#[cfg(feature = "alloc")]
+pub mod token;
+pub mod tracked;
+pub mod typed;
+pub mod value;
+
+pub use closure::{run2, run3, run4};
+pub use stale::tick;
+pub use swap::swap_ownership;
+pub use thief::current_thief;
+
+/// Why the outgoing task of a [`PreemptionError`] lost its requirement, or never got it
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PreemptionKind {
+    /// Another task stole the requirement
+    Stolen,
+    /// The outgoing task released its requirements because its [lease](thief::PreemptibleFuture::with_lease) expired
+    LeaseExpired,
+    /// The requirement was [poisoned](requirement::RequirementInfoSource::is_poisoned) by a task that panicked
+    Poisoned,
+    /// The requirement was [frozen](requirement::RevocableCell::freeze)
+    Frozen,
+    /// The requirement refused the outgoing task, see [`PreemptionError::refused()`]
+    Refused,
+    /// The outgoing task owns the requirement, but an earlier [borrow](requirement::RevocableCell::try_borrow_mut) of its data is still live
+    Borrowed,
+}
 
 /// Contains information about a preemption, including the newly scheduled incoming task, the newly cancelled outgoing task, and the requirement that was preempted
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PreemptionError {
+    kind: PreemptionKind,
     incoming: Option<thief::ThiefInfo>,
     outgoing: thief::ThiefInfo,
     requirement: requirement::RequirementInfo,
+    acquired_at: Option<clock::Tick>,
+    stolen_at: Option<clock::Tick>,
+    shared: bool,
+    last_steal: Option<history::StealRecord>,
+}
+
+impl PreemptionError {
+    /// Creates an error of `kind` for `outgoing` losing `requirement`, with no incoming task, timestamps or steal record.
+    pub(crate) fn new(
+        kind: PreemptionKind,
+        outgoing: thief::ThiefInfo,
+        requirement: requirement::RequirementInfo,
+    ) -> Self {
+        Self {
+            kind,
+            incoming: None,
+            outgoing,
+            requirement,
+            acquired_at: None,
+            stolen_at: None,
+            shared: false,
+            last_steal: None,
+        }
+    }
+
+    /// Returns why the outgoing task lost its requirement
+    pub fn kind(&self) -> PreemptionKind {
+        self.kind
+    }
+
+    /// Returns the task that stole the requirement, if it was still the owner when the preemption was detected
+    pub fn incoming(&self) -> Option<thief::ThiefInfo> {
+        self.incoming
+    }
+
+    /// Returns the task that was preempted
+    pub fn outgoing(&self) -> thief::ThiefInfo {
+        self.outgoing
+    }
+
+    /// Returns the requirement that was stolen
+    pub fn requirement(&self) -> requirement::RequirementInfo {
+        self.requirement
+    }
+
+    /// Returns when the outgoing task acquired the requirement, if the requirement has a clock
+    pub fn acquired_at(&self) -> Option<clock::Tick> {
+        self.acquired_at
+    }
+
+    /// Returns when the requirement was stolen, if the requirement has a clock
+    pub fn stolen_at(&self) -> Option<clock::Tick> {
+        self.stolen_at
+    }
+
+    /// Returns `true` if the outgoing task lost a shared read claim rather than exclusive ownership
+    pub fn shared(&self) -> bool {
+        self.shared
+    }
+
+    /// Returns the most recent steal of the requirement, if it keeps a [history](requirement::RevocableCell::with_history)
+    ///
+    /// This is normally the steal that preempted the outgoing task, and is shown by the alternate `{:#}` form of [`Display`](core::fmt::Display).
+    pub fn last_steal(&self) -> Option<history::StealRecord> {
+        self.last_steal
+    }
+
+    /// Returns `true` if the outgoing task released its requirements because its [lease](thief::PreemptibleFuture::with_lease) expired, rather than being stolen from
+    pub fn lease_expired(&self) -> bool {
+        self.kind == PreemptionKind::LeaseExpired
+    }
+
+    /// Returns `true` if the outgoing task never started because its requirement was [poisoned](requirement::RequirementInfoSource::is_poisoned) by a task that panicked
+    pub fn poisoned(&self) -> bool {
+        self.kind == PreemptionKind::Poisoned
+    }
+
+    /// Returns `true` if the outgoing task never started because its requirement was [frozen](requirement::RevocableCell::freeze)
+    pub fn frozen(&self) -> bool {
+        self.kind == PreemptionKind::Frozen
+    }
+
+    /// Returns `true` if the outgoing task was refused a requirement, because [`swap_ownership()`] could not claim the other one,
+    /// its priority is below the cell's [minimum steal priority](requirement::RevocableCell::with_min_steal_priority),
+    /// or the cell's [policy] protects the owner, which is then the [incoming](Self::incoming) task
+    pub fn refused(&self) -> bool {
+        self.kind == PreemptionKind::Refused
+    }
+
+    /// Returns `true` if the outgoing task owns the requirement but was refused a [borrow](requirement::RevocableCell::try_borrow_mut)
+    /// because an earlier borrow of its data is still live
+    pub fn borrowed(&self) -> bool {
+        self.kind == PreemptionKind::Borrowed
+    }
 }
 
 /// Result that is either `Ok` or `PreemptionError`
@@ -17,18 +179,67 @@ pub type Result<T> = core::result::Result<T, PreemptionError>;
 
 impl core::fmt::Display for PreemptionError {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let (outgoing, requirement) = (self.outgoing, self.requirement);
+        match self.kind {
+            PreemptionKind::Stolen => {}
+            PreemptionKind::Borrowed => {
+                return write!(
+                    f,
+                    "outgoing task {outgoing} could not borrow its requirement {requirement}, which is already borrowed"
+                );
+            }
+            PreemptionKind::Refused => {
+                return write!(
+                    f,
+                    "outgoing task {outgoing} was refused its requirement {requirement}"
+                );
+            }
+            PreemptionKind::Frozen => {
+                return write!(
+                    f,
+                    "outgoing task {outgoing} did not start because its requirement {requirement} is frozen"
+                );
+            }
+            PreemptionKind::Poisoned => {
+                return write!(
+                    f,
+                    "outgoing task {outgoing} did not start because its requirement {requirement} was poisoned by a panicking task"
+                );
+            }
+            PreemptionKind::LeaseExpired => {
+                return write!(
+                    f,
+                    "outgoing task {outgoing} gave up its requirement {requirement} after its lease expired"
+                );
+            }
+        }
         if let Some(incoming) = self.incoming {
             write!(
                 f,
-                "outgoing task {} was preempted by incoming task {} stealing its requirement {}",
-                self.outgoing, incoming, self.requirement
-            )
+                "outgoing task {} was preempted by incoming task {} stealing its {}requirement {}",
+                self.outgoing,
+                incoming,
+                if self.shared { "shared " } else { "" },
+                self.requirement
+            )?;
         } else {
             write!(
                 f,
-                "outgoing task {} was preempted by an unknown incoming task stealing its requirement {}",
-                self.outgoing, self.requirement
-            )
+                "outgoing task {} was preempted by an unknown incoming task stealing its {}requirement {}",
+                self.outgoing,
+                if self.shared { "shared " } else { "" },
+                self.requirement
+            )?;
+        }
+        if let Some(stolen_at) = self.stolen_at {
+            write!(f, " at {stolen_at}")?;
+        }
+        if let Some(acquired_at) = self.acquired_at {
+            write!(f, " (acquired at {acquired_at})")?;
+        }
+        if let Some(steal) = self.last_steal.filter(|_| f.alternate()) {
+            write!(f, ", last steal: {steal}")?;
         }
+        Ok(())
     }
 }
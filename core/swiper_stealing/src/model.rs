@@ -0,0 +1,551 @@
+//! Executable specification of the ownership policies of a cell, for differential testing.
+//!
+//! [`OwnershipModel`] keeps the ownership state of every cell and every task in plain `HashMap`s and applies [`Op`]s purely functionally,
+//! returning the next model rather than mutating itself.
+//! Each [`Op`] is something a [`PreemptibleFuture`](crate::thief::PreemptibleFuture) over a single cell does,
+//! and a [`Policy`] combines the rules deciding who may take a cell: thief [priorities](ThiefInfo::priority),
+//! the cell's [minimum steal priority](crate::requirement::RevocableCell::with_min_steal_priority),
+//! its [`PreemptionPolicy`](crate::policy::PreemptionPolicy), and task [leases](crate::thief::PreemptibleFuture::with_lease).
+//! The tests of this module drive random policies and operation sequences through both the model and real cells,
+//! asserting that every [`Outcome`] and [`Observation`] agrees.
+
+extern crate std;
+
+use std::{collections::HashMap, sync::OnceLock};
+
+use crate::{name::Name, requirement::TenureEnd, thief::ThiefInfo};
+
+/// Which owners a task may preempt, mirroring the policies of [`crate::policy`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Preemption {
+    /// [`PriorityPolicy`](crate::policy::PriorityPolicy): owners of equal or lower priority
+    Priority,
+    /// [`AlwaysSteal`](crate::policy::AlwaysSteal): every owner
+    Always,
+    /// [`NeverSteal`](crate::policy::NeverSteal): no owner, so a cell is only taken once it is free
+    Never,
+}
+
+/// Rules deciding who may take a cell and for how long, see the [module-level documentation](self)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Policy {
+    pub preemption: Preemption,
+    /// Thieves below this priority are refused, even from a cell nobody owns
+    pub min_steal_priority: u8,
+    /// Consecutive polls without [renewing](Op::Poll::renew) after which a task gives its cell up as [`TenureEnd::Expired`]
+    pub lease: Option<u32>,
+}
+
+impl Policy {
+    /// The policy of a new cell, polled by tasks without a lease
+    pub const DEFAULT: Self = Self {
+        preemption: Preemption::Priority,
+        min_steal_priority: 0,
+        lease: None,
+    };
+
+    /// Returns `true` if `incoming` may take a cell owned by `owner`, or unowned if `owner` is `None`.
+    pub fn may_steal(self, incoming: ThiefInfo, owner: Option<ThiefInfo>) -> bool {
+        incoming.priority >= self.min_steal_priority
+            && owner.is_none_or(|owner| match self.preemption {
+                Preemption::Priority => incoming.priority >= owner.priority,
+                Preemption::Always => true,
+                Preemption::Never => false,
+            })
+    }
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// An operation by the task of `thief` on the cell with index `cell`
+///
+/// Each thief runs at most one task per cell, which resolves on the first [`Outcome`] other than [`Outcome::Acquired`] or [`Outcome::Running`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// Starts a [polite](crate::thief::PreemptibleFuture::new_polite) task, which only takes the cell if it has no owner,
+    /// replacing the thief's previous task on the cell
+    Acquire { cell: usize, thief: ThiefInfo },
+    /// Starts a task, which takes the cell if the policy lets it preempt the owner,
+    /// replacing the thief's previous task on the cell
+    Steal { cell: usize, thief: ThiefInfo },
+    /// Polls the task, which checks that it still owns the cell and [renews its lease](crate::thief::renew_lease) if `renew` is set
+    Poll {
+        cell: usize,
+        thief: ThiefInfo,
+        renew: bool,
+    },
+    /// Polls the task to completion, releasing the cell if it still owns it
+    Release { cell: usize, thief: ThiefInfo },
+    /// Drops the task, which releases the cell as [`TenureEnd::Aborted`] if it still owns it
+    Cancel { cell: usize, thief: ThiefInfo },
+}
+
+impl Op {
+    /// Returns the index of the cell this operation applies to.
+    pub fn cell(self) -> usize {
+        match self {
+            Self::Acquire { cell, .. }
+            | Self::Steal { cell, .. }
+            | Self::Poll { cell, .. }
+            | Self::Release { cell, .. }
+            | Self::Cancel { cell, .. } => cell,
+        }
+    }
+
+    /// Returns the thief whose task performs this operation.
+    pub fn thief(self) -> ThiefInfo {
+        match self {
+            Self::Acquire { thief, .. }
+            | Self::Steal { thief, .. }
+            | Self::Poll { thief, .. }
+            | Self::Release { thief, .. }
+            | Self::Cancel { thief, .. } => thief,
+        }
+    }
+}
+
+/// Result of applying an [`Op`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The new task took the cell and is running
+    Acquired,
+    /// The polite task found the cell owned by another task, and resolved without taking it
+    Busy,
+    /// The new task was refused by the policy, and resolved without taking the cell
+    Refused,
+    /// The task still owns the cell
+    Running,
+    /// The task lost the cell to another task, and resolved
+    Preempted,
+    /// The task's lease ran out, so it released the cell and resolved
+    Expired,
+    /// The task completed, releasing the cell
+    Completed,
+    /// The thief has no task on the cell
+    Idle,
+    /// The task was dropped
+    Cancelled,
+}
+
+/// Observable ownership state of one cell
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Observation {
+    pub owner: Option<ThiefInfo>,
+    pub generation: u64,
+    /// The most recent ended tenure, without its timestamp
+    pub last_owner: Option<(ThiefInfo, TenureEnd)>,
+}
+
+/// A running task, and the polls left on its lease
+#[derive(Debug, Clone, Copy)]
+struct Task {
+    lease: Option<u32>,
+}
+
+/// Reference implementation of a [`Policy`], see the [module-level documentation](self)
+#[derive(Debug, Clone)]
+pub struct OwnershipModel {
+    policy: Policy,
+    cells: HashMap<usize, Observation>,
+    tasks: HashMap<(usize, Name), Task>,
+}
+
+impl OwnershipModel {
+    /// Creates a model in which every cell starts without an owner, and no thief runs a task.
+    pub fn new(policy: Policy) -> Self {
+        Self {
+            policy,
+            cells: HashMap::new(),
+            tasks: HashMap::new(),
+        }
+    }
+
+    /// Returns the policy this model follows.
+    pub fn policy(&self) -> Policy {
+        self.policy
+    }
+
+    /// Returns the model after applying `op`, and the outcome of `op`.
+    pub fn apply(&self, op: Op) -> (Self, Outcome) {
+        let mut next = self.clone();
+        let outcome = next.step(op);
+        (next, outcome)
+    }
+
+    /// Returns what an inspection of `cell` sees.
+    pub fn observe(&self, cell: usize) -> Observation {
+        self.cells.get(&cell).copied().unwrap_or_default()
+    }
+
+    fn step(&mut self, op: Op) -> Outcome {
+        let (index, thief) = (op.cell(), op.thief());
+        let policy = self.policy;
+        let task = self.tasks.remove(&(index, thief.name));
+        let cell = self.cells.entry(index).or_default();
+        // only the task of `thief` on this cell can own it as `thief`
+        let owned = cell.owner == Some(thief);
+        match op {
+            Op::Acquire { .. } | Op::Steal { .. } => {
+                if task.is_some() && owned {
+                    end(cell, TenureEnd::Aborted);
+                }
+                // the policy is checked before politeness
+                if !policy.may_steal(thief, cell.owner) {
+                    return Outcome::Refused;
+                }
+                if matches!(op, Op::Acquire { .. }) && cell.owner.is_some() {
+                    return Outcome::Busy;
+                }
+                if let Some(displaced) = cell.owner.replace(thief) {
+                    cell.last_owner = Some((displaced, TenureEnd::Preempted { by: thief }));
+                }
+                cell.generation += 1;
+                // the first poll already counts against the lease
+                let task = Task {
+                    lease: policy.lease,
+                };
+                self.poll(index, thief, task, false, Outcome::Acquired)
+            }
+            _ if task.is_none() => Outcome::Idle,
+            Op::Cancel { .. } => {
+                if owned {
+                    end(cell, TenureEnd::Aborted);
+                }
+                Outcome::Cancelled
+            }
+            _ if !owned => Outcome::Preempted,
+            Op::Release { .. } => {
+                end(cell, TenureEnd::Completed);
+                Outcome::Completed
+            }
+            Op::Poll { renew, .. } => {
+                let task = task.expect("idle tasks are handled above");
+                self.poll(index, thief, task, renew, Outcome::Running)
+            }
+        }
+    }
+
+    /// Counts a pending poll of the task owning `cell` against its lease, keeping the task unless the lease expired.
+    fn poll(
+        &mut self,
+        cell: usize,
+        thief: ThiefInfo,
+        mut task: Task,
+        renew: bool,
+        pending: Outcome,
+    ) -> Outcome {
+        if let Some(left) = task.lease.as_mut() {
+            *left = if renew {
+                self.policy
+                    .lease
+                    .expect("leased tasks follow a leased policy")
+            } else {
+                left.saturating_sub(1)
+            };
+            if *left == 0 {
+                end(
+                    self.cells.get_mut(&cell).expect("the task owns the cell"),
+                    TenureEnd::Expired,
+                );
+                return Outcome::Expired;
+            }
+        }
+        self.tasks.insert((cell, thief.name), task);
+        pending
+    }
+}
+
+fn end(cell: &mut Observation, ended: TenureEnd) {
+    if let Some(owner) = cell.owner.take() {
+        cell.last_owner = Some((owner, ended));
+    }
+}
+
+/// Returns the thieves of the model, with priorities 0, 1, 1 and 2 so that equal priorities contend too.
+pub fn thieves() -> [ThiefInfo; 4] {
+    static THIEVES: OnceLock<[ThiefInfo; 4]> = OnceLock::new();
+    *THIEVES.get_or_init(|| {
+        [("t0", 0), ("t1", 1), ("t2", 1), ("t3", 2)]
+            .map(|(name, priority)| ThiefInfo::new(name).with_priority(priority))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        cell::Cell,
+        future::poll_fn,
+        pin::Pin,
+        task::{Context, Poll, Waker},
+    };
+    use std::{boxed::Box, rc::Rc};
+
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{
+        policy::{AlwaysSteal, NeverSteal},
+        requirement::{RequirementInfoSource, RevocableCell},
+        thief::{PreemptibleFuture, renew_lease},
+    };
+
+    const CELLS: usize = 2;
+
+    /// What the inner future of a task does when it is next polled
+    #[derive(Debug, Clone, Copy)]
+    enum Command {
+        Wait,
+        Renew,
+        Finish,
+    }
+
+    struct Task {
+        future: Pin<Box<dyn Future<Output = crate::Result<()>>>>,
+        command: Rc<Cell<Command>>,
+    }
+
+    /// Real cells and tasks following a policy
+    struct System {
+        policy: Policy,
+        cells: &'static [RevocableCell<()>; CELLS],
+        tasks: HashMap<(usize, Name), Task>,
+    }
+
+    impl System {
+        fn new(policy: Policy) -> Self {
+            let cells: &'static [RevocableCell<()>; CELLS] =
+                Box::leak(Box::new(core::array::from_fn(|_| {
+                    RevocableCell::new((), "cell")
+                        .with_min_steal_priority(policy.min_steal_priority)
+                })));
+            for cell in cells {
+                match policy.preemption {
+                    Preemption::Priority => {}
+                    Preemption::Always => cell.set_policy(&AlwaysSteal),
+                    Preemption::Never => cell.set_policy(&NeverSteal),
+                }
+            }
+            Self {
+                policy,
+                cells,
+                tasks: HashMap::new(),
+            }
+        }
+
+        /// Polls the task of `thief` on `cell` after handing it `command`, keeping it while it is pending.
+        fn poll(&mut self, cell: usize, thief: ThiefInfo, command: Command) -> Outcome {
+            let Some(mut task) = self.tasks.remove(&(cell, thief.name)) else {
+                return Outcome::Idle;
+            };
+            task.command.set(command);
+            let mut cx = Context::from_waker(Waker::noop());
+            match task.future.as_mut().poll(&mut cx) {
+                Poll::Pending => {
+                    self.tasks.insert((cell, thief.name), task);
+                    Outcome::Running
+                }
+                Poll::Ready(Ok(())) => Outcome::Completed,
+                Poll::Ready(Err(err)) if err.lease_expired() => Outcome::Expired,
+                Poll::Ready(Err(err)) if err.refused() => Outcome::Refused,
+                Poll::Ready(Err(_)) => Outcome::Preempted,
+            }
+        }
+
+        fn apply(&mut self, op: Op) -> Outcome {
+            let (cell, thief) = (op.cell(), op.thief());
+            match op {
+                Op::Acquire { .. } | Op::Steal { .. } => {
+                    drop(self.tasks.remove(&(cell, thief.name)));
+                    let command = Rc::new(Cell::new(Command::Wait));
+                    let inner = poll_fn({
+                        let command = Rc::clone(&command);
+                        move |_| match command.get() {
+                            Command::Wait => Poll::Pending,
+                            Command::Renew => {
+                                renew_lease();
+                                Poll::Pending
+                            }
+                            Command::Finish => Poll::Ready(()),
+                        }
+                    });
+                    let requirements = [&self.cells[cell] as _];
+                    let mut future = match op {
+                        Op::Acquire { .. } => {
+                            PreemptibleFuture::new_polite(inner, thief.name, requirements)
+                        }
+                        _ => PreemptibleFuture::new(inner, thief.name, requirements),
+                    }
+                    .with_priority(thief.priority);
+                    if let Some(polls) = self.policy.lease {
+                        future = future.with_lease(polls);
+                    }
+                    self.tasks.insert(
+                        (cell, thief.name),
+                        Task {
+                            future: Box::pin(future),
+                            command,
+                        },
+                    );
+                    match self.poll(cell, thief, Command::Wait) {
+                        Outcome::Running => Outcome::Acquired,
+                        // a polite task that was not refused resolves because the cell is owned
+                        Outcome::Preempted if matches!(op, Op::Acquire { .. }) => Outcome::Busy,
+                        outcome => outcome,
+                    }
+                }
+                Op::Poll { renew, .. } => {
+                    let command = if renew { Command::Renew } else { Command::Wait };
+                    self.poll(cell, thief, command)
+                }
+                Op::Release { .. } => self.poll(cell, thief, Command::Finish),
+                Op::Cancel { .. } => match self.tasks.remove(&(cell, thief.name)) {
+                    Some(_) => Outcome::Cancelled,
+                    None => Outcome::Idle,
+                },
+            }
+        }
+
+        fn observe(&self, cell: usize) -> Observation {
+            let cell = &self.cells[cell];
+            Observation {
+                owner: cell.current_owner().map(canonical),
+                generation: cell.generation(),
+                last_owner: cell
+                    .last_owner()
+                    .map(|last| (canonical(last.thief), canonical_end(last.ended))),
+            }
+        }
+    }
+
+    /// Returns the thief of the model with the same name as `thief`, created by a real task.
+    fn canonical(thief: ThiefInfo) -> ThiefInfo {
+        thieves()
+            .into_iter()
+            .find(|candidate| candidate.same_name(&thief))
+            .expect("tasks are named after `thieves()`")
+    }
+
+    fn canonical_end(ended: TenureEnd) -> TenureEnd {
+        match ended {
+            TenureEnd::Preempted { by } => TenureEnd::Preempted { by: canonical(by) },
+            ended => ended,
+        }
+    }
+
+    fn policy() -> impl Strategy<Value = Policy> {
+        let preemption = prop_oneof![
+            Just(Preemption::Priority),
+            Just(Preemption::Always),
+            Just(Preemption::Never),
+        ];
+        (preemption, 0..3u8, proptest::option::of(1..4u32)).prop_map(
+            |(preemption, min_steal_priority, lease)| Policy {
+                preemption,
+                min_steal_priority,
+                lease,
+            },
+        )
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        (0..CELLS, 0..4usize, 0..6u8).prop_map(|(cell, thief, kind)| {
+            let thief = thieves()[thief];
+            match kind {
+                0 => Op::Acquire { cell, thief },
+                1 => Op::Steal { cell, thief },
+                2 => Op::Poll {
+                    cell,
+                    thief,
+                    renew: false,
+                },
+                3 => Op::Poll {
+                    cell,
+                    thief,
+                    renew: true,
+                },
+                4 => Op::Release { cell, thief },
+                _ => Op::Cancel { cell, thief },
+            }
+        })
+    }
+
+    /// Runs `ops` through the model and real cells following `policy`, failing at the first disagreement.
+    fn differential(policy: Policy, ops: &[Op]) -> Result<(), TestCaseError> {
+        let mut model = OwnershipModel::new(policy);
+        let mut real = System::new(policy);
+        for (step, &op) in ops.iter().enumerate() {
+            let (next, expected) = model.apply(op);
+            model = next;
+            prop_assert_eq!(real.apply(op), expected, "step {}: {:?}", step, op);
+            for cell in 0..CELLS {
+                prop_assert_eq!(
+                    real.observe(cell),
+                    model.observe(cell),
+                    "step {}, cell {}",
+                    step,
+                    cell
+                );
+            }
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(256))]
+
+        #[test]
+        fn real_cells_match_model(policy in policy(), ops in proptest::collection::vec(op(), 1..48)) {
+            differential(policy, &ops)?;
+        }
+    }
+
+    /// The four families of policies, each run with operation sequences proptest explores on its own
+    fn families() -> [Policy; 4] {
+        [
+            Policy::DEFAULT,
+            Policy {
+                min_steal_priority: 1,
+                ..Policy::DEFAULT
+            },
+            Policy {
+                lease: Some(2),
+                ..Policy::DEFAULT
+            },
+            Policy {
+                preemption: Preemption::Never,
+                ..Policy::DEFAULT
+            },
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn each_family_matches_model(family in 0..4usize, ops in proptest::collection::vec(op(), 1..48)) {
+            differential(families()[family], &ops)?;
+        }
+    }
+
+    #[test]
+    fn model_is_pure() {
+        let [low, .., high] = thieves();
+        let model = OwnershipModel::new(Policy::DEFAULT);
+        let (next, outcome) = model.apply(Op::Steal {
+            cell: 0,
+            thief: high,
+        });
+        assert_eq!(outcome, Outcome::Acquired);
+        assert_eq!(model.observe(0).owner, None);
+        assert_eq!(next.observe(0).owner, Some(high));
+
+        // a lower priority cannot preempt under the default policy
+        let (_, outcome) = next.apply(Op::Steal {
+            cell: 0,
+            thief: low,
+        });
+        assert_eq!(outcome, Outcome::Refused);
+    }
+}
@@ -0,0 +1,397 @@
+//! A [`RevocableCell`] accessed through lock guards, so a struct can be mutated with ordinary method calls.
+//!
+//! A [`RequirementMutex`] is listed as a requirement of a [`PreemptibleFuture`] like any other cell,
+//! either explicitly or by passing it to a `#[preemptible]` function.
+//! Inside that future, [`lock()`](RequirementMutex::lock) hands out a [`RequirementGuard`] that derefs to the data.
+//!
+//! The guard does not own the mutex, the enclosing future does.
+//! When another task steals the mutex, the enclosing future is cancelled at its next poll,
+//! so a guard held across an await is dropped with it instead of being used again.
+//! A guard that escaped its task panics if it is dereferenced after the mutex was stolen.
+
+use core::{
+    cell::Cell,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+use crate::{
+    clock::Clock,
+    guard::BorrowFlag,
+    history::StealRecord,
+    requirement::{
+        Claims, LastOwner, LastPreemption, Requirement, RequirementId, RequirementInfo,
+        RequirementInfoSource, RevocableCell, RevocableData, StealOutcome, TenureEnd,
+    },
+    thief::{PreemptibleFuture, ThiefInfo, owned_by_current},
+};
+
+/// A [`RevocableCell`] whose data is accessed through a [`RequirementGuard`], see the [module-level documentation](self)
+pub struct RequirementMutex<T> {
+    cell: RevocableCell<T>,
+    /// Generation of the cell when the live guard was created
+    locked: Cell<Option<u64>>,
+}
+
+impl<T> RequirementMutex<T> {
+    /// Creates a new [`RequirementMutex`] with ownership of `data`.
+    pub fn new(data: T, name: &'static str) -> Self {
+        Self {
+            cell: RevocableCell::new(data, name),
+            locked: Cell::new(None),
+        }
+    }
+
+    /// Timestamps ownership changes of this mutex using `clock`.
+    pub fn with_clock(self, clock: &'static dyn Clock) -> Self {
+        Self {
+            cell: self.cell.with_clock(clock),
+            ..self
+        }
+    }
+
+    /// Returns a mutable reference to the guarded value, which is safe since no task can hold the mutex.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.cell.get_mut()
+    }
+
+    /// Returns the accesses counted for this mutex, see [`RevocableCell::audit()`].
+    #[cfg(feature = "debug-audit")]
+    pub fn audit(&self) -> crate::audit::AccessAudit {
+        self.cell.audit()
+    }
+
+    /// Consumes the mutex, returning the guarded value.
+    pub fn into_inner(self) -> T {
+        self.cell.into_inner()
+    }
+
+    /// Returns a guard if the [`PreemptibleFuture`] being polled owns this mutex and no other guard is live.
+    pub fn try_lock(&self) -> Option<RequirementGuard<'_, T>> {
+        let generation = self.cell.generation();
+        if !owned_by_current(&self.cell) || self.locked.get() == Some(generation) {
+            return None;
+        }
+        self.locked.set(Some(generation));
+        Some(RequirementGuard {
+            mutex: self,
+            generation,
+            _not_send: PhantomData,
+        })
+    }
+
+    /// Waits until [`try_lock()`](Self::try_lock) succeeds.
+    ///
+    /// A future listing this mutex as a requirement owns it from its first poll, so this only waits for an earlier guard to be dropped.
+    /// Called from a task that does not list this mutex, it waits until that task is cancelled.
+    pub async fn lock(&self) -> RequirementGuard<'_, T> {
+        let notify = self.cell.ownership_notify();
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            notify.notified().await;
+        }
+    }
+
+    /// Same as [`RevocableCell::run`], but `func` receives a guard instead of a reference.
+    ///
+    /// # Errors
+    ///
+    /// If access to this mutex has been stolen by a different future, the future returns `Err<PreemptionError>` when next polled.
+    pub fn run<'a, Out>(
+        &'a self,
+        name: &'static str,
+        func: impl AsyncFnOnce(RequirementGuard<'a, T>) -> Out + 'a,
+    ) -> PreemptibleFuture<'a, impl Future<Output = Out>, Out, 1> {
+        let inner = async move { func(self.lock().await).await };
+        PreemptibleFuture::new(inner, name, [self])
+    }
+}
+
+impl<T> Requirement for RequirementMutex<T> {
+    fn steal_ownership(&self, thief: &ThiefInfo) {
+        self.cell.steal_ownership(thief);
+    }
+
+    fn try_steal_ownership(&self, thief: &ThiefInfo) -> StealOutcome {
+        self.cell.try_steal_ownership(thief)
+    }
+
+    fn claim_shared(&self, thief: &ThiefInfo) -> StealOutcome {
+        self.cell.claim_shared(thief)
+    }
+
+    fn release_shared(&self, generation: u64) {
+        self.cell.release_shared(generation);
+    }
+
+    fn release_ownership(&self) {
+        self.cell.release_ownership();
+    }
+
+    fn end_ownership(&self, ended: TenureEnd) {
+        self.cell.end_ownership(ended);
+    }
+
+    fn revoke(&self, by: &ThiefInfo) {
+        self.cell.revoke(by);
+    }
+
+    fn poison(&self) {
+        self.cell.poison();
+    }
+
+    fn is_owned_by(&self, thief: &ThiefInfo) -> bool {
+        self.cell.is_owned_by(thief)
+    }
+
+    fn mark_polled(&self) {
+        self.cell.mark_polled();
+    }
+
+    fn register_owner_waker(&self, waker: &core::task::Waker) {
+        self.cell.register_owner_waker(waker);
+    }
+
+    fn register_release_waker(&self, waker: &core::task::Waker) {
+        self.cell.register_release_waker(waker);
+    }
+
+    fn meta_any(&self) -> Option<&dyn core::any::Any> {
+        self.cell.meta_any()
+    }
+}
+
+impl<T> RequirementInfoSource for RequirementMutex<T> {
+    fn current_owner(&self) -> Option<ThiefInfo> {
+        self.cell.current_owner()
+    }
+
+    fn current_claims(&self) -> Claims {
+        self.cell.current_claims()
+    }
+
+    fn info(&self) -> RequirementInfo {
+        self.cell.info()
+    }
+
+    fn id(&self) -> RequirementId {
+        self.cell.id()
+    }
+
+    fn generation(&self) -> u64 {
+        self.cell.generation()
+    }
+
+    fn last_preemption(&self) -> Option<LastPreemption> {
+        self.cell.last_preemption()
+    }
+
+    fn last_owner(&self) -> Option<LastOwner> {
+        self.cell.last_owner()
+    }
+
+    fn last_steal(&self) -> Option<StealRecord> {
+        self.cell.last_steal()
+    }
+
+    fn yielded_by(&self) -> Option<ThiefInfo> {
+        self.cell.yielded_by()
+    }
+
+    fn is_poisoned(&self) -> bool {
+        self.cell.is_poisoned()
+    }
+
+    fn is_frozen(&self) -> bool {
+        self.cell.is_frozen()
+    }
+
+    fn min_steal_priority(&self) -> u8 {
+        self.cell.min_steal_priority()
+    }
+
+    fn may_steal(&self, incoming: &ThiefInfo) -> bool {
+        self.cell.may_steal(incoming)
+    }
+}
+
+impl<T> RevocableData<T> for RequirementMutex<T> {
+    unsafe fn data_ptr(&self) -> *mut T {
+        unsafe { self.cell.data_ptr() }
+    }
+
+    fn borrow_flag(&self) -> Option<&BorrowFlag> {
+        self.cell.borrow_flag()
+    }
+
+    fn mark_changed(&self) {
+        self.cell.mark_changed();
+    }
+
+    #[cfg(feature = "debug-audit")]
+    fn record_access(&self, thief: &ThiefInfo, write: bool) {
+        self.cell.record_access(thief, write);
+    }
+}
+
+/// Access to the data of a [`RequirementMutex`], created by [`RequirementMutex::lock()`]
+pub struct RequirementGuard<'a, T> {
+    mutex: &'a RequirementMutex<T>,
+    generation: u64,
+    // the ownership check relies on the tracking of the thread polling the owner
+    _not_send: PhantomData<*mut T>,
+}
+
+impl<T> RequirementGuard<'_, T> {
+    fn validate(&self) {
+        assert!(
+            self.mutex.cell.generation() == self.generation,
+            "{} was stolen while a guard of its previous owner was still in use",
+            self.mutex.cell.info(),
+        );
+    }
+
+    #[cfg(feature = "debug-audit")]
+    fn record_access(&self, write: bool) {
+        if let Some(owner) = self.mutex.cell.current_owner() {
+            self.mutex.cell.record_audit(&owner, write);
+        }
+    }
+}
+
+impl<T> Deref for RequirementGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.validate();
+        #[cfg(feature = "debug-audit")]
+        self.record_access(false);
+        // the owner of this generation created the only live guard
+        unsafe { &*self.mutex.cell.data_ptr() }
+    }
+}
+
+impl<T> DerefMut for RequirementGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.validate();
+        #[cfg(feature = "debug-audit")]
+        self.record_access(true);
+        unsafe { &mut *self.mutex.cell.data_ptr() }
+    }
+}
+
+impl<T> Drop for RequirementGuard<'_, T> {
+    fn drop(&mut self) {
+        // a guard of a newer owner may already be live
+        if self.mutex.locked.get() == Some(self.generation) {
+            self.mutex.locked.set(None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::{
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use futures_lite::future::yield_now;
+
+    use super::*;
+
+    struct Counter {
+        value: i32,
+    }
+
+    impl Counter {
+        fn increment(&mut self) {
+            self.value += 1;
+        }
+
+        fn decrement(&mut self) -> bool {
+            if self.value == 0 {
+                return false;
+            }
+            self.value -= 1;
+            true
+        }
+    }
+
+    #[test]
+    fn increment_decrement() {
+        let counter = RequirementMutex::new(Counter { value: 0 }, "counter");
+        let mut increment = pin!(counter.run("increment", async |mut counter| {
+            loop {
+                counter.increment();
+                yield_now().await;
+            }
+        }));
+        let mut decrement = pin!(PreemptibleFuture::new(
+            async {
+                let mut guard = counter.lock().await;
+                while guard.decrement() {
+                    yield_now().await;
+                }
+            },
+            "decrement",
+            [&counter],
+        ));
+        let mut cx = Context::from_waker(Waker::noop());
+
+        for _ in 0..3 {
+            assert!(increment.as_mut().poll(&mut cx).is_pending());
+        }
+        assert!(decrement.as_mut().poll(&mut cx).is_pending());
+        let Poll::Ready(Err(err)) = increment.as_mut().poll(&mut cx) else {
+            panic!("increment should be preempted by decrement");
+        };
+        assert_eq!(
+            err.incoming().map(|thief| thief.name.as_str()),
+            Some("decrement")
+        );
+
+        // increment was cancelled with its guard still alive, so decrement can keep locking
+        while decrement.as_mut().poll(&mut cx).is_pending() {}
+        assert!(counter.current_owner().is_none());
+    }
+
+    #[test]
+    fn try_lock_requires_ownership() {
+        let counter = RequirementMutex::new(Counter { value: 0 }, "counter");
+        assert!(counter.try_lock().is_none());
+
+        let mut task = pin!(PreemptibleFuture::new(
+            async {
+                let guard = counter.try_lock();
+                assert!(guard.is_some());
+                // only one guard can be live at a time
+                assert!(counter.try_lock().is_none());
+                drop(guard);
+                assert!(counter.try_lock().is_some());
+            },
+            "task",
+            [&counter],
+        ));
+        let mut cx = Context::from_waker(Waker::noop());
+        assert_eq!(task.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+    }
+
+    #[test]
+    #[should_panic(expected = "was stolen while a guard of its previous owner was still in use")]
+    fn escaped_guard_panics() {
+        let counter = RequirementMutex::new(Counter { value: 0 }, "counter");
+        let thief = ThiefInfo::new("thief");
+        let mut task = pin!(PreemptibleFuture::new(counter.lock(), "leaky", [&counter]));
+        let mut cx = Context::from_waker(Waker::noop());
+        let Poll::Ready(Ok(mut guard)) = task.as_mut().poll(&mut cx) else {
+            panic!("the mutex should be locked on the first poll");
+        };
+        counter.steal_ownership(&thief);
+        guard.increment();
+    }
+}
@@ -0,0 +1,241 @@
+//! Interned names of requirements and thieves.
+//!
+//! A [`Name`] is an index into a fixed-capacity static registry, so names compare and hash as integers
+//! and [`RequirementInfo`](crate::requirement::RequirementInfo) and [`ThiefInfo`](crate::thief::ThiefInfo) stay small enough to copy into every event and error.
+//! Registering the same string twice returns the same [`Name`], and [`Display`] resolves a name back to its string.
+//!
+//! Constructors throughout the crate accept any `impl Into<Name>`, so `&'static str` literals keep working.
+//! With the `alloc` feature, [`Name::from_string()`] also interns names built at runtime, which also makes `String`s accepted as names.
+//! Once the registry is full, new names resolve to [`Name::OVERFLOW`] instead of panicking, so cells and thieves can still be created.
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+use core::{
+    cell::UnsafeCell,
+    fmt::{Debug, Display},
+    hint::spin_loop,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+/// Maximum number of distinct names in the registry
+pub const NAME_CAPACITY: usize = 256;
+
+static NAMES: Registry<NAME_CAPACITY> = Registry::new();
+
+/// Error returned by [`try_register_name()`] when the registry already holds [`NAME_CAPACITY`] distinct names
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegistryFull;
+
+impl Display for RegistryFull {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "the name registry is full ({NAME_CAPACITY} names)")
+    }
+}
+
+/// An interned name, see the [module-level documentation](self)
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Name(u16);
+
+impl Name {
+    /// Name given to everything registered after the registry filled up, displayed as `<overflow>`
+    pub const OVERFLOW: Self = Self(u16::MAX);
+
+    /// Interns `name`, same as [`register_name()`].
+    pub fn new(name: &'static str) -> Self {
+        register_name(name)
+    }
+
+    /// Interns a name built at runtime, leaking it only if it was not registered before.
+    ///
+    /// Returns [`Name::OVERFLOW`] if the registry is full.
+    #[cfg(feature = "alloc")]
+    pub fn from_string(name: alloc::string::String) -> Self {
+        NAMES
+            .intern(&name, || {
+                alloc::boxed::Box::leak(name.clone().into_boxed_str())
+            })
+            .map_or(Self::OVERFLOW, Self)
+    }
+
+    /// Returns the string this name was registered with.
+    pub fn as_str(self) -> &'static str {
+        NAMES.get(self.0)
+    }
+
+    /// Returns the registry index of this name, for storing it in an atomic.
+    #[cfg(feature = "sync")]
+    pub(crate) fn to_raw(self) -> u16 {
+        self.0
+    }
+
+    /// Returns the name at a registry index returned by [`to_raw()`](Self::to_raw).
+    #[cfg(feature = "sync")]
+    pub(crate) fn from_raw(raw: u16) -> Self {
+        Self(raw)
+    }
+}
+
+/// Interns `name`, returning the existing [`Name`] if it was registered before.
+///
+/// Returns [`Name::OVERFLOW`] if `name` is new and the registry is full, see [`try_register_name()`].
+pub fn register_name(name: &'static str) -> Name {
+    try_register_name(name).unwrap_or(Name::OVERFLOW)
+}
+
+/// Same as [`register_name()`], returning an error instead of [`Name::OVERFLOW`] if the registry is full.
+///
+/// # Errors
+///
+/// Returns [`RegistryFull`] if `name` is new and the registry already holds [`NAME_CAPACITY`] names.
+pub fn try_register_name(name: &'static str) -> Result<Name, RegistryFull> {
+    NAMES.intern(name, || name).map(Name).ok_or(RegistryFull)
+}
+
+impl From<&'static str> for Name {
+    fn from(name: &'static str) -> Self {
+        register_name(name)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl From<alloc::string::String> for Name {
+    fn from(name: alloc::string::String) -> Self {
+        Self::from_string(name)
+    }
+}
+
+impl PartialEq<str> for Name {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Name {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl Display for Name {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Debug for Name {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+/// Append-only list of strings, readable without locking
+struct Registry<const N: usize> {
+    /// Taken while appending, so concurrent registrations of the same string agree on its index
+    lock: AtomicBool,
+    /// Number of published entries, which are never written again
+    len: AtomicUsize,
+    names: [UnsafeCell<&'static str>; N],
+}
+
+// entries below `len` are immutable, and entries at or above it are only written while holding `lock`
+unsafe impl<const N: usize> Sync for Registry<N> {}
+
+impl<const N: usize> Registry<N> {
+    const fn new() -> Self {
+        assert!(
+            N <= u16::MAX as usize,
+            "names are indexed by u16, below the overflow name"
+        );
+        Self {
+            lock: AtomicBool::new(false),
+            len: AtomicUsize::new(0),
+            names: [const { UnsafeCell::new("") }; N],
+        }
+    }
+
+    fn get(&self, index: u16) -> &'static str {
+        if index == Name::OVERFLOW.0 {
+            return "<overflow>";
+        }
+        debug_assert!((index as usize) < self.len.load(Ordering::Acquire));
+        // indices are only handed out once their entry is published
+        unsafe { *self.names[index as usize].get() }
+    }
+
+    fn find(&self, name: &str, len: usize) -> Option<u16> {
+        (0..len as u16).find(|&index| self.get(index) == name)
+    }
+
+    /// Returns the index of `name`, appending the string returned by `store` if it is new and there is room.
+    fn intern(&self, name: &str, store: impl FnOnce() -> &'static str) -> Option<u16> {
+        if let Some(index) = self.find(name, self.len.load(Ordering::Acquire)) {
+            return Some(index);
+        }
+        while self
+            .lock
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            spin_loop();
+        }
+        let len = self.len.load(Ordering::Relaxed);
+        let index = self.find(name, len).or_else(|| {
+            (len < N).then(|| {
+                // the entry is unpublished and the lock is held, so nothing else accesses it
+                unsafe { *self.names[len].get() = store() };
+                self.len.store(len + 1, Ordering::Release);
+                len as u16
+            })
+        });
+        self.lock.store(false, Ordering::Release);
+        index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::format;
+
+    use super::*;
+
+    #[test]
+    fn interned_duplicates() {
+        let a = register_name("interned_drive");
+        let b = Name::from("interned_drive");
+        let c = register_name("interned_arm");
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_eq!(a, "interned_drive");
+    }
+
+    #[test]
+    fn registry_capacity() {
+        let registry = Registry::<2>::new();
+        assert_eq!(registry.intern("a", || "a"), Some(0));
+        assert_eq!(registry.intern("b", || "b"), Some(1));
+        assert_eq!(registry.intern("c", || "c"), None);
+        // existing names are still found once the registry is full
+        assert_eq!(registry.intern("a", || "a"), Some(0));
+        assert_eq!(registry.get(1), "b");
+        assert_eq!(registry.get(Name::OVERFLOW.0), "<overflow>");
+    }
+
+    #[test]
+    fn display_round_trip() {
+        let name = register_name("wheel[0]");
+        assert_eq!(format!("{name}"), "wheel[0]");
+        assert_eq!(format!("{name:?}"), "\"wheel[0]\"");
+        assert_eq!(name.as_str(), "wheel[0]");
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn runtime_names() {
+        let dynamic = Name::from_string(format!("wheel_{}", 3));
+        assert_eq!(dynamic, register_name("wheel_3"));
+        assert_eq!(Name::from_string(format!("wheel_{}", 3)), dynamic);
+    }
+}
@@ -0,0 +1,396 @@
+//! Notifications of ownership changes, for tasks that re-plan whenever a requirement changes hands.
+//!
+//! Cells also keep a list of tasks waiting for them to be released, used by [`RevocableCell::run_when_free()`] and [`RevocableCell::register_release_waker()`],
+//! and of tasks waiting for their data to change, used by [`RevocableCell::changed()`].
+//! Without the `alloc` feature the list holds at most [`WAITER_CAPACITY`] tasks,
+//! and further waiters are woken immediately so they poll again instead of being forgotten.
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+use core::{
+    any::Any,
+    cell::Cell,
+    future::poll_fn,
+    task::{Poll, Waker},
+};
+
+use crate::requirement::RevocableCell;
+
+/// Per-cell state backing [`OwnershipNotify`]: a transition counter and a single waiting waker
+#[derive(Default)]
+pub(crate) struct NotifySlot {
+    transitions: Cell<u64>,
+    waker: Cell<Option<Waker>>,
+}
+
+impl NotifySlot {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records an ownership transition, waking the waiting task if there is one.
+    pub(crate) fn transition(&self) {
+        self.transitions.set(self.transitions.get().wrapping_add(1));
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Wakes `waker` at the next transition, replacing the waiting task if there is one.
+    pub(crate) fn register(&self, waker: &Waker) {
+        self.waker.set(Some(waker.clone()));
+    }
+}
+
+/// Maximum number of tasks waiting for the release of one cell without the `alloc` feature
+pub const WAITER_CAPACITY: usize = 4;
+
+/// Per-cell list of tasks to wake when the cell is released
+#[derive(Default)]
+pub(crate) struct WaiterList {
+    #[cfg(feature = "alloc")]
+    wakers: Cell<alloc::vec::Vec<Waker>>,
+    #[cfg(not(feature = "alloc"))]
+    wakers: [Cell<Option<Waker>>; WAITER_CAPACITY],
+}
+
+impl WaiterList {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wakes `waker` at the next release, unless it is already waiting.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn register(&self, waker: &Waker) {
+        let mut wakers = self.wakers.take();
+        if !wakers.iter().any(|waiting| waiting.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+        self.wakers.set(wakers);
+    }
+
+    /// Wakes `waker` at the next release, unless it is already waiting, or right away if the list is full.
+    #[cfg(not(feature = "alloc"))]
+    pub(crate) fn register(&self, waker: &Waker) {
+        let mut free = None;
+        for slot in &self.wakers {
+            match slot.take() {
+                Some(waiting) if waiting.will_wake(waker) => return slot.set(Some(waiting)),
+                Some(waiting) => slot.set(Some(waiting)),
+                None => free = free.or(Some(slot)),
+            }
+        }
+        match free {
+            Some(slot) => slot.set(Some(waker.clone())),
+            None => waker.wake_by_ref(),
+        }
+    }
+
+    /// Wakes and forgets every waiting task.
+    pub(crate) fn wake_all(&self) {
+        #[cfg(feature = "alloc")]
+        self.wakers.take().into_iter().for_each(Waker::wake);
+        #[cfg(not(feature = "alloc"))]
+        self.wakers
+            .iter()
+            .filter_map(Cell::take)
+            .for_each(Waker::wake);
+    }
+}
+
+/// Waits for ownership transitions of a [`RevocableCell`], created by [`RevocableCell::ownership_notify()`].
+///
+/// Transitions are acquiring, stealing, and releasing the cell.
+/// Only one task can wait at a time; a newer waiter replaces the waker of an older one.
+#[derive(Clone, Copy)]
+pub struct OwnershipNotify<'a> {
+    slot: &'a NotifySlot,
+}
+
+impl OwnershipNotify<'_> {
+    /// Completes at the first ownership transition after this call.
+    ///
+    /// The transition count is captured when `notified` is called rather than when the future is first polled,
+    /// so a transition in between is not missed.
+    pub fn notified(&self) -> impl Future<Output = ()> + '_ {
+        let seen = self.slot.transitions.get();
+        poll_fn(move |cx| {
+            if self.slot.transitions.get() != seen {
+                return Poll::Ready(());
+            }
+            self.slot.register(cx.waker());
+            Poll::Pending
+        })
+    }
+}
+
+/// Number of completed mutable accesses to a cell's data, returned by [`RevocableCell::version()`]
+///
+/// Requirements without data of their own report the default version, zero.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version(pub(crate) u64);
+
+impl Version {
+    /// Returns the number of completed mutable accesses.
+    pub fn get(self) -> u64 {
+        self.0
+    }
+}
+
+impl<T: ?Sized, M: Any, const H: usize> RevocableCell<T, M, H> {
+    /// Returns the current version of this cell's data.
+    ///
+    /// The version advances whenever a [`RevocableGuard`](crate::guard::RevocableGuard) that handed out mutable access is dropped,
+    /// which includes every [`run()`](Self::run) future once its body finishes or is cancelled,
+    /// and whenever [`try_replace()`](Self::try_replace) replaces the value.
+    /// A future stolen before its body first ran never handed out access, so it leaves the version unchanged.
+    pub fn version(&self) -> Version {
+        Version(self.raw_version())
+    }
+
+    /// Completes with the new version once this cell's data has changed since `since`.
+    ///
+    /// Observing never claims the cell, so the observer is never preempted and never preempts the owner.
+    pub fn changed(&self, since: Version) -> impl Future<Output = Version> + '_ {
+        poll_fn(move |cx| {
+            let version = self.version();
+            if version != since {
+                return Poll::Ready(version);
+            }
+            self.observers().register(cx.waker());
+            Poll::Pending
+        })
+    }
+
+    /// Returns `true` if the data changed since the last call, and clears the flag.
+    ///
+    /// The flag is set by the same accesses that advance the [`version()`](Self::version),
+    /// but it is consumed by whoever reads it, so a default task can ask whether anything wrote the data since it last ran,
+    /// without remembering a version of its own.
+    pub fn take_dirty(&self) -> bool {
+        self.dirty_flag().replace(false)
+    }
+
+    /// Wakes `waker` once, the next time the owner of this cell releases it, completes, or is dropped.
+    ///
+    /// Steals do not wake it, since the cell stays owned, and registering the same waker twice before a release wakes it once.
+    /// This is the building block of [`run_when_free()`](Self::run_when_free), for schedulers waiting on cells without busy polling.
+    pub fn register_release_waker(&self, waker: &Waker) {
+        self.waiters().register(waker);
+    }
+
+    /// Returns a handle for waiting on this cell's ownership transitions.
+    pub fn ownership_notify(&self) -> OwnershipNotify<'_> {
+        OwnershipNotify {
+            slot: self.notify_slot(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::{
+        pin::pin,
+        task::{Context, Waker},
+    };
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        task::Wake,
+    };
+
+    use super::*;
+    use crate::{
+        requirement::{Requirement, RequirementInfoSource},
+        thief::{PreemptibleFuture, ThiefInfo},
+    };
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn changed_once() {
+        let cell = RevocableCell::new(0, "drive");
+        let wakes = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(wakes.clone());
+        let mut observer_cx = Context::from_waker(&waker);
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let seen = cell.version();
+        let mut changed = pin!(cell.changed(seen));
+        assert!(changed.as_mut().poll(&mut observer_cx).is_pending());
+
+        // claiming the cell is not a change, only finishing a mutable access is
+        let mut writer = pin!(cell.run("writer", async |x| {
+            *x += 1;
+            futures_lite::future::yield_now().await;
+        }));
+        assert!(writer.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(wakes.0.load(Ordering::Relaxed), 0);
+        assert!(writer.as_mut().poll(&mut cx).is_ready());
+        assert_eq!(wakes.0.load(Ordering::Relaxed), 1);
+        assert_eq!(cell.current_owner(), None);
+
+        let Poll::Ready(version) = changed.as_mut().poll(&mut observer_cx) else {
+            panic!("the observer should see the write");
+        };
+        assert!(version > seen);
+        assert_eq!(version, cell.version());
+        assert!(
+            pin!(cell.changed(version))
+                .poll(&mut observer_cx)
+                .is_pending()
+        );
+    }
+
+    #[test]
+    fn dirty_between_tasks() {
+        let setpoint = RevocableCell::new(0, "setpoint");
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(!setpoint.take_dirty());
+
+        // the default task only reads the flag, so it does not dirty the setpoint itself
+        let mut holds = std::vec::Vec::new();
+        let mut check = || holds.push(setpoint.take_dirty());
+
+        check();
+        let mut command = pin!(setpoint.run("command", async |x| *x = 5));
+        assert!(command.as_mut().poll(&mut cx).is_ready());
+        check();
+        check();
+
+        // a write that started before the check is seen by the next one
+        let mut slow = pin!(setpoint.run("slow", async |x| {
+            *x = 3;
+            futures_lite::future::yield_now().await;
+        }));
+        assert!(slow.as_mut().poll(&mut cx).is_pending());
+        check();
+        assert!(slow.as_mut().poll(&mut cx).is_ready());
+        check();
+        assert_eq!(holds, [false, true, false, false, true]);
+    }
+
+    #[test]
+    fn release_wakes_once() {
+        let cell = RevocableCell::new(0, "drive");
+        let wakes = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(wakes.clone());
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut owner = pin!(PreemptibleFuture::new(
+            futures_lite::future::pending::<()>(),
+            "owner",
+            [&cell]
+        ));
+        assert!(owner.as_mut().poll(&mut cx).is_pending());
+        cell.register_release_waker(&waker);
+        cell.register_release_waker(&waker);
+
+        // a steal hands the cell over without freeing it
+        let mut stealer = pin!(cell.run("stealer", async |x| {
+            *x = 1;
+            futures_lite::future::yield_now().await;
+        }));
+        assert!(stealer.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(wakes.0.load(Ordering::Relaxed), 0);
+
+        assert!(stealer.as_mut().poll(&mut cx).is_ready());
+        assert_eq!(wakes.0.load(Ordering::Relaxed), 1);
+
+        // the waker was forgotten, so later releases need a new registration
+        let writer = ThiefInfo::new("writer");
+        cell.steal_ownership(&writer);
+        cell.release_ownership();
+        assert_eq!(wakes.0.load(Ordering::Relaxed), 1);
+        cell.register_release_waker(&waker);
+        cell.steal_ownership(&writer);
+        cell.release_ownership();
+        assert_eq!(wakes.0.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn version_at_steal() {
+        let cell = RevocableCell::new(0, "drive");
+        let mut cx = Context::from_waker(Waker::noop());
+        assert_eq!(cell.try_set(1), Ok(()));
+        let seen = cell.version();
+        assert_eq!(seen.get(), 1);
+
+        // a task preempted without touching the data leaves the version alone
+        let mut idle = pin!(PreemptibleFuture::new(
+            futures_lite::future::pending::<()>(),
+            "idle",
+            [&cell]
+        ));
+        assert!(idle.as_mut().poll(&mut cx).is_pending());
+        let mut writer = pin!(cell.run("writer", async |x| *x += 1));
+        assert_eq!(writer.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(cell.version().get(), 2);
+
+        // the error reports the version the cell was stolen at, not the thief's write
+        let Poll::Ready(Err(err)) = idle.as_mut().poll(&mut cx) else {
+            panic!("idle should be preempted by writer");
+        };
+        assert_eq!(err.requirement().version, seen);
+        assert_eq!(cell.version().get(), 2);
+    }
+
+    #[test]
+    fn notified_after_call() {
+        let cell = RevocableCell::new(0, "drive");
+        let thief = ThiefInfo::new("thief");
+        let notify = cell.ownership_notify();
+        let mut cx = Context::from_waker(Waker::noop());
+
+        // a transition before the call is not observed
+        cell.steal_ownership(&thief);
+        let mut late = pin!(notify.notified());
+        assert!(late.as_mut().poll(&mut cx).is_pending());
+
+        // a transition between the call and the first poll is not missed
+        let mut early = pin!(notify.notified());
+        cell.release_ownership();
+        assert!(early.as_mut().poll(&mut cx).is_ready());
+        assert!(late.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn sequential_notifications() {
+        let cell = RevocableCell::new(0, "drive");
+        let thief = ThiefInfo::new("thief");
+        let notify = cell.ownership_notify();
+        let wakes = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(wakes.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        for i in 1..=3 {
+            let mut notified = pin!(notify.notified());
+            assert!(notified.as_mut().poll(&mut cx).is_pending());
+            if i % 2 == 1 {
+                cell.steal_ownership(&thief);
+            } else {
+                cell.release_ownership();
+            }
+            assert_eq!(wakes.0.load(Ordering::Relaxed), i);
+            assert!(notified.as_mut().poll(&mut cx).is_ready());
+        }
+
+        // releasing an already unowned cell is not a transition
+        cell.release_ownership();
+        let mut notified = pin!(notify.notified());
+        assert!(notified.as_mut().poll(&mut cx).is_pending());
+        cell.release_ownership();
+        assert!(notified.as_mut().poll(&mut cx).is_pending());
+    }
+}
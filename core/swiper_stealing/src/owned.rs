@@ -0,0 +1,171 @@
+//! Preemptible futures owning their requirements, for executors that only accept `'static` futures.
+//!
+//! A [`PreemptibleFuture`] borrows its requirements, so it cannot outlive the cells on the stack.
+//! An [`OwnedPreemptibleFuture`] instead holds an [`Rc`] of each requirement and runs an ordinary [`PreemptibleFuture`] over them,
+//! so preemption works exactly the same and the future is `'static` whenever its inner future is.
+//! `Rc`, `Box` and references of requirements are requirements themselves, so the same cell can be listed through a handle by borrowed futures too.
+
+extern crate alloc;
+
+use alloc::rc::Rc;
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{
+    Result,
+    name::Name,
+    requirement::{HasRequirements, Requirement},
+    thief::{PreemptibleFuture, ThiefInfo},
+};
+
+/// A [`PreemptibleFuture`] holding [`Rc`] handles of its requirements, see the [module-level documentation](self)
+pub struct OwnedPreemptibleFuture<Fut, Output, R, const N: usize>
+where
+    Fut: Future<Output = Output>,
+{
+    // declared before the handles so it releases its claims, then is dropped, while they are still alive
+    future: PreemptibleFuture<'static, Fut, Output, N>,
+    handles: [Rc<R>; N],
+}
+
+impl<Fut, Output, R, const N: usize> OwnedPreemptibleFuture<Fut, Output, R, N>
+where
+    Fut: Future<Output = Output>,
+    R: Requirement + 'static,
+{
+    /// Same as [`PreemptibleFuture::new()`], keeping `handles` alive for as long as the future exists.
+    pub fn new(inner: Fut, name: impl Into<Name>, handles: [Rc<R>; N]) -> Self {
+        // the handles are stored next to the future, which is dropped first,
+        // and an `Rc` never moves its requirement, so the references stay valid for the whole life of the future
+        let requirements = handles
+            .each_ref()
+            .map(|handle| unsafe { &*Rc::as_ptr(handle) } as &'static dyn Requirement);
+        Self {
+            future: PreemptibleFuture::new(inner, name, requirements),
+            handles,
+        }
+    }
+}
+
+impl<Fut, Output, R, const N: usize> OwnedPreemptibleFuture<Fut, Output, R, N>
+where
+    Fut: Future<Output = Output>,
+{
+    /// Returns the metadata of the future's thief.
+    pub fn info(&self) -> ThiefInfo {
+        self.future.info
+    }
+
+    /// Returns the handles of the requirements this future steals when first polled.
+    pub fn handles(&self) -> &[Rc<R>; N] {
+        &self.handles
+    }
+}
+
+impl<Fut, Output, R, const N: usize> Future for OwnedPreemptibleFuture<Fut, Output, R, N>
+where
+    Fut: Future<Output = Output>,
+{
+    type Output = Result<Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // the inner future is pinned along with the wrapper, and the handles are never pinned
+        unsafe { self.map_unchecked_mut(|this| &mut this.future) }.poll(cx)
+    }
+}
+
+impl<Fut, Output, R, const N: usize> HasRequirements for OwnedPreemptibleFuture<Fut, Output, R, N>
+where
+    Fut: Future<Output = Output>,
+{
+    fn visit_requirements(&self, visit: &mut dyn FnMut(&dyn Requirement)) {
+        self.future.visit_requirements(visit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, vec::Vec};
+    use core::{cell::RefCell, task::Waker};
+
+    use futures_lite::future::yield_now;
+
+    use super::*;
+    use crate::requirement::{RequirementInfoSource, RevocableCell};
+
+    /// Polls spawned `'static` tasks in turn until all of them are done.
+    #[derive(Default)]
+    struct LocalExecutor {
+        tasks: Vec<Pin<Box<dyn Future<Output = ()>>>>,
+    }
+
+    impl LocalExecutor {
+        fn spawn(&mut self, task: impl Future<Output = ()> + 'static) {
+            self.tasks.push(Box::pin(task));
+        }
+
+        fn run(&mut self) {
+            let mut cx = Context::from_waker(Waker::noop());
+            while !self.tasks.is_empty() {
+                self.tasks
+                    .retain_mut(|task| task.as_mut().poll(&mut cx).is_pending());
+            }
+        }
+    }
+
+    #[test]
+    fn spawned_tasks_preempt() {
+        let arm = Rc::new(RevocableCell::new(0, "arm"));
+        let results = Rc::new(RefCell::new(Vec::new()));
+        let mut executor = LocalExecutor::default();
+
+        for (name, polls) in [("raise", 3), ("stow", 1)] {
+            let cell = arm.clone();
+            let task = OwnedPreemptibleFuture::new(
+                async move {
+                    for _ in 0..polls {
+                        yield_now().await;
+                    }
+                    // the owner owns the cell while its inner future is polled
+                    assert!(cell.current_owner().is_some_and(|owner| owner.name == name));
+                },
+                name,
+                [arm.clone()],
+            );
+            let results = results.clone();
+            executor.spawn(async move {
+                let completed = task.await.is_ok();
+                results.borrow_mut().push((name, completed));
+            });
+        }
+        executor.run();
+
+        // stow was spawned last, so it stole the arm from raise on its first poll
+        assert_eq!(*results.borrow(), [("raise", false), ("stow", true)]);
+        assert!(arm.current_owner().is_none());
+        assert_eq!(Rc::strong_count(&arm), 1);
+    }
+
+    #[test]
+    fn handles_are_requirements() {
+        let arm = Rc::new(RevocableCell::new(0, "arm"));
+        let wrist = Box::new(RevocableCell::new(0, "wrist"));
+        let elbow = RevocableCell::new(0, "elbow");
+        let thief = ThiefInfo::new("thief");
+
+        // each handle delegates to its cell
+        let handles: [&dyn Requirement; 3] = [&arm, &wrist, &&elbow];
+        for handle in handles {
+            handle.steal_ownership(&thief);
+        }
+        for cell in [&*arm, &*wrist, &elbow] {
+            assert!(
+                cell.current_owner()
+                    .is_some_and(|owner| owner.same_thief(&thief))
+            );
+        }
+        assert_eq!(handles[0].info(), RequirementInfoSource::info(&*arm));
+    }
+}
@@ -0,0 +1,114 @@
+//! Rules deciding whether a task may preempt the current owner of a cell, chosen per cell.
+//!
+//! Before stealing anything, a [`PreemptibleFuture`](crate::thief::PreemptibleFuture) asks every requirement
+//! whether it [may steal](crate::requirement::RequirementInfoSource::may_steal) it from its owner.
+//! If one refuses, the future steals none of them and resolves on its first poll to a [`PreemptionError`](crate::PreemptionError)
+//! whose [`refused()`](crate::PreemptionError::refused) is set, naming the owner, which keeps running.
+//! Direct steals of a refusing cell return [`StealOutcome::Refused`](crate::requirement::StealOutcome::Refused) with the owner.
+//!
+//! Cells start with [`PriorityPolicy`], and [`RevocableCell::set_policy()`] changes it at any time.
+//! [`AlwaysSteal`] lets the newest task win regardless of priority, while [`NeverSteal`] lets the first claimant keep the cell until it finishes.
+
+use core::any::Any;
+
+use crate::{requirement::RevocableCell, thief::ThiefInfo};
+
+/// Decides whether a task may preempt the owner of a cell, see the [module-level documentation](self)
+pub trait PreemptionPolicy {
+    /// Returns `true` if `incoming` may preempt `outgoing`, the current owner.
+    fn may_steal(&self, incoming: &ThiefInfo, outgoing: &ThiefInfo) -> bool;
+}
+
+/// A policy where the newest task always wins, regardless of priority
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AlwaysSteal;
+
+impl PreemptionPolicy for AlwaysSteal {
+    fn may_steal(&self, _incoming: &ThiefInfo, _outgoing: &ThiefInfo) -> bool {
+        true
+    }
+}
+
+/// A policy where the first task to claim a cell keeps it until it finishes
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NeverSteal;
+
+impl PreemptionPolicy for NeverSteal {
+    fn may_steal(&self, _incoming: &ThiefInfo, _outgoing: &ThiefInfo) -> bool {
+        false
+    }
+}
+
+/// A policy where tasks preempt owners of equal or lower [priority](ThiefInfo::priority), used by default
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PriorityPolicy;
+
+impl PreemptionPolicy for PriorityPolicy {
+    fn may_steal(&self, incoming: &ThiefInfo, outgoing: &ThiefInfo) -> bool {
+        incoming.priority >= outgoing.priority
+    }
+}
+
+impl<T: ?Sized, M: Any, const H: usize> RevocableCell<T, M, H> {
+    /// Replaces the policy deciding whether tasks may preempt the owner of this cell, see the [module-level documentation](self).
+    pub fn set_policy(&self, policy: &'static dyn PreemptionPolicy) {
+        self.policy_cell().set(policy);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::task::{Context, Poll, Waker};
+    use std::boxed::Box;
+
+    use futures_lite::future::pending;
+
+    use super::*;
+    use crate::{
+        requirement::{Requirement, RequirementInfoSource, StealOutcome},
+        thief::PreemptibleFuture,
+    };
+
+    #[test]
+    fn policies_on_one_cell() {
+        let arm = RevocableCell::new(0, "arm");
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut routine =
+            Box::pin(PreemptibleFuture::new(pending::<()>(), "routine", [&arm]).with_priority(2));
+        assert!(routine.as_mut().poll(&mut cx).is_pending());
+
+        // by default, priority decides
+        let mut telemetry = Box::pin(PreemptibleFuture::new(async {}, "telemetry", [&arm]));
+        let Poll::Ready(Err(err)) = telemetry.as_mut().poll(&mut cx) else {
+            panic!("telemetry should be refused the arm");
+        };
+        assert!(err.refused());
+        assert_eq!(err.outgoing().name, "telemetry");
+        assert_eq!(
+            err.incoming().map(|thief| thief.name.as_str()),
+            Some("routine")
+        );
+
+        // under first claimant wins, even a higher priority is refused, directly or through a future
+        arm.set_policy(&NeverSteal);
+        let operator = ThiefInfo::new("operator").with_priority(5);
+        assert!(matches!(
+            arm.try_steal_ownership(&operator),
+            StealOutcome::Refused { owner: Some(owner) } if owner.name == "routine"
+        ));
+        let mut forced =
+            Box::pin(PreemptibleFuture::new(async {}, "override", [&arm]).with_priority(5));
+        assert!(matches!(forced.as_mut().poll(&mut cx), Poll::Ready(Err(err)) if err.refused()));
+        assert!(routine.as_mut().poll(&mut cx).is_pending());
+
+        // under newest wins, priority is ignored
+        arm.set_policy(&AlwaysSteal);
+        let mut telemetry = Box::pin(PreemptibleFuture::new(async {}, "telemetry", [&arm]));
+        assert_eq!(telemetry.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(matches!(routine.as_mut().poll(&mut cx), Poll::Ready(Err(err)) if !err.refused()));
+        assert!(arm.current_owner().is_none());
+    }
+}
@@ -0,0 +1,296 @@
+//! A fixed set of interchangeable cells, for tasks that need any one of several identical resources.
+//!
+//! Each slot of a [`RevocablePool`] is an ordinary [`RevocableCell`] named `base[index]`,
+//! so a task holding slot 2 only conflicts with other users of slot 2.
+//! [`acquire_any()`](RevocablePool::acquire_any) hands out the first unowned slot as a [`PooledGuard`],
+//! waiting for a slot to be released when all of them are owned.
+//! Pools created with [`steal_oldest()`](RevocablePool::steal_oldest) instead steal the slot that was acquired least recently.
+
+use core::{
+    array,
+    cell::Cell,
+    future::poll_fn,
+    ops::{Deref, DerefMut},
+    task::Poll,
+};
+
+use crate::{
+    name::Name,
+    requirement::{Requirement, RequirementInfoSource, RevocableCell, StealOutcome},
+    thief::ThiefInfo,
+};
+
+/// `N` cells handed out to whichever task asks first, see the [module-level documentation](self)
+pub struct RevocablePool<T, const N: usize> {
+    cells: [RevocableCell<T>; N],
+    /// Owner of each slot acquired through the pool
+    thieves: [Cell<ThiefInfo>; N],
+    /// Value of `acquisitions` when each slot was last acquired through the pool
+    acquired: [Cell<u64>; N],
+    acquisitions: Cell<u64>,
+    steal_oldest: bool,
+}
+
+impl<T, const N: usize> RevocablePool<T, N> {
+    /// Creates a pool of cells named `base[0]` to `base[N - 1]`, with data produced by `init` for each index.
+    pub fn new(init: impl FnMut(usize) -> T, base: impl Into<Name>) -> Self {
+        let base = base.into();
+        Self {
+            cells: RevocableCell::array(init, base),
+            thieves: array::from_fn(|_| Cell::new(ThiefInfo::new(base))),
+            acquired: array::from_fn(|_| Cell::new(0)),
+            acquisitions: Cell::new(0),
+            steal_oldest: false,
+        }
+    }
+
+    /// Makes [`acquire_any()`](Self::acquire_any) steal the least recently acquired slot instead of waiting when all slots are owned.
+    pub fn steal_oldest(self) -> Self {
+        Self {
+            steal_oldest: true,
+            ..self
+        }
+    }
+
+    /// Returns the cell of slot `index`, for tasks that need one slot in particular.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not less than `N`.
+    pub fn slot(&self, index: usize) -> &RevocableCell<T> {
+        &self.cells[index]
+    }
+
+    /// Returns the cells of every slot.
+    pub fn slots(&self) -> &[RevocableCell<T>; N] {
+        &self.cells
+    }
+
+    /// Returns the number of slots that currently have no owner.
+    pub fn available(&self) -> usize {
+        self.cells
+            .iter()
+            .filter(|cell| cell.current_owner().is_none())
+            .count()
+    }
+
+    /// Acquires the first unowned slot for `name`, or returns `None` if all slots are owned or the slot refuses the steal.
+    ///
+    /// Pools created with [`steal_oldest()`](Self::steal_oldest) only return `None` if the oldest slot refuses the steal.
+    pub fn try_acquire_any(&self, name: &'static str) -> Option<PooledGuard<'_, T, N>> {
+        let free = self
+            .cells
+            .iter()
+            .position(|cell| cell.current_owner().is_none());
+        let index = match free {
+            Some(index) => index,
+            None if self.steal_oldest => (0..N).min_by_key(|&index| self.acquired[index].get())?,
+            None => return None,
+        };
+        self.acquire(index, name)
+    }
+
+    /// Acquires the first unowned slot for `name`, waiting for a slot to be released if all of them are owned.
+    ///
+    /// Only one task can wait at a time, as with [`OwnershipNotify`](crate::notify::OwnershipNotify).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pool has no slots.
+    pub async fn acquire_any(&self, name: &'static str) -> PooledGuard<'_, T, N> {
+        assert!(N > 0, "cannot acquire from an empty pool");
+        poll_fn(|cx| match self.try_acquire_any(name) {
+            Some(guard) => Poll::Ready(guard),
+            None => {
+                for cell in &self.cells {
+                    cell.notify_slot().register(cx.waker());
+                }
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    fn acquire(&self, index: usize, name: &'static str) -> Option<PooledGuard<'_, T, N>> {
+        let cell = &self.cells[index];
+        let thief = ThiefInfo::new(name);
+        if let StealOutcome::Refused { .. } = cell.try_steal_ownership(&thief) {
+            return None;
+        }
+        self.thieves[index].set(thief);
+        self.acquisitions.set(self.acquisitions.get() + 1);
+        self.acquired[index].set(self.acquisitions.get());
+        Some(PooledGuard {
+            pool: self,
+            index,
+            generation: cell.generation(),
+        })
+    }
+}
+
+/// Ownership of one slot of a [`RevocablePool`], created by [`RevocablePool::acquire_any()`]
+///
+/// The slot is released when the guard is dropped, unless it was stolen in the meantime.
+pub struct PooledGuard<'a, T, const N: usize> {
+    pool: &'a RevocablePool<T, N>,
+    index: usize,
+    generation: u64,
+}
+
+impl<T, const N: usize> PooledGuard<'_, T, N> {
+    /// Returns the index of the acquired slot.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the cell of the acquired slot.
+    pub fn cell(&self) -> &RevocableCell<T> {
+        &self.pool.cells[self.index]
+    }
+
+    /// Returns `true` if the slot has not been stolen since it was acquired.
+    pub fn is_valid(&self) -> bool {
+        self.cell().generation() == self.generation
+    }
+
+    fn validate(&self) {
+        assert!(
+            self.is_valid(),
+            "{} was stolen while {} still held it",
+            self.cell().info(),
+            self.pool.thieves[self.index].get(),
+        );
+    }
+}
+
+impl<T, const N: usize> Deref for PooledGuard<'_, T, N> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.validate();
+        // the guard's thief owns the slot, and each acquisition creates one guard
+        unsafe { &*self.cell().data_ptr() }
+    }
+}
+
+impl<T, const N: usize> DerefMut for PooledGuard<'_, T, N> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.validate();
+        unsafe { &mut *self.cell().data_ptr() }
+    }
+}
+
+impl<T, const N: usize> Drop for PooledGuard<'_, T, N> {
+    fn drop(&mut self) {
+        // a task that stole the slot keeps it
+        if self.is_valid() {
+            self.cell().release_ownership();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::{
+        pin::pin,
+        task::{Context, Waker},
+    };
+
+    use futures_lite::future::yield_now;
+
+    use super::*;
+
+    #[test]
+    fn third_task_waits() {
+        let pool = RevocablePool::<u32, 2>::new(|_| 0, "dma");
+        let transfer = async |name, chunks| {
+            let mut channel = pool.acquire_any(name).await;
+            for _ in 0..chunks {
+                *channel += 1;
+                yield_now().await;
+            }
+            channel.index()
+        };
+        let mut a = pin!(transfer("a", 1));
+        let mut b = pin!(transfer("b", 3));
+        let mut c = pin!(transfer("c", 2));
+        let mut cx = Context::from_waker(Waker::noop());
+
+        assert!(a.as_mut().poll(&mut cx).is_pending());
+        assert!(b.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(pool.available(), 0);
+        assert!(c.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(
+            pool.slot(0)
+                .current_owner()
+                .map(|thief| thief.name.as_str()),
+            Some("a")
+        );
+        assert_eq!(
+            pool.slot(1)
+                .current_owner()
+                .map(|thief| thief.name.as_str()),
+            Some("b")
+        );
+
+        // a frees slot 0, which c acquires while b still holds slot 1
+        assert_eq!(a.as_mut().poll(&mut cx), Poll::Ready(0));
+        assert!(c.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(
+            pool.slot(0)
+                .current_owner()
+                .map(|thief| thief.name.as_str()),
+            Some("c")
+        );
+        assert!(b.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(
+            pool.slot(1)
+                .current_owner()
+                .map(|thief| thief.name.as_str()),
+            Some("b")
+        );
+
+        while c.as_mut().poll(&mut cx).is_pending() {}
+        while b.as_mut().poll(&mut cx).is_pending() {}
+        assert_eq!(pool.available(), 2);
+        assert_eq!((pool.slot(0).peek(), pool.slot(1).peek()), (3, 3));
+    }
+
+    #[test]
+    fn steal_oldest_slot() {
+        let pool = RevocablePool::<u32, 2>::new(|index| index as u32, "dma").steal_oldest();
+        let a = pool.try_acquire_any("a").unwrap();
+        let b = pool.try_acquire_any("b").unwrap();
+        let c = pool.try_acquire_any("c").unwrap();
+        assert_eq!(c.index(), a.index());
+        assert!(!a.is_valid());
+        assert!(b.is_valid());
+
+        // dropping the stolen guard leaves the slot with its thief
+        drop(a);
+        assert_eq!(
+            pool.slot(0)
+                .current_owner()
+                .map(|thief| thief.name.as_str()),
+            Some("c")
+        );
+        assert_eq!(*c, 0);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Requirement { name: dma[0], type: u32 }  was stolen while Thief { name: b }  still held it"
+    )]
+    fn slot_stolen_by_task() {
+        let pool = RevocablePool::<u32, 1>::new(|_| 0, "dma");
+        let mut cx = Context::from_waker(Waker::noop());
+        let Poll::Ready(mut guard) = pin!(pool.acquire_any("b")).poll(&mut cx) else {
+            panic!("the pool has a free slot");
+        };
+        let mut task = pin!(pool.slot(0).run("flush", async |x| *x = 0));
+        assert_eq!(task.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        *guard += 1;
+    }
+}
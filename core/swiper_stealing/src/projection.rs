@@ -0,0 +1,574 @@
+//! Requirements on parts of a [`RevocableCell`], so tasks using disjoint fields of one struct do not preempt each other.
+//!
+//! A [`ProjectedCell`] is created by [`RevocableCell::project()`] and has its own owner,
+//! so claiming one projection never preempts the owners of other projections.
+//! Projections still overlap their parent cell:
+//! claiming a projection preempts a task owning the whole cell, and stealing the whole cell revokes every projection.
+//! While a projection is revoked, its [`current_owner()`](RequirementInfoSource::current_owner) is the owner of the whole cell.
+//!
+//! A [`RevocableSlice`] applies the same rules to the elements of an array, without the disjointness contract of [`RevocableCell::project()`]:
+//! each index has one owner shared by all [`RevocableElement`] handles to it, reported as the array's name with that index.
+
+use core::cell::Cell;
+
+use crate::{
+    guard::{BorrowFlag, RevocableGuard},
+    name::Name,
+    requirement::{
+        LastPreemption, Requirement, RequirementId, RequirementInfo, RequirementInfoSource,
+        RevocableCell, RevocableData, StealOutcome,
+    },
+    thief::{PreemptibleFuture, ThiefInfo},
+};
+
+/// A part of a [`RevocableCell`] with its own owner, see the [module-level documentation](self)
+pub struct ProjectedCell<'a, T, U> {
+    parent: &'a RevocableCell<T>,
+    project: fn(&mut T) -> &mut U,
+    slot: ClaimSlot,
+    name: Name,
+}
+
+/// Ownership of one part of a cell, shared by [`ProjectedCell`] and the elements of a [`RevocableSlice`]
+struct ClaimSlot {
+    owner: Cell<Option<ThiefInfo>>,
+    /// Generation of the parent when `owner` claimed this part
+    claimed: Cell<u64>,
+    generation: Cell<u64>,
+    last_preemption: Cell<Option<LastPreemption>>,
+    /// Thief holding a live guard of this part
+    borrowed: BorrowFlag,
+    id: RequirementId,
+}
+
+impl ClaimSlot {
+    fn new() -> Self {
+        Self {
+            owner: Cell::new(None),
+            claimed: Cell::new(0),
+            generation: Cell::new(0),
+            last_preemption: Cell::new(None),
+            borrowed: BorrowFlag::new(),
+            id: RequirementId::next(),
+        }
+    }
+
+    /// Returns the owner of this part, if the whole cell was not stolen since it was claimed.
+    fn claim<T>(&self, parent: &RevocableCell<T>) -> Option<ThiefInfo> {
+        // claims are revoked as soon as the parent moves on
+        self.owner
+            .get()
+            .filter(|_| self.claimed.get() == parent.generation())
+    }
+
+    fn current_owner<T>(&self, parent: &RevocableCell<T>) -> Option<ThiefInfo> {
+        self.claim(parent).or_else(|| parent.current_owner())
+    }
+
+    fn steal<T>(&self, parent: &RevocableCell<T>, thief: &ThiefInfo) -> StealOutcome {
+        if parent.is_frozen() || thief.priority < parent.min_steal_priority() {
+            return StealOutcome::Refused {
+                owner: self.current_owner(parent),
+            };
+        }
+        let displaced = self.current_owner(parent);
+        match self.claim(parent) {
+            Some(_) => self.last_preemption.set(Some(LastPreemption {
+                by: *thief,
+                generation: self.generation.get(),
+                acquired_at: None,
+                stolen_at: None,
+                version: parent.version(),
+                slot: None,
+            })),
+            None => {
+                parent.revoke(thief);
+                let projected = parent.projected();
+                projected.set(projected.get() + 1);
+            }
+        }
+        self.owner.set(Some(*thief));
+        self.claimed.set(parent.generation());
+        self.generation.set(self.generation.get().wrapping_add(1));
+        StealOutcome::Acquired { displaced }
+    }
+
+    fn release<T>(&self, parent: &RevocableCell<T>) {
+        if self.claim(parent).is_some() {
+            let projected = parent.projected();
+            projected.set(projected.get() - 1);
+            if projected.get() == 0 {
+                parent.waiters().wake_all();
+            }
+        }
+        self.owner.set(None);
+    }
+}
+
+impl<T> RevocableCell<T> {
+    /// Creates a requirement on the part of this cell's data returned by `project`.
+    ///
+    /// # Safety
+    ///
+    /// `project` must return a part of its argument, such as one of its fields,
+    /// that does not overlap the part returned by any other projection of this cell that is claimed at the same time.
+    pub unsafe fn project<U>(
+        &self,
+        project: fn(&mut T) -> &mut U,
+        name: impl Into<Name>,
+    ) -> ProjectedCell<'_, T, U> {
+        ProjectedCell {
+            parent: self,
+            project,
+            slot: ClaimSlot::new(),
+            name: name.into(),
+        }
+    }
+}
+
+impl<'a, T, U> ProjectedCell<'a, T, U> {
+    /// Returns the cell this projection is a part of.
+    pub fn parent(&self) -> &'a RevocableCell<T> {
+        self.parent
+    }
+
+    /// Same as [`RevocableCell::run()`], only claiming this projection.
+    ///
+    /// # Errors
+    ///
+    /// If this projection or its whole cell has been stolen by a different future, the future returns `Err<PreemptionError>` when next polled.
+    pub fn run<'b, Out>(
+        &'b self,
+        name: &'static str,
+        func: impl AsyncFnOnce(&mut U) -> Out + 'b,
+    ) -> PreemptibleFuture<'b, impl Future<Output = Out>, Out, 1> {
+        let inner = async move {
+            let mut guard = RevocableGuard::current(self).expect("run owns its projection");
+            func(&mut guard).await
+        };
+        PreemptibleFuture::new(inner, name, [self]).lending_data()
+    }
+}
+
+impl<T, U> Requirement for ProjectedCell<'_, T, U> {
+    fn steal_ownership(&self, thief: &ThiefInfo) {
+        self.try_steal_ownership(thief);
+    }
+
+    fn try_steal_ownership(&self, thief: &ThiefInfo) -> StealOutcome {
+        self.slot.steal(self.parent, thief)
+    }
+
+    fn release_ownership(&self) {
+        self.slot.release(self.parent);
+    }
+
+    /// Poisons the whole cell, which every projection reports as poisoned.
+    fn poison(&self) {
+        self.parent.poison();
+    }
+}
+
+impl<T, U> RequirementInfoSource for ProjectedCell<'_, T, U> {
+    fn current_owner(&self) -> Option<ThiefInfo> {
+        self.slot.current_owner(self.parent)
+    }
+
+    fn info(&self) -> RequirementInfo {
+        RequirementInfo {
+            name: self.name,
+            index: None,
+            id: self.slot.id,
+            type_name: core::any::type_name::<U>(),
+            version: self.parent.version(),
+        }
+    }
+
+    fn generation(&self) -> u64 {
+        self.slot.generation.get()
+    }
+
+    fn last_preemption(&self) -> Option<LastPreemption> {
+        self.slot.last_preemption.get()
+    }
+
+    fn is_poisoned(&self) -> bool {
+        self.parent.is_poisoned()
+    }
+
+    fn is_frozen(&self) -> bool {
+        self.parent.is_frozen()
+    }
+
+    fn min_steal_priority(&self) -> u8 {
+        self.parent.min_steal_priority()
+    }
+
+    fn may_steal(&self, incoming: &ThiefInfo) -> bool {
+        self.parent.may_steal(incoming)
+    }
+}
+
+impl<T, U> RevocableData<U> for ProjectedCell<'_, T, U> {
+    unsafe fn data_ptr(&self) -> *mut U {
+        // the caller owns this projection, and `project` only returns the part it covers
+        (self.project)(unsafe { &mut *self.parent.data_ptr() })
+    }
+
+    fn borrow_flag(&self) -> Option<&BorrowFlag> {
+        Some(&self.slot.borrowed)
+    }
+
+    fn mark_changed(&self) {
+        self.parent.mark_changed();
+    }
+
+    #[cfg(feature = "debug-audit")]
+    fn record_access(&self, thief: &ThiefInfo, write: bool) {
+        self.parent.record_access(thief, write);
+    }
+}
+
+/// An array in a [`RevocableCell`] whose elements can also be claimed one at a time, see the [module-level documentation](self)
+pub struct RevocableSlice<T, const N: usize> {
+    cell: RevocableCell<[T; N]>,
+    slots: [ClaimSlot; N],
+}
+
+impl<T, const N: usize> RevocableSlice<T, N> {
+    /// Creates a new [`RevocableSlice`] with ownership of `data`, whose elements are named `name[index]`.
+    pub fn new(data: [T; N], name: impl Into<Name>) -> Self {
+        Self {
+            cell: RevocableCell::new(data, name),
+            slots: core::array::from_fn(|_| ClaimSlot::new()),
+        }
+    }
+
+    /// Returns the cell holding the whole array, claiming it revokes the owner of every element.
+    pub fn whole(&self) -> &RevocableCell<[T; N]> {
+        &self.cell
+    }
+
+    /// Returns a requirement on the element at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not less than `N`.
+    pub fn element(&self, index: usize) -> RevocableElement<'_, T, N> {
+        assert!(index < N, "index {index} is out of bounds for {N} elements");
+        RevocableElement { slice: self, index }
+    }
+
+    /// Consumes the slice, returning the guarded array.
+    pub fn into_inner(self) -> [T; N] {
+        self.cell.into_inner()
+    }
+}
+
+/// One element of a [`RevocableSlice`], created by [`RevocableSlice::element()`]
+///
+/// Every element handle for the same index shares its owner.
+#[derive(Clone, Copy)]
+pub struct RevocableElement<'a, T, const N: usize> {
+    slice: &'a RevocableSlice<T, N>,
+    index: usize,
+}
+
+impl<T, const N: usize> RevocableElement<'_, T, N> {
+    /// Returns the index of this element.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Same as [`RevocableCell::run()`], only claiming this element.
+    ///
+    /// # Errors
+    ///
+    /// If this element or the whole slice has been stolen by a different future, the future returns `Err<PreemptionError>` when next polled.
+    pub fn run<'b, Out>(
+        &'b self,
+        name: &'static str,
+        func: impl AsyncFnOnce(&mut T) -> Out + 'b,
+    ) -> PreemptibleFuture<'b, impl Future<Output = Out>, Out, 1> {
+        let inner = async move {
+            let mut guard = RevocableGuard::current(self).expect("run owns its element");
+            func(&mut guard).await
+        };
+        PreemptibleFuture::new(inner, name, [self]).lending_data()
+    }
+
+    fn slot(&self) -> &ClaimSlot {
+        &self.slice.slots[self.index]
+    }
+}
+
+impl<T, const N: usize> Requirement for RevocableElement<'_, T, N> {
+    fn steal_ownership(&self, thief: &ThiefInfo) {
+        self.try_steal_ownership(thief);
+    }
+
+    fn try_steal_ownership(&self, thief: &ThiefInfo) -> StealOutcome {
+        self.slot().steal(&self.slice.cell, thief)
+    }
+
+    fn release_ownership(&self) {
+        self.slot().release(&self.slice.cell);
+    }
+
+    /// Poisons the whole slice, which every element reports as poisoned.
+    fn poison(&self) {
+        self.slice.cell.poison();
+    }
+}
+
+impl<T, const N: usize> RequirementInfoSource for RevocableElement<'_, T, N> {
+    fn current_owner(&self) -> Option<ThiefInfo> {
+        self.slot().current_owner(&self.slice.cell)
+    }
+
+    fn info(&self) -> RequirementInfo {
+        RequirementInfo {
+            index: Some(self.index),
+            id: self.slot().id,
+            type_name: core::any::type_name::<T>(),
+            ..self.slice.cell.info()
+        }
+    }
+
+    fn generation(&self) -> u64 {
+        self.slot().generation.get()
+    }
+
+    fn last_preemption(&self) -> Option<LastPreemption> {
+        self.slot().last_preemption.get()
+    }
+
+    fn is_poisoned(&self) -> bool {
+        self.slice.cell.is_poisoned()
+    }
+
+    fn is_frozen(&self) -> bool {
+        self.slice.cell.is_frozen()
+    }
+
+    fn min_steal_priority(&self) -> u8 {
+        self.slice.cell.min_steal_priority()
+    }
+
+    fn may_steal(&self, incoming: &ThiefInfo) -> bool {
+        self.slice.cell.may_steal(incoming)
+    }
+}
+
+impl<T, const N: usize> RevocableData<T> for RevocableElement<'_, T, N> {
+    unsafe fn data_ptr(&self) -> *mut T {
+        // offsetting the array pointer avoids borrowing the elements owned by other tasks
+        unsafe { self.slice.cell.data_ptr().cast::<T>().add(self.index) }
+    }
+
+    fn borrow_flag(&self) -> Option<&BorrowFlag> {
+        Some(&self.slot().borrowed)
+    }
+
+    fn mark_changed(&self) {
+        self.slice.cell.mark_changed();
+    }
+
+    #[cfg(feature = "debug-audit")]
+    fn record_access(&self, thief: &ThiefInfo, write: bool) {
+        self.slice.cell.record_access(thief, write);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use futures_lite::future::yield_now;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Robot {
+        drive: i32,
+        arm: i32,
+    }
+
+    fn drive(robot: &mut Robot) -> &mut i32 {
+        &mut robot.drive
+    }
+
+    fn arm(robot: &mut Robot) -> &mut i32 {
+        &mut robot.arm
+    }
+
+    async fn bump(x: &mut i32) {
+        loop {
+            *x += 1;
+            yield_now().await;
+        }
+    }
+
+    fn owner_name(requirement: &dyn RequirementInfoSource) -> Option<&'static str> {
+        requirement.current_owner().map(|thief| thief.name.as_str())
+    }
+
+    #[test]
+    fn disjoint_projections() {
+        let robot = RevocableCell::new(Robot::default(), "robot");
+        let (drive, arm) = unsafe { (robot.project(drive, "drive"), robot.project(arm, "arm")) };
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut a = pin!(drive.run("a", bump));
+        let mut b = pin!(arm.run("b", bump));
+
+        for _ in 0..3 {
+            assert!(a.as_mut().poll(&mut cx).is_pending());
+            assert!(b.as_mut().poll(&mut cx).is_pending());
+        }
+        assert_eq!(owner_name(&drive), Some("a"));
+        assert_eq!(owner_name(&arm), Some("b"));
+        assert_eq!(owner_name(&robot), None);
+        assert!(robot.try_set(Robot::default()).is_err());
+    }
+
+    #[test]
+    fn same_projection_preempts() {
+        let robot = RevocableCell::new(Robot::default(), "robot");
+        let (drive, arm) = unsafe { (robot.project(drive, "drive"), robot.project(arm, "arm")) };
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut a = pin!(drive.run("a", bump));
+        let mut b = pin!(arm.run("b", bump));
+        let mut c = pin!(drive.run("c", async |x| *x = 0));
+
+        assert!(a.as_mut().poll(&mut cx).is_pending());
+        assert!(b.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(c.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        let Poll::Ready(Err(err)) = a.as_mut().poll(&mut cx) else {
+            panic!("a should be preempted by c");
+        };
+        assert_eq!(err.incoming().map(|thief| thief.name.as_str()), Some("c"));
+        assert_eq!(err.requirement().name, "drive");
+        assert!(b.as_mut().poll(&mut cx).is_pending());
+    }
+
+    #[test]
+    fn parent_preempts_projections() {
+        let robot = RevocableCell::new(Robot::default(), "robot");
+        let (drive, arm) = unsafe { (robot.project(drive, "drive"), robot.project(arm, "arm")) };
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut a = pin!(drive.run("a", bump));
+        let mut b = pin!(arm.run("b", bump));
+        let mut reset = pin!(robot.run("reset", async |robot| {
+            *robot = Robot::default();
+            yield_now().await;
+        }));
+
+        assert!(a.as_mut().poll(&mut cx).is_pending());
+        assert!(b.as_mut().poll(&mut cx).is_pending());
+        assert!(reset.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(owner_name(&drive), Some("reset"));
+        assert_eq!(owner_name(&arm), Some("reset"));
+        for task in [a.as_mut(), b.as_mut()] {
+            let Poll::Ready(Err(err)) = task.poll(&mut cx) else {
+                panic!("projections should be revoked by reset");
+            };
+            assert_eq!(
+                err.incoming().map(|thief| thief.name.as_str()),
+                Some("reset")
+            );
+        }
+        assert_eq!(reset.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(robot.try_set(Robot::default()).is_ok());
+    }
+
+    #[test]
+    fn projection_preempts_parent() {
+        let robot = RevocableCell::new(Robot::default(), "robot");
+        let drive = unsafe { robot.project(drive, "drive") };
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut whole = pin!(robot.run("whole", async |robot| bump(&mut robot.arm).await));
+        {
+            let mut a = pin!(drive.run("a", bump));
+            assert!(whole.as_mut().poll(&mut cx).is_pending());
+            assert!(a.as_mut().poll(&mut cx).is_pending());
+            let Poll::Ready(Err(err)) = whole.as_mut().poll(&mut cx) else {
+                panic!("whole should be preempted by a");
+            };
+            assert_eq!(err.incoming().map(|thief| thief.name.as_str()), Some("a"));
+            assert_eq!(owner_name(&robot), None);
+            assert!(robot.try_set(Robot::default()).is_err());
+            assert!(a.as_mut().poll(&mut cx).is_pending());
+        }
+
+        // the whole cell is only free again once the projection is released
+        assert_eq!(owner_name(&drive), None);
+        assert!(robot.try_set(Robot::default()).is_ok());
+    }
+
+    #[test]
+    fn disjoint_elements() {
+        let motors = RevocableSlice::new([0; 4], "motors");
+        let (first, second) = (motors.element(0), motors.element(1));
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut a = pin!(first.run("a", bump));
+        let mut b = pin!(second.run("b", bump));
+
+        for _ in 0..3 {
+            assert!(a.as_mut().poll(&mut cx).is_pending());
+            assert!(b.as_mut().poll(&mut cx).is_pending());
+        }
+        assert_eq!(owner_name(&motors.element(0)), Some("a"));
+        assert_eq!(owner_name(&motors.element(1)), Some("b"));
+        assert_eq!(owner_name(&motors.element(2)), None);
+        let info = motors.element(1).info();
+        assert_eq!((info.name.as_str(), info.index), ("motors", Some(1)));
+        assert_ne!(info.id, motors.element(0).info().id);
+
+        // another handle to the same element preempts its owner
+        let again = motors.element(0);
+        let mut c = pin!(again.run("c", async |x| *x = 0));
+        assert_eq!(c.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(a.as_mut().poll(&mut cx).is_ready());
+        assert!(b.as_mut().poll(&mut cx).is_pending());
+    }
+
+    #[test]
+    fn whole_and_elements() {
+        let motors = RevocableSlice::new([0; 2], "motors");
+        let mut cx = Context::from_waker(Waker::noop());
+        let (first, second) = (motors.element(0), motors.element(1));
+        let mut a = pin!(first.run("a", bump));
+        let mut b = pin!(second.run("b", bump));
+        let mut stop = pin!(motors.whole().run("stop", async |motors| {
+            *motors = [0; 2];
+            yield_now().await;
+        }));
+
+        // claiming the whole slice revokes every element
+        assert!(a.as_mut().poll(&mut cx).is_pending());
+        assert!(b.as_mut().poll(&mut cx).is_pending());
+        assert!(stop.as_mut().poll(&mut cx).is_pending());
+        for task in [a.as_mut(), b.as_mut()] {
+            let Poll::Ready(Err(err)) = task.poll(&mut cx) else {
+                panic!("elements should be revoked by stop");
+            };
+            assert_eq!(
+                err.incoming().map(|thief| thief.name.as_str()),
+                Some("stop")
+            );
+        }
+
+        // claiming an element preempts the owner of the whole slice
+        let mut c = pin!(first.run("c", async |x| *x += 5));
+        assert_eq!(c.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        let Poll::Ready(Err(err)) = stop.as_mut().poll(&mut cx) else {
+            panic!("stop should be preempted by c");
+        };
+        assert_eq!(err.incoming().map(|thief| thief.name.as_str()), Some("c"));
+        // nothing owns the slice now, so reading it is fine
+        assert_eq!(motors.whole().peek(), [5, 0]);
+    }
+}
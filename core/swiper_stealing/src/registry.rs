@@ -0,0 +1,114 @@
+//! Thread-local list of registered cells, for answering "what is owned right now and by whom" at runtime.
+//!
+//! Cells created with [`RevocableCell::new_registered()`] add an entry here and keep its owner up to date on every ownership change,
+//! so the registry never points into a cell and cells can still be moved after registration.
+//! The entry is removed when the cell is dropped or consumed.
+//! Cells are not `Send`, so each thread only sees the cells it created.
+
+extern crate std;
+
+use core::cell::RefCell;
+use std::{thread_local, vec::Vec};
+
+use crate::{
+    name::Name,
+    requirement::{RequirementId, RequirementInfo, RequirementInfoSource, RevocableCell},
+    thief::ThiefInfo,
+};
+
+thread_local! {
+    static ENTRIES: RefCell<Vec<(RequirementInfo, Option<ThiefInfo>)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Returns every registered cell of this thread with its current owner, in registration order.
+pub fn snapshot() -> Vec<(RequirementInfo, Option<ThiefInfo>)> {
+    ENTRIES.with_borrow(Clone::clone)
+}
+
+/// Keeps the registry entry of one cell, removing it when dropped
+pub(crate) struct Registration(RequirementId);
+
+impl Registration {
+    fn new(info: RequirementInfo) -> Self {
+        ENTRIES.with_borrow_mut(|entries| entries.push((info, None)));
+        Self(info.id)
+    }
+
+    /// Records `owner` as the current owner of the registered cell.
+    pub(crate) fn update(&self, owner: Option<ThiefInfo>) {
+        ENTRIES.with_borrow_mut(|entries| {
+            if let Some(entry) = entries.iter_mut().find(|(info, _)| info.id == self.0) {
+                entry.1 = owner;
+            }
+        });
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        // thread locals may already be gone while the thread exits
+        let _ =
+            ENTRIES.try_with(|entries| entries.borrow_mut().retain(|(info, _)| info.id != self.0));
+    }
+}
+
+impl<T> RevocableCell<T> {
+    /// Same as [`new()`](Self::new), but lists the cell in this thread's [registry](self) until it is dropped.
+    pub fn new_registered(data: T, name: impl Into<Name>) -> Self {
+        let mut cell = Self::new(data, name);
+        cell.set_registration(Registration::new(cell.info()));
+        cell
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        pin::pin,
+        task::{Context, Waker},
+    };
+
+    use futures_lite::future::pending;
+
+    use super::*;
+
+    fn owners() -> Vec<(&'static str, Option<&'static str>)> {
+        snapshot()
+            .into_iter()
+            .map(|(info, owner)| (info.name.as_str(), owner.map(|owner| owner.name.as_str())))
+            .collect()
+    }
+
+    #[test]
+    fn snapshot_owners() {
+        let drive = RevocableCell::new_registered(0, "registered_drive");
+        let arm = RevocableCell::new_registered(0, "registered_arm");
+        let intake = RevocableCell::new_registered(0, "registered_intake");
+        let _unregistered = RevocableCell::new(0, "unregistered");
+        let mut cx = Context::from_waker(Waker::noop());
+
+        {
+            let mut a = pin!(drive.run("a", async |_| pending::<()>().await));
+            let mut b = pin!(intake.run("b", async |_| pending::<()>().await));
+            assert!(a.as_mut().poll(&mut cx).is_pending());
+            assert!(b.as_mut().poll(&mut cx).is_pending());
+            assert_eq!(
+                owners(),
+                [
+                    ("registered_drive", Some("a")),
+                    ("registered_arm", None),
+                    ("registered_intake", Some("b")),
+                ]
+            );
+        }
+
+        // the dropped tasks release their cells, and dropped cells deregister
+        drop(arm);
+        assert_eq!(
+            owners(),
+            [("registered_drive", None), ("registered_intake", None)]
+        );
+        drop((drive, intake));
+        assert!(snapshot().is_empty());
+    }
+}
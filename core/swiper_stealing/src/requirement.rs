@@ -1,116 +1,2394 @@
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
 use core::{
+    any::{Any, TypeId},
     cell::{Cell, UnsafeCell},
-    fmt::Display,
+    fmt::{Debug, Display},
+    hash::{Hash, Hasher},
+    num::NonZeroU64,
     ptr::NonNull,
+    task::Waker,
+};
+
+use crate::{
+    clock::{Clock, Tick},
+    counter::IdCounter,
+    guard::BorrowFlag,
+    history::{History, OwnershipChange, OwnershipRecord, StealRecord},
+    name::Name,
+    notify::{NotifySlot, Version, WaiterList},
+    policy::{PreemptionPolicy, PriorityPolicy},
+    thief::ThiefInfo,
 };
 
-use crate::thief::ThiefInfo;
+#[cfg(feature = "sync")]
+pub mod sync;
+
+/// Unique identity of a requirement, assigned from a global counter when the requirement is created.
+///
+/// Unlike names, ids are never shared between requirements, so they are suitable as set or map keys.
+/// The counter is 64 bits wide, so it never wraps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RequirementId(NonZeroU64);
+
+impl RequirementId {
+    /// Returns a new id, distinct from all ids previously returned.
+    pub fn next() -> Self {
+        static NEXT_ID: IdCounter = IdCounter::new();
+        Self(NEXT_ID.next())
+    }
+}
+
+impl Display for RequirementId {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
 
 /// Contains metadata about a [`RevocableCell`]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///
+/// Equality only considers the [`id`](Self::id) and [`type_name`](Self::type_name), and hashing only the id,
+/// so infos of the same requirement compare equal even when reported under different names.
+/// The alternate `{:#}` form of [`Display`] also shows the id, telling apart requirements that share a name.
+#[derive(Debug, Clone, Copy)]
 pub struct RequirementInfo {
-    pub name: &'static str,
+    pub name: Name,
+    /// Index of the requirement within a group sharing the base `name`, see [`RevocableCell::new_indexed`],
+    /// or the slot the outgoing task lost for a [`PreemptionError`](crate::PreemptionError) of a [`SemaphoreCell`](crate::semaphore::SemaphoreCell)
+    pub index: Option<usize>,
+    pub id: RequirementId,
+    /// Type of the guarded data, as returned by [`type_name()`](core::any::type_name)
+    pub type_name: &'static str,
+    /// [`Version`] of the guarded data when this info was taken, or when the requirement was stolen for a [`PreemptionError`](crate::PreemptionError)
+    pub version: Version,
+}
+
+impl RequirementInfo {
+    /// Returns the unique identity of the requirement, see [`RequirementId`].
+    pub fn id(&self) -> RequirementId {
+        self.id
+    }
+}
+
+impl PartialEq for RequirementInfo {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id && self.type_name == other.type_name
+    }
+}
+
+impl Eq for RequirementInfo {}
+
+impl Hash for RequirementInfo {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
 }
 
 impl Display for RequirementInfo {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "Requirement {{ name: {} }} ", self.name)
+        write!(f, "Requirement {{ name: {}", self.name)?;
+        if let Some(index) = self.index {
+            write!(f, "[{index}]")?;
+        }
+        write!(f, ", type: {}", self.type_name)?;
+        if f.alternate() {
+            write!(f, ", id: {}", self.id)?;
+        }
+        write!(f, " }} ")
+    }
+}
+
+/// Record of the most recent time a requirement was stolen from a live owner.
+///
+/// Stored by the requirement at steal time so a preempted task can name its thief even if the thief has already completed and released the requirement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LastPreemption {
+    /// The task that stole the requirement
+    pub by: ThiefInfo,
+    /// The [`generation`](RequirementInfoSource::generation) of the ownership tenure that was ended
+    pub generation: u64,
+    /// When the ended tenure began, if the requirement has a [`Clock`]
+    pub acquired_at: Option<Tick>,
+    /// When the requirement was stolen, if the requirement has a [`Clock`]
+    pub stolen_at: Option<Tick>,
+    /// [`Version`] of the guarded data when the requirement was stolen
+    pub version: Version,
+    /// Slot of a [`SemaphoreCell`](crate::semaphore::SemaphoreCell) held by the ended tenure, `None` for requirements with a single owner
+    pub slot: Option<usize>,
+}
+
+/// How an ownership tenure ended, stored in a [`LastOwner`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TenureEnd {
+    /// The owner finished and released the requirement
+    Completed,
+    /// The requirement was stolen by another task
+    Preempted { by: ThiefInfo },
+    /// The owner held the requirement for longer than it was allowed to
+    Expired,
+    /// The owner was dropped or torn down before finishing
+    Aborted,
+}
+
+/// The most recent owner of a requirement whose tenure has ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LastOwner {
+    pub thief: ThiefInfo,
+    pub ended: TenureEnd,
+    /// When the tenure ended, if the requirement has a [`Clock`]
+    pub at: Option<Tick>,
+}
+
+/// Callback run by [`RevocableCell`] whenever it is stolen, see [`RevocableCell::set_on_steal()`]
+///
+/// Called with the cell's info, the outgoing owner if there is one, and the incoming thief.
+pub type StealHook = fn(&RequirementInfo, Option<&ThiefInfo>, &ThiefInfo);
+
+/// Counts of ownership changes of a [`RevocableCell`], returned by [`RevocableCell::stats()`]
+///
+/// Counters wrap around at `u32::MAX`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RequirementStats {
+    /// Number of times the cell was stolen from a live owner or from its readers
+    pub steals: u32,
+    /// Number of times the cell was acquired without displacing anyone
+    pub acquisitions: u32,
+    /// Number of times an owner gave up the cell without being preempted
+    pub releases: u32,
+}
+
+/// Result of [`Requirement::try_steal_ownership`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StealOutcome {
+    /// The thief is now the owner, `displaced` is the previous owner if there was one
+    Acquired { displaced: Option<ThiefInfo> },
+    /// Ownership did not change, because the requirement is [frozen](RevocableCell::freeze),
+    /// the thief is below its [minimum steal priority](RequirementInfoSource::min_steal_priority),
+    /// or its [policy](crate::policy) protects `owner`, the current owner if there is one
+    Refused { owner: Option<ThiefInfo> },
+}
+
+/// Claims held on a requirement, returned by [`RequirementInfoSource::current_claims()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Claims {
+    Unclaimed,
+    /// A single task owns the requirement
+    Exclusive(ThiefInfo),
+    /// `readers` tasks share the requirement, see [`Requirement::claim_shared()`]
+    Shared {
+        readers: usize,
+    },
+}
+
+/// Error returned by the `try_` accessors of [`RevocableCell`] when the cell has an owner
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellOwned {
+    /// `None` if the cell itself is unowned but it has readers or claimed [projections](crate::projection)
+    pub owner: Option<ThiefInfo>,
+}
+
+impl Display for CellOwned {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.owner {
+            Some(owner) => write!(f, "the cell is owned by {owner}"),
+            None => f.write_str("the cell has readers or claimed projections"),
+        }
+    }
+}
+
+/// Read-only view of a requirement's ownership state.
+///
+/// Telemetry and inspection code should accept this trait rather than [`Requirement`], so it cannot steal or release ownership.
+/// Any `&dyn Requirement` can be upcast into a `&dyn RequirementInfoSource`.
+///
+/// ```compile_fail,E0599
+/// # use swiper_stealing::{requirement::{RequirementInfoSource, RevocableCell}, thief::ThiefInfo};
+/// fn inspect(source: &dyn RequirementInfoSource, thief: &ThiefInfo) {
+///     source.steal_ownership(thief);
+/// }
+/// ```
+pub trait RequirementInfoSource {
+    /// Returns information about the current flag owner.
+    fn current_owner(&self) -> Option<ThiefInfo>;
+
+    /// Returns information about the current requirement.
+    fn info(&self) -> RequirementInfo;
+
+    /// Returns the exclusive owner or the number of readers of this requirement.
+    ///
+    /// The default implementation only reports the [`current_owner()`](Self::current_owner).
+    fn current_claims(&self) -> Claims {
+        match self.current_owner() {
+            Some(owner) => Claims::Exclusive(owner),
+            None => Claims::Unclaimed,
+        }
+    }
+
+    /// Returns the unique identity of this requirement.
+    fn id(&self) -> RequirementId {
+        self.info().id
+    }
+
+    /// Returns a counter identifying the current ownership tenure, incremented every time ownership is stolen.
+    ///
+    /// Requirements that do not track tenures always return 0.
+    fn generation(&self) -> u64 {
+        0
+    }
+
+    /// Returns the most recent preemption of a live owner, if any.
+    ///
+    /// Only the latest preemption is kept: each steal that displaces an owner overwrites the record, while releases and steals of an unowned requirement leave it untouched.
+    fn last_preemption(&self) -> Option<LastPreemption> {
+        None
+    }
+
+    /// Returns the most recent owner whose tenure ended, by release or by preemption.
+    ///
+    /// Requirements that do not track owners always return `None`.
+    fn last_owner(&self) -> Option<LastOwner> {
+        None
+    }
+
+    /// Returns the most recent steal from a live owner.
+    ///
+    /// Requirements that do not keep a history always return `None`.
+    fn last_steal(&self) -> Option<StealRecord> {
+        None
+    }
+
+    /// Returns the task that temporarily released this requirement with [`yield_requirement()`](crate::thief::yield_requirement), if any.
+    ///
+    /// Requirements that cannot be yielded always return `None`.
+    fn yielded_by(&self) -> Option<ThiefInfo> {
+        None
+    }
+
+    /// Returns `true` if a task panicked while owning this requirement, see [`RevocableCell::clear_poison()`].
+    ///
+    /// Requirements that cannot be poisoned always return `false`.
+    fn is_poisoned(&self) -> bool {
+        false
+    }
+
+    /// Returns `true` if this requirement refuses every steal, see [`RevocableCell::freeze()`].
+    ///
+    /// Requirements that cannot be frozen always return `false`.
+    fn is_frozen(&self) -> bool {
+        false
+    }
+
+    /// Returns the lowest [`ThiefInfo::priority`] allowed to steal this requirement, see [`RevocableCell::with_min_steal_priority()`].
+    ///
+    /// Requirements without a floor always return 0.
+    fn min_steal_priority(&self) -> u8 {
+        0
+    }
+
+    /// Returns `true` if `incoming` may preempt the current owner under this requirement's [policy](crate::policy), or if there is no other owner.
+    ///
+    /// The default implementation applies the [`PriorityPolicy`].
+    fn may_steal(&self, incoming: &ThiefInfo) -> bool {
+        self.current_owner().is_none_or(|outgoing| {
+            outgoing.same_thief(incoming)
+                || crate::policy::PriorityPolicy.may_steal(incoming, &outgoing)
+        })
     }
 }
 
 /// Keeps track of the current owner of a requirement.
 ///
-/// Thiefs ([`PreemptibleFuture`]) acts as guards to the requirement by ensuring they do not access a revoked requirement.
-/// Requirements should be checked for equality using `std::ptr::eq`, rather than `ThiefInfo` because `ThiefInfo` is not unique.
+/// Thiefs ([`PreemptibleFuture`](crate::thief::PreemptibleFuture)) acts as guards to the requirement by ensuring they do not access a revoked requirement.
+/// Owners are compared with [`ThiefInfo::same_thief()`], which `==` also uses.
 ///
 /// This provides a type-independent reference to downcast `RevocableCell<T>` into.
-pub trait Requirement {
+pub trait Requirement: RequirementInfoSource {
     /// Sets the current owner of this requirement to the provided `thief`.
     /// This will revoke access to the previous owner, if it existed.
+    ///
+    /// Does nothing if the requirement refuses the steal, so callers that go on to access the data should use
+    /// [`try_steal_ownership()`](Self::try_steal_ownership) and check for [`StealOutcome::Refused`].
     fn steal_ownership(&self, thief: &ThiefInfo);
 
+    /// Sets the current owner of this requirement to the provided `thief`, reporting who was displaced.
+    ///
+    /// The default implementation reads [`current_owner()`](RequirementInfoSource::current_owner) before delegating to [`steal_ownership()`](Self::steal_ownership).
+    fn try_steal_ownership(&self, thief: &ThiefInfo) -> StealOutcome {
+        let displaced = self.current_owner();
+        self.steal_ownership(thief);
+        StealOutcome::Acquired { displaced }
+    }
+
     /// Releases the current flag owner.
     /// This means no thief will have access to this requirement.
     fn release_ownership(&self);
 
-    /// Returns information about the current flag owner.
-    fn current_owner(&self) -> Option<&ThiefInfo>;
+    /// Adds `thief` as a reader of this requirement, preempting the exclusive owner if there is one.
+    ///
+    /// Readers do not preempt each other, and are all preempted by the next [`steal_ownership()`](Self::steal_ownership).
+    /// They share the [`generation()`](RequirementInfoSource::generation) current when they claimed the requirement.
+    /// The default implementation claims the requirement exclusively instead.
+    fn claim_shared(&self, thief: &ThiefInfo) -> StealOutcome {
+        self.try_steal_ownership(thief)
+    }
 
-    /// Returns information about the current requirement.
-    fn info(&self) -> RequirementInfo;
+    /// Removes a reader that claimed this requirement during `generation`, doing nothing if the readers of that generation were preempted.
+    ///
+    /// The default implementation does nothing, since the default [`claim_shared()`](Self::claim_shared) never adds readers.
+    fn release_shared(&self, generation: u64) {
+        let _ = generation;
+    }
+
+    /// Releases the current flag owner, recording `ended` as the reason in [`last_owner()`](RequirementInfoSource::last_owner).
+    ///
+    /// [`release_ownership()`](Self::release_ownership) is the same as ending with [`TenureEnd::Completed`].
+    /// The default implementation ignores `ended`.
+    fn end_ownership(&self, ended: TenureEnd) {
+        let _ = ended;
+        self.release_ownership();
+    }
+
+    /// Ends the tenure of `thief` with `ended`, doing nothing if `thief` is not an owner.
+    ///
+    /// Requirements with several owners, such as a [`SemaphoreCell`](crate::semaphore::SemaphoreCell), only release the slot of `thief`.
+    /// The default implementation checks [`is_owned_by()`](Self::is_owned_by) before delegating to [`end_ownership()`](Self::end_ownership).
+    fn end_ownership_of(&self, thief: &ThiefInfo, ended: TenureEnd) {
+        if self.is_owned_by(thief) {
+            self.end_ownership(ended);
+        }
+    }
+
+    /// Ends the current owner's tenure as a preemption by `by`, without making `by` the owner.
+    ///
+    /// This preempts the owner while leaving the requirement free, as [projections](crate::projection) and [hierarchies](crate::hierarchy) do to their parents.
+    /// The default implementation ends ownership as [`TenureEnd::Preempted`] if there is an owner.
+    fn revoke(&self, by: &ThiefInfo) {
+        if self.current_owner().is_some() {
+            self.end_ownership(TenureEnd::Preempted { by: *by });
+        }
+    }
+
+    /// Marks this requirement as poisoned after its owner panicked, so tasks listing it fail on their first poll.
+    ///
+    /// The default implementation does nothing.
+    fn poison(&self) {}
+
+    /// Returns `true` if `thief` is the exclusive owner of this requirement.
+    ///
+    /// This is the ownership check [`PreemptibleFuture`](crate::thief::PreemptibleFuture) makes on every poll, so requirements can answer it without building a [`ThiefInfo`].
+    /// The default implementation compares the [`current_owner()`](RequirementInfoSource::current_owner) with [`ThiefInfo::same_thief()`].
+    fn is_owned_by(&self, thief: &ThiefInfo) -> bool {
+        self.current_owner()
+            .is_some_and(|owner| owner.same_thief(thief))
+    }
+
+    /// Records that the owner of this requirement was polled, for [stale owner reclamation](crate::stale).
+    ///
+    /// The default implementation does nothing.
+    fn mark_polled(&self) {}
+
+    /// Wakes `waker` when the current owner, just polled with it, is preempted, replacing the waker of its previous poll.
+    ///
+    /// The default implementation does nothing, so owners only notice a steal the next time their executor polls them.
+    fn register_owner_waker(&self, waker: &Waker) {
+        let _ = waker;
+    }
+
+    /// Wakes `waker` the next time the owner of this requirement releases it, see [`RevocableCell::register_release_waker()`].
+    ///
+    /// The default implementation wakes `waker` right away, so the task polls again instead of waiting forever.
+    fn register_release_waker(&self, waker: &Waker) {
+        waker.wake_by_ref();
+    }
+
+    /// Returns the metadata attached to this requirement, for downcasting to its concrete type, see [`RevocableCell::with_meta()`].
+    ///
+    /// The default implementation returns `None`.
+    fn meta_any(&self) -> Option<&dyn Any> {
+        None
+    }
+}
+
+/// Implements [`Requirement`] for a pointer to a requirement by delegating to the pointee.
+macro_rules! impl_pointer {
+    ($($pointer:ty),+) => {$(
+        impl<R: Requirement + ?Sized> Requirement for $pointer {
+            fn steal_ownership(&self, thief: &ThiefInfo) {
+                (**self).steal_ownership(thief);
+            }
+
+            fn try_steal_ownership(&self, thief: &ThiefInfo) -> StealOutcome {
+                (**self).try_steal_ownership(thief)
+            }
+
+            fn release_ownership(&self) {
+                (**self).release_ownership();
+            }
+
+            fn claim_shared(&self, thief: &ThiefInfo) -> StealOutcome {
+                (**self).claim_shared(thief)
+            }
+
+            fn release_shared(&self, generation: u64) {
+                (**self).release_shared(generation);
+            }
+
+            fn end_ownership(&self, ended: TenureEnd) {
+                (**self).end_ownership(ended);
+            }
+
+            fn end_ownership_of(&self, thief: &ThiefInfo, ended: TenureEnd) {
+                (**self).end_ownership_of(thief, ended);
+            }
+
+            fn revoke(&self, by: &ThiefInfo) {
+                (**self).revoke(by);
+            }
+
+            fn poison(&self) {
+                (**self).poison();
+            }
+
+            fn is_owned_by(&self, thief: &ThiefInfo) -> bool {
+                (**self).is_owned_by(thief)
+            }
+
+            fn mark_polled(&self) {
+                (**self).mark_polled();
+            }
+
+            fn register_owner_waker(&self, waker: &Waker) {
+                (**self).register_owner_waker(waker);
+            }
+
+            fn register_release_waker(&self, waker: &Waker) {
+                (**self).register_release_waker(waker);
+            }
+
+            fn meta_any(&self) -> Option<&dyn Any> {
+                (**self).meta_any()
+            }
+        }
+
+        impl<R: Requirement + ?Sized> RequirementInfoSource for $pointer {
+            fn current_owner(&self) -> Option<ThiefInfo> {
+                (**self).current_owner()
+            }
+
+            fn info(&self) -> RequirementInfo {
+                (**self).info()
+            }
+
+            fn current_claims(&self) -> Claims {
+                (**self).current_claims()
+            }
+
+            fn id(&self) -> RequirementId {
+                (**self).id()
+            }
+
+            fn generation(&self) -> u64 {
+                (**self).generation()
+            }
+
+            fn last_preemption(&self) -> Option<LastPreemption> {
+                (**self).last_preemption()
+            }
+
+            fn last_owner(&self) -> Option<LastOwner> {
+                (**self).last_owner()
+            }
+
+            fn last_steal(&self) -> Option<StealRecord> {
+                (**self).last_steal()
+            }
+
+            fn yielded_by(&self) -> Option<ThiefInfo> {
+                (**self).yielded_by()
+            }
+
+
+            fn is_poisoned(&self) -> bool {
+                (**self).is_poisoned()
+            }
+
+            fn is_frozen(&self) -> bool {
+                (**self).is_frozen()
+            }
+
+            fn min_steal_priority(&self) -> u8 {
+                (**self).min_steal_priority()
+            }
+
+            fn may_steal(&self, incoming: &ThiefInfo) -> bool {
+                (**self).may_steal(incoming)
+            }
+        }
+    )+};
+}
+
+impl_pointer!(&R);
+#[cfg(feature = "alloc")]
+impl_pointer!(alloc::rc::Rc<R>, alloc::boxed::Box<R>);
+
+/// Implemented by futures and compositions of futures that steal a known set of requirements.
+pub trait HasRequirements {
+    /// Calls `visit` with every requirement this future may steal, including those of nested futures.
+    fn visit_requirements(&self, visit: &mut dyn FnMut(&dyn Requirement));
 }
 
-/// A pointer to a mutable location in memory that enables reference holders to call [`steal_flag()`](Self::steal_flag) to revoke flags from other reference holders.
+/// Returns a requirement needed by both `a` and `b`, if there is one.
 ///
-/// This struct cannot be directly used in a safe manner, and must be accessed inside a [`PreemptibleFuture`].
-pub struct RevocableCell<T> {
-    pub data: UnsafeCell<T>,
-    owner: Cell<Option<NonNull<ThiefInfo>>>,
-    name: &'static str,
+/// Requirements are compared by [`RequirementId`], so distinct requirements with the same name do not overlap.
+pub fn requirements_overlap(
+    a: &(impl HasRequirements + ?Sized),
+    b: &(impl HasRequirements + ?Sized),
+) -> Option<RequirementInfo> {
+    let mut overlap = None;
+    a.visit_requirements(&mut |a_req| {
+        if overlap.is_some() {
+            return;
+        }
+        let id = a_req.id();
+        b.visit_requirements(&mut |b_req| {
+            if overlap.is_none() && b_req.id() == id {
+                overlap = Some(a_req.info());
+            }
+        });
+    });
+    overlap
 }
 
-impl<T> RevocableCell<T> {
-    /// Creates a new [`RevocableCell`] with ownership of `data`.
+/// A [`Requirement`] that guards a value of type `T`.
+///
+/// This lets generated code, such as the expansion of the `preemptible` macro, accept any kind of cell guarding a `T`.
+pub trait RevocableData<T: ?Sized>: Requirement {
+    /// Returns a raw pointer to the guarded value.
     ///
-    /// The cell will default having no owner.
-    pub fn new(data: T, name: &'static str) -> Self {
+    /// # Safety
+    ///
+    /// The pointer may only be dereferenced while the caller is the current owner of this requirement,
+    /// and no other reference to the value may be in use at the same time.
+    unsafe fn data_ptr(&self) -> *mut T;
+
+    /// Records that the guarded value was mutated, waking the tasks observing it.
+    ///
+    /// Called by a [`RevocableGuard`](crate::guard::RevocableGuard) that handed out mutable access when it is dropped.
+    /// The default implementation does nothing.
+    fn mark_changed(&self) {}
+
+    /// Counts an access to the guarded value by `thief` in the [audit](crate::audit), as a write if `write` is set.
+    ///
+    /// The default implementation does nothing.
+    #[cfg(feature = "debug-audit")]
+    fn record_access(&self, thief: &ThiefInfo, write: bool) {
+        let _ = (thief, write);
+    }
+
+    /// Returns the flag recording which thief holds a live [`RevocableGuard`](crate::guard::RevocableGuard) of the value,
+    /// so that no thief is handed two mutable references to it.
+    ///
+    /// The default implementation returns `None`, which leaves guards of this requirement unchecked.
+    fn borrow_flag(&self) -> Option<&BorrowFlag> {
+        None
+    }
+}
+
+macro_rules! impl_data_pointer {
+    ($($pointer:ty),+) => {$(
+        impl<T: ?Sized, R: RevocableData<T> + ?Sized> RevocableData<T> for $pointer {
+            unsafe fn data_ptr(&self) -> *mut T {
+                unsafe { (**self).data_ptr() }
+            }
+
+            fn mark_changed(&self) {
+                (**self).mark_changed();
+            }
+
+            #[cfg(feature = "debug-audit")]
+            fn record_access(&self, thief: &ThiefInfo, write: bool) {
+                (**self).record_access(thief, write);
+            }
+
+            fn borrow_flag(&self) -> Option<&BorrowFlag> {
+                (**self).borrow_flag()
+            }
+        }
+    )+};
+}
+
+impl_data_pointer!(&R);
+#[cfg(feature = "alloc")]
+impl_data_pointer!(alloc::rc::Rc<R>, alloc::boxed::Box<R>);
+
+/// A pointer to a mutable location in memory that enables reference holders to call [`steal_ownership()`](Requirement::steal_ownership) to revoke flags from other reference holders.
+///
+/// This struct cannot be directly used in a safe manner, and must be accessed inside a [`PreemptibleFuture`](crate::thief::PreemptibleFuture).
+///
+/// `M` is the type of the metadata carried by the cell, see [`with_meta()`](Self::with_meta),
+/// and `H` is the capacity of the optional ownership history, see [`with_history()`](Self::with_history).
+///
+/// `T` may be unsized, such as a trait object chosen at startup, by coercing a reference or `Box` of a sized cell.
+/// Guards hand out `&mut T` as usual, but an unsized cell is listed as a requirement through that reference or box,
+/// since [`run()`](Self::run) and other constructors of futures need a sized cell.
+pub struct RevocableCell<T: ?Sized, M = (), const H: usize = 0> {
+    /// Ownership and bookkeeping, kept apart from the history so [`with_history()`](RevocableCell::with_history) moves it whole
+    state: CellState,
+    history: History<H>,
+    steals: History<H, StealRecord>,
+    /// Attached with [`with_meta()`](Self::with_meta)
+    meta: M,
+    // last, so that it can be unsized
+    data: UnsafeCell<T>,
+}
+
+/// State of a [`RevocableCell`] that depends on neither its data nor its history capacity
+struct CellState {
+    owner: Cell<Option<ThiefInfo>>,
+    /// Owner that released the cell for the duration of a [`yield_requirement()`](crate::thief::yield_requirement)
+    yielded: Cell<Option<ThiefInfo>>,
+    /// Whether a task panicked while owning the cell, see [`clear_poison()`](RevocableCell::clear_poison)
+    poisoned: Cell<bool>,
+    /// Whether steals are refused, see [`freeze()`](RevocableCell::freeze)
+    frozen: Cell<bool>,
+    /// Snapshot of the data owned by a [`run_transactional()`](RevocableCell::run_transactional), swapped back in when its owner is preempted
+    ///
+    /// Untyped so that only `data` depends on `T`, which lets the cell be unsized
+    rollback: Cell<Option<NonNull<()>>>,
+    /// Number of [projections](crate::projection) claimed since the cell was last stolen
+    projected: Cell<usize>,
+    /// Number of readers sharing the current generation
+    readers: Cell<usize>,
+    generation: Cell<u64>,
+    last_preemption: Cell<Option<LastPreemption>>,
+    last_owner: Cell<Option<LastOwner>>,
+    stats: Cell<RequirementStats>,
+    on_steal: Cell<Option<StealHook>>,
+    /// Set while `on_steal` runs, to catch steals from inside the hook
+    in_hook: Cell<bool>,
+    acquired_at: Cell<Option<Tick>>,
+    /// [`current_tick()`](crate::stale::current_tick) when the owner last acquired or was polled with the cell
+    touched: Cell<u32>,
+    /// Waker of the owner's most recent poll, woken when it is preempted
+    owner_waker: Cell<Option<Waker>>,
+    /// Thief holding a live guard of the data
+    borrowed: BorrowFlag,
+    clock: Option<&'static dyn Clock>,
+    /// Steals by thieves with a lower priority are refused, see [`with_min_steal_priority()`](RevocableCell::with_min_steal_priority)
+    min_steal_priority: u8,
+    /// Decides whether the owner may be preempted, see [`set_policy()`](RevocableCell::set_policy)
+    policy: Cell<&'static dyn PreemptionPolicy>,
+    notify: NotifySlot,
+    waiters: WaiterList,
+    /// Number of completed mutable accesses, see [`changed()`](RevocableCell::changed)
+    version: Cell<u64>,
+    /// Set by every completed mutable access, cleared by [`take_dirty()`](RevocableCell::take_dirty)
+    dirty: Cell<bool>,
+    observers: WaiterList,
+    #[cfg(feature = "futures")]
+    events: crate::events::Subscribers,
+    #[cfg(feature = "introspection")]
+    registration: Option<crate::registry::Registration>,
+    #[cfg(feature = "debug-audit")]
+    audit: Cell<crate::audit::AccessAudit>,
+    name: Name,
+    index: Option<usize>,
+    id: RequirementId,
+}
+
+impl CellState {
+    fn new(name: Name) -> Self {
         Self {
-            data: data.into(),
             owner: Cell::new(None),
+            yielded: Cell::new(None),
+            poisoned: Cell::new(false),
+            frozen: Cell::new(false),
+            rollback: Cell::new(None),
+            projected: Cell::new(0),
+            readers: Cell::new(0),
+            generation: Cell::new(0),
+            last_preemption: Cell::new(None),
+            last_owner: Cell::new(None),
+            stats: Cell::new(RequirementStats::default()),
+            on_steal: Cell::new(None),
+            in_hook: Cell::new(false),
+            acquired_at: Cell::new(None),
+            touched: Cell::new(0),
+            owner_waker: Cell::new(None),
+            borrowed: BorrowFlag::new(),
+            clock: None,
+            min_steal_priority: 0,
+            policy: Cell::new(&PriorityPolicy),
+            notify: NotifySlot::new(),
+            waiters: WaiterList::new(),
+            version: Cell::new(0),
+            dirty: Cell::new(false),
+            observers: WaiterList::new(),
+            #[cfg(feature = "futures")]
+            events: crate::events::Subscribers::new(),
+            #[cfg(feature = "introspection")]
+            registration: None,
+            #[cfg(feature = "debug-audit")]
+            audit: Cell::new(crate::audit::AccessAudit::default()),
             name,
+            index: None,
+            id: RequirementId::next(),
         }
     }
 }
 
-impl<T> Requirement for RevocableCell<T> {
-    fn steal_ownership(&self, thief: &ThiefInfo) {
-        self.owner.set(Some(thief.into()));
+impl<T> RevocableCell<T> {
+    /// Creates a new [`RevocableCell`] with ownership of `data`.
+    ///
+    /// The cell will default having no owner.
+    pub fn new(data: T, name: impl Into<Name>) -> Self {
+        Self::with_meta(data, name, ())
     }
 
-    fn release_ownership(&self) {
-        self.owner.set(None);
+    /// Creates a new [`RevocableCell`] with a name built at runtime, interned by [`Name::from_string()`].
+    ///
+    /// Each distinct name is only allocated once, so recreating cells with the same names does not leak memory.
+    #[cfg(feature = "alloc")]
+    pub fn new_named(data: T, name: alloc::string::String) -> Self {
+        Self::new(data, Name::from_string(name))
     }
 
-    fn current_owner(&self) -> Option<&ThiefInfo> {
-        self.owner.get().map(|ptr| unsafe { ptr.as_ref() })
+    /// Creates a new [`RevocableCell`] named `base[index]`, for groups of cells sharing a base name.
+    pub fn new_indexed(data: T, base: impl Into<Name>, index: usize) -> Self {
+        let mut cell = Self::new(data, base);
+        cell.state.index = Some(index);
+        cell
     }
 
-    fn info(&self) -> RequirementInfo {
-        RequirementInfo { name: self.name }
+    /// Creates an array of cells named `base[0]` to `base[N - 1]`, with data produced by `init` for each index.
+    pub fn array<const N: usize>(
+        mut init: impl FnMut(usize) -> T,
+        base: impl Into<Name>,
+    ) -> [Self; N] {
+        let base = base.into();
+        core::array::from_fn(|index| Self::new_indexed(init(index), base, index))
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use core::ptr;
+impl<T, M: Any> RevocableCell<T, M> {
+    /// Creates a new [`RevocableCell`] with ownership of `data`, carrying `meta`, such as the subsystem or bus id of the hardware behind it.
+    ///
+    /// Code holding the cell as a `&dyn Requirement`, for example when handling a [`PreemptionError`](crate::PreemptionError),
+    /// gets `meta` back with [`meta_any()`](Requirement::meta_any), while code holding the cell itself uses [`meta()`](Self::meta).
+    pub fn with_meta(data: T, name: impl Into<Name>, meta: M) -> Self {
+        Self {
+            state: CellState::new(name.into()),
+            history: History::new(),
+            steals: History::new(),
+            meta,
+            data: data.into(),
+        }
+    }
 
-    use super::*;
+    /// Moves this cell to the heap and leaks it, so futures using it can be `'static` and handed to spawners.
+    ///
+    /// A leaked cell is never dropped, which is fine: owners release their claims when their futures complete or are dropped,
+    /// not when the cell goes away.
+    /// See [`PreemptibleFuture::new_static()`](crate::thief::PreemptibleFuture::new_static) for futures listing it as a requirement.
+    #[cfg(feature = "alloc")]
+    pub fn leak(self) -> &'static mut Self
+    where
+        T: 'static,
+    {
+        alloc::boxed::Box::leak(alloc::boxed::Box::new(self))
+    }
 
-    #[test]
-    fn flag_stealing() {
-        let cell = RevocableCell::new(0, "test");
-        let thief1 = ThiefInfo { name: "test" };
-        let thief2 = ThiefInfo { name: "test" };
-        {
-            assert!(cell.current_owner().is_none());
-            cell.steal_ownership(&thief1);
-            assert!(ptr::eq(
-                cell.current_owner().expect("should be owned"),
-                &thief1
-            ));
-            // steal new flag
-            cell.steal_ownership(&thief2);
-            assert!(cell.current_owner().is_some());
-            assert!(ptr::eq(
-                cell.current_owner().expect("should be owned"),
-                &thief2
-            ));
-            assert!(!ptr::eq(
-                cell.current_owner().expect("should be owned"),
-                &thief1
-            ));
+    /// Records the last `K` ownership transitions of this cell, readable through [`history()`](RevocableCell::history),
+    /// and the last `K` steals from a live owner, readable through [`steal_history()`](RevocableCell::steal_history).
+    ///
+    /// Records are stored inline in the cell, so this costs `K` [`OwnershipRecord`]s and `K` [`StealRecord`]s of space.
+    pub fn with_history<const K: usize>(self) -> RevocableCell<T, M, K> {
+        RevocableCell {
+            state: self.state,
+            history: History::new(),
+            steals: History::new(),
+            meta: self.meta,
+            data: self.data,
         }
-        cell.release_ownership();
-        assert!(cell.current_owner().is_none());
+    }
+}
+
+impl<T: ?Sized, M: Any, const H: usize> RevocableCell<T, M, H> {
+    /// Returns a mutable reference to the guarded value.
+    ///
+    /// This is always safe, since the exclusive borrow guarantees no future is running on this cell.
+    /// It also forgets the current owner, which may be left over from a future that was leaked without releasing the cell,
+    /// so the next task acquires it instead of preempting a stale thief.
+    pub fn get_mut(&mut self) -> &mut T {
+        *self.state.owner.get_mut() = None;
+        *self.state.yielded.get_mut() = None;
+        *self.state.rollback.get_mut() = None;
+        *self.state.projected.get_mut() = 0;
+        *self.state.readers.get_mut() = 0;
+        *self.state.acquired_at.get_mut() = None;
+        self.owner_changed();
+        self.data.get_mut()
+    }
+
+    /// Consumes the cell, returning the guarded value.
+    ///
+    /// An owner that is still registered, such as a future leaked before it completed, is ignored:
+    /// futures borrow the cell, so taking it by value proves none of them can be polled against it again.
+    pub fn into_inner(self) -> T
+    where
+        T: Sized,
+    {
+        debug_assert!(
+            !crate::thief::owned_by_current(&self),
+            "{} was consumed while its owner is being polled",
+            self.info()
+        );
+        self.data.into_inner()
+    }
+
+    /// Returns a copy of the guarded value without claiming the cell, even while a task owns it.
+    ///
+    /// The owner is not preempted, and can only be between polls while the value is copied on a single threaded executor.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from the task that owns the cell, see [`peek_with()`](Self::peek_with).
+    pub fn peek(&self) -> T
+    where
+        T: Copy,
+    {
+        self.peek_with(|data| *data)
+    }
+
+    /// Calls `f` with a reference to the guarded value without claiming the cell, even while a task owns it.
+    ///
+    /// `f` is synchronous and its result cannot borrow the value, so the reference is never held across an await,
+    /// where the owner could mutate the value. `f` must not run the owner, for example by blocking on it.
+    ///
+    /// This must not be called from the owning task, including futures nested inside it, which may hold a `&mut T` to the value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called while the future that owns the cell is being polled.
+    pub fn peek_with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        assert!(
+            !crate::thief::owned_by_current(self),
+            "{} was peeked by its own owner, which may hold a mutable reference to it",
+            self.info()
+        );
+        // owners only use their reference while being polled, which cannot happen during a synchronous call
+        f(unsafe { &*self.data.get() })
+    }
+
+    /// Replaces the guarded value with `value` if the cell has no owner, returning the old value.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CellOwned`] without touching the value if a task owns the cell.
+    pub fn try_replace(&self, value: T) -> Result<T, CellOwned>
+    where
+        T: Sized,
+    {
+        self.unowned()?;
+        // unowned means no task holds a reference to the data
+        let previous = core::mem::replace(unsafe { &mut *self.data.get() }, value);
+        self.mark_changed();
+        Ok(previous)
+    }
+
+    /// Sets the guarded value to `value` if the cell has no owner, see [`try_replace()`](Self::try_replace).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CellOwned`] without touching the value if a task owns the cell.
+    pub fn try_set(&self, value: T) -> Result<(), CellOwned>
+    where
+        T: Sized,
+    {
+        self.try_replace(value).map(drop)
+    }
+
+    /// Takes the guarded value, leaving `T::default()`, if the cell has no owner.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CellOwned`] without touching the value if a task owns the cell.
+    pub fn try_take(&self) -> Result<T, CellOwned>
+    where
+        T: Default,
+    {
+        self.try_replace(T::default())
+    }
+
+    pub(crate) fn unowned(&self) -> Result<(), CellOwned> {
+        // a task that yielded the cell still holds its reference
+        match self.current_owner().or_else(|| self.yielded_by()) {
+            Some(owner) => Err(CellOwned { owner: Some(owner) }),
+            None if self.state.projected.get() > 0 || self.state.readers.get() > 0 => {
+                Err(CellOwned { owner: None })
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Returns a raw pointer to the guarded value, for low level integrations that cannot go through [`run()`](RevocableCell::run).
+    ///
+    /// Prefer [`run()`](RevocableCell::run), or [`get_mut()`](RevocableCell::get_mut) when no future is borrowing the cell.
+    ///
+    /// # Safety
+    ///
+    /// The pointer may only be dereferenced while no other reference to the value is in use,
+    /// which in practice means the caller owns this cell or no future running on it is being polled.
+    pub unsafe fn data_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+
+    #[doc(hidden)]
+    #[deprecated(note = "use `run`, `get_mut` or the unsafe `data_ptr` instead")]
+    pub fn data(&self) -> &UnsafeCell<T> {
+        &self.data
+    }
+
+    /// Timestamps ownership changes of this cell using `clock`.
+    pub fn with_clock(mut self, clock: &'static dyn Clock) -> Self
+    where
+        T: Sized,
+    {
+        self.state.clock = Some(clock);
+        self
+    }
+
+    /// Returns the metadata the cell was created [`with_meta()`](RevocableCell::with_meta).
+    pub fn meta(&self) -> &M {
+        &self.meta
+    }
+
+    /// Refuses steals by thieves whose [`priority`](ThiefInfo::priority) is below `priority`, even from a cell nobody owns.
+    ///
+    /// Refused steals return [`StealOutcome::Refused`], and [`PreemptibleFuture`](crate::thief::PreemptibleFuture)s below the floor resolve to a [`PreemptionError`](crate::PreemptionError)
+    /// whose [`refused()`](crate::PreemptionError::refused) is set on their first poll, leaving the current owner running.
+    pub fn with_min_steal_priority(mut self, priority: u8) -> Self
+    where
+        T: Sized,
+    {
+        self.state.min_steal_priority = priority;
+        self
+    }
+
+    /// Runs `hook` every time this cell is stolen, before the incoming thief becomes the owner, replacing any previous hook.
+    ///
+    /// The hook runs synchronously inside the steal, so it can act on the hardware behind the cell as soon as control changes hands,
+    /// without waiting for the outgoing task to be polled again.
+    ///
+    /// # Panics
+    ///
+    /// Stealing this cell from inside its own hook panics, since the steal in progress would overwrite the hook's steal.
+    pub fn set_on_steal(&self, hook: StealHook) {
+        self.state.on_steal.set(Some(hook));
+    }
+
+    /// Removes the hook set by [`set_on_steal()`](Self::set_on_steal).
+    pub fn clear_on_steal(&self) {
+        self.state.on_steal.set(None);
+    }
+
+    /// Lets tasks acquire this cell again after a task panicked while owning it, see [`RequirementInfoSource::is_poisoned()`].
+    ///
+    /// The panicking task may have left the data half updated, so it should be checked or reset first.
+    pub fn clear_poison(&self) {
+        self.state.poisoned.set(false);
+    }
+
+    /// Makes this cell permanently read-only, for configuration that must not change once set.
+    ///
+    /// Steals are refused with [`StealOutcome::Refused`], and futures listing the cell resolve to a [`PreemptionError`](crate::PreemptionError)
+    /// whose [`frozen()`](crate::PreemptionError::frozen) is set on their first poll.
+    /// A task owning the cell when it is frozen keeps it until it finishes, and [`peek()`](Self::peek) keeps working.
+    pub fn freeze(&self) {
+        self.state.frozen.set(true);
+    }
+
+    fn call_on_steal(&self, incoming: &ThiefInfo) {
+        if let Some(hook) = self.state.on_steal.get() {
+            assert!(
+                !self.state.in_hook.replace(true),
+                "{} was stolen from its own on_steal hook",
+                self.info()
+            );
+            hook(&self.info(), self.current_owner().as_ref(), incoming);
+            self.state.in_hook.set(false);
+        }
+    }
+
+    /// Returns how often this cell was stolen, acquired, and released.
+    pub fn stats(&self) -> RequirementStats {
+        self.state.stats.get()
+    }
+
+    fn count(&self, counter: impl FnOnce(&mut RequirementStats) -> &mut u32) {
+        let mut stats = self.state.stats.get();
+        let count = counter(&mut stats);
+        *count = count.wrapping_add(1);
+        self.state.stats.set(stats);
+    }
+
+    /// Returns when the current owner acquired this cell, if it is owned and has a [`Clock`].
+    pub fn acquired_at(&self) -> Option<Tick> {
+        self.state.acquired_at.get()
+    }
+
+    /// Returns the retained ownership transitions, oldest first.
+    ///
+    /// Always empty unless the cell was created [`with_history()`](RevocableCell::with_history).
+    pub fn history(&self) -> impl Iterator<Item = OwnershipRecord> + '_ {
+        self.history.iter()
+    }
+
+    /// Returns the retained steals from a live owner, oldest first.
+    ///
+    /// Always empty unless the cell was created [`with_history()`](RevocableCell::with_history).
+    pub fn steal_history(&self) -> impl Iterator<Item = StealRecord> + '_ {
+        self.steals.iter()
+    }
+
+    /// Forgets all retained ownership transitions and steals.
+    pub fn clear_history(&self) {
+        self.history.clear();
+        self.steals.clear();
+    }
+
+    fn record_steal(&self, outgoing: ThiefInfo, incoming: &ThiefInfo, at: Option<Tick>) {
+        self.count(|stats| &mut stats.steals);
+        self.steals.record(StealRecord {
+            outgoing,
+            incoming: *incoming,
+            sequence: crate::thief::poll_sequence(),
+            at,
+        });
+    }
+
+    pub(crate) fn set_yielded(&self, thief: Option<&ThiefInfo>) {
+        self.state.yielded.set(thief.copied());
+    }
+
+    /// Registers `snapshot` to be restored when the current owner is preempted.
+    ///
+    /// # Safety
+    ///
+    /// `snapshot` must stay valid until it is passed to [`clear_rollback()`](Self::clear_rollback).
+    pub(crate) unsafe fn set_rollback(&self, snapshot: NonNull<T>) {
+        self.state.rollback.set(Some(snapshot.cast()));
+    }
+
+    /// Forgets `snapshot` if it is still registered, leaving snapshots of later owners in place.
+    pub(crate) fn clear_rollback(&self, snapshot: NonNull<T>) {
+        if self.state.rollback.get() == Some(snapshot.cast()) {
+            self.state.rollback.set(None);
+        }
+    }
+
+    /// Swaps the registered snapshot back into the cell, leaving the half-written data with the preempted owner.
+    fn roll_back(&self) {
+        if let Some(snapshot) = self.state.rollback.take() {
+            // the preempted owner is suspended, and the incoming owner has not accessed the data yet,
+            // and the snapshot is a separate value of the same type, so swapping their bytes swaps the values
+            unsafe {
+                let len = core::mem::size_of_val(&*self.data.get());
+                core::ptr::swap_nonoverlapping(
+                    self.data.get().cast::<u8>(),
+                    snapshot.as_ptr().cast::<u8>(),
+                    len,
+                );
+            }
+        }
+    }
+
+    pub(crate) fn projected(&self) -> &Cell<usize> {
+        &self.state.projected
+    }
+
+    pub(crate) fn notify_slot(&self) -> &NotifySlot {
+        &self.state.notify
+    }
+
+    pub(crate) fn waiters(&self) -> &WaiterList {
+        &self.state.waiters
+    }
+
+    pub(crate) fn observers(&self) -> &WaiterList {
+        &self.state.observers
+    }
+
+    pub(crate) fn raw_version(&self) -> u64 {
+        self.state.version.get()
+    }
+
+    pub(crate) fn touched(&self) -> u32 {
+        self.state.touched.get()
+    }
+
+    pub(crate) fn dirty_flag(&self) -> &Cell<bool> {
+        &self.state.dirty
+    }
+
+    pub(crate) fn policy_cell(&self) -> &Cell<&'static dyn PreemptionPolicy> {
+        &self.state.policy
+    }
+
+    /// Returns `true` if neither the whole cell nor any of its projections are claimed.
+    pub(crate) fn is_free(&self) -> bool {
+        self.current_claims() == Claims::Unclaimed && self.state.projected.get() == 0
+    }
+
+    #[cfg(feature = "debug-audit")]
+    pub(crate) fn audit_cell(&self) -> &Cell<crate::audit::AccessAudit> {
+        &self.state.audit
+    }
+
+    #[cfg(feature = "futures")]
+    pub(crate) fn subscribers(&self) -> &crate::events::Subscribers {
+        &self.state.events
+    }
+
+    #[cfg(feature = "introspection")]
+    pub(crate) fn set_registration(&mut self, registration: crate::registry::Registration) {
+        registration.update(self.current_owner());
+        self.state.registration = Some(registration);
+    }
+
+    /// Publishes the current owner to the [registry](crate::registry) if this cell is registered.
+    fn owner_changed(&self) {
+        #[cfg(feature = "introspection")]
+        if let Some(registration) = &self.state.registration {
+            registration.update(self.current_owner());
+        }
+    }
+}
+
+impl<T: Default> Default for RevocableCell<T> {
+    /// Creates an unowned cell of `T::default()`, named after `T`.
+    fn default() -> Self {
+        Self::from(T::default())
+    }
+}
+
+impl<T> From<T> for RevocableCell<T> {
+    /// Creates an unowned cell of `data`, named after `T` by [`type_name()`](core::any::type_name).
+    fn from(data: T) -> Self {
+        Self::new(data, core::any::type_name::<T>())
+    }
+}
+
+impl<T: Debug + ?Sized, M: Any, const H: usize> Debug for RevocableCell<T, M, H> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let owner = self.current_owner().or_else(|| self.yielded_by());
+        let claimed =
+            owner.is_some() || self.state.projected.get() > 0 || self.state.readers.get() > 0;
+        let mut debug = f.debug_struct("RevocableCell");
+        debug.field("name", &self.state.name);
+        if let Some(index) = self.state.index {
+            debug.field("index", &index);
+        }
+        debug.field("owner", &owner.map(|owner| owner.name));
+        if claimed {
+            // the owner may be mutating the data
+            debug.field("data", &format_args!("<claimed>"));
+        } else {
+            // unowned means no task holds a reference to the data
+            debug.field("data", &unsafe { &*self.data.get() });
+        }
+        debug.finish()
+    }
+}
+
+impl<T: ?Sized, M: Any, const H: usize> Requirement for RevocableCell<T, M, H> {
+    fn steal_ownership(&self, thief: &ThiefInfo) {
+        self.try_steal_ownership(thief);
+    }
+
+    fn try_steal_ownership(&self, thief: &ThiefInfo) -> StealOutcome {
+        if self.state.frozen.get()
+            || thief.priority < self.state.min_steal_priority
+            || !self.may_steal(thief)
+        {
+            return StealOutcome::Refused {
+                owner: self.current_owner(),
+            };
+        }
+        self.call_on_steal(thief);
+        let displaced = self.state.owner.replace(Some(*thief));
+        self.state.touched.set(crate::stale::current_tick());
+        let now = self.state.clock.map(|clock| clock.now());
+        let had_readers = self.state.readers.replace(0) > 0;
+        if displaced.is_none() && had_readers {
+            self.count(|stats| &mut stats.steals);
+            self.state.last_preemption.set(Some(LastPreemption {
+                by: *thief,
+                generation: self.state.generation.get(),
+                acquired_at: None,
+                stolen_at: now,
+                version: self.version(),
+                slot: None,
+            }));
+        } else if displaced.is_none() {
+            self.count(|stats| &mut stats.acquisitions);
+        }
+        if let Some(displaced) = displaced {
+            self.state.last_preemption.set(Some(LastPreemption {
+                by: *thief,
+                generation: self.state.generation.get(),
+                acquired_at: self.state.acquired_at.get(),
+                stolen_at: now,
+                version: self.version(),
+                slot: None,
+            }));
+            self.state.last_owner.set(Some(LastOwner {
+                thief: displaced,
+                ended: TenureEnd::Preempted { by: *thief },
+                at: now,
+            }));
+            self.record_steal(displaced, thief, now);
+            self.roll_back();
+        }
+        // the waker belongs to the displaced owner, or to a previous tenure if there was none
+        let owner_waker = self
+            .state
+            .owner_waker
+            .take()
+            .filter(|_| displaced.is_some());
+        self.state
+            .generation
+            .set(self.state.generation.get().wrapping_add(1));
+        // a new tenure covers the whole cell, revoking every projection
+        self.state.projected.set(0);
+        self.state.acquired_at.set(now);
+        let record = OwnershipRecord {
+            thief: *thief,
+            kind: if displaced.is_some() {
+                OwnershipChange::Stolen
+            } else {
+                OwnershipChange::Acquired
+            },
+            at: now,
+        };
+        self.history.record(record);
+        #[cfg(feature = "futures")]
+        self.state
+            .events
+            .publish(crate::events::PreemptionEvent::Ownership { record, displaced });
+        self.state.notify.transition();
+        self.owner_changed();
+        if let Some(waker) = owner_waker {
+            waker.wake();
+        }
+        StealOutcome::Acquired { displaced }
+    }
+
+    fn release_ownership(&self) {
+        self.end_ownership(TenureEnd::Completed);
+    }
+
+    fn claim_shared(&self, thief: &ThiefInfo) -> StealOutcome {
+        if self.state.frozen.get()
+            || thief.priority < self.state.min_steal_priority
+            || !self.may_steal(thief)
+        {
+            return StealOutcome::Refused {
+                owner: self.current_owner(),
+            };
+        }
+        let displaced = self.current_owner();
+        self.revoke(thief);
+        self.state.readers.set(self.state.readers.get() + 1);
+        if displaced.is_none() && self.state.readers.get() == 1 {
+            self.state.notify.transition();
+        }
+        StealOutcome::Acquired { displaced }
+    }
+
+    fn release_shared(&self, generation: u64) {
+        let readers = self.state.readers.get();
+        if generation == self.state.generation.get()
+            && self.state.owner.get().is_none()
+            && readers > 0
+        {
+            self.state.readers.set(readers - 1);
+            if readers == 1 {
+                self.state.notify.transition();
+                self.state.waiters.wake_all();
+            }
+        }
+    }
+
+    fn end_ownership(&self, ended: TenureEnd) {
+        if let Some(thief) = self.state.owner.take() {
+            let at = self.state.clock.map(|clock| clock.now());
+            let record = OwnershipRecord {
+                thief,
+                kind: OwnershipChange::Released,
+                at,
+            };
+            self.history.record(record);
+            #[cfg(feature = "futures")]
+            self.state
+                .events
+                .publish(crate::events::PreemptionEvent::Ownership {
+                    record,
+                    displaced: None,
+                });
+            self.state
+                .last_owner
+                .set(Some(LastOwner { thief, ended, at }));
+            if !matches!(ended, TenureEnd::Preempted { .. }) {
+                self.count(|stats| &mut stats.releases);
+            }
+            self.state.notify.transition();
+            self.state.waiters.wake_all();
+            self.owner_changed();
+        }
+        self.state.acquired_at.set(None);
+        if let Some(waker) = self.state.owner_waker.take()
+            && matches!(ended, TenureEnd::Preempted { .. })
+        {
+            waker.wake();
+        }
+    }
+
+    fn revoke(&self, by: &ThiefInfo) {
+        let Some(owner) = self.current_owner().filter(|_| !self.state.frozen.get()) else {
+            return;
+        };
+        self.call_on_steal(by);
+        let now = self.state.clock.map(|clock| clock.now());
+        self.state.last_preemption.set(Some(LastPreemption {
+            by: *by,
+            generation: self.state.generation.get(),
+            acquired_at: self.state.acquired_at.get(),
+            stolen_at: now,
+            version: self.version(),
+            slot: None,
+        }));
+        self.record_steal(owner, by, now);
+        self.roll_back();
+        self.end_ownership(TenureEnd::Preempted { by: *by });
+    }
+
+    fn poison(&self) {
+        self.state.poisoned.set(true);
+    }
+
+    fn is_owned_by(&self, thief: &ThiefInfo) -> bool {
+        self.state
+            .owner
+            .get()
+            .is_some_and(|owner| owner.same_thief(thief))
+    }
+
+    fn mark_polled(&self) {
+        self.state.touched.set(crate::stale::current_tick());
+    }
+
+    fn register_release_waker(&self, waker: &Waker) {
+        self.state.waiters.register(waker);
+    }
+
+    fn register_owner_waker(&self, waker: &Waker) {
+        match self.state.owner_waker.take() {
+            Some(previous) if previous.will_wake(waker) => {
+                self.state.owner_waker.set(Some(previous))
+            }
+            _ => self.state.owner_waker.set(Some(waker.clone())),
+        }
+    }
+
+    fn meta_any(&self) -> Option<&dyn Any> {
+        // cells without metadata carry `()`
+        (TypeId::of::<M>() != TypeId::of::<()>()).then_some(&self.meta as &dyn Any)
+    }
+}
+
+impl<T: ?Sized, M: Any, const H: usize> RequirementInfoSource for RevocableCell<T, M, H> {
+    fn current_owner(&self) -> Option<ThiefInfo> {
+        self.state.owner.get()
+    }
+
+    fn current_claims(&self) -> Claims {
+        match (self.current_owner(), self.state.readers.get()) {
+            (Some(owner), _) => Claims::Exclusive(owner),
+            (None, 0) => Claims::Unclaimed,
+            (None, readers) => Claims::Shared { readers },
+        }
+    }
+
+    fn info(&self) -> RequirementInfo {
+        RequirementInfo {
+            name: self.state.name,
+            index: self.state.index,
+            id: self.state.id,
+            type_name: core::any::type_name::<T>(),
+            version: self.version(),
+        }
+    }
+
+    fn generation(&self) -> u64 {
+        self.state.generation.get()
+    }
+
+    fn last_preemption(&self) -> Option<LastPreemption> {
+        self.state.last_preemption.get()
+    }
+
+    fn last_owner(&self) -> Option<LastOwner> {
+        self.state.last_owner.get()
+    }
+
+    fn last_steal(&self) -> Option<StealRecord> {
+        self.steals.last()
+    }
+
+    fn yielded_by(&self) -> Option<ThiefInfo> {
+        self.state.yielded.get()
+    }
+
+    fn is_poisoned(&self) -> bool {
+        self.state.poisoned.get()
+    }
+
+    fn is_frozen(&self) -> bool {
+        self.state.frozen.get()
+    }
+
+    fn min_steal_priority(&self) -> u8 {
+        self.state.min_steal_priority
+    }
+
+    fn may_steal(&self, incoming: &ThiefInfo) -> bool {
+        self.current_owner().is_none_or(|outgoing| {
+            outgoing.same_thief(incoming) || self.state.policy.get().may_steal(incoming, &outgoing)
+        })
+    }
+}
+
+impl<T: ?Sized, M: Any, const H: usize> RevocableData<T> for RevocableCell<T, M, H> {
+    unsafe fn data_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+
+    fn borrow_flag(&self) -> Option<&BorrowFlag> {
+        Some(&self.state.borrowed)
+    }
+
+    fn mark_changed(&self) {
+        self.state
+            .version
+            .set(self.state.version.get().wrapping_add(1));
+        self.state.dirty.set(true);
+        self.state.observers.wake_all();
+    }
+
+    #[cfg(feature = "debug-audit")]
+    fn record_access(&self, thief: &ThiefInfo, write: bool) {
+        self.record_audit(thief, write);
+    }
+}
+
+/// Wraps another [`Requirement`] to report it under a different name.
+///
+/// Ownership is delegated to the wrapped requirement, so stealing through the wrapper
+/// preempts owners of the original and vice versa; only [`info()`](RequirementInfoSource::info) differs.
+/// This backs the `#[swiper(name = "...")]` parameter attribute of the `preemptible` macro.
+pub struct RenamedRequirement<'a> {
+    inner: &'a dyn Requirement,
+    name: Name,
+}
+
+impl<'a> RenamedRequirement<'a> {
+    /// Creates a view of `inner` whose [`RequirementInfo`] is reported as `name`.
+    pub fn new(inner: &'a dyn Requirement, name: impl Into<Name>) -> Self {
+        Self {
+            inner,
+            name: name.into(),
+        }
+    }
+}
+
+impl Requirement for RenamedRequirement<'_> {
+    fn steal_ownership(&self, thief: &ThiefInfo) {
+        self.inner.steal_ownership(thief);
+    }
+
+    fn try_steal_ownership(&self, thief: &ThiefInfo) -> StealOutcome {
+        self.inner.try_steal_ownership(thief)
+    }
+
+    fn release_ownership(&self) {
+        self.inner.release_ownership();
+    }
+
+    fn claim_shared(&self, thief: &ThiefInfo) -> StealOutcome {
+        self.inner.claim_shared(thief)
+    }
+
+    fn release_shared(&self, generation: u64) {
+        self.inner.release_shared(generation);
+    }
+
+    fn end_ownership(&self, ended: TenureEnd) {
+        self.inner.end_ownership(ended);
+    }
+
+    fn end_ownership_of(&self, thief: &ThiefInfo, ended: TenureEnd) {
+        self.inner.end_ownership_of(thief, ended);
+    }
+
+    fn revoke(&self, by: &ThiefInfo) {
+        self.inner.revoke(by);
+    }
+
+    fn poison(&self) {
+        self.inner.poison();
+    }
+
+    fn is_owned_by(&self, thief: &ThiefInfo) -> bool {
+        self.inner.is_owned_by(thief)
+    }
+
+    fn mark_polled(&self) {
+        self.inner.mark_polled();
+    }
+
+    fn register_owner_waker(&self, waker: &Waker) {
+        self.inner.register_owner_waker(waker);
+    }
+
+    fn register_release_waker(&self, waker: &Waker) {
+        self.inner.register_release_waker(waker);
+    }
+
+    fn meta_any(&self) -> Option<&dyn Any> {
+        self.inner.meta_any()
+    }
+}
+
+impl RequirementInfoSource for RenamedRequirement<'_> {
+    fn current_owner(&self) -> Option<ThiefInfo> {
+        self.inner.current_owner()
+    }
+
+    fn current_claims(&self) -> Claims {
+        self.inner.current_claims()
+    }
+
+    fn info(&self) -> RequirementInfo {
+        let mut info = self.inner.info();
+        info.name = self.name;
+        info.index = None;
+        info
+    }
+
+    fn id(&self) -> RequirementId {
+        self.inner.id()
+    }
+
+    fn generation(&self) -> u64 {
+        self.inner.generation()
+    }
+
+    fn last_preemption(&self) -> Option<LastPreemption> {
+        self.inner.last_preemption()
+    }
+
+    fn last_owner(&self) -> Option<LastOwner> {
+        self.inner.last_owner()
+    }
+
+    fn last_steal(&self) -> Option<StealRecord> {
+        self.inner.last_steal()
+    }
+
+    fn yielded_by(&self) -> Option<ThiefInfo> {
+        self.inner.yielded_by()
+    }
+
+    fn is_poisoned(&self) -> bool {
+        self.inner.is_poisoned()
+    }
+
+    fn is_frozen(&self) -> bool {
+        self.inner.is_frozen()
+    }
+
+    fn min_steal_priority(&self) -> u8 {
+        self.inner.min_steal_priority()
+    }
+
+    fn may_steal(&self, incoming: &ThiefInfo) -> bool {
+        self.inner.may_steal(incoming)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn flag_stealing() {
+        let cell = RevocableCell::new(0, "test");
+        let thief1 = ThiefInfo::new("test");
+        let thief2 = ThiefInfo::new("test");
+        {
+            assert!(cell.current_owner().is_none());
+            cell.steal_ownership(&thief1);
+            assert!(
+                cell.current_owner()
+                    .is_some_and(|owner| owner.same_thief(&thief1))
+            );
+            // steal new flag
+            cell.steal_ownership(&thief2);
+            assert!(cell.current_owner().is_some());
+            assert!(
+                cell.current_owner()
+                    .is_some_and(|owner| owner.same_thief(&thief2))
+            );
+            assert!(
+                !cell
+                    .current_owner()
+                    .is_some_and(|owner| owner.same_thief(&thief1))
+            );
+        }
+        cell.release_ownership();
+        assert!(cell.current_owner().is_none());
+    }
+
+    #[test]
+    fn get_mut_between_owners() {
+        let mut cell = RevocableCell::new(0, "test");
+        *cell.get_mut() += 1;
+
+        let thief1 = ThiefInfo::new("thief1");
+        cell.steal_ownership(&thief1);
+        cell.release_ownership();
+        *cell.get_mut() += 1;
+
+        // a thief that never released is forgotten, so the next steal is not a preemption
+        let stale = ThiefInfo::new("stale");
+        cell.steal_ownership(&stale);
+        *cell.get_mut() += 1;
+        assert!(cell.current_owner().is_none());
+        let thief2 = ThiefInfo::new("thief2");
+        assert_eq!(
+            cell.try_steal_ownership(&thief2),
+            StealOutcome::Acquired { displaced: None }
+        );
+        cell.release_ownership();
+        assert_eq!(*cell.get_mut(), 3);
+        assert_eq!(cell.last_owner().map(|last| last.thief), Some(thief2));
+    }
+
+    #[test]
+    fn into_inner_after_run() {
+        let mut cx = Context::from_waker(Waker::noop());
+        let cell = RevocableCell::new(0, "test");
+        {
+            let task = pin!(cell.run("task", async |x| *x += 2));
+            assert_eq!(task.poll(&mut cx), Poll::Ready(Ok(())));
+        }
+        assert_eq!(cell.into_inner(), 2);
+
+        // a future dropped before completing releases the cell
+        let cell = RevocableCell::new(1, "test");
+        {
+            let task = pin!(crate::thief::PreemptibleFuture::new(
+                core::future::pending::<()>(),
+                "task",
+                [&cell],
+            ));
+            assert!(task.poll(&mut cx).is_pending());
+        }
+        assert!(cell.current_owner().is_none());
+        assert_eq!(
+            cell.last_owner().map(|last| last.ended),
+            Some(TenureEnd::Aborted)
+        );
+
+        // a leaked future leaves a stale owner, which is ignored
+        extern crate std;
+        let mut task = std::boxed::Box::pin(crate::thief::PreemptibleFuture::new(
+            core::future::pending::<()>(),
+            "task",
+            [&cell],
+        ));
+        assert!(task.as_mut().poll(&mut cx).is_pending());
+        core::mem::forget(task);
+        assert!(cell.current_owner().is_some());
+        assert_eq!(cell.into_inner(), 1);
+    }
+
+    #[test]
+    fn replace_when_unowned() {
+        let mut cx = Context::from_waker(Waker::noop());
+        let buffer = RevocableCell::new(5, "buffer");
+        assert_eq!(buffer.try_replace(6), Ok(5));
+        {
+            let mut task = pin!(buffer.run("task", async |x| {
+                *x += 1;
+                futures_lite::future::yield_now().await;
+            }));
+            assert!(task.as_mut().poll(&mut cx).is_pending());
+            let owner = buffer.current_owner();
+            assert_eq!(owner.map(|owner| owner.name), Some("task".into()));
+            let owned = Err(CellOwned { owner });
+            assert_eq!(buffer.try_replace(0), owned);
+            assert_eq!(buffer.try_set(0), owned.map(drop));
+            assert_eq!(buffer.try_take(), owned);
+            assert_eq!(task.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        }
+        assert_eq!(buffer.try_take(), Ok(7));
+        assert_eq!(buffer.try_set(1), Ok(()));
+        assert_eq!(buffer.into_inner(), 1);
+    }
+
+    #[test]
+    fn steal_hook() {
+        extern crate std;
+        use std::{thread_local, vec::Vec};
+
+        type Call = (&'static str, Option<&'static str>, &'static str);
+        thread_local! {
+            static CALLS: core::cell::RefCell<Vec<Call>> = const { core::cell::RefCell::new(Vec::new()) };
+        }
+        fn record(info: &RequirementInfo, outgoing: Option<&ThiefInfo>, incoming: &ThiefInfo) {
+            let call = (
+                info.name.as_str(),
+                outgoing.map(|thief| thief.name.as_str()),
+                incoming.name.as_str(),
+            );
+            CALLS.with_borrow_mut(|calls| calls.push(call));
+        }
+
+        let motor = RevocableCell::new(0.0, "motor");
+        motor.set_on_steal(record);
+        let auto = ThiefInfo::new("auto");
+        let driver = ThiefInfo::new("driver");
+        motor.steal_ownership(&auto);
+        motor.steal_ownership(&driver);
+        motor.release_ownership();
+        motor.clear_on_steal();
+        motor.steal_ownership(&auto);
+        assert_eq!(
+            CALLS.take(),
+            [("motor", None, "auto"), ("motor", Some("auto"), "driver")]
+        );
+    }
+
+    #[test]
+    fn peek_while_owned() {
+        let mut cx = Context::from_waker(Waker::noop());
+        let setpoint = RevocableCell::new(0, "setpoint");
+        let mut task = pin!(setpoint.run("ramp", async |x| {
+            loop {
+                *x += 10;
+                futures_lite::future::yield_now().await;
+            }
+        }));
+        for expected in [10, 20, 30] {
+            assert!(task.as_mut().poll(&mut cx).is_pending());
+            assert_eq!(setpoint.peek(), expected);
+            assert!(setpoint.peek_with(|x| *x % 10 == 0));
+        }
+        // peeking never claimed the cell, so the owner keeps running
+        assert_eq!(
+            setpoint.current_owner().map(|thief| thief.name.as_str()),
+            Some("ramp")
+        );
+        assert!(task.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(setpoint.peek(), 40);
+    }
+
+    #[test]
+    #[should_panic = "peeked by its own owner"]
+    fn peek_from_owner() {
+        let setpoint = RevocableCell::new(0, "setpoint");
+        let task = setpoint.run("ramp", async |x| {
+            *x += 1;
+            setpoint.peek()
+        });
+        let _ = futures_lite::future::block_on(task);
+    }
+
+    #[test]
+    fn debug_output() {
+        extern crate std;
+        use std::format;
+
+        let cell = RevocableCell::new_indexed([1, 2], "wheel", 3);
+        assert_eq!(
+            format!("{cell:?}"),
+            r#"RevocableCell { name: "wheel", index: 3, owner: None, data: [1, 2] }"#
+        );
+        let thief = ThiefInfo::new("drive");
+        cell.steal_ownership(&thief);
+        assert_eq!(
+            format!("{cell:?}"),
+            r#"RevocableCell { name: "wheel", index: 3, owner: Some("drive"), data: <claimed> }"#
+        );
+    }
+
+    #[test]
+    fn type_named_cells() {
+        extern crate std;
+        use std::string::ToString;
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let cell = RevocableCell::<u8>::default();
+        let other = RevocableCell::from(0u8);
+        assert_eq!(cell.info().name, "u8");
+        assert_eq!(cell.info().type_name, "u8");
+        let drive = RevocableCell::new((0.0f32, 0.0f32), "drive");
+        assert_eq!(drive.info().type_name, "(f32, f32)");
+        assert_eq!(
+            drive.info().to_string(),
+            "Requirement { name: drive, type: (f32, f32) } "
+        );
+        assert_ne!(cell.id(), other.id());
+
+        let mut victim = pin!(cell.run("victim", async |_| {
+            core::future::pending::<()>().await;
+        }));
+        assert!(victim.as_mut().poll(&mut cx).is_pending());
+        let thief = pin!(cell.run("thief", async |x| *x = 1));
+        assert!(thief.poll(&mut cx).is_ready());
+        let Poll::Ready(Err(err)) = victim.as_mut().poll(&mut cx) else {
+            panic!("victim should be preempted");
+        };
+        assert!(
+            err.to_string()
+                .contains("Requirement { name: u8, type: u8 }")
+        );
+    }
+
+    #[test]
+    fn unsized_payloads() {
+        use crate::{guard::RevocableGuard, thief::PreemptibleFuture};
+
+        let mut count = 0;
+        let counter = RevocableCell::new(
+            move || {
+                count += 1;
+                count
+            },
+            "counter",
+        );
+        let controller: &RevocableCell<dyn FnMut() -> i32> = &counter;
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut step = pin!(PreemptibleFuture::new(
+            async {
+                let mut controller =
+                    RevocableGuard::current(&controller).expect("step owns controller");
+                controller() + controller()
+            },
+            "step",
+            [&controller],
+        ));
+        assert_eq!(step.as_mut().poll(&mut cx), Poll::Ready(Ok(3)));
+        assert_eq!(controller.info().name, "counter");
+        assert_eq!(controller.version().get(), 1);
+        assert!(controller.current_owner().is_none());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn boxed_unsized_payloads() {
+        extern crate std;
+        use std::boxed::Box;
+
+        use futures_lite::future::pending;
+
+        use crate::{guard::RevocableGuard, thief::PreemptibleFuture};
+
+        let scale: Box<RevocableCell<dyn Fn(i32) -> i32>> =
+            Box::new(RevocableCell::new(|x| x * 2, "scale"));
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut hold = pin!(PreemptibleFuture::new(pending::<()>(), "hold", [&scale]));
+        assert!(hold.as_mut().poll(&mut cx).is_pending());
+        let mut apply = pin!(PreemptibleFuture::new(
+            async { RevocableGuard::current(&scale).expect("apply owns scale")(21) },
+            "apply",
+            [&scale],
+        ));
+        assert_eq!(apply.as_mut().poll(&mut cx), Poll::Ready(Ok(42)));
+        assert!(matches!(hold.as_mut().poll(&mut cx), Poll::Ready(Err(_))));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn leaked_cells_make_static_futures() {
+        extern crate std;
+        use core::pin::Pin;
+        use std::boxed::Box;
+
+        use futures_lite::future::{block_on, yield_now, zip};
+
+        use crate::{guard::RevocableGuard, thief::PreemptibleFuture};
+
+        let drivetrain: &'static RevocableCell<i32> = RevocableCell::new(0, "drivetrain").leak();
+        let drive: Pin<Box<dyn Future<Output = crate::Result<()>>>> =
+            Box::pin(drivetrain.run("drive", async |speed| {
+                *speed = 1;
+                yield_now().await;
+                *speed = 2;
+            }));
+        let brake: Pin<Box<dyn Future<Output = crate::Result<()>>>> =
+            Box::pin(PreemptibleFuture::new_static(
+                async {
+                    *RevocableGuard::current(drivetrain).expect("brake owns drivetrain") = 0;
+                },
+                "brake",
+                [drivetrain],
+            ));
+
+        let (drive, brake) = block_on(zip(drive, brake));
+        assert_eq!(
+            drive
+                .expect_err("brake preempts drive")
+                .incoming()
+                .map(|thief| thief.name),
+            Some("brake".into())
+        );
+        assert_eq!(brake, Ok(()));
+        assert_eq!(drivetrain.peek(), 0);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn runtime_names() {
+        extern crate std;
+        use std::{format, string::ToString};
+
+        let wheels: [_; 2] =
+            core::array::from_fn(|i| RevocableCell::new_named(0, format!("wheel_{i}")));
+        assert_eq!(wheels[1].info().name, "wheel_1");
+        assert_eq!(
+            RevocableCell::new_named(0, format!("wheel_{}", 1))
+                .info()
+                .name,
+            wheels[1].info().name
+        );
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut victim = pin!(wheels[0].run(format!("spin_{}", 0), async |_| {
+            core::future::pending::<()>().await;
+        }));
+        assert!(victim.as_mut().poll(&mut cx).is_pending());
+        let thief = pin!(wheels[0].run("brake", async |x| *x = 1));
+        assert!(thief.poll(&mut cx).is_ready());
+        let Poll::Ready(Err(err)) = victim.as_mut().poll(&mut cx) else {
+            panic!("victim should be preempted");
+        };
+        assert_eq!(err.outgoing().name, "spin_0");
+        assert_eq!(
+            err.to_string(),
+            "outgoing task Thief { name: spin_0 }  was preempted by incoming task Thief { name: brake }  stealing its requirement Requirement { name: wheel_0, type: i32 } "
+        );
+    }
+
+    #[test]
+    fn renamed_requirement() {
+        let cell = RevocableCell::new(0, "original");
+        let renamed = RenamedRequirement::new(&cell, "renamed");
+        let thief = ThiefInfo::new("test");
+
+        assert_eq!(renamed.info().name, "renamed");
+        assert_eq!(cell.info().name, "original");
+
+        renamed.steal_ownership(&thief);
+        assert!(
+            cell.current_owner()
+                .is_some_and(|owner| owner.same_thief(&thief))
+        );
+        cell.release_ownership();
+        assert!(renamed.current_owner().is_none());
+    }
+
+    #[test]
+    fn try_steal_reports_displaced() {
+        struct DefaultImpl<'a>(&'a RevocableCell<i32>);
+
+        impl Requirement for DefaultImpl<'_> {
+            fn steal_ownership(&self, thief: &ThiefInfo) {
+                self.0.steal_ownership(thief);
+            }
+
+            fn release_ownership(&self) {
+                self.0.release_ownership();
+            }
+        }
+
+        impl RequirementInfoSource for DefaultImpl<'_> {
+            fn current_owner(&self) -> Option<ThiefInfo> {
+                self.0.current_owner()
+            }
+
+            fn info(&self) -> RequirementInfo {
+                self.0.info()
+            }
+        }
+
+        let cell = RevocableCell::new(0, "test");
+        let thief1 = ThiefInfo::new("thief1");
+        let thief2 = ThiefInfo::new("thief2");
+
+        for requirement in [&cell as &dyn Requirement, &DefaultImpl(&cell)] {
+            assert_eq!(
+                requirement.try_steal_ownership(&thief1),
+                StealOutcome::Acquired { displaced: None }
+            );
+            assert_eq!(
+                requirement.try_steal_ownership(&thief2),
+                StealOutcome::Acquired {
+                    displaced: Some(thief1)
+                }
+            );
+            assert!(
+                requirement
+                    .current_owner()
+                    .is_some_and(|owner| owner.same_thief(&thief2))
+            );
+            requirement.release_ownership();
+        }
+    }
+
+    #[test]
+    fn is_owned_by_matches_current_owner() {
+        struct DefaultImpl<'a>(&'a dyn Requirement);
+
+        impl Requirement for DefaultImpl<'_> {
+            fn steal_ownership(&self, thief: &ThiefInfo) {
+                self.0.steal_ownership(thief);
+            }
+
+            fn release_ownership(&self) {
+                self.0.release_ownership();
+            }
+        }
+
+        impl RequirementInfoSource for DefaultImpl<'_> {
+            fn current_owner(&self) -> Option<ThiefInfo> {
+                self.0.current_owner()
+            }
+
+            fn info(&self) -> RequirementInfo {
+                self.0.info()
+            }
+        }
+
+        let cell = RevocableCell::new(0, "test");
+        let other = RevocableCell::new(0, "other");
+        let set = crate::set::RequirementSet::new("both", [&cell, &other]);
+        // same name, different thieves
+        let thief1 = ThiefInfo::new("thief");
+        let thief2 = ThiefInfo::new("thief");
+
+        // the overrides give the same answer as comparing the current owner, which the default does
+        let check = |steps: &[(&dyn Requirement, Option<&ThiefInfo>)]| {
+            for &(requirement, owner) in steps {
+                match owner {
+                    Some(owner) => requirement.steal_ownership(owner),
+                    None => requirement.release_ownership(),
+                }
+                for candidate in [&thief1, &thief2] {
+                    let expected = owner.is_some_and(|owner| owner.same_thief(candidate));
+                    assert_eq!(requirement.is_owned_by(candidate), expected);
+                    assert_eq!(DefaultImpl(requirement).is_owned_by(candidate), expected);
+                }
+            }
+        };
+        check(&[
+            (&cell, Some(&thief1)),
+            (&cell, Some(&thief2)),
+            (&cell, None),
+            (&set, Some(&thief1)),
+            (&set, None),
+        ]);
+
+        // a set is only owned by a thief owning every member
+        set.steal_ownership(&thief1);
+        other.steal_ownership(&thief2);
+        assert!(!set.is_owned_by(&thief1));
+        assert!(!set.is_owned_by(&thief2));
+    }
+
+    #[test]
+    fn metadata_downcasts() {
+        #[derive(Debug, PartialEq)]
+        enum Subsystem {
+            Drive,
+            Arm,
+        }
+
+        struct Bus {
+            can_id: u8,
+        }
+
+        let drive = RevocableCell::with_meta(0, "drive", Subsystem::Drive);
+        let arm = RevocableCell::with_meta(0, "arm", Subsystem::Arm);
+        // owned metadata only known at runtime
+        let can_id = 7;
+        let wrist = RevocableCell::with_meta(0, "wrist", Bus { can_id });
+        let plain = RevocableCell::new(0, "plain");
+        let requirements: [&dyn Requirement; 4] = [&drive, &arm, &wrist, &plain];
+
+        let subsystems = requirements.map(|requirement| {
+            requirement
+                .meta_any()
+                .and_then(|meta| meta.downcast_ref::<Subsystem>())
+        });
+        assert_eq!(
+            subsystems,
+            [Some(&Subsystem::Drive), Some(&Subsystem::Arm), None, None]
+        );
+        assert_eq!(wrist.meta().can_id, 7);
+        assert!(plain.meta_any().is_none());
+
+        // wrappers report the metadata of the requirement they wrap
+        let renamed = RenamedRequirement::new(&drive, "base");
+        assert!(
+            renamed
+                .meta_any()
+                .is_some_and(|meta| meta.is::<Subsystem>())
+        );
+    }
+
+    #[test]
+    fn last_preemption_record() {
+        let cell = RevocableCell::new(0, "test");
+        let thief1 = ThiefInfo::new("thief1");
+        let thief2 = ThiefInfo::new("thief2");
+        let thief3 = ThiefInfo::new("thief3");
+
+        // acquiring an unowned cell is not a preemption
+        cell.steal_ownership(&thief1);
+        assert_eq!(cell.generation(), 1);
+        assert_eq!(cell.last_preemption(), None);
+
+        cell.steal_ownership(&thief2);
+        assert_eq!(cell.generation(), 2);
+        let expected = LastPreemption {
+            by: thief2,
+            generation: 1,
+            acquired_at: None,
+            stolen_at: None,
+            version: Version::default(),
+            slot: None,
+        };
+        assert_eq!(cell.last_preemption(), Some(expected));
+
+        // releasing and re-acquiring keeps the record
+        cell.release_ownership();
+        assert_eq!(cell.last_preemption(), Some(expected));
+        cell.steal_ownership(&thief1);
+        assert_eq!(cell.last_preemption(), Some(expected));
+
+        // a later preemption overwrites it
+        cell.steal_ownership(&thief3);
+        assert_eq!(
+            cell.last_preemption(),
+            Some(LastPreemption {
+                by: thief3,
+                generation: 3,
+                acquired_at: None,
+                stolen_at: None,
+                version: Version::default(),
+                slot: None,
+            })
+        );
+    }
+
+    #[test]
+    fn last_owner_end_kinds() {
+        extern crate std;
+        use std::boxed::Box;
+
+        let clock: &'static ManualClock = Box::leak(Box::new(ManualClock::new()));
+        let cell = RevocableCell::new(0, "test").with_clock(clock);
+        let thief1 = ThiefInfo::new("thief1");
+        let thief2 = ThiefInfo::new("thief2");
+        let last = |thief, ended, at| {
+            Some(LastOwner {
+                thief,
+                ended,
+                at: Some(Tick(at)),
+            })
+        };
+
+        assert_eq!(cell.last_owner(), None);
+        // acquiring an unowned cell ends no tenure
+        cell.steal_ownership(&thief1);
+        assert_eq!(cell.last_owner(), None);
+
+        clock.advance(1);
+        cell.steal_ownership(&thief2);
+        let preempted = TenureEnd::Preempted { by: thief2 };
+        assert_eq!(cell.last_owner(), last(thief1, preempted, 1));
+
+        clock.advance(1);
+        cell.release_ownership();
+        assert_eq!(cell.last_owner(), last(thief2, TenureEnd::Completed, 2));
+        // releasing an unowned cell keeps the record
+        cell.end_ownership(TenureEnd::Aborted);
+        assert_eq!(cell.last_owner(), last(thief2, TenureEnd::Completed, 2));
+
+        for (at, ended) in [(3, TenureEnd::Expired), (4, TenureEnd::Aborted)] {
+            clock.set(Tick(at));
+            cell.steal_ownership(&thief1);
+            cell.end_ownership(ended);
+            assert_eq!(cell.last_owner(), last(thief1, ended, at));
+        }
+    }
+
+    #[test]
+    fn ownership_history() {
+        extern crate std;
+        use std::{boxed::Box, vec::Vec};
+
+        let clock: &'static ManualClock = Box::leak(Box::new(ManualClock::new()));
+        let cell = RevocableCell::new(0, "test")
+            .with_clock(clock)
+            .with_history::<3>();
+        let thief1 = ThiefInfo::new("thief1");
+        let thief2 = ThiefInfo::new("thief2");
+
+        let record = |thief, kind, at| OwnershipRecord {
+            thief,
+            kind,
+            at: Some(Tick(at)),
+        };
+
+        assert_eq!(cell.history().count(), 0);
+        cell.steal_ownership(&thief1);
+        clock.advance(1);
+        cell.steal_ownership(&thief2);
+        assert_eq!(
+            cell.history().collect::<Vec<_>>(),
+            [
+                record(thief1, OwnershipChange::Acquired, 0),
+                record(thief2, OwnershipChange::Stolen, 1),
+            ]
+        );
+
+        clock.advance(1);
+        cell.release_ownership();
+        clock.advance(1);
+        cell.steal_ownership(&thief1);
+        clock.advance(1);
+        cell.release_ownership();
+        // releasing an unowned cell is not a transition
+        cell.release_ownership();
+
+        // only the last 3 of 5 transitions are retained
+        assert_eq!(
+            cell.history().collect::<Vec<_>>(),
+            [
+                record(thief2, OwnershipChange::Released, 2),
+                record(thief1, OwnershipChange::Acquired, 3),
+                record(thief1, OwnershipChange::Released, 4),
+            ]
+        );
+
+        cell.clear_history();
+        assert_eq!(cell.history().count(), 0);
+        cell.steal_ownership(&thief2);
+        assert_eq!(
+            cell.history().collect::<Vec<_>>(),
+            [record(thief2, OwnershipChange::Acquired, 4)]
+        );
+
+        // cells without history never record
+        let plain = RevocableCell::new(0, "plain");
+        plain.steal_ownership(&thief1);
+        assert_eq!(plain.history().count(), 0);
+    }
+
+    #[test]
+    fn requirement_identity() {
+        let a = RevocableCell::new(0, "same");
+        let b = RevocableCell::new(0, "same");
+        assert_ne!(a.id(), b.id());
+        assert_ne!(a.info(), b.info());
+        assert_eq!(a.info(), a.info());
+        assert_eq!(a.info().id(), a.id());
+
+        // only the alternate form tells them apart
+        extern crate std;
+        use std::format;
+        assert_eq!(format!("{}", a.info()), format!("{}", b.info()));
+        assert_eq!(
+            format!("{:#}", a.info()),
+            format!("Requirement {{ name: same, type: i32, id: {} }} ", a.id())
+        );
+
+        // renaming keeps the identity
+        let renamed = RenamedRequirement::new(&a, "renamed");
+        assert_eq!(renamed.id(), a.id());
+        assert_eq!(renamed.info(), a.info());
+
+        // so does enabling history
+        let id = a.id();
+        assert_eq!(a.with_history::<1>().id(), id);
+    }
+
+    #[test]
+    fn indexed_names() {
+        extern crate std;
+        use std::string::ToString;
+
+        let single = RevocableCell::new_indexed(0, "wheel", 7);
+        assert_eq!(single.info().index, Some(7));
+        assert_eq!(
+            single.info().to_string(),
+            "Requirement { name: wheel[7], type: i32 } "
+        );
+
+        let mut wheels = RevocableCell::<usize>::array::<4>(|i| i * 10, "wheel");
+        for (i, wheel) in wheels.iter_mut().enumerate() {
+            assert_eq!(wheel.info().name, "wheel");
+            assert_eq!(wheel.info().index, Some(i));
+            assert_eq!(*wheel.get_mut(), i * 10);
+        }
+        assert_ne!(wheels[0].id(), wheels[1].id());
+
+        // renaming replaces the indexed name entirely
+        assert_eq!(
+            RenamedRequirement::new(&wheels[1], "front_right")
+                .info()
+                .index,
+            None
+        );
+    }
+
+    #[test]
+    fn read_only_inspector() {
+        fn describe(source: &dyn RequirementInfoSource) -> Option<&'static str> {
+            source.current_owner().map(|owner| owner.name.as_str())
+        }
+
+        let cell = RevocableCell::new(0, "test");
+        let thief = ThiefInfo::new("thief");
+        let requirements: [&dyn Requirement; 1] = [&cell];
+
+        assert_eq!(describe(requirements[0]), None);
+        requirements[0].steal_ownership(&thief);
+        assert_eq!(describe(requirements[0]), Some("thief"));
+        assert_eq!(describe(&cell), Some("thief"));
     }
 }
@@ -0,0 +1,423 @@
+//! A `Sync` variant of [`RevocableCell`](super::RevocableCell), for executors that poll tasks from several threads.
+//!
+//! A [`SyncRevocableCell`] keeps its owner in atomics instead of a `Cell`: stealing swaps the owner, and ownership checks load it.
+//! Owners are identified by a unique id assigned to each [`SyncPreemptibleFuture`] rather than by address,
+//! so a thief running on another thread is never dereferenced.
+//! A [`SyncPreemptibleFuture`] is `Send` whenever its inner future is.
+//!
+//! Ownership is checked before and after every poll of the inner future, so a task stolen from while it was being polled
+//! resolves to `Err(PreemptionError)` from that same poll, and releasing a requirement never clobbers a thief that stole it concurrently.
+//! This guarantee is weaker than for single threaded cells: a steal can happen in the middle of the victim's poll,
+//! so the victim and the thief may both hold a reference to the data until the victim's poll returns.
+//! [`SyncRevocableCell::run()`] is `unsafe` for that reason, and its callers promise that two futures of the same cell are never polled at the same time.
+//! Futures listing only the [`SyncOwnership`] of cells never reach their data, so they can be polled from any number of threads at once.
+
+use core::{
+    cell::UnsafeCell,
+    hint::spin_loop,
+    num::NonZeroU64,
+    pin::Pin,
+    sync::atomic::{AtomicU8, AtomicU16, AtomicU32, AtomicUsize, Ordering, fence},
+    task::{Context, Poll, ready},
+};
+
+use crate::{
+    PreemptionError, PreemptionKind, Result,
+    name::Name,
+    notify::Version,
+    requirement::{RequirementId, RequirementInfo},
+    thief::ThiefInfo,
+};
+
+/// Ownership state of a [`SyncRevocableCell`], independent of its data so futures can list cells of different types
+pub struct SyncOwnership {
+    /// Id of the owning [`SyncPreemptibleFuture`], or 0 if the cell is unowned
+    owner: AtomicUsize,
+    /// Odd while a steal writes `owner` and the fields describing its thief, see [`read()`](Self::read)
+    seq: AtomicU32,
+    /// Name of the most recent thief, kept after it releases the cell
+    owner_name: AtomicU16,
+    /// Low and high halves of the id of the [`ThiefInfo`] of the most recent thief, 0 before the first steal
+    owner_info: [AtomicU32; 2],
+    /// Priority of the most recent thief
+    owner_priority: AtomicU8,
+    generation: AtomicU32,
+    name: Name,
+    id: RequirementId,
+    type_name: &'static str,
+}
+
+impl SyncOwnership {
+    fn new<T>(name: Name) -> Self {
+        Self {
+            owner: AtomicUsize::new(0),
+            seq: AtomicU32::new(0),
+            owner_name: AtomicU16::new(0),
+            owner_info: [const { AtomicU32::new(0) }; 2],
+            owner_priority: AtomicU8::new(0),
+            generation: AtomicU32::new(0),
+            name,
+            id: RequirementId::next(),
+            type_name: core::any::type_name::<T>(),
+        }
+    }
+
+    /// Returns the metadata of the cell.
+    pub fn info(&self) -> RequirementInfo {
+        RequirementInfo {
+            name: self.name,
+            index: None,
+            id: self.id,
+            type_name: self.type_name,
+            version: Version::default(),
+        }
+    }
+
+    /// Returns the current owner, which may already have been replaced by a concurrent steal.
+    pub fn current_owner(&self) -> Option<ThiefInfo> {
+        self.read(|| {
+            let owned = self.owner.load(Ordering::Acquire) != 0;
+            self.load_thief().filter(|_| owned)
+        })
+    }
+
+    /// Returns the most recent thief of the cell, even if it has released it since, or `None` if it was never stolen.
+    fn last_thief(&self) -> Option<ThiefInfo> {
+        self.read(|| self.load_thief())
+    }
+
+    fn load_thief(&self) -> Option<ThiefInfo> {
+        let id = u64::from(self.owner_info[0].load(Ordering::Relaxed))
+            | u64::from(self.owner_info[1].load(Ordering::Relaxed)) << 32;
+        Some(ThiefInfo {
+            name: Name::from_raw(self.owner_name.load(Ordering::Relaxed)),
+            id: NonZeroU64::new(id)?,
+            priority: self.owner_priority.load(Ordering::Relaxed),
+        })
+    }
+
+    /// Runs `read` until no steal wrote the thief fields in the meantime, so it never sees a mix of two thieves.
+    fn read<R>(&self, read: impl Fn() -> R) -> R {
+        loop {
+            let seq = self.seq.load(Ordering::Acquire);
+            if seq & 1 == 0 {
+                let value = read();
+                fence(Ordering::Acquire);
+                if self.seq.load(Ordering::Relaxed) == seq {
+                    return value;
+                }
+            }
+            spin_loop();
+        }
+    }
+
+    /// Returns the number of times the cell was stolen, wrapping around at `u32::MAX`.
+    pub fn generation(&self) -> u32 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    fn steal(&self, thief: usize, info: ThiefInfo) {
+        // concurrent steals take turns, so `owner` and the thief fields always describe the same thief
+        let mut seq = self.seq.load(Ordering::Relaxed);
+        loop {
+            if seq & 1 == 0 {
+                match self.seq.compare_exchange_weak(
+                    seq,
+                    seq.wrapping_add(1),
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => break,
+                    Err(current) => seq = current,
+                }
+            } else {
+                spin_loop();
+                seq = self.seq.load(Ordering::Relaxed);
+            }
+        }
+        fence(Ordering::Release);
+        self.owner_name.store(info.name.to_raw(), Ordering::Relaxed);
+        // split in halves, since not every target has 64 bit atomics
+        self.owner_info[0].store(info.id.get() as u32, Ordering::Relaxed);
+        self.owner_info[1].store((info.id.get() >> 32) as u32, Ordering::Relaxed);
+        self.owner_priority.store(info.priority, Ordering::Relaxed);
+        self.owner.swap(thief, Ordering::AcqRel);
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        self.seq.store(seq.wrapping_add(2), Ordering::Release);
+    }
+
+    fn is_owner(&self, thief: usize) -> bool {
+        self.owner.load(Ordering::Acquire) == thief
+    }
+
+    /// Releases the cell if `thief` still owns it.
+    fn release(&self, thief: usize) {
+        let _ = self
+            .owner
+            .compare_exchange(thief, 0, Ordering::AcqRel, Ordering::Acquire);
+    }
+}
+
+/// A [`RevocableCell`](super::RevocableCell) that can be shared between threads, see the [module-level documentation](self)
+pub struct SyncRevocableCell<T> {
+    data: UnsafeCell<T>,
+    ownership: SyncOwnership,
+}
+
+// the data is only accessed by the owning task, which can run on any thread
+unsafe impl<T: Send> Sync for SyncRevocableCell<T> {}
+
+impl<T> SyncRevocableCell<T> {
+    /// Creates a new [`SyncRevocableCell`] with ownership of `data`.
+    pub fn new(data: T, name: impl Into<Name>) -> Self {
+        Self {
+            data: data.into(),
+            ownership: SyncOwnership::new::<T>(name.into()),
+        }
+    }
+
+    /// Returns the ownership state of this cell, for listing it as a requirement of a [`SyncPreemptibleFuture`].
+    pub fn ownership(&self) -> &SyncOwnership {
+        &self.ownership
+    }
+
+    /// Returns a mutable reference to the guarded value, which is safe since the exclusive borrow means no task is running on this cell.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    /// Consumes the cell, returning the guarded value.
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+
+    /// Returns a raw pointer to the underlying data in this cell.
+    ///
+    /// # Safety
+    ///
+    /// The caller must own this cell for the lifetime of any reference created from the pointer.
+    pub unsafe fn data_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+
+    /// Same as [`RevocableCell::run()`](super::RevocableCell::run), returning a future that can be polled from any thread.
+    ///
+    /// # Safety
+    ///
+    /// The future must not be polled while another future created by `run` on this cell is being polled, on any thread.
+    /// A steal only takes effect once the victim's poll returns, so two concurrent polls would both mutate the data.
+    ///
+    /// # Errors
+    ///
+    /// If this cell has been stolen by a different future, the future returns `Err<PreemptionError>` when next polled,
+    /// or when the poll it was stolen during returns.
+    pub unsafe fn run<'a, Out>(
+        &'a self,
+        name: impl Into<Name>,
+        func: impl AsyncFnOnce(&mut T) -> Out + 'a,
+    ) -> SyncPreemptibleFuture<'a, impl Future<Output = Out>, 1> {
+        // the reference is only created once the future has stolen the cell
+        let inner = async move { func(unsafe { &mut *self.data_ptr() }).await };
+        SyncPreemptibleFuture::new(inner, name, [&self.ownership])
+    }
+}
+
+/// A future that steals [`SyncRevocableCell`]s when first polled, see the [module-level documentation](self)
+pub struct SyncPreemptibleFuture<'a, Fut, const N: usize> {
+    inner: Fut,
+    info: ThiefInfo,
+    id: usize,
+    requirements: [&'a SyncOwnership; N],
+    first_run: bool,
+}
+
+impl<'a, Fut: Future, const N: usize> SyncPreemptibleFuture<'a, Fut, N> {
+    /// Creates a future that steals `requirements` when first polled, then runs `inner` until it completes or is preempted.
+    pub fn new(inner: Fut, name: impl Into<Name>, requirements: [&'a SyncOwnership; N]) -> Self {
+        // 0 marks an unowned cell
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+        Self {
+            inner,
+            info: ThiefInfo::new(name),
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            requirements,
+            first_run: true,
+        }
+    }
+
+    /// Returns the error for the first requirement this future no longer owns.
+    fn check_owned(&self) -> Option<PreemptionError> {
+        let requirement = self
+            .requirements
+            .iter()
+            .find(|requirement| !requirement.is_owner(self.id))?;
+        // only a thief overwrites the thief fields after this future stole the requirement
+        Some(PreemptionError {
+            incoming: requirement.last_thief(),
+            ..PreemptionError::new(PreemptionKind::Stolen, self.info, requirement.info())
+        })
+    }
+}
+
+impl<Fut: Future, const N: usize> Future for SyncPreemptibleFuture<'_, Fut, N> {
+    type Output = Result<Fut::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // inner is structurally pinned, the other fields are not
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.first_run {
+            this.first_run = false;
+            for requirement in this.requirements {
+                requirement.steal(this.id, this.info);
+            }
+        } else if let Some(err) = this.check_owned() {
+            return Poll::Ready(Err(err));
+        }
+
+        let res = unsafe { Pin::new_unchecked(&mut this.inner) }.poll(cx);
+        // a thief may have stolen a requirement on another thread during the poll
+        if let Some(err) = this.check_owned() {
+            return Poll::Ready(Err(err));
+        }
+        let out = ready!(res);
+        for requirement in this.requirements {
+            requirement.release(this.id);
+        }
+        Poll::Ready(Ok(out))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::sync::atomic::AtomicBool;
+    use core::{
+        pin::pin,
+        task::{Context, Waker},
+    };
+    use std::{
+        collections::{HashMap, HashSet},
+        sync::mpsc,
+        thread,
+        vec::Vec,
+    };
+
+    use futures_lite::future::yield_now;
+
+    use super::*;
+
+    fn assert_send<T: Send>(value: T) -> T {
+        value
+    }
+
+    #[test]
+    fn stolen_across_threads() {
+        let arm = SyncRevocableCell::new(0, "arm");
+        let (polled, wait_polled) = mpsc::channel();
+        let (stolen, wait_stolen) = mpsc::channel();
+
+        // the polls of both tasks are sequenced through the channels
+        let task = assert_send(unsafe {
+            arm.run::<()>("a", async |x| {
+                loop {
+                    *x += 1;
+                    yield_now().await;
+                }
+            })
+        });
+        thread::scope(|scope| {
+            let victim = scope.spawn(move || {
+                let mut task = pin!(task);
+                let mut cx = Context::from_waker(Waker::noop());
+                assert!(task.as_mut().poll(&mut cx).is_pending());
+                polled.send(()).unwrap();
+                wait_stolen.recv().unwrap();
+                let Poll::Ready(Err(err)) = task.as_mut().poll(&mut cx) else {
+                    panic!("a should be preempted by b");
+                };
+                err
+            });
+
+            wait_polled.recv().unwrap();
+            let mut cx = Context::from_waker(Waker::noop());
+            let mut thief = pin!(unsafe { arm.run("b", async |x| *x += 10) });
+            assert_eq!(thief.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+            stolen.send(()).unwrap();
+
+            let err = victim.join().unwrap();
+            assert_eq!(err.incoming().map(|thief| thief.name.as_str()), Some("b"));
+            assert_eq!(err.requirement().name, "arm");
+        });
+        // b released the cell, and the preempted task did not take it back
+        assert_eq!(arm.ownership().current_owner(), None);
+        assert_eq!(arm.ownership().generation(), 2);
+        assert_eq!(arm.into_inner(), 11);
+    }
+
+    #[test]
+    fn concurrent_steals() {
+        let counter = SyncRevocableCell::new(0u32, "counter");
+        thread::scope(|scope| {
+            for name in ["a", "b", "c", "d"] {
+                let counter = &counter;
+                scope.spawn(move || {
+                    let mut cx = Context::from_waker(Waker::noop());
+                    for _ in 0..100 {
+                        // concurrent thieves are polled at the same time, so they only list the ownership and never reach the data
+                        let mut task = pin!(SyncPreemptibleFuture::new(
+                            async {},
+                            name,
+                            [counter.ownership()]
+                        ));
+                        // a concurrent steal may preempt the task, but never corrupts ownership
+                        let _ = task.as_mut().poll(&mut cx);
+                    }
+                });
+            }
+        });
+        assert_eq!(counter.ownership().current_owner(), None);
+        assert_eq!(counter.ownership().generation(), 400);
+    }
+
+    #[test]
+    fn owner_never_torn() {
+        let counter = SyncRevocableCell::new(0u32, "counter");
+        let done = AtomicBool::new(false);
+        let (thieves, observed) = thread::scope(|scope| {
+            let stealers = ["a", "b", "c", "d"].map(|name| {
+                let counter = &counter;
+                scope.spawn(move || {
+                    let mut cx = Context::from_waker(Waker::noop());
+                    let mut ids = HashSet::new();
+                    for _ in 0..100 {
+                        let mut task = pin!(SyncPreemptibleFuture::new(
+                            yield_now(),
+                            name,
+                            [counter.ownership()]
+                        ));
+                        ids.insert(task.info.id);
+                        let _ = task.as_mut().poll(&mut cx);
+                    }
+                    (name, ids)
+                })
+            });
+            let reader = scope.spawn(|| {
+                let mut observed = Vec::new();
+                while !done.load(Ordering::Relaxed) {
+                    observed.extend(counter.ownership().current_owner());
+                }
+                observed
+            });
+            let thieves: HashMap<_, _> = stealers.map(|stealer| stealer.join().unwrap()).into();
+            done.store(true, Ordering::Relaxed);
+            (thieves, reader.join().unwrap())
+        });
+        for owner in observed {
+            assert!(
+                thieves[owner.name.as_str()].contains(&owner.id),
+                "{owner} is torn"
+            );
+        }
+    }
+}
@@ -0,0 +1,318 @@
+//! A requirement with several owners at once, for a resource that a fixed number of tasks can use concurrently.
+//!
+//! A [`SemaphoreCell`] has `K` slots, each with its own data, and is listed as a requirement like any other cell.
+//! A task claiming it takes a free slot if there is one, and otherwise steals the slot acquired longest ago,
+//! preempting only the task that held that slot.
+//! The [`PreemptionError`](crate::PreemptionError) of a preempted task reports the lost slot as the requirement's index.
+//! Inside the task, [`with_slot()`](SemaphoreCell::with_slot) gives access to the data of the slot it holds.
+
+use core::cell::{Cell, UnsafeCell};
+
+use crate::{
+    current_thief,
+    name::Name,
+    notify::Version,
+    requirement::{
+        LastOwner, LastPreemption, Requirement, RequirementId, RequirementInfo,
+        RequirementInfoSource, StealOutcome, TenureEnd,
+    },
+    thief::ThiefInfo,
+};
+
+/// `K` slots of data owned by up to `K` tasks at once, see the [module-level documentation](self)
+pub struct SemaphoreCell<T, const K: usize> {
+    data: [UnsafeCell<T>; K],
+    owners: [Cell<Option<ThiefInfo>>; K],
+    /// Value of `acquisitions` when each slot was last acquired
+    acquired: [Cell<u64>; K],
+    acquisitions: Cell<u64>,
+    /// Number of preemptions, which identifies the tenures ended by them
+    generation: Cell<u64>,
+    last_preemption: Cell<Option<LastPreemption>>,
+    last_owner: Cell<Option<LastOwner>>,
+    /// Set while [`with_slot()`](Self::with_slot) hands out a reference
+    in_slot: Cell<bool>,
+    name: Name,
+    id: RequirementId,
+}
+
+impl<T, const K: usize> SemaphoreCell<T, K> {
+    /// Creates a semaphore named `name`, with data produced by `init` for each slot.
+    pub fn new(mut init: impl FnMut(usize) -> T, name: impl Into<Name>) -> Self {
+        Self {
+            data: core::array::from_fn(|index| UnsafeCell::new(init(index))),
+            owners: core::array::from_fn(|_| Cell::new(None)),
+            acquired: core::array::from_fn(|_| Cell::new(0)),
+            acquisitions: Cell::new(0),
+            generation: Cell::new(0),
+            last_preemption: Cell::new(None),
+            last_owner: Cell::new(None),
+            in_slot: Cell::new(false),
+            name: name.into(),
+            id: RequirementId::next(),
+        }
+    }
+
+    /// Returns the slot owned by `thief`, if any.
+    pub fn slot_of(&self, thief: &ThiefInfo) -> Option<usize> {
+        self.owners
+            .iter()
+            .position(|owner| owner.get().is_some_and(|owner| owner.same_thief(thief)))
+    }
+
+    /// Returns the owner of each slot.
+    pub fn owners(&self) -> [Option<ThiefInfo>; K] {
+        core::array::from_fn(|index| self.owners[index].get())
+    }
+
+    /// Returns the number of slots that currently have no owner.
+    pub fn available(&self) -> usize {
+        self.owners
+            .iter()
+            .filter(|owner| owner.get().is_none())
+            .count()
+    }
+
+    /// Calls `f` with the index and data of the slot owned by the [`PreemptibleFuture`](crate::thief::PreemptibleFuture) being polled.
+    ///
+    /// Returns `None` without calling `f` outside of an owner, or from inside another call of `with_slot()`.
+    pub fn with_slot<R>(&self, f: impl FnOnce(usize, &mut T) -> R) -> Option<R> {
+        let index = self.slot_of(&current_thief()?)?;
+        if self.in_slot.replace(true) {
+            return None;
+        }
+        // only the owner of a slot reaches its data, during a synchronous call that cannot be re-entered
+        let result = f(index, unsafe { &mut *self.data[index].get() });
+        self.in_slot.set(false);
+        Some(result)
+    }
+
+    /// Consumes the semaphore, returning the data of every slot.
+    pub fn into_inner(self) -> [T; K] {
+        self.data.map(UnsafeCell::into_inner)
+    }
+
+    /// Returns the occupied slot acquired most recently.
+    fn newest(&self) -> Option<usize> {
+        (0..K)
+            .filter(|&index| self.owners[index].get().is_some())
+            .max_by_key(|&index| self.acquired[index].get())
+    }
+
+    fn end_slot(&self, index: usize, ended: TenureEnd) {
+        if let Some(thief) = self.owners[index].take() {
+            self.last_owner.set(Some(LastOwner {
+                thief,
+                ended,
+                at: None,
+            }));
+        }
+    }
+}
+
+impl<T, const K: usize> Requirement for SemaphoreCell<T, K> {
+    fn steal_ownership(&self, thief: &ThiefInfo) {
+        self.try_steal_ownership(thief);
+    }
+
+    fn try_steal_ownership(&self, thief: &ThiefInfo) -> StealOutcome {
+        if K == 0 {
+            return StealOutcome::Refused { owner: None };
+        }
+        if self.slot_of(thief).is_some() {
+            return StealOutcome::Acquired { displaced: None };
+        }
+        let index = self
+            .owners
+            .iter()
+            .position(|owner| owner.get().is_none())
+            .or_else(|| (0..K).min_by_key(|&index| self.acquired[index].get()))
+            .unwrap_or(0);
+        let displaced = self.owners[index].replace(Some(*thief));
+        if let Some(displaced) = displaced {
+            self.last_preemption.set(Some(LastPreemption {
+                by: *thief,
+                generation: self.generation.get(),
+                acquired_at: None,
+                stolen_at: None,
+                version: Version::default(),
+                slot: Some(index),
+            }));
+            self.last_owner.set(Some(LastOwner {
+                thief: displaced,
+                ended: TenureEnd::Preempted { by: *thief },
+                at: None,
+            }));
+            self.generation.set(self.generation.get() + 1);
+        }
+        self.acquisitions.set(self.acquisitions.get() + 1);
+        self.acquired[index].set(self.acquisitions.get());
+        StealOutcome::Acquired { displaced }
+    }
+
+    /// Releases the slot acquired most recently, use [`end_ownership_of()`](Requirement::end_ownership_of) to release a given owner.
+    fn release_ownership(&self) {
+        self.end_ownership(TenureEnd::Completed);
+    }
+
+    fn end_ownership(&self, ended: TenureEnd) {
+        if let Some(index) = self.newest() {
+            self.end_slot(index, ended);
+        }
+    }
+
+    fn end_ownership_of(&self, thief: &ThiefInfo, ended: TenureEnd) {
+        if let Some(index) = self.slot_of(thief) {
+            self.end_slot(index, ended);
+        }
+    }
+
+    fn is_owned_by(&self, thief: &ThiefInfo) -> bool {
+        self.slot_of(thief).is_some()
+    }
+}
+
+impl<T, const K: usize> RequirementInfoSource for SemaphoreCell<T, K> {
+    /// Returns the owner of the slot acquired most recently.
+    fn current_owner(&self) -> Option<ThiefInfo> {
+        self.owners[self.newest()?].get()
+    }
+
+    fn info(&self) -> RequirementInfo {
+        RequirementInfo {
+            name: self.name,
+            index: None,
+            id: self.id,
+            type_name: core::any::type_name::<T>(),
+            version: Version::default(),
+        }
+    }
+
+    fn id(&self) -> RequirementId {
+        self.id
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation.get()
+    }
+
+    fn last_preemption(&self) -> Option<LastPreemption> {
+        self.last_preemption.get()
+    }
+
+    fn last_owner(&self) -> Option<LastOwner> {
+        self.last_owner.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use futures_lite::future::pending;
+
+    use super::*;
+    use crate::thief::PreemptibleFuture;
+
+    fn owners<const K: usize>(rollers: &SemaphoreCell<i32, K>) -> [Option<&'static str>; K] {
+        rollers
+            .owners()
+            .map(|owner| owner.map(|owner| owner.name.as_str()))
+    }
+
+    #[test]
+    fn under_capacity() {
+        let rollers = SemaphoreCell::new(|_| 0, "rollers");
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut intake = pin!(PreemptibleFuture::new(
+            async {
+                rollers.with_slot(|index, speed| *speed = 10 + index as i32);
+                pending::<()>().await;
+            },
+            "intake",
+            [&rollers],
+        ));
+        let mut feed = pin!(PreemptibleFuture::new(
+            async {
+                rollers.with_slot(|index, speed| *speed = 10 + index as i32);
+                pending::<()>().await;
+            },
+            "feed",
+            [&rollers],
+        ));
+        assert!(intake.as_mut().poll(&mut cx).is_pending());
+        assert!(feed.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(owners(&rollers), [Some("intake"), Some("feed")]);
+        assert_eq!(rollers.available(), 0);
+
+        // both owners keep running side by side
+        assert!(intake.as_mut().poll(&mut cx).is_pending());
+        assert!(feed.as_mut().poll(&mut cx).is_pending());
+        assert!(rollers.with_slot(|_, _| ()).is_none());
+    }
+
+    #[test]
+    fn at_capacity_steals_oldest() {
+        let rollers = SemaphoreCell::new(|_| 0, "rollers");
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut first = pin!(PreemptibleFuture::new(pending::<()>(), "first", [&rollers]));
+        let mut second = pin!(PreemptibleFuture::new(
+            pending::<()>(),
+            "second",
+            [&rollers]
+        ));
+        let mut third = pin!(PreemptibleFuture::new(pending::<()>(), "third", [&rollers]));
+        assert!(first.as_mut().poll(&mut cx).is_pending());
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+        assert!(third.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(owners(&rollers), [Some("third"), Some("second")]);
+
+        let Poll::Ready(Err(err)) = first.as_mut().poll(&mut cx) else {
+            panic!("first should be preempted by third");
+        };
+        assert_eq!(
+            err.incoming().map(|thief| thief.name.as_str()),
+            Some("third")
+        );
+        assert_eq!(err.requirement().index, Some(0));
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+        assert!(third.as_mut().poll(&mut cx).is_pending());
+    }
+
+    #[test]
+    fn release_reorders_slots() {
+        let rollers = SemaphoreCell::new(|_| 0, "rollers");
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut first = pin!(PreemptibleFuture::new(async {}, "first", [&rollers]));
+        let mut second = pin!(PreemptibleFuture::new(
+            pending::<()>(),
+            "second",
+            [&rollers]
+        ));
+        let mut third = pin!(PreemptibleFuture::new(pending::<()>(), "third", [&rollers]));
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(first.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(owners(&rollers), [Some("second"), None]);
+
+        // the finished task freed its slot, so the next task takes it without preempting anyone
+        assert!(third.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(owners(&rollers), [Some("second"), Some("third")]);
+
+        // second is now the oldest, so it is the one stolen at capacity
+        let mut fourth = pin!(PreemptibleFuture::new(
+            pending::<()>(),
+            "fourth",
+            [&rollers]
+        ));
+        assert!(fourth.as_mut().poll(&mut cx).is_pending());
+        assert!(matches!(second.as_mut().poll(&mut cx), Poll::Ready(Err(_))));
+        assert!(third.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(owners(&rollers), [Some("fourth"), Some("third")]);
+    }
+}
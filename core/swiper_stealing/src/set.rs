@@ -0,0 +1,239 @@
+//! Several requirements grouped under one name, for tasks that always need the same cells together.
+//!
+//! A [`RequirementSet`] is itself a [`Requirement`], so it can be listed anywhere a cell can:
+//! stealing the set steals every member, and releasing it releases every member.
+//! The set is only owned while all members agree on their owner, so a member stolen on its own
+//! preempts the task holding the set at its next poll.
+//!
+//! Combinators compare requirements by id, so they do not see overlaps between a set and its members.
+
+use crate::{
+    history::StealRecord,
+    name::Name,
+    notify::Version,
+    requirement::{
+        Requirement, RequirementId, RequirementInfo, RequirementInfoSource, StealOutcome, TenureEnd,
+    },
+    thief::ThiefInfo,
+};
+
+/// `N` requirements stolen and released together, see the [module-level documentation](self)
+pub struct RequirementSet<'a, const N: usize> {
+    members: [&'a dyn Requirement; N],
+    name: Name,
+    id: RequirementId,
+}
+
+impl<'a, const N: usize> RequirementSet<'a, N> {
+    /// Groups `members` into a set reported as `name`.
+    pub fn new(name: impl Into<Name>, members: [&'a dyn Requirement; N]) -> Self {
+        Self {
+            members,
+            name: name.into(),
+            id: RequirementId::next(),
+        }
+    }
+
+    /// Returns the requirements grouped by this set.
+    pub fn members(&self) -> &[&'a dyn Requirement; N] {
+        &self.members
+    }
+}
+
+impl<const N: usize> Requirement for RequirementSet<'_, N> {
+    fn steal_ownership(&self, thief: &ThiefInfo) {
+        self.try_steal_ownership(thief);
+    }
+
+    fn try_steal_ownership(&self, thief: &ThiefInfo) -> StealOutcome {
+        // refuse before stealing anything, so a refusing member never splits the set between owners
+        let refusing = self.members.iter().find(|member| {
+            member.is_frozen()
+                || thief.priority < member.min_steal_priority()
+                || !member.may_steal(thief)
+        });
+        if let Some(member) = refusing {
+            return StealOutcome::Refused {
+                owner: member.current_owner(),
+            };
+        }
+        let displaced = self.current_owner();
+        for member in self.members {
+            member.steal_ownership(thief);
+        }
+        StealOutcome::Acquired { displaced }
+    }
+
+    fn release_ownership(&self) {
+        for member in self.members {
+            member.release_ownership();
+        }
+    }
+
+    fn end_ownership(&self, ended: TenureEnd) {
+        for member in self.members {
+            member.end_ownership(ended);
+        }
+    }
+
+    fn end_ownership_of(&self, thief: &ThiefInfo, ended: TenureEnd) {
+        for member in self.members {
+            member.end_ownership_of(thief, ended);
+        }
+    }
+
+    fn poison(&self) {
+        for member in self.members {
+            member.poison();
+        }
+    }
+
+    fn is_owned_by(&self, thief: &ThiefInfo) -> bool {
+        !self.members.is_empty() && self.members.iter().all(|member| member.is_owned_by(thief))
+    }
+
+    fn mark_polled(&self) {
+        for member in self.members {
+            member.mark_polled();
+        }
+    }
+
+    fn register_owner_waker(&self, waker: &core::task::Waker) {
+        for member in self.members {
+            member.register_owner_waker(waker);
+        }
+    }
+
+    fn register_release_waker(&self, waker: &core::task::Waker) {
+        for member in self.members {
+            member.register_release_waker(waker);
+        }
+    }
+}
+
+impl<const N: usize> RequirementInfoSource for RequirementSet<'_, N> {
+    /// Returns the owner of every member, or `None` if they do not all have the same owner.
+    fn current_owner(&self) -> Option<ThiefInfo> {
+        let (first, rest) = self.members.split_first()?;
+        let owner = first.current_owner()?;
+        rest.iter()
+            .all(|member| {
+                member
+                    .current_owner()
+                    .is_some_and(|other| owner.same_thief(&other))
+            })
+            .then_some(owner)
+    }
+
+    fn info(&self) -> RequirementInfo {
+        RequirementInfo {
+            name: self.name,
+            index: None,
+            id: self.id,
+            type_name: core::any::type_name::<Self>(),
+            version: Version::default(),
+        }
+    }
+
+    /// Returns the sum of the members' generations, which changes whenever any member is stolen.
+    fn generation(&self) -> u64 {
+        self.members
+            .iter()
+            .fold(0, |sum, member| sum.wrapping_add(member.generation()))
+    }
+
+    fn last_steal(&self) -> Option<StealRecord> {
+        self.members
+            .iter()
+            .filter_map(|member| member.last_steal())
+            .max_by_key(|steal| steal.sequence)
+    }
+
+    /// Returns `true` if any member is poisoned.
+    fn is_poisoned(&self) -> bool {
+        self.members.iter().any(|member| member.is_poisoned())
+    }
+
+    /// Returns `true` if any member is frozen.
+    fn is_frozen(&self) -> bool {
+        self.members.iter().any(|member| member.is_frozen())
+    }
+
+    fn min_steal_priority(&self) -> u8 {
+        self.members
+            .iter()
+            .map(|member| member.min_steal_priority())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns `true` if every member may be stolen by `incoming`.
+    fn may_steal(&self, incoming: &ThiefInfo) -> bool {
+        self.members.iter().all(|member| member.may_steal(incoming))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use futures_lite::future::pending;
+
+    use super::*;
+    use crate::{requirement::RevocableCell, thief::PreemptibleFuture};
+
+    #[test]
+    fn member_stolen_individually() {
+        let left = RevocableCell::new(0, "left");
+        let right = RevocableCell::new(0, "right");
+        let gyro = RevocableCell::new(0.0, "gyro");
+        let drivetrain = RequirementSet::new("drivetrain", [&left, &right, &gyro]);
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut drive = pin!(PreemptibleFuture::new(
+            pending::<()>(),
+            "drive",
+            [&drivetrain]
+        ));
+        assert!(drive.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(
+            drivetrain.current_owner().map(|owner| owner.name.as_str()),
+            Some("drive")
+        );
+        assert_eq!(
+            gyro.current_owner().map(|owner| owner.name.as_str()),
+            Some("drive")
+        );
+        assert!(drive.as_mut().poll(&mut cx).is_pending());
+
+        let mut calibrate = pin!(gyro.run("calibrate", async |angle| *angle = 0.5));
+        assert_eq!(calibrate.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(drivetrain.current_owner().is_none());
+        let Poll::Ready(Err(err)) = drive.as_mut().poll(&mut cx) else {
+            panic!("drive should be preempted through its set");
+        };
+        assert_eq!(err.requirement().name, "drivetrain");
+        // like any task over several cells, the preempted task keeps the members that were not stolen
+        assert_eq!(
+            left.current_owner().map(|owner| owner.name.as_str()),
+            Some("drive")
+        );
+    }
+
+    #[test]
+    fn released_together() {
+        let left = RevocableCell::new(0, "left");
+        let right = RevocableCell::new(0, "right");
+        let drivetrain = RequirementSet::new("drivetrain", [&left, &right]);
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut turn = pin!(PreemptibleFuture::new(async {}, "turn", [&drivetrain]));
+        assert_eq!(turn.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(left.current_owner().is_none());
+        assert!(right.current_owner().is_none());
+        assert_eq!(drivetrain.generation(), 2);
+    }
+}
@@ -0,0 +1,153 @@
+//! Writer and reader handles of one cell, for producer/consumer pipelines where the consumer must never be preempted.
+//!
+//! [`RevocableCell::split()`] borrows the cell exclusively and returns a [`WriteHandle`] and a [`ReadHandle`].
+//! Writers run as ordinary exclusive tasks through the write handle, so they steal from and preempt each other as usual.
+//! The read handle never claims the cell: [`read_latest()`](ReadHandle::read_latest) clones the data while no writer holds it,
+//! and returns the last clone while one does, so a reader never sees an update in progress.
+//! A writer preempted mid-update leaves its partial changes behind, as with [`run()`](RevocableCell::run),
+//! unless it uses [`run_transactional()`](RevocableCell::run_transactional).
+
+use core::{any::Any, cell::RefCell};
+
+use crate::{name::Name, notify::Version, requirement::RevocableCell, thief::PreemptibleFuture};
+
+/// Exclusive access to a split cell, created by [`RevocableCell::split()`]
+pub struct WriteHandle<'a, T, M: Any = (), const H: usize = 0> {
+    cell: &'a RevocableCell<T, M, H>,
+}
+
+impl<T, M: Any, const H: usize> Clone for WriteHandle<'_, T, M, H> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, M: Any, const H: usize> Copy for WriteHandle<'_, T, M, H> {}
+
+impl<'a, T, M: Any, const H: usize> WriteHandle<'a, T, M, H> {
+    /// Returns the split cell, for listing it as a requirement alongside others.
+    pub fn cell(&self) -> &'a RevocableCell<T, M, H> {
+        self.cell
+    }
+
+    /// Same as [`RevocableCell::run()`] on the split cell.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`RevocableCell::run()`].
+    pub fn run<Out>(
+        &self,
+        name: impl Into<Name>,
+        func: impl AsyncFnOnce(&mut T) -> Out + 'a,
+    ) -> PreemptibleFuture<'a, impl Future<Output = Out>, Out, 1> {
+        self.cell.run(name, func)
+    }
+}
+
+/// Snapshot access to a split cell, created by [`RevocableCell::split()`]
+pub struct ReadHandle<'a, T, M: Any = (), const H: usize = 0> {
+    cell: &'a RevocableCell<T, M, H>,
+    /// Clone of the data when no writer last held it
+    latest: RefCell<T>,
+}
+
+impl<T: Clone, M: Any, const H: usize> ReadHandle<'_, T, M, H> {
+    /// Returns a clone of the data, or of the last data seen without a writer if a writer holds the cell.
+    ///
+    /// This never claims the cell, so it neither preempts writers nor is preempted by them.
+    pub fn read_latest(&self) -> T {
+        if self.cell.unowned().is_ok() {
+            // an unowned cell has no task holding a reference to its data
+            self.latest
+                .borrow_mut()
+                .clone_from(unsafe { &*self.cell.data_ptr() });
+        }
+        self.latest.borrow().clone()
+    }
+}
+
+impl<T, M: Any, const H: usize> ReadHandle<'_, T, M, H> {
+    /// Returns the current version of the data, see [`RevocableCell::version()`].
+    pub fn version(&self) -> Version {
+        self.cell.version()
+    }
+
+    /// Completes with the new version once the data has changed since `since`, see [`RevocableCell::changed()`].
+    pub fn changed(&self, since: Version) -> impl Future<Output = Version> + '_ {
+        self.cell.changed(since)
+    }
+}
+
+impl<T: Clone, M: Any, const H: usize> RevocableCell<T, M, H> {
+    /// Splits this cell into a handle for exclusive writers and a handle for snapshot readers, see [`snapshot`](crate::snapshot).
+    ///
+    /// The cell is borrowed exclusively so every task goes through the handles.
+    pub fn split(&mut self) -> (WriteHandle<'_, T, M, H>, ReadHandle<'_, T, M, H>) {
+        let latest = RefCell::new(self.get_mut().clone());
+        let cell = &*self;
+        (WriteHandle { cell }, ReadHandle { cell, latest })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use futures_lite::future::yield_now;
+
+    use super::*;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Setpoint {
+        position: i32,
+        velocity: i32,
+    }
+
+    #[test]
+    fn reader_sees_consistent_snapshots() {
+        let mut cell = RevocableCell::new(
+            Setpoint {
+                position: 0,
+                velocity: 0,
+            },
+            "setpoint",
+        );
+        let (writer, reader) = cell.split();
+        let mut cx = Context::from_waker(Waker::noop());
+        let consistent = |setpoint: Setpoint| setpoint.velocity == setpoint.position * 2;
+
+        let mut slow = pin!(writer.run("slow", async |setpoint| {
+            setpoint.position = 1;
+            yield_now().await;
+            setpoint.velocity = 2;
+        }));
+        assert!(slow.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(reader.read_latest().position, 0);
+
+        // another writer preempts the half finished update, the reader keeps the last snapshot
+        let mut fast = pin!(writer.run("fast", async |setpoint| {
+            setpoint.position = 3;
+            yield_now().await;
+            setpoint.velocity = 6;
+        }));
+        assert!(fast.as_mut().poll(&mut cx).is_pending());
+        assert!(matches!(slow.as_mut().poll(&mut cx), Poll::Ready(Err(_))));
+        assert!(consistent(reader.read_latest()));
+
+        let seen = reader.version();
+        let mut changed = pin!(reader.changed(seen));
+        assert!(changed.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(fast.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(changed.as_mut().poll(&mut cx).is_ready());
+        assert_eq!(
+            reader.read_latest(),
+            Setpoint {
+                position: 3,
+                velocity: 6,
+            }
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! Disjoint borrows of a cell's data, so one task can hand different fields to different helpers.
+//!
+//! `#[derive(SplitBorrow)]` from `swiper-derive` groups fields marked `#[split(Piece)]` into one `Piece<'a>` struct per group,
+//! holding `&'a mut` references to those fields. [`SplitBorrow::split()`] returns every piece at once,
+//! and guards such as [`RequirementGuard::split()`] split the guarded data after a single ownership check,
+//! so helpers can take only the piece they need instead of the whole struct.
+
+use core::any::Any;
+
+use crate::{mutex::RequirementGuard, requirement::RevocableCell};
+
+/// A struct whose fields can be borrowed as disjoint pieces, usually derived
+pub trait SplitBorrow {
+    /// A tuple of the pieces borrowing disjoint fields
+    type Pieces<'a>
+    where
+        Self: 'a;
+
+    /// Borrows every piece at once.
+    fn split(&mut self) -> Self::Pieces<'_>;
+}
+
+impl<T: SplitBorrow> RequirementGuard<'_, T> {
+    /// Splits the guarded data into its pieces, checking ownership once.
+    pub fn split(&mut self) -> T::Pieces<'_> {
+        SplitBorrow::split(&mut **self)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: SplitBorrow> crate::auto_steal::AutoStealGuard<'_, T> {
+    /// Splits the guarded data into its pieces, checking ownership once.
+    pub fn split(&mut self) -> T::Pieces<'_> {
+        SplitBorrow::split(&mut **self)
+    }
+}
+
+impl<T: SplitBorrow, M: Any, const H: usize> RevocableCell<T, M, H> {
+    /// Same as [`run()`](RevocableCell::run), but `func` receives the pieces of the data instead of a reference.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`run()`](RevocableCell::run).
+    pub fn run_split<'a, Out>(
+        &'a self,
+        name: &'static str,
+        func: impl AsyncFnOnce(T::Pieces<'_>) -> Out + 'a,
+    ) -> crate::thief::PreemptibleFuture<'a, impl Future<Output = Out>, Out, 1> {
+        self.run(name, async move |data| func(data.split()).await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::task::{Context, Poll, Waker};
+
+    use super::*;
+    use crate::requirement::RequirementInfoSource;
+
+    struct Robot {
+        left: f64,
+        right: f64,
+        angle: f64,
+    }
+
+    struct Drive<'a> {
+        left: &'a mut f64,
+        right: &'a mut f64,
+    }
+
+    impl SplitBorrow for Robot {
+        type Pieces<'a> = (Drive<'a>, &'a mut f64);
+
+        fn split(&mut self) -> Self::Pieces<'_> {
+            (
+                Drive {
+                    left: &mut self.left,
+                    right: &mut self.right,
+                },
+                &mut self.angle,
+            )
+        }
+    }
+
+    #[test]
+    fn run_split_pieces() {
+        let robot = RevocableCell::new(
+            Robot {
+                left: 0.0,
+                right: 0.0,
+                angle: 0.0,
+            },
+            "robot",
+        );
+        {
+            let mut task = core::pin::pin!(robot.run_split("split", async |(drive, angle)| {
+                *drive.left = 1.0;
+                *drive.right = -1.0;
+                *angle = 90.0;
+            }));
+            let mut cx = Context::from_waker(Waker::noop());
+            assert_eq!(task.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        }
+        assert!(robot.current_owner().is_none());
+        let robot = robot.into_inner();
+        assert_eq!((robot.left, robot.right, robot.angle), (1.0, -1.0, 90.0));
+    }
+}
@@ -0,0 +1,100 @@
+//! Reclaiming cells from owners that stopped being polled, so a leaked or starved task cannot wedge a requirement.
+//!
+//! A task that owns a cell and is never polled again, for example because its executor forgot it without dropping it,
+//! keeps the cell owned forever, and tasks waiting for it to be free such as [`run_default()`](crate::requirement::RevocableCell::run_default) never run.
+//! Schedulers opt in by calling [`tick()`] once per loop, which advances a global counter,
+//! and [`reclaim_if_stale()`](RevocableCell::reclaim_if_stale) on the cells they care about.
+//! Every cell remembers the tick at which its owner last acquired it or was polled with it,
+//! and is reclaimed once that is more than the allowed age ago.
+
+use core::{
+    any::Any,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::{
+    requirement::{Requirement, RequirementInfoSource, RevocableCell, TenureEnd},
+    thief::ThiefInfo,
+};
+
+static TICKS: AtomicU32 = AtomicU32::new(0);
+
+/// Advances the tick counter used to find stale owners, see the [module-level documentation](self).
+pub fn tick() {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns the number of calls to [`tick()`] so far, wrapping around at `u32::MAX`.
+pub fn current_tick() -> u32 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+impl<T, M: Any, const H: usize> RevocableCell<T, M, H> {
+    /// Releases this cell if its owner has not acquired it or been polled with it for more than `max_age` [ticks](tick),
+    /// returning the stale owner.
+    ///
+    /// Ownership ends as [`TenureEnd::Expired`], so the owner resolves to a [`PreemptionError`](crate::PreemptionError) if it is ever polled again.
+    pub fn reclaim_if_stale(&self, max_age: u32) -> Option<ThiefInfo> {
+        let owner = self.current_owner()?;
+        if current_tick().wrapping_sub(self.touched()) <= max_age {
+            return None;
+        }
+        self.end_ownership(TenureEnd::Expired);
+        Some(owner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::{
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+    use std::boxed::Box;
+
+    use futures_lite::future::pending;
+
+    use super::*;
+    use crate::thief::PreemptibleFuture;
+
+    #[test]
+    fn wedged_owner_is_reclaimed() {
+        let drivetrain = RevocableCell::new(0, "drivetrain");
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut starved = Box::pin(PreemptibleFuture::new(
+            pending::<()>(),
+            "starved",
+            [&drivetrain],
+        ));
+        assert!(starved.as_mut().poll(&mut cx).is_pending());
+
+        // polling the owner keeps it fresh
+        tick();
+        tick();
+        assert!(starved.as_mut().poll(&mut cx).is_pending());
+        tick();
+        assert_eq!(drivetrain.reclaim_if_stale(1), None);
+
+        // then it is never polled again
+        tick();
+        let Some(stale) = drivetrain.reclaim_if_stale(1) else {
+            panic!("starved should be reclaimed");
+        };
+        assert_eq!(stale.name, "starved");
+        assert_eq!(
+            drivetrain.last_owner().map(|owner| owner.ended),
+            Some(TenureEnd::Expired)
+        );
+
+        let mut hold = pin!(drivetrain.run("hold", async |x| *x = 1));
+        assert_eq!(hold.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(matches!(
+            starved.as_mut().poll(&mut cx),
+            Poll::Ready(Err(_))
+        ));
+        assert_eq!(drivetrain.reclaim_if_stale(0), None);
+    }
+}
@@ -0,0 +1,110 @@
+//! Cells stored in `static`s, so every task can list them as requirements without borrowing them from a stack frame.
+//!
+//! A [`RevocableCell`] cannot be built in a constant, since its name is interned and its id is taken from a global counter,
+//! and it is not `Sync`, so it cannot be a `static` either.
+//! A [`StaticRevocableCell`] holds the initial data and name in a `const` constructor and builds the cell on first use,
+//! then derefs to it for the rest of the program.
+//!
+//! Creating one is `unsafe`: the caller promises that the static is only ever used from a single thread,
+//! as is the case on the single core, single executor targets this is meant for.
+//! Multithreaded programs should use a [`SyncRevocableCell`](crate::requirement::sync::SyncRevocableCell) instead.
+
+use core::{
+    cell::{Cell, OnceCell},
+    ops::Deref,
+};
+
+use crate::requirement::RevocableCell;
+
+/// A [`RevocableCell`] that can be created in a `static`, see the [module-level documentation](self)
+pub struct StaticRevocableCell<T> {
+    /// Initial data, taken when the cell is built
+    data: Cell<Option<T>>,
+    name: &'static str,
+    cell: OnceCell<RevocableCell<T>>,
+}
+
+// the creator promised that the cell is only used from one thread
+unsafe impl<T> Sync for StaticRevocableCell<T> {}
+
+impl<T> StaticRevocableCell<T> {
+    /// Creates a cell named `name` holding `data`, built when it is first dereferenced.
+    ///
+    /// # Safety
+    ///
+    /// The cell must only be used from a single thread, since neither it nor the [`RevocableCell`] it builds are thread safe.
+    pub const unsafe fn new(data: T, name: &'static str) -> Self {
+        Self {
+            data: Cell::new(Some(data)),
+            name,
+            cell: OnceCell::new(),
+        }
+    }
+
+    /// Returns the cell, building it if this is the first use.
+    pub fn get(&self) -> &RevocableCell<T> {
+        self.cell.get_or_init(|| {
+            let data = self.data.take().expect("the data is only taken once");
+            RevocableCell::new(data, self.name)
+        })
+    }
+}
+
+impl<T> Deref for StaticRevocableCell<T> {
+    type Target = RevocableCell<T>;
+
+    fn deref(&self) -> &RevocableCell<T> {
+        self.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use futures_lite::future::yield_now;
+
+    use super::*;
+    use crate::requirement::RequirementInfoSource;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct DriveState {
+        left: i32,
+        right: i32,
+    }
+
+    impl DriveState {
+        const INIT: Self = Self { left: 0, right: 0 };
+    }
+
+    // only used by the test below
+    static DRIVETRAIN: StaticRevocableCell<DriveState> =
+        unsafe { StaticRevocableCell::new(DriveState::INIT, "drivetrain") };
+
+    #[test]
+    fn static_requirement() {
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut forward = pin!(DRIVETRAIN.run("forward", async |drive| {
+            drive.left = 1;
+            yield_now().await;
+            drive.right = 1;
+        }));
+        assert!(forward.as_mut().poll(&mut cx).is_pending());
+
+        let mut turn = pin!(DRIVETRAIN.run("turn", async |drive| {
+            *drive = DriveState { left: 1, right: -1 };
+        }));
+        assert_eq!(turn.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(matches!(
+            forward.as_mut().poll(&mut cx),
+            Poll::Ready(Err(_))
+        ));
+        assert_eq!(DRIVETRAIN.peek(), DriveState { left: 1, right: -1 });
+        assert_eq!(DRIVETRAIN.info().name, "drivetrain");
+        assert!(DRIVETRAIN.current_owner().is_none());
+    }
+}
@@ -0,0 +1,312 @@
+//! Exchanging one owned requirement for another without a window where the owner holds neither.
+//!
+//! [`swap_ownership()`] claims the new requirement before releasing the old one,
+//! and refuses the swap, keeping the old requirement, if the new one is claimed by another task, frozen, or poisoned.
+//! Nothing is stolen by a refused swap.
+//!
+//! A [`Swappable`] lets a [`PreemptibleFuture`](crate::thief::PreemptibleFuture) swap mid-execution:
+//! the future lists the swappable as one of its requirements, which stands for whichever of its two requirements is active,
+//! and [`swap()`](Swappable::swap) moves the future's ownership to the other one.
+//! The future is then preempted by steals of the newly active requirement only.
+
+use core::cell::Cell;
+
+use crate::{
+    PreemptionError, PreemptionKind, Result, current_thief,
+    history::StealRecord,
+    requirement::{
+        Claims, LastOwner, LastPreemption, Requirement, RequirementId, RequirementInfo,
+        RequirementInfoSource, StealOutcome, TenureEnd,
+    },
+    thief::ThiefInfo,
+};
+
+/// Moves the ownership of `thief` from `a` to `b`, claiming `b` before releasing `a`.
+///
+/// # Errors
+///
+/// Returns the error for `a` if `thief` does not own it.
+/// Returns a [`refused()`](PreemptionError::refused) error for `b`, leaving `thief` the owner of `a`,
+/// if `b` is claimed by another task, frozen, or poisoned.
+pub fn swap_ownership(a: &dyn Requirement, b: &dyn Requirement, thief: &ThiefInfo) -> Result<()> {
+    let owner = a.current_owner();
+    if !owner.is_some_and(|owner| owner.same_thief(thief)) {
+        return Err(PreemptionError {
+            incoming: owner,
+            last_steal: a.last_steal(),
+            ..PreemptionError::new(PreemptionKind::Stolen, *thief, a.info())
+        });
+    }
+    let claimed = match b.current_claims() {
+        Claims::Unclaimed => false,
+        Claims::Exclusive(owner) => !owner.same_thief(thief),
+        Claims::Shared { .. } => true,
+    };
+    let refused = || PreemptionError {
+        incoming: b.current_owner(),
+        ..PreemptionError::new(PreemptionKind::Refused, *thief, b.info())
+    };
+    if claimed || b.is_frozen() || b.is_poisoned() {
+        return Err(refused());
+    }
+    // claim first, so an observer always sees the thief owning one of them
+    if let StealOutcome::Refused { .. } = b.try_steal_ownership(thief) {
+        return Err(refused());
+    }
+    a.release_ownership();
+    Ok(())
+}
+
+/// A requirement standing for whichever of two requirements is active, see the [module-level documentation](self)
+pub struct Swappable<'a> {
+    requirements: [&'a dyn Requirement; 2],
+    active: Cell<usize>,
+}
+
+impl<'a> Swappable<'a> {
+    /// Creates a swappable standing for `a` until the first [`swap()`](Self::swap).
+    pub fn new(a: &'a dyn Requirement, b: &'a dyn Requirement) -> Self {
+        Self {
+            requirements: [a, b],
+            active: Cell::new(0),
+        }
+    }
+
+    /// Returns the index of the active requirement, `0` for `a` and `1` for `b`.
+    pub fn active_index(&self) -> usize {
+        self.active.get()
+    }
+
+    /// Returns the requirement this swappable currently stands for.
+    pub fn active(&self) -> &'a dyn Requirement {
+        self.requirements[self.active.get()]
+    }
+
+    /// Moves the ownership of the [`PreemptibleFuture`](crate::thief::PreemptibleFuture) being polled to the inactive requirement, making it active.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`swap_ownership()`], the active requirement is unchanged on error.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a [`PreemptibleFuture`](crate::thief::PreemptibleFuture).
+    pub fn swap(&self) -> Result<()> {
+        let thief = current_thief().expect("swap is called from a preemptible future");
+        let active = self.active.get();
+        swap_ownership(
+            self.requirements[active],
+            self.requirements[1 - active],
+            &thief,
+        )?;
+        self.active.set(1 - active);
+        Ok(())
+    }
+}
+
+impl Requirement for Swappable<'_> {
+    fn steal_ownership(&self, thief: &ThiefInfo) {
+        self.active().steal_ownership(thief);
+    }
+
+    fn try_steal_ownership(&self, thief: &ThiefInfo) -> StealOutcome {
+        self.active().try_steal_ownership(thief)
+    }
+
+    fn claim_shared(&self, thief: &ThiefInfo) -> StealOutcome {
+        self.active().claim_shared(thief)
+    }
+
+    fn release_shared(&self, generation: u64) {
+        self.active().release_shared(generation);
+    }
+
+    fn release_ownership(&self) {
+        self.active().release_ownership();
+    }
+
+    fn end_ownership(&self, ended: TenureEnd) {
+        self.active().end_ownership(ended);
+    }
+
+    fn end_ownership_of(&self, thief: &ThiefInfo, ended: TenureEnd) {
+        self.active().end_ownership_of(thief, ended);
+    }
+
+    fn revoke(&self, by: &ThiefInfo) {
+        self.active().revoke(by);
+    }
+
+    fn poison(&self) {
+        self.active().poison();
+    }
+
+    fn is_owned_by(&self, thief: &ThiefInfo) -> bool {
+        self.active().is_owned_by(thief)
+    }
+
+    fn mark_polled(&self) {
+        self.active().mark_polled();
+    }
+
+    fn register_owner_waker(&self, waker: &core::task::Waker) {
+        self.active().register_owner_waker(waker);
+    }
+
+    fn register_release_waker(&self, waker: &core::task::Waker) {
+        self.active().register_release_waker(waker);
+    }
+
+    fn meta_any(&self) -> Option<&dyn core::any::Any> {
+        self.active().meta_any()
+    }
+}
+
+impl RequirementInfoSource for Swappable<'_> {
+    fn current_owner(&self) -> Option<ThiefInfo> {
+        self.active().current_owner()
+    }
+
+    fn current_claims(&self) -> Claims {
+        self.active().current_claims()
+    }
+
+    fn info(&self) -> RequirementInfo {
+        self.active().info()
+    }
+
+    fn id(&self) -> RequirementId {
+        self.active().id()
+    }
+
+    fn generation(&self) -> u64 {
+        self.active().generation()
+    }
+
+    fn last_preemption(&self) -> Option<LastPreemption> {
+        self.active().last_preemption()
+    }
+
+    fn last_owner(&self) -> Option<LastOwner> {
+        self.active().last_owner()
+    }
+
+    fn last_steal(&self) -> Option<StealRecord> {
+        self.active().last_steal()
+    }
+
+    fn yielded_by(&self) -> Option<ThiefInfo> {
+        self.active().yielded_by()
+    }
+
+    fn is_poisoned(&self) -> bool {
+        self.active().is_poisoned()
+    }
+
+    fn is_frozen(&self) -> bool {
+        self.active().is_frozen()
+    }
+
+    fn min_steal_priority(&self) -> u8 {
+        self.active().min_steal_priority()
+    }
+
+    fn may_steal(&self, incoming: &ThiefInfo) -> bool {
+        self.active().may_steal(incoming)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use futures_lite::future::pending;
+
+    use super::*;
+    use crate::{requirement::RevocableCell, thief::PreemptibleFuture};
+
+    fn owner(requirement: &dyn Requirement) -> Option<&'static str> {
+        requirement.current_owner().map(|owner| owner.name.as_str())
+    }
+
+    #[test]
+    fn swap_succeeds() {
+        let active = RevocableCell::new(0, "active");
+        let staging = RevocableCell::new(0, "staging");
+        let trajectory = Swappable::new(&active, &staging);
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut follow = pin!(PreemptibleFuture::new(
+            async {
+                assert_eq!(owner(&active), Some("follow"));
+                trajectory.swap().expect("staging is free");
+                assert_eq!((owner(&active), owner(&staging)), (None, Some("follow")));
+                pending::<()>().await;
+            },
+            "follow",
+            [&trajectory]
+        ));
+        assert!(follow.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(trajectory.active_index(), 1);
+        assert_eq!(
+            active.last_owner().map(|owner| owner.ended),
+            Some(TenureEnd::Completed)
+        );
+
+        // the old requirement no longer belongs to the future, so stealing it preempts nothing
+        let mut plan = pin!(PreemptibleFuture::new(pending::<()>(), "plan", [&active]));
+        assert!(plan.as_mut().poll(&mut cx).is_pending());
+        assert!(follow.as_mut().poll(&mut cx).is_pending());
+
+        let mut load = pin!(PreemptibleFuture::new(pending::<()>(), "load", [&staging]));
+        assert!(load.as_mut().poll(&mut cx).is_pending());
+        let Poll::Ready(Err(err)) = follow.as_mut().poll(&mut cx) else {
+            panic!("follow should be preempted by load");
+        };
+        assert_eq!(err.requirement().name, "staging");
+    }
+
+    #[test]
+    fn swap_refused() {
+        let active = RevocableCell::new(0, "active");
+        let staging = RevocableCell::new(0, "staging");
+        let trajectory = Swappable::new(&active, &staging);
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut load = pin!(PreemptibleFuture::new(pending::<()>(), "load", [&staging]));
+        assert!(load.as_mut().poll(&mut cx).is_pending());
+        let mut follow = pin!(PreemptibleFuture::new(
+            async {
+                let err = trajectory.swap().expect_err("staging is claimed by load");
+                // the refused swap keeps the old requirement and steals nothing
+                assert_eq!(
+                    (owner(&active), owner(&staging)),
+                    (Some("follow"), Some("load"))
+                );
+                err
+            },
+            "follow",
+            [&trajectory]
+        ));
+        let Poll::Ready(Ok(err)) = follow.as_mut().poll(&mut cx) else {
+            panic!("follow should finish after the refused swap");
+        };
+        assert!(err.refused());
+        assert_eq!(
+            err.incoming().map(|thief| thief.name.as_str()),
+            Some("load")
+        );
+        assert_eq!(err.requirement().name, "staging");
+        assert_eq!(trajectory.active_index(), 0);
+        assert!(load.as_mut().poll(&mut cx).is_pending());
+
+        // swapping away from a requirement the thief does not own is a preemption, not a refusal
+        let stranger = ThiefInfo::new("stranger");
+        let err = swap_ownership(&staging, &active, &stranger).expect_err("stranger owns nothing");
+        assert!(!err.refused());
+        assert_eq!(err.requirement().name, "staging");
+    }
+}
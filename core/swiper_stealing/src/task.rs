@@ -0,0 +1,164 @@
+//! Object-safe preemptible tasks, for schedulers storing tasks of different types together.
+//!
+//! [`PreemptibleFuture`] is generic over its inner future, output, and number of requirements,
+//! so schedulers store tasks as `Pin<Box<dyn PreemptibleTask>>` instead.
+//! Futures with a unit output are tasks as-is. Other outputs are routed into a callback with [`PreemptibleFuture::into_task()`].
+
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{
+    PreemptionError,
+    requirement::{HasRequirements, Requirement, RequirementId},
+    thief::{PreemptibleFuture, ThiefInfo},
+};
+
+/// An object-safe view of a [`PreemptibleFuture`] whose output has been dealt with
+pub trait PreemptibleTask: HasRequirements {
+    /// Polls the task, resolving once it completes or is preempted.
+    fn poll_task(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), PreemptionError>>;
+
+    /// Returns the identity this task steals requirements as.
+    fn info(&self) -> ThiefInfo;
+
+    /// Calls `visit` with the id of each requirement this task steals when first polled.
+    fn requirement_ids(&self, visit: &mut dyn FnMut(RequirementId));
+}
+
+impl<Fut, const N: usize> PreemptibleTask for PreemptibleFuture<'_, Fut, (), N>
+where
+    Fut: Future<Output = ()>,
+{
+    fn poll_task(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), PreemptionError>> {
+        self.poll(cx)
+    }
+
+    fn info(&self) -> ThiefInfo {
+        self.info
+    }
+
+    fn requirement_ids(&self, visit: &mut dyn FnMut(RequirementId)) {
+        PreemptibleFuture::requirement_ids(self).for_each(visit);
+    }
+}
+
+/// Task returned by [`PreemptibleFuture::into_task()`]
+pub struct OutputTask<'mutex, Fut, Output, const N: usize, F>
+where
+    Fut: Future<Output = Output>,
+{
+    future: PreemptibleFuture<'mutex, Fut, Output, N>,
+    on_output: Option<F>,
+}
+
+impl<'mutex, Fut, Output, const N: usize> PreemptibleFuture<'mutex, Fut, Output, N>
+where
+    Fut: Future<Output = Output>,
+{
+    /// Turns this future into a [`PreemptibleTask`] passing its output to `on_output` on completion.
+    ///
+    /// To keep the output, have `on_output` write it into a slot such as a `Cell<Option<Output>>`.
+    pub fn into_task<F: FnOnce(Output)>(
+        self,
+        on_output: F,
+    ) -> OutputTask<'mutex, Fut, Output, N, F> {
+        OutputTask {
+            future: self,
+            on_output: Some(on_output),
+        }
+    }
+}
+
+impl<Fut, Output, const N: usize, F> HasRequirements for OutputTask<'_, Fut, Output, N, F>
+where
+    Fut: Future<Output = Output>,
+{
+    fn visit_requirements(&self, visit: &mut dyn FnMut(&dyn Requirement)) {
+        self.future.visit_requirements(visit);
+    }
+}
+
+impl<Fut, Output, const N: usize, F> PreemptibleTask for OutputTask<'_, Fut, Output, N, F>
+where
+    Fut: Future<Output = Output>,
+    F: FnOnce(Output),
+{
+    fn poll_task(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), PreemptionError>> {
+        // the future is structurally pinned, the callback is not
+        let this = unsafe { self.get_unchecked_mut() };
+        unsafe { Pin::new_unchecked(&mut this.future) }
+            .poll(cx)
+            .map_ok(|out| {
+                let on_output = this.on_output.take().expect("task polled after completion");
+                on_output(out);
+            })
+    }
+
+    fn info(&self) -> ThiefInfo {
+        self.future.info
+    }
+
+    fn requirement_ids(&self, visit: &mut dyn FnMut(RequirementId)) {
+        self.future.requirement_ids().for_each(visit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::{cell::Cell, task::Waker};
+    use std::{boxed::Box, vec, vec::Vec};
+
+    use super::*;
+    use crate::requirement::{RequirementInfoSource, RevocableCell};
+
+    #[test]
+    fn heterogeneous_tasks() {
+        let drive = RevocableCell::new(0, "drive");
+        let arm = RevocableCell::new(0, "arm");
+        let arm_angle = Cell::new(None);
+
+        let mut tasks: Vec<Pin<Box<dyn PreemptibleTask + '_>>> = vec![
+            Box::pin(drive.run("forward", async |x| {
+                *x += 1;
+                futures_lite::future::yield_now().await;
+            })),
+            Box::pin(
+                arm.run("raise", async |x| {
+                    *x = 90;
+                    *x
+                })
+                .into_task(|angle| arm_angle.set(Some(angle))),
+            ),
+            Box::pin(PreemptibleFuture::new(
+                futures_lite::future::yield_now(),
+                "both",
+                [&drive, &arm],
+            )),
+        ];
+
+        let mut ids = Vec::new();
+        tasks[2].requirement_ids(&mut |id| ids.push(id));
+        assert_eq!(ids, [drive.id(), arm.id()]);
+        assert_eq!(tasks[1].info().name, "raise");
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut results: Vec<_> = tasks
+            .iter_mut()
+            .map(|task| task.as_mut().poll_task(&mut cx))
+            .collect();
+        assert_eq!(results, [Poll::Pending, Poll::Ready(Ok(())), Poll::Pending]);
+        assert_eq!(arm_angle.get(), Some(90));
+
+        // "both" stole the drivetrain from "forward"
+        results = vec![
+            tasks[0].as_mut().poll_task(&mut cx),
+            tasks[2].as_mut().poll_task(&mut cx),
+        ];
+        assert!(matches!(&results[0], Poll::Ready(Err(err)) if err.outgoing().name == "forward"));
+        assert_eq!(results[1], Poll::Ready(Ok(())));
+    }
+}
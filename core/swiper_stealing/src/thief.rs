@@ -1,17 +1,85 @@
-use crate::{PreemptionError, Result, requirement::RevocableCell};
+use crate::{
+    PreemptionError, PreemptionKind, Result, counter::IdCounter, guard::RevocableGuard, name::Name,
+    requirement::RevocableCell,
+};
 use core::{
-    fmt::Display,
+    any::Any,
+    fmt::{Debug, Display},
+    future::poll_fn,
+    num::NonZeroU64,
     pin::Pin,
-    ptr,
-    task::{Context, Poll},
+    ptr::NonNull,
+    sync::atomic::{AtomicU32, Ordering},
+    task::{Context, Poll, ready},
 };
 
-use crate::requirement::Requirement;
+use crate::requirement::{
+    Claims, HasRequirements, Requirement, RequirementId, RequirementInfo, RequirementInfoSource,
+    StealOutcome, TenureEnd,
+};
 
 /// Contains metadata about a [`PreemptibleFuture`]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///
+/// Equality compares identities like [`same_thief()`](Self::same_thief), use [`same_name()`](Self::same_name) to compare names only.
+#[derive(Clone, Copy)]
 pub struct ThiefInfo {
-    pub name: &'static str,
+    pub name: Name,
+    pub(crate) id: NonZeroU64,
+    /// Priority compared against the [minimum steal priority](RevocableCell::with_min_steal_priority) of requirements
+    /// and, under the default [policy](crate::policy), against the priority of their owners, 0 by default
+    pub priority: u8,
+}
+
+impl ThiefInfo {
+    /// Creates the metadata of a thief named `name`, distinct from all thieves previously created.
+    pub fn new(name: impl Into<Name>) -> Self {
+        Self {
+            name: name.into(),
+            id: next_thief_id(),
+            priority: 0,
+        }
+    }
+
+    /// Sets the priority of this thief, keeping its identity.
+    pub fn with_priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Returns `true` if both infos were copied from the same [`new()`](Self::new) call, so they describe the same thief.
+    ///
+    /// Requirements identify their owner this way, never by address, so a future created where a dropped one lived is a different thief.
+    pub fn same_thief(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+
+    /// Returns `true` if both thieves have the same name, even if they are different thieves.
+    pub fn same_name(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+/// Returns an id never returned before.
+fn next_thief_id() -> NonZeroU64 {
+    static NEXT_ID: IdCounter = IdCounter::new();
+    NEXT_ID.next()
+}
+
+impl PartialEq for ThiefInfo {
+    fn eq(&self, other: &Self) -> bool {
+        self.same_thief(other)
+    }
+}
+
+impl Eq for ThiefInfo {}
+
+impl Debug for ThiefInfo {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // the id depends on how many thieves were created before, so it is left out
+        f.debug_struct("ThiefInfo")
+            .field("name", &self.name)
+            .finish()
+    }
 }
 
 impl Display for ThiefInfo {
@@ -20,12 +88,230 @@ impl Display for ThiefInfo {
     }
 }
 
+#[cfg(feature = "std")]
+mod current {
+    extern crate std;
+
+    use core::cell::Cell;
+
+    use super::ThiefInfo;
+
+    std::thread_local! {
+        static CURRENT: Cell<Option<ThiefInfo>> = const { Cell::new(None) };
+    }
+
+    pub(super) fn replace(thief: Option<ThiefInfo>) -> Option<ThiefInfo> {
+        CURRENT.with(|current| current.replace(thief))
+    }
+
+    pub(super) fn get() -> Option<ThiefInfo> {
+        CURRENT.with(Cell::get)
+    }
+
+    std::thread_local! {
+        static RENEWED: Cell<bool> = const { Cell::new(false) };
+    }
+
+    pub(super) fn replace_renewed(renewed: bool) -> bool {
+        RENEWED.with(|current| current.replace(renewed))
+    }
+}
+
+#[cfg(not(feature = "std"))]
+mod current {
+    use core::cell::Cell;
+
+    use super::ThiefInfo;
+
+    struct SingleThreaded(Cell<Option<ThiefInfo>>);
+
+    // without std there is a single thread of execution polling futures
+    unsafe impl Sync for SingleThreaded {}
+
+    static CURRENT: SingleThreaded = SingleThreaded(Cell::new(None));
+
+    pub(super) fn replace(thief: Option<ThiefInfo>) -> Option<ThiefInfo> {
+        CURRENT.0.replace(thief)
+    }
+
+    pub(super) fn get() -> Option<ThiefInfo> {
+        CURRENT.0.get()
+    }
+
+    struct SingleThreadedFlag(Cell<bool>);
+
+    // same as `SingleThreaded`
+    unsafe impl Sync for SingleThreadedFlag {}
+
+    static RENEWED: SingleThreadedFlag = SingleThreadedFlag(Cell::new(false));
+
+    pub(super) fn replace_renewed(renewed: bool) -> bool {
+        RENEWED.0.replace(renewed)
+    }
+}
+
+static POLLS: AtomicU32 = AtomicU32::new(0);
+
+/// Returns the number of times any [`PreemptibleFuture`] has been polled, wrapping around at `u32::MAX`.
+///
+/// Steal records are stamped with this counter, which orders steals of different requirements.
+pub fn poll_sequence() -> u32 {
+    POLLS.load(Ordering::Relaxed)
+}
+
+/// Returns the [`PreemptibleFuture`] whose inner future is currently being polled, if any.
+///
+/// Nested preemptible futures see their own identity, and the outer one is restored once the inner poll returns.
+pub fn current_thief() -> Option<ThiefInfo> {
+    current::get()
+}
+
+/// Renews the lease of the innermost leased [`PreemptibleFuture`] being polled, see [`PreemptibleFuture::with_lease()`].
+///
+/// Does nothing if no leased future is being polled.
+pub fn renew_lease() {
+    current::replace_renewed(true);
+}
+
+/// Returns `true` if `requirement` is owned by the [`PreemptibleFuture`] that is currently being polled.
+pub(crate) fn owned_by_current(requirement: &(impl RequirementInfoSource + ?Sized)) -> bool {
+    current::get().is_some_and(|thief| {
+        requirement
+            .current_owner()
+            .is_some_and(|owner| owner.same_thief(&thief))
+    })
+}
+
+/// Releases `cell` for the duration of `inner`, so other tasks can use it without preempting the enclosing task, then takes it back.
+///
+/// Must be awaited inside the [`PreemptibleFuture`] that owns `cell`, which does not treat the release as a preemption.
+/// If another task still holds the cell when `inner` completes, or the cell refuses to be taken back, the cell is not stolen back:
+/// this future never resolves, and the enclosing task fails with a [`PreemptionError`] when it is next polled.
+/// `inner` must not access the cell's data, since other tasks may own it meanwhile.
+///
+/// # Panics
+///
+/// Panics if the current task does not own `cell`.
+pub async fn yield_requirement<T, M: Any, const H: usize, Out>(
+    cell: &RevocableCell<T, M, H>,
+    inner: impl Future<Output = Out>,
+) -> Out {
+    let thief = current::get()
+        .filter(|_| owned_by_current(cell))
+        .unwrap_or_else(|| panic!("{} can only be yielded by its owner", cell.info()));
+    let guard = YieldGuard(cell);
+    cell.set_yielded(Some(&thief));
+    cell.release_ownership();
+    let out = inner.await;
+    let taken_back = cell.current_owner().is_none()
+        && matches!(
+            cell.try_steal_ownership(&thief),
+            StealOutcome::Acquired { .. }
+        );
+    if taken_back {
+        drop(guard);
+        return out;
+    }
+    // the enclosing task loses the cell like any preempted task
+    drop(guard);
+    core::future::poll_fn(|cx| {
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await
+}
+
+/// Clears the yield marker of a cell, including when a [`yield_requirement()`] is dropped early
+struct YieldGuard<'a, T, M: Any, const H: usize>(&'a RevocableCell<T, M, H>);
+
+impl<T, M: Any, const H: usize> Drop for YieldGuard<'_, T, M, H> {
+    fn drop(&mut self) {
+        self.0.set_yielded(None);
+    }
+}
+
+/// Keeps the snapshot of a [`RevocableCell::run_transactional()`] registered with its cell until the transaction ends
+struct RollbackGuard<'a, T, M: Any, const H: usize> {
+    cell: &'a RevocableCell<T, M, H>,
+    snapshot: NonNull<T>,
+}
+
+impl<'a, T, M: Any, const H: usize> RollbackGuard<'a, T, M, H> {
+    fn arm(cell: &'a RevocableCell<T, M, H>, snapshot: &mut T) -> Self {
+        let snapshot = NonNull::from(snapshot);
+        // the snapshot lives in the pinned future next to this guard, which clears it first when dropped
+        unsafe { cell.set_rollback(snapshot) };
+        Self { cell, snapshot }
+    }
+}
+
+impl<T, M: Any, const H: usize> Drop for RollbackGuard<'_, T, M, H> {
+    fn drop(&mut self) {
+        self.cell.clear_rollback(self.snapshot);
+    }
+}
+
+/// Marks every requirement still held by `thief` as poisoned, after the task panicked.
+///
+/// Guards dropped while unwinding may already have released a requirement, which is still held as long as nobody stole it since,
+/// so it keeps the `generation` it had when the task acquired it.
+fn poison_owned(requirements: &[&dyn Requirement], generations: &[u64], thief: &ThiefInfo) {
+    for (req, &generation) in requirements.iter().zip(generations) {
+        let held = match req.current_owner() {
+            Some(owner) => owner.same_thief(thief),
+            None => req.generation() == generation,
+        };
+        if held {
+            req.poison();
+        }
+    }
+}
+
+/// Poisons the requirements of a task whose poll is unwinding, for targets without `catch_unwind`
+#[cfg(not(feature = "std"))]
+struct PoisonOnUnwind<'a> {
+    requirements: &'a [&'a dyn Requirement],
+    generations: &'a [u64],
+    thief: ThiefInfo,
+    /// Cleared once the poll returned, so a normal drop leaves the requirements alone
+    armed: bool,
+}
+
+#[cfg(not(feature = "std"))]
+impl Drop for PoisonOnUnwind<'_> {
+    fn drop(&mut self) {
+        if self.armed {
+            poison_owned(self.requirements, self.generations, &self.thief);
+        }
+    }
+}
+
+/// Restores the previous [`current_thief()`] when dropped, including while unwinding
+struct CurrentThiefGuard(Option<ThiefInfo>);
+
+impl CurrentThiefGuard {
+    fn enter(thief: &ThiefInfo) -> Self {
+        Self(current::replace(Some(*thief)))
+    }
+}
+
+impl Drop for CurrentThiefGuard {
+    fn drop(&mut self) {
+        current::replace(self.0);
+    }
+}
+
 /// Wraps a [`Future`] to safetly implement preemption against other [`PreemptibleFuture`] with overlapping [`RevocableCell`] requirements. This provides the following guarantees if used with safe apis:
 ///
 /// - each `RevocableCell` can have at most 1 owner task
 /// - each `PreemptibleFuture` task is guaranteed to own all required `RevocableCell` arguments when it is first polled
 /// - any `PreemptibleFuture` that no longer has ownership over any of its requirements is cancelled when it is next polled
 ///
+/// Requirements are stolen in ascending [`RequirementId`] order, whatever order they are listed in.
+/// If the inner future panics, the requirements it owns are [poisoned](RequirementInfoSource::is_poisoned) and released before the panic resumes,
+/// and futures listing a poisoned requirement resolve to a [`PreemptionError`] on their first poll.
+/// Without `std` the panic cannot be caught, so the requirements are only released once the future is dropped.
+///
 /// See the [module-level documentation](self) for more on the preemption and requirement system.
 pub struct PreemptibleFuture<'mutex, Fut, Output, const N: usize>
 where
@@ -34,23 +320,410 @@ where
     inner: Fut,
     pub info: ThiefInfo,
     requirements: [&'mutex dyn Requirement; N],
+    displaced: [Option<ThiefInfo>; N],
+    generations: [u64; N],
     first_run: bool,
+    /// Whether the requirements are claimed as a reader, see [`new_shared()`](Self::new_shared)
+    shared: bool,
+    /// Whether to give up instead of stealing claimed requirements, see [`new_polite()`](Self::new_polite)
+    polite: bool,
+    /// Preemption found by [`poll_preempt_only()`](Self::poll_preempt_only), returned by the next poll
+    pending: Option<PreemptionError>,
+    lease: Option<Lease>,
+    /// Whether the claims were already ended, so dropping the future leaves them alone
+    released: bool,
+    /// Whether lost requirements are stolen back instead of failing, see [`reacquiring()`](Self::reacquiring)
+    reacquiring: bool,
+    /// Whether the inner future holds a reference to the data across polls, so it cannot be reacquiring, see [`lending_data()`](Self::lending_data)
+    lends_data: bool,
+    reacquisitions: u32,
+}
+
+/// Polls left before a [`PreemptibleFuture::with_lease()`] gives up its requirements
+#[derive(Debug, Clone, Copy)]
+struct Lease {
+    polls: u32,
+    remaining: u32,
+}
+
+impl<Fut, Output, const N: usize> PreemptibleFuture<'static, Fut, Output, N>
+where
+    Fut: Future<Output = Output>,
+{
+    /// Same as [`new()`](Self::new), for requirements that live for the rest of the program such as [leaked](RevocableCell::leak) cells,
+    /// so the future is `'static` whenever `inner` is and can be handed to executors like `tokio::task::spawn_local()`.
+    pub fn new_static(
+        inner: Fut,
+        name: impl Into<Name>,
+        requirements: [&'static dyn Requirement; N],
+    ) -> Self {
+        Self::new(inner, name, requirements)
+    }
 }
 
 impl<'mutex, Fut, Output, const N: usize> PreemptibleFuture<'mutex, Fut, Output, N>
 where
     Fut: Future<Output = Output>,
 {
-    pub fn new(inner: Fut, name: &'static str, requirements: [&'mutex dyn Requirement; N]) -> Self {
+    pub fn new(
+        inner: Fut,
+        name: impl Into<Name>,
+        requirements: [&'mutex dyn Requirement; N],
+    ) -> Self {
         Self {
             inner,
-            info: ThiefInfo { name },
+            info: ThiefInfo::new(name),
             requirements,
+            displaced: [None; N],
+            generations: [0; N],
             first_run: true,
+            shared: false,
+            polite: false,
+            pending: None,
+            lease: None,
+            released: false,
+            reacquiring: false,
+            lends_data: false,
+            reacquisitions: 0,
+        }
+    }
+
+    /// Same as [`new()`](Self::new), but claims every requirement with [`Requirement::claim_shared()`],
+    /// so the future coexists with other readers and is only preempted by exclusive owners.
+    pub fn new_shared(
+        inner: Fut,
+        name: impl Into<Name>,
+        requirements: [&'mutex dyn Requirement; N],
+    ) -> Self {
+        let mut future = Self::new(inner, name, requirements);
+        future.shared = true;
+        future
+    }
+
+    /// Same as [`new()`](Self::new), but resolves to an error on the first poll instead of stealing if any requirement is already claimed.
+    ///
+    /// The error reports the current owner as the incoming task, and nothing is stolen from it.
+    /// Once all requirements have been acquired, the future is preempted like any other.
+    pub fn new_polite(
+        inner: Fut,
+        name: impl Into<Name>,
+        requirements: [&'mutex dyn Requirement; N],
+    ) -> Self {
+        let mut future = Self::new(inner, name, requirements);
+        future.polite = true;
+        future
+    }
+
+    /// Steals requirements with priority `priority`, so the future can take cells whose
+    /// [minimum steal priority](RevocableCell::with_min_steal_priority) is at most `priority`.
+    ///
+    /// Under the default [`PriorityPolicy`](crate::policy::PriorityPolicy), if any requirement is owned by a thief of higher priority,
+    /// the future steals none of them and resolves on its first poll to a [`PreemptionError`] whose [`refused()`](PreemptionError::refused) is set, naming that owner.
+    /// Owners of equal or lower priority are preempted as usual.
+    pub fn with_priority(mut self, priority: u8) -> Self {
+        self.info.priority = priority;
+        self
+    }
+
+    /// Steals lost requirements back when next polled instead of resolving to a [`PreemptionError`], for idempotent background tasks.
+    ///
+    /// The inner future keeps running as if it had never been preempted.
+    /// If a requirement refuses the steal, for example because its owner has a higher [priority](Self::with_priority),
+    /// the future stays pending until a requirement is released and tries again, while frozen or poisoned requirements still resolve to an error.
+    ///
+    /// The inner future must reach the data of its requirements through a fresh [`RevocableGuard::current()`] after every await,
+    /// since a reference held across an await would still point at data the thief has mutated meanwhile.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the future hands its body a reference to the data, as futures returned by [`RevocableCell::run()`] and its siblings do.
+    pub fn reacquiring(mut self) -> Self {
+        assert!(
+            !self.lends_data,
+            "{} holds a reference to its data across polls, so it cannot steal it back",
+            self.info.name
+        );
+        self.reacquiring = true;
+        self
+    }
+
+    /// Marks the inner future as holding a reference to the data of its requirements across polls, which rules out [`reacquiring()`](Self::reacquiring).
+    pub(crate) fn lending_data(mut self) -> Self {
+        self.lends_data = true;
+        self
+    }
+
+    /// Returns how often this future stole its requirements back, see [`reacquiring()`](Self::reacquiring).
+    pub fn reacquisitions(&self) -> u32 {
+        self.reacquisitions
+    }
+
+    /// Gives up all requirements once the inner future has returned `Pending` for `polls` consecutive polls without renewing its lease.
+    ///
+    /// This is a watchdog for tasks that stop making progress, so the requirements become free for other tasks.
+    /// The inner future renews the lease by calling [`renew_lease()`] while it is polled, which resets the count to `polls`.
+    /// When the lease expires, ownership ends as [`TenureEnd::Expired`] and the future resolves to a [`PreemptionError`]
+    /// whose [`lease_expired()`](PreemptionError::lease_expired) is set.
+    /// Futures without requirements never expire, since they hold nothing.
+    pub fn with_lease(mut self, polls: u32) -> Self {
+        self.lease = Some(Lease {
+            polls,
+            remaining: polls,
+        });
+        self
+    }
+
+    /// Counts a poll that returned `Pending` against the lease, returning the error once it expires.
+    fn spend_lease(&mut self, renewed: bool) -> Option<PreemptionError> {
+        let lease = self.lease.as_mut()?;
+        lease.remaining = if renewed {
+            lease.polls
+        } else {
+            lease.remaining.saturating_sub(1)
+        };
+        if renewed || lease.remaining > 0 {
+            return None;
+        }
+        let requirement = self.requirements.first()?.info();
+        self.release_claims(TenureEnd::Expired);
+        Some(PreemptionError {
+            shared: self.shared,
+            ..PreemptionError::new(PreemptionKind::LeaseExpired, self.info, requirement)
+        })
+    }
+
+    /// Returns the error for the first requirement that is claimed by another task, for polite futures.
+    fn first_claimed(&self) -> Option<PreemptionError> {
+        let requirement = self
+            .requirements
+            .iter()
+            .find(|req| req.current_claims() != Claims::Unclaimed)?;
+        Some(PreemptionError {
+            incoming: requirement.current_owner(),
+            ..PreemptionError::new(PreemptionKind::Stolen, self.info, requirement.info())
+        })
+    }
+
+    /// Returns the error for the first frozen or poisoned requirement, or one this future's priority is too low to steal,
+    /// either because of the requirement's minimum or because its [policy](crate::policy) protects the owner, which keeps the future from starting.
+    fn first_refused(&self) -> Option<PreemptionError> {
+        let priority = self.info.priority;
+        let requirement = self.requirements.iter().find(|req| {
+            req.is_frozen()
+                || req.is_poisoned()
+                || req.min_steal_priority() > priority
+                || !req.may_steal(&self.info)
+        })?;
+        let kind = if requirement.is_frozen() {
+            PreemptionKind::Frozen
+        } else if requirement.is_poisoned() {
+            PreemptionKind::Poisoned
+        } else {
+            PreemptionKind::Refused
+        };
+        Some(PreemptionError {
+            incoming: requirement
+                .current_owner()
+                .filter(|_| kind == PreemptionKind::Refused),
+            shared: self.shared,
+            ..PreemptionError::new(kind, self.info, requirement.info())
+        })
+    }
+
+    /// Returns the ids of the requirements this future steals when first polled.
+    pub fn requirement_ids(&self) -> impl Iterator<Item = RequirementId> + '_ {
+        self.requirements.iter().map(|req| req.id())
+    }
+
+    /// Returns the previous owner of each requirement at the time this future stole it.
+    ///
+    /// Entries are `None` until the first poll, or if the requirement was unowned when stolen.
+    pub fn displaced(&self) -> &[Option<ThiefInfo>; N] {
+        &self.displaced
+    }
+
+    /// Runs the future created by `factory` to completion after this future is preempted, before resolving with the preemption error.
+    ///
+    /// This emulates async drop for cleanup that has to await something, such as a confirmation that a motor stopped.
+    /// The cleanup future is given the [`PreemptionError`] but no access to the stolen requirements,
+    /// and is not itself preemptible, so the thief is never blocked by it.
+    pub fn with_async_cleanup<F, C>(self, factory: F) -> AsyncCleanup<'mutex, Fut, Output, N, F, C>
+    where
+        F: FnOnce(PreemptionError) -> C,
+        C: Future<Output = ()>,
+    {
+        AsyncCleanup {
+            future: self,
+            factory: Some(factory),
+            cleanup: None,
+        }
+    }
+
+    /// Checks whether this future was preempted, without polling the inner future.
+    ///
+    /// Returns `None` before the first poll, since requirements are only stolen then.
+    /// A preemption found here is kept and returned by the next poll, even if the thief has released the requirement since.
+    pub fn poll_preempt_only(self: Pin<&mut Self>) -> Option<PreemptionError> {
+        // only the unpinned bookkeeping fields are touched
+        let instance = unsafe { self.get_unchecked_mut() };
+        instance.pending = instance.take_preemption();
+        instance.pending.clone()
+    }
+
+    /// Returns the preemption found by [`poll_preempt_only()`](Self::poll_preempt_only) if any, otherwise checks ownership again.
+    fn take_preemption(&mut self) -> Option<PreemptionError> {
+        if self.first_run {
+            return None;
+        }
+        self.pending.take().or_else(|| self.check_owned())
+    }
+
+    /// Returns the error for the first requirement this future no longer owns.
+    ///
+    /// Requirements released with [`yield_requirement()`] are skipped, and the generation of owned requirements is refreshed since it changes when they are taken back.
+    fn check_owned(&mut self) -> Option<PreemptionError> {
+        let info = self.info;
+        for (requirement, generation) in self.requirements.iter().zip(&mut self.generations) {
+            // cancel if requirement is owned by a different task or not owned by any task
+            // having a requirement not be owned should not actually occur (since it's physically unsafe)
+            // but it is a valid state so it must be handled
+            if requirement.is_owned_by(&info) {
+                *generation = requirement.generation();
+                requirement.mark_polled();
+                continue;
+            }
+            if self.shared && Self::reading(*requirement, *generation) {
+                continue;
+            }
+            if requirement
+                .yielded_by()
+                .is_some_and(|thief| thief.same_thief(&info))
+            {
+                continue;
+            }
+
+            // prefer the record of our own preemption, which survives the thief releasing the requirement
+            // fall back to the current owner if a later preemption overwrote it
+            return Some(match requirement.last_preemption() {
+                Some(preemption) if preemption.generation == *generation => PreemptionError {
+                    incoming: Some(preemption.by),
+                    acquired_at: preemption.acquired_at,
+                    stolen_at: preemption.stolen_at,
+                    shared: self.shared,
+                    last_steal: requirement.last_steal(),
+                    ..PreemptionError::new(PreemptionKind::Stolen, info, {
+                        let info = requirement.info();
+                        RequirementInfo {
+                            version: preemption.version,
+                            index: preemption.slot.or(info.index),
+                            ..info
+                        }
+                    })
+                },
+                _ => PreemptionError {
+                    incoming: requirement.current_owner(),
+                    shared: self.shared,
+                    last_steal: requirement.last_steal(),
+                    ..PreemptionError::new(PreemptionKind::Stolen, info, requirement.info())
+                },
+            });
+        }
+        None
+    }
+
+    /// Returns `true` if `requirement` still has the readers of `generation`.
+    fn reading(requirement: &dyn Requirement, generation: u64) -> bool {
+        requirement.generation() == generation
+            && matches!(requirement.current_claims(), Claims::Shared { .. })
+    }
+
+    /// Steals every requirement this future does not hold, in canonical order,
+    /// so tasks listing the same requirements differently never acquire them in opposite orders.
+    fn steal_all(&mut self) {
+        let mut order: [usize; N] = core::array::from_fn(|i| i);
+        order.sort_unstable_by_key(|&i| self.requirements[i].id());
+        for i in order {
+            let req = self.requirements[i];
+            // a reacquiring future still holds the requirements that were not stolen from it
+            let held = !self.first_run
+                && (req.is_owned_by(&self.info)
+                    || (self.shared && Self::reading(req, self.generations[i]))
+                    || req
+                        .yielded_by()
+                        .is_some_and(|thief| thief.same_thief(&self.info)));
+            if held {
+                continue;
+            }
+            let outcome = if self.shared {
+                req.claim_shared(&self.info)
+            } else {
+                req.try_steal_ownership(&self.info)
+            };
+            match outcome {
+                StealOutcome::Acquired { displaced } => self.displaced[i] = displaced,
+                // only possible if a steal hook froze the cell, the next poll reports that it is not owned
+                StealOutcome::Refused { .. } => {}
+            }
+            self.generations[i] = req.generation();
+        }
+    }
+
+    /// Releases every requirement this future still owns as [`TenureEnd::Aborted`], for futures dropped before completing.
+    fn release_owned(&mut self) {
+        if !self.first_run && !self.released {
+            self.release_claims(TenureEnd::Aborted);
+        }
+    }
+
+    /// Ends every claim this future still holds, recording `ended` for exclusive ones.
+    fn release_claims(&mut self, ended: TenureEnd) {
+        self.released = true;
+        for (req, generation) in self.requirements.iter().zip(self.generations) {
+            if req.is_owned_by(&self.info) {
+                req.end_ownership_of(&self.info, ended);
+            } else if self.shared {
+                req.release_shared(generation);
+            }
         }
     }
 }
 
+impl<Fut, Output, const N: usize> Debug for PreemptibleFuture<'_, Fut, Output, N>
+where
+    Fut: Future<Output = Output>,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PreemptibleFuture")
+            .field("info", &self.info)
+            .field(
+                "requirements",
+                &self.requirements.map(|req| req.info().name),
+            )
+            .field("started", &!self.first_run)
+            .field("displaced", &self.displaced)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Fut, Output, const N: usize> Drop for PreemptibleFuture<'_, Fut, Output, N>
+where
+    Fut: Future<Output = Output>,
+{
+    fn drop(&mut self) {
+        // a cancelled task should not keep its requirements owned, requirements stolen since are left to their new owner
+        self.release_owned();
+    }
+}
+
+impl<Fut, Output, const N: usize> HasRequirements for PreemptibleFuture<'_, Fut, Output, N>
+where
+    Fut: Future<Output = Output>,
+{
+    fn visit_requirements(&self, visit: &mut dyn FnMut(&dyn Requirement)) {
+        self.requirements.iter().for_each(|req| visit(*req));
+    }
+}
+
 impl<Fut, Output, const N: usize> Future for PreemptibleFuture<'_, Fut, Output, N>
 where
     Fut: Future<Output = Output>,
@@ -63,52 +736,149 @@ where
         // the inner representation needs to be extracted
         // and the movement sensitive part (inner Future) needs to be re-pinned
         let instance = unsafe { self.get_unchecked_mut() };
-        let inner = unsafe { Pin::new_unchecked(&mut instance.inner) };
-        let info = unsafe { Pin::new_unchecked(&mut instance.info) }.get_mut();
+        POLLS.fetch_add(1, Ordering::Relaxed);
 
         // steal ownership of all resources on first run
-        // otherwise check if the `current_owner()` of reach resource points to this `ThiefInfo`
+        // otherwise check if the `current_owner()` of reach resource is this `ThiefInfo`
+        if let Some(err) = instance.take_preemption() {
+            if !instance.reacquiring {
+                return Poll::Ready(Err(err));
+            }
+            match instance.first_refused() {
+                Some(err) if err.refused() => {
+                    // try again once whoever holds the requirements lets go
+                    for req in &instance.requirements {
+                        req.register_release_waker(cx.waker());
+                    }
+                    return Poll::Pending;
+                }
+                Some(err) => return Poll::Ready(Err(err)),
+                None => {
+                    instance.steal_all();
+                    instance.reacquisitions += 1;
+                }
+            }
+        }
+
+        if let Some(err) = instance.first_refused().filter(|_| instance.first_run) {
+            return Poll::Ready(Err(err));
+        }
+
+        if let Some(err) = instance
+            .first_claimed()
+            .filter(|_| instance.first_run && instance.polite)
+        {
+            return Poll::Ready(Err(err));
+        }
+
         if instance.first_run {
-            instance
-                .requirements
-                .iter()
-                .for_each(|req| req.steal_ownership(info));
+            instance.steal_all();
             instance.first_run = false;
-        } else {
-            for requirement in instance.requirements {
-                // cancel if requirement is owned by a different task or not owned by any task
-                // having a requirement not be owned should not actually occur (since it's physically unsafe)
-                // but it is a valid state so it must be handled
-                let incoming = if let Some(owner) = requirement.current_owner() {
-                    if ptr::eq(owner, info) {
-                        continue;
-                    }
-                    Some(*owner)
-                } else {
-                    None
-                };
+        }
 
-                let err = PreemptionError {
-                    incoming,
-                    outgoing: *info,
-                    requirement: requirement.info(),
+        let inner = unsafe { Pin::new_unchecked(&mut instance.inner) };
+        let info = &instance.info;
+
+        // we verified ownership of all resources now
+        let outer_renewed = current::replace_renewed(false);
+        let res = {
+            let _current = CurrentThiefGuard::enter(info);
+            #[cfg(feature = "std")]
+            {
+                extern crate std;
+                std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| inner.poll(cx)))
+                    .unwrap_or_else(|panic| {
+                        // the next owner must not see data the panic left half updated, readers changed nothing
+                        if !instance.shared {
+                            poison_owned(
+                                &instance.requirements,
+                                &instance.generations,
+                                &instance.info,
+                            );
+                        }
+                        instance.release_claims(TenureEnd::Aborted);
+                        std::panic::resume_unwind(panic)
+                    })
+            }
+            #[cfg(not(feature = "std"))]
+            {
+                // readers changed nothing, so they never poison
+                let mut poison = PoisonOnUnwind {
+                    requirements: &instance.requirements,
+                    generations: &instance.generations,
+                    thief: *info,
+                    armed: !instance.shared,
                 };
-                return Poll::Ready(Err(err));
+                let res = inner.poll(cx);
+                poison.armed = false;
+                res
             }
         }
-
-        // we verified ownership of all resources now
-        let res = inner.poll(cx).map(Ok);
+        .map(Ok);
+        let renewed = current::replace_renewed(outer_renewed);
         if res.is_ready() {
-            for req in instance.requirements {
-                req.release_ownership();
+            // guards handed to the inner future may have released their requirement already
+            instance.release_claims(TenureEnd::Completed);
+        } else if let Some(err) = instance.spend_lease(renewed) {
+            return Poll::Ready(Err(err));
+        } else {
+            // a steal wakes this task right away, instead of waiting for whatever the inner future awaits
+            for req in &instance.requirements {
+                if req.is_owned_by(&instance.info) {
+                    req.register_owner_waker(cx.waker());
+                }
             }
         }
         res
     }
 }
 
-impl<T> RevocableCell<T> {
+/// Future returned by [`PreemptibleFuture::with_async_cleanup()`]
+pub struct AsyncCleanup<'mutex, Fut, Output, const N: usize, F, C>
+where
+    Fut: Future<Output = Output>,
+{
+    future: PreemptibleFuture<'mutex, Fut, Output, N>,
+    factory: Option<F>,
+    cleanup: Option<(C, PreemptionError)>,
+}
+
+impl<Fut, Output, const N: usize, F, C> Future for AsyncCleanup<'_, Fut, Output, N, F, C>
+where
+    Fut: Future<Output = Output>,
+    F: FnOnce(PreemptionError) -> C,
+    C: Future<Output = ()>,
+{
+    type Output = Result<Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // the future and cleanup are structurally pinned, the factory is not
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.cleanup.is_none() {
+            match unsafe { Pin::new_unchecked(&mut this.future) }.poll(cx) {
+                Poll::Ready(Err(err)) => {
+                    let factory = this.factory.take().expect("preempted only once");
+                    this.cleanup = Some((factory(err.clone()), err));
+                }
+                res => return res,
+            }
+        }
+        let (cleanup, err) = this.cleanup.as_mut().expect("cleanup was just created");
+        ready!(unsafe { Pin::new_unchecked(cleanup) }.poll(cx));
+        Poll::Ready(Err(err.clone()))
+    }
+}
+
+impl<Fut, Output, const N: usize, F, C> HasRequirements for AsyncCleanup<'_, Fut, Output, N, F, C>
+where
+    Fut: Future<Output = Output>,
+{
+    fn visit_requirements(&self, visit: &mut dyn FnMut(&dyn Requirement)) {
+        self.future.visit_requirements(visit);
+    }
+}
+
+impl<T, M: Any, const H: usize> RevocableCell<T, M, H> {
     /// Creates a future that provides access to this cell's inner data when polled.
     ///
     /// Consistent with the functionality of `PreemptibleFuture`, this future
@@ -121,26 +891,210 @@ impl<T> RevocableCell<T> {
     /// this future will return `Err<PreemptionError>` with metadata about the
     /// event. Otherwise, the future will run to completation and return the
     /// wrapped function's return value as `Ok`.
-    pub async fn run<Out>(
-        &self,
-        name: &'static str,
-        func: impl AsyncFnOnce(&mut T) -> Out,
-    ) -> Result<Out> {
-        let inner = func(unsafe { &mut *self.data.get() });
-        PreemptibleFuture::new(inner, name, [self]).await
+    pub fn run<'a, Out>(
+        &'a self,
+        name: impl Into<Name>,
+        func: impl AsyncFnOnce(&mut T) -> Out + 'a,
+    ) -> PreemptibleFuture<'a, impl Future<Output = Out>, Out, 1> {
+        // the async block defers creating the guard until the inner future is first polled,
+        // which only happens after ownership has been stolen
+        let inner = async move {
+            let mut guard = RevocableGuard::current(self).expect("run owns its cell");
+            func(&mut guard).await
+        };
+        PreemptibleFuture::new(inner, name, [self]).lending_data()
     }
-}
 
-#[cfg(test)]
-mod tests {
+    /// Same as [`run()`](Self::run), but undoes the changes of `func` if the future is preempted before it completes.
+    ///
+    /// The data is cloned when the future is first polled, and the clone is swapped back in while the cell is being stolen,
+    /// so the incoming task never observes a half-finished update.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`run()`](Self::run), with the data restored to its value from before the first poll.
+    pub fn run_transactional<'a, Out>(
+        &'a self,
+        name: impl Into<Name>,
+        func: impl AsyncFnOnce(&mut T) -> Out + 'a,
+    ) -> PreemptibleFuture<'a, impl Future<Output = Out>, Out, 1>
+    where
+        T: Clone,
+    {
+        let inner = async move {
+            let mut guard = RevocableGuard::current(self).expect("run_transactional owns its cell");
+            let mut snapshot = T::clone(&guard);
+            let _rollback = RollbackGuard::arm(self, &mut snapshot);
+            func(&mut guard).await
+        };
+        PreemptibleFuture::new(inner, name, [self]).lending_data()
+    }
 
-    use core::{future::poll_fn, task};
+    /// Same as [`run()`](Self::run), but `func` works on a copy of the data, which only replaces the data if the future completes.
+    ///
+    /// The copy is made when the future is first polled and written back in the poll that completes `func`, while the future still owns the cell.
+    /// A preempted future drops its copy, so the cell keeps its value from before the first poll.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`run()`](Self::run).
+    pub fn run_staged<'a, Out>(
+        &'a self,
+        name: impl Into<Name>,
+        func: impl AsyncFnOnce(&mut T) -> Out + 'a,
+    ) -> PreemptibleFuture<'a, impl Future<Output = Out>, Out, 1>
+    where
+        T: Clone,
+    {
+        let inner = async move {
+            let mut guard = RevocableGuard::current(self).expect("run_staged owns its cell");
+            let mut draft = T::clone(&guard);
+            let out = func(&mut draft).await;
+            *guard = draft;
+            out
+        };
+        PreemptibleFuture::new(inner, name, [self]).lending_data()
+    }
+
+    /// Same as [`run()`](Self::run), but only reads the cell, so any number of shared futures can run on it at once.
+    ///
+    /// Shared futures preempt an exclusive owner when first polled, and are all preempted by the next exclusive future.
+    ///
+    /// # Errors
+    ///
+    /// If an exclusive future has stolen this cell since, the future returns `Err<PreemptionError>` when next polled,
+    /// with [`shared()`](PreemptionError::shared) set.
+    pub fn run_shared<'a, Out>(
+        &'a self,
+        name: impl Into<Name>,
+        func: impl AsyncFnOnce(&T) -> Out + 'a,
+    ) -> PreemptibleFuture<'a, impl Future<Output = Out>, Out, 1> {
+        let data = unsafe { self.data_ptr() };
+        // the reference is created once the future is a reader, and readers only coexist with other readers
+        let inner = async move { func(unsafe { &*data }).await };
+        PreemptibleFuture::new_shared(inner, name, [self]).lending_data()
+    }
+
+    /// Same as [`run()`](Self::run), but gives up instead of stealing this cell if another future already owns it.
+    ///
+    /// # Errors
+    ///
+    /// If the cell is owned when first polled, the future returns `Err<PreemptionError>` with the owner as
+    /// [`incoming()`](PreemptionError::incoming), and the owner keeps running.
+    /// Once the future has acquired the cell, it can be preempted like any other.
+    pub fn try_run<'a, Out>(
+        &'a self,
+        name: impl Into<Name>,
+        func: impl AsyncFnOnce(&mut T) -> Out + 'a,
+    ) -> PreemptibleFuture<'a, impl Future<Output = Out>, Out, 1> {
+        let inner = async move {
+            let mut guard = RevocableGuard::current(self).expect("try_run owns its cell");
+            func(&mut guard).await
+        };
+        PreemptibleFuture::new_polite(inner, name, [self]).lending_data()
+    }
+
+    /// Same as [`run()`](Self::run), but waits for the cell to be released instead of stealing it from its owner.
+    ///
+    /// The waiting task is woken whenever the cell is released, and keeps waiting if another future steals the cell in the meantime.
+    /// Once the cell is free, it is acquired as by [`run()`](Self::run), so the future can still be preempted afterwards.
+    ///
+    /// # Errors
+    ///
+    /// If the cell is stolen by a different future after it was acquired, the future returns `Err<PreemptionError>` when next polled.
+    pub async fn run_when_free<'a, Out>(
+        &'a self,
+        name: impl Into<Name>,
+        func: impl AsyncFnOnce(&mut T) -> Out + 'a,
+    ) -> Result<Out> {
+        poll_fn(|cx| {
+            if self.is_free() {
+                return Poll::Ready(());
+            }
+            self.waiters().register(cx.waker());
+            Poll::Pending
+        })
+        .await;
+        self.run(name, func).await
+    }
+
+    /// Runs the default task of this cell whenever no other task owns it, like the default command of a subsystem.
+    ///
+    /// Each run of `task` is an ordinary exclusive [`run()`](Self::run) named `name`, so any other task can steal the cell from it.
+    /// Once a run completes or is preempted, the next one starts as soon as the cell is free again,
+    /// and no sooner than the next poll, so a default that completes immediately does not block the executor.
+    /// The returned future never completes, it is meant to be spawned next to the tasks using the cell or raced against them.
+    /// A default deciding between holding and coasting can call [`take_dirty()`](Self::take_dirty) to learn whether a command wrote the data since it last checked.
+    pub async fn run_default(
+        &self,
+        name: impl Into<Name>,
+        mut task: impl AsyncFnMut(&mut T),
+    ) -> core::convert::Infallible {
+        let name = name.into();
+        loop {
+            poll_fn(|cx| {
+                if self.is_free() {
+                    return Poll::Ready(());
+                }
+                self.waiters().register(cx.waker());
+                Poll::Pending
+            })
+            .await;
+            let _ = self.run(name, async |data| task(data).await).await;
+            let mut yielded = false;
+            poll_fn(|cx| {
+                if core::mem::replace(&mut yielded, true) {
+                    return Poll::Ready(());
+                }
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            })
+            .await;
+        }
+    }
 
-    use crate::requirement::RevocableCell;
+    /// Same as [`run()`](Self::run), but runs `cleanup` on the data once `main` is preempted, before resolving to the preemption error.
+    ///
+    /// `cleanup` steals the cell back like any other [`run()`](Self::run) named `name`, so the task that preempted `main`
+    /// is preempted in turn and resolves to a [`PreemptionError`] when next polled.
+    /// This gives the cleanup exclusive access, for example to ramp motors down instead of leaving them at their last command.
+    /// If the cell refuses the steal, for example because the thief has a higher [priority](PreemptibleFuture::with_priority), cleanup is skipped.
+    ///
+    /// Cleanup is bounded: if a third task steals the cell, cleanup is abandoned at its next poll.
+    ///
+    /// # Errors
+    ///
+    /// If `main` is preempted, the future returns the `Err<PreemptionError>` of `main` once cleanup has completed or been abandoned.
+    pub async fn run_with_cleanup<'a, Out>(
+        &'a self,
+        name: impl Into<Name>,
+        main: impl AsyncFnOnce(&mut T) -> Out + 'a,
+        cleanup: impl AsyncFnOnce(&mut T) + 'a,
+    ) -> Result<Out> {
+        let name = name.into();
+        let err = match self.run(name, main).await {
+            Ok(out) => return Ok(out),
+            Err(err) => err,
+        };
+        // a third task stealing the cell abandons the cleanup like any preempted task
+        let _ = self.run(name, cleanup).await;
+        Err(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use core::{future::poll_fn, pin::pin, task};
+
+    use crate::{
+        clock::{ManualClock, Tick},
+        requirement::{RequirementInfoSource, RequirementStats, RevocableCell},
+    };
     extern crate std;
 
     use super::*;
-    use std::boxed::Box;
+    use std::{boxed::Box, string::ToString};
 
     #[test]
     fn future_mutexing() {
@@ -166,27 +1120,29 @@ mod tests {
         let mut pinned_plus_5 = Box::pin(plus_5);
         let res = pinned_plus_5.as_mut().poll(&mut cx_plus_5);
         assert!(res.is_pending());
-        assert_eq!(unsafe { *resource.data.get() }, 5);
+        // reading between polls is fine, since neither future is using its reference
+        assert_eq!(resource.peek(), 5);
         let res = pinned_plus_5.as_mut().poll(&mut cx_plus_5);
         assert!(res.is_pending());
-        assert_eq!(unsafe { *resource.data.get() }, 10);
+        assert_eq!(resource.peek(), 10);
 
         // now poll minus_1, this should steal from plus_5
         let mut cx_minus_1 = Context::from_waker(task::Waker::noop());
         let mut pinned_minus_1 = Box::pin(minus_1);
         let res = pinned_minus_1.as_mut().poll(&mut cx_minus_1);
         assert!(res.is_pending());
-        assert_eq!(unsafe { *resource.data.get() }, 9);
+        assert_eq!(resource.peek(), 9);
 
         // poll plus_5 again, should finish with preemption error
         let res = pinned_plus_5.as_mut().poll(&mut cx_plus_5);
         assert!(res.is_ready());
-        assert_eq!(unsafe { *resource.data.get() }, 9);
+        assert_eq!(resource.peek(), 9);
 
         if let Poll::Ready(Result::Err(PreemptionError {
             incoming,
             outgoing,
             requirement,
+            ..
         })) = res
         {
             assert!(incoming.is_some_and(|inc| inc.name == "minus_1"));
@@ -197,6 +1153,1322 @@ mod tests {
         // minus_1 should still work
         let res = pinned_minus_1.as_mut().poll(&mut cx_minus_1);
         assert!(res.is_pending());
-        assert_eq!(unsafe { *resource.data.get() }, 8);
+        assert_eq!(resource.peek(), 8);
+    }
+
+    #[test]
+    fn records_displaced_owner() {
+        let resource = RevocableCell::new(0, "test");
+        let other = RevocableCell::new(0, "other");
+
+        let mut victim = Box::pin(PreemptibleFuture::new(
+            core::future::pending::<()>(),
+            "victim",
+            [&resource],
+        ));
+        let mut thief = Box::pin(PreemptibleFuture::new(
+            core::future::pending::<()>(),
+            "thief",
+            [&resource, &other],
+        ));
+        let mut cx = Context::from_waker(task::Waker::noop());
+
+        assert_eq!(thief.displaced(), &[None, None]);
+        assert!(victim.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(victim.displaced(), &[None]);
+
+        assert!(thief.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(thief.displaced(), &[Some(victim.info), None]);
+    }
+
+    #[test]
+    fn poisoned_by_panic() {
+        let resource = RevocableCell::new(0, "test");
+        let mut cx = Context::from_waker(task::Waker::noop());
+
+        let mut panicking = Box::pin(resource.run("panicking", async |x| {
+            *x = 1;
+            panic!("half updated");
+        }));
+        let poll = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            panicking.as_mut().poll(&mut cx)
+        }));
+        assert!(poll.is_err());
+        assert!(resource.current_owner().is_none());
+        assert!(resource.is_poisoned());
+
+        let mut next = Box::pin(resource.run("next", async |x| *x));
+        let Poll::Ready(Err(err)) = next.as_mut().poll(&mut cx) else {
+            panic!("next should not start on a poisoned cell");
+        };
+        assert!(err.poisoned());
+        assert_eq!(err.outgoing().name, "next");
+        assert_eq!(err.requirement(), resource.info());
+
+        resource.clear_poison();
+        let mut after = Box::pin(resource.run("after", async |x| {
+            *x = 0;
+        }));
+        assert_eq!(after.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(!resource.is_poisoned());
+    }
+
+    #[test]
+    fn frozen_while_owned() {
+        let config = RevocableCell::new(1, "config");
+        let mut cx = Context::from_waker(task::Waker::noop());
+
+        let mut tune = Box::pin(config.run("tune", async |gain| {
+            *gain = 2;
+            futures_lite::future::yield_now().await;
+            *gain = 3;
+        }));
+        assert!(tune.as_mut().poll(&mut cx).is_pending());
+        config.freeze();
+
+        // the owner keeps the cell until it finishes
+        let mut retune = Box::pin(config.run("retune", async |gain| *gain = 4));
+        let Poll::Ready(Err(err)) = retune.as_mut().poll(&mut cx) else {
+            panic!("retune should fail on a frozen cell");
+        };
+        assert!(err.frozen());
+        assert!(!err.poisoned());
+        assert_eq!(
+            config.current_owner().map(|owner| owner.name.as_str()),
+            Some("tune")
+        );
+        assert_eq!(tune.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(config.peek(), 3);
+    }
+
+    #[test]
+    fn frozen_refuses_steals() {
+        let config = RevocableCell::new(1, "config");
+        config.freeze();
+        let mut cx = Context::from_waker(task::Waker::noop());
+
+        let thief = ThiefInfo::new("thief");
+        assert_eq!(
+            config.try_steal_ownership(&thief),
+            StealOutcome::Refused { owner: None }
+        );
+        assert!(config.current_owner().is_none());
+
+        let mut read = Box::pin(PreemptibleFuture::new_shared(async {}, "read", [&config]));
+        assert!(matches!(
+            read.as_mut().poll(&mut cx),
+            Poll::Ready(Err(err)) if err.frozen()
+        ));
+        assert_eq!(config.peek(), 1);
+    }
+
+    #[test]
+    fn min_steal_priority() {
+        const OVERRIDE: u8 = 3;
+        let drivetrain = RevocableCell::new(0, "drivetrain").with_min_steal_priority(OVERRIDE);
+        let mut cx = Context::from_waker(task::Waker::noop());
+
+        let mut auto = Box::pin(
+            PreemptibleFuture::new(core::future::pending::<()>(), "auto", [&drivetrain])
+                .with_priority(OVERRIDE),
+        );
+        assert!(auto.as_mut().poll(&mut cx).is_pending());
+
+        // below the floor, the incoming task is refused and the owner keeps running
+        let mut telemetry = Box::pin(
+            PreemptibleFuture::new(async {}, "telemetry", [&drivetrain])
+                .with_priority(OVERRIDE - 1),
+        );
+        let Poll::Ready(Err(err)) = telemetry.as_mut().poll(&mut cx) else {
+            panic!("telemetry should be refused the drivetrain");
+        };
+        assert!(err.refused());
+        assert_eq!(err.outgoing().name, "telemetry");
+        assert_eq!(
+            err.incoming().map(|thief| thief.name.as_str()),
+            Some("auto")
+        );
+        assert!(auto.as_mut().poll(&mut cx).is_pending());
+
+        // the floor applies to a free cell and to direct steals too
+        let weak = ThiefInfo::new("weak");
+        let refused = StealOutcome::Refused {
+            owner: drivetrain.current_owner(),
+        };
+        assert!(
+            matches!(refused, StealOutcome::Refused { owner: Some(owner) } if owner.name == "auto")
+        );
+        assert_eq!(drivetrain.try_steal_ownership(&weak), refused);
+        assert_eq!(drivetrain.claim_shared(&weak), refused);
+
+        // a priority equal to the floor and to the owner's may steal
+        let mut operator = Box::pin(
+            PreemptibleFuture::new(async {}, "operator", [&drivetrain]).with_priority(OVERRIDE),
+        );
+        assert_eq!(operator.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(matches!(auto.as_mut().poll(&mut cx), Poll::Ready(Err(err)) if !err.refused()));
+    }
+
+    #[test]
+    fn owner_priority() {
+        const AUTO: u8 = 2;
+        let drivetrain = RevocableCell::new(0, "drivetrain");
+        let arm = RevocableCell::new(0, "arm");
+        let mut cx = Context::from_waker(task::Waker::noop());
+
+        let mut telemetry = Box::pin(PreemptibleFuture::new(
+            core::future::pending::<()>(),
+            "telemetry",
+            [&drivetrain],
+        ));
+        assert!(telemetry.as_mut().poll(&mut cx).is_pending());
+
+        // a higher priority steals from a lower one
+        let mut auto = Box::pin(
+            PreemptibleFuture::new(core::future::pending::<()>(), "auto", [&drivetrain])
+                .with_priority(AUTO),
+        );
+        assert!(auto.as_mut().poll(&mut cx).is_pending());
+        assert!(
+            matches!(telemetry.as_mut().poll(&mut cx), Poll::Ready(Err(err)) if !err.refused())
+        );
+
+        // a lower priority is refused everything, including the requirements nobody owns
+        let mut logger = Box::pin(PreemptibleFuture::new(
+            async {},
+            "logger",
+            [&arm, &drivetrain],
+        ));
+        let Poll::Ready(Err(err)) = logger.as_mut().poll(&mut cx) else {
+            panic!("logger should be refused the drivetrain");
+        };
+        assert!(err.refused());
+        assert_eq!(err.requirement().name, "drivetrain");
+        assert_eq!(
+            err.incoming().map(|thief| thief.name.as_str()),
+            Some("auto")
+        );
+        assert!(arm.current_owner().is_none());
+        assert!(auto.as_mut().poll(&mut cx).is_pending());
+
+        // an equal priority steals like today
+        let mut driver = Box::pin(
+            PreemptibleFuture::new(async {}, "driver", [&arm, &drivetrain]).with_priority(AUTO),
+        );
+        assert_eq!(driver.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(matches!(auto.as_mut().poll(&mut cx), Poll::Ready(Err(err)) if !err.refused()));
+    }
+
+    #[test]
+    fn reacquiring_tasks_alternate() {
+        let leds = RevocableCell::new(0, "leds");
+        let mut cx = Context::from_waker(task::Waker::noop());
+        let blink = |on| {
+            let leds = &leds;
+            async move {
+                loop {
+                    *RevocableGuard::current(leds).expect("blink owns the leds") = on;
+                    futures_lite::future::yield_now().await;
+                }
+            }
+        };
+
+        let mut on = Box::pin(PreemptibleFuture::new(blink(1), "on", [&leds]).reacquiring());
+        let mut off = Box::pin(PreemptibleFuture::new(blink(2), "off", [&leds]).reacquiring());
+        for _ in 0..3 {
+            assert!(on.as_mut().poll(&mut cx).is_pending());
+            assert_eq!(leds.peek(), 1);
+            assert!(off.as_mut().poll(&mut cx).is_pending());
+            assert_eq!(leds.peek(), 2);
+        }
+        assert_eq!((on.reacquisitions(), off.reacquisitions()), (2, 2));
+    }
+
+    #[test]
+    #[should_panic = "cannot steal it back"]
+    fn reacquiring_rejects_lent_data() {
+        let leds = RevocableCell::new(0, "leds");
+        drop(leds.run("blink", async |x| *x = 1).reacquiring());
+    }
+
+    #[test]
+    #[should_panic = "cannot steal it back"]
+    fn reacquiring_rejects_polite_lent_data() {
+        let leds = RevocableCell::new(0, "leds");
+        drop(leds.try_run("blink", async |x| *x = 1).reacquiring());
+    }
+
+    #[test]
+    fn reacquiring_waits_for_refusal() {
+        use std::{
+            sync::{
+                Arc,
+                atomic::{AtomicUsize, Ordering},
+            },
+            task::Wake,
+        };
+
+        struct CountingWaker(AtomicUsize);
+
+        impl Wake for CountingWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let leds = RevocableCell::new(0, "leds");
+        let wakes = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = task::Waker::from(wakes.clone());
+        let mut cx = Context::from_waker(task::Waker::noop());
+        let mut status_cx = Context::from_waker(&waker);
+
+        let mut status = Box::pin(
+            PreemptibleFuture::new(core::future::pending::<()>(), "status", [&leds]).reacquiring(),
+        );
+        assert!(status.as_mut().poll(&mut status_cx).is_pending());
+        let mut alarm = Box::pin(
+            PreemptibleFuture::new(futures_lite::future::yield_now(), "alarm", [&leds])
+                .with_priority(1),
+        );
+        assert!(alarm.as_mut().poll(&mut cx).is_pending());
+
+        // the alarm outranks the status task, which waits instead of erroring
+        let woken = wakes.0.load(Ordering::Relaxed);
+        assert!(status.as_mut().poll(&mut status_cx).is_pending());
+        assert_eq!(status.reacquisitions(), 0);
+        assert_eq!(
+            leds.current_owner().map(|owner| owner.name),
+            Some("alarm".into())
+        );
+
+        assert_eq!(alarm.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(wakes.0.load(Ordering::Relaxed), woken + 1);
+        assert!(status.as_mut().poll(&mut status_cx).is_pending());
+        assert_eq!(status.reacquisitions(), 1);
+        assert!(leds.is_owned_by(&status.info));
+    }
+
+    #[test]
+    fn dropped_victim_keeps_thief() {
+        let resource = RevocableCell::new(0, "test");
+        let mut cx = Context::from_waker(task::Waker::noop());
+
+        // both tasks share a name, so only their identity tells them apart
+        let mut victim = Box::pin(PreemptibleFuture::new(
+            core::future::pending::<()>(),
+            "task",
+            [&resource],
+        ));
+        assert!(victim.as_mut().poll(&mut cx).is_pending());
+        let mut thief = Box::pin(PreemptibleFuture::new(
+            core::future::pending::<()>(),
+            "task",
+            [&resource],
+        ));
+        assert!(thief.as_mut().poll(&mut cx).is_pending());
+        assert!(victim.as_mut().poll(&mut cx).is_ready());
+
+        drop(victim);
+        assert!(
+            resource
+                .current_owner()
+                .is_some_and(|owner| owner.same_thief(&thief.info))
+        );
+        drop(thief);
+        assert!(resource.current_owner().is_none());
+    }
+
+    #[test]
+    fn identity_survives_address_reuse() {
+        let resource = RevocableCell::new(0, "test");
+        let mut cx = Context::from_waker(task::Waker::noop());
+
+        let mut slot = pin!(None);
+        let mut previous: Option<(ThiefInfo, *const ())> = None;
+        for _ in 0..3 {
+            // the previous task is dropped in place, so every task lives at the same address
+            slot.set(Some(PreemptibleFuture::new(
+                core::future::pending::<()>(),
+                "task",
+                [&resource],
+            )));
+            let mut task = slot.as_mut().as_pin_mut().unwrap();
+            let address = core::ptr::from_ref(&*task).cast::<()>();
+            assert!(task.as_mut().poll(&mut cx).is_pending());
+            if let Some((info, previous_address)) = previous {
+                assert_eq!(address, previous_address);
+                assert!(!info.same_thief(&task.info));
+                assert_ne!(info, task.info);
+                assert!(info.same_name(&task.info));
+                assert!(!resource.is_owned_by(&info));
+            }
+            assert!(resource.is_owned_by(&task.info));
+            previous = Some((task.info, address));
+        }
+        slot.set(None);
+        assert!(resource.current_owner().is_none());
+    }
+
+    #[test]
+    fn dropped_before_noticing_preemption() {
+        let resource = RevocableCell::new(0, "test");
+        let mut cx = Context::from_waker(task::Waker::noop());
+
+        let mut victim = Box::pin(PreemptibleFuture::new(
+            core::future::pending::<()>(),
+            "victim",
+            [&resource],
+        ));
+        assert!(victim.as_mut().poll(&mut cx).is_pending());
+        let mut thief = Box::pin(PreemptibleFuture::new(
+            core::future::pending::<()>(),
+            "thief",
+            [&resource],
+        ));
+        assert!(thief.as_mut().poll(&mut cx).is_pending());
+
+        // the victim is dropped, like the losing branch of a select, without being polled since the steal
+        drop(victim);
+        assert_eq!(
+            resource.current_owner().map(|owner| owner.name),
+            Some("thief".into())
+        );
+        assert!(thief.as_mut().poll(&mut cx).is_pending());
+        drop(thief);
+        assert!(resource.current_owner().is_none());
+    }
+
+    #[test]
+    fn incoming_after_thief_finished() {
+        let resource = RevocableCell::new(0, "test");
+        let mut cx = Context::from_waker(task::Waker::noop());
+
+        let mut victim = Box::pin(resource.run("victim", async |_| {
+            core::future::pending::<()>().await;
+        }));
+        assert!(victim.as_mut().poll(&mut cx).is_pending());
+
+        // thief acquires, completes, and releases before the victim is polled again
+        let mut thief = Box::pin(resource.run("thief", async |x| *x = 1));
+        assert!(thief.as_mut().poll(&mut cx).is_ready());
+        assert!(resource.current_owner().is_none());
+
+        let Poll::Ready(Err(err)) = victim.as_mut().poll(&mut cx) else {
+            panic!("victim should be preempted");
+        };
+        assert_eq!(err.incoming().map(|inc| inc.name.as_str()), Some("thief"));
+        assert_eq!(err.outgoing().name, "victim");
+    }
+
+    #[test]
+    fn incoming_after_multiple_steals() {
+        let resource = RevocableCell::new(0, "test");
+        let mut cx = Context::from_waker(task::Waker::noop());
+
+        let mut first = Box::pin(resource.run("first", async |_| {
+            core::future::pending::<()>().await;
+        }));
+        let mut second = Box::pin(resource.run("second", async |_| {
+            core::future::pending::<()>().await;
+        }));
+        let mut third = Box::pin(resource.run("third", async |_| {}));
+
+        assert!(first.as_mut().poll(&mut cx).is_pending());
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+        assert!(third.as_mut().poll(&mut cx).is_ready());
+
+        // the latest preemption is recorded exactly
+        let Poll::Ready(Err(err)) = second.as_mut().poll(&mut cx) else {
+            panic!("second should be preempted");
+        };
+        assert_eq!(err.incoming().map(|inc| inc.name.as_str()), Some("third"));
+
+        // the record of the earlier preemption was overwritten and nobody owns the requirement
+        let Poll::Ready(Err(err)) = first.as_mut().poll(&mut cx) else {
+            panic!("first should be preempted");
+        };
+        assert_eq!(err.incoming(), None);
+    }
+
+    #[test]
+    fn preemption_timestamps() {
+        let clock: &'static ManualClock = Box::leak(Box::new(ManualClock::new()));
+        let resource = RevocableCell::new(0, "test").with_clock(clock);
+        let mut cx = Context::from_waker(task::Waker::noop());
+
+        let mut victim = Box::pin(resource.run("victim", async |_| {
+            core::future::pending::<()>().await;
+        }));
+        let mut thief = Box::pin(resource.run("thief", async |_| {
+            core::future::pending::<()>().await;
+        }));
+
+        clock.advance(3);
+        assert!(victim.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(resource.acquired_at(), Some(Tick(3)));
+
+        clock.advance(4);
+        assert!(thief.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(resource.acquired_at(), Some(Tick(7)));
+
+        clock.advance(1);
+        let Poll::Ready(Err(err)) = victim.as_mut().poll(&mut cx) else {
+            panic!("victim should be preempted");
+        };
+        assert_eq!(err.acquired_at(), Some(Tick(3)));
+        assert_eq!(err.stolen_at(), Some(Tick(7)));
+        assert!(err.to_string().ends_with(" at tick 7 (acquired at tick 3)"));
+    }
+
+    /// Advances on every reading, so timestamps give the order in which cells were acquired
+    struct StepClock(core::cell::Cell<u64>);
+
+    impl crate::clock::Clock for StepClock {
+        fn now(&self) -> Tick {
+            self.0.set(self.0.get() + 1);
+            Tick(self.0.get())
+        }
+    }
+
+    #[test]
+    fn canonical_steal_order() {
+        let clock: &'static StepClock = Box::leak(Box::new(StepClock(0.into())));
+        let a = RevocableCell::new(0, "a").with_clock(clock);
+        let b = RevocableCell::new(0, "b").with_clock(clock);
+        let mut cx = Context::from_waker(task::Waker::noop());
+
+        // a was created first, so both tasks acquire it before b
+        let mut ab = Box::pin(PreemptibleFuture::new(
+            core::future::pending::<()>(),
+            "ab",
+            [&a, &b],
+        ));
+        assert!(ab.as_mut().poll(&mut cx).is_pending());
+        assert!(a.acquired_at() < b.acquired_at());
+
+        let mut ba = Box::pin(PreemptibleFuture::new(
+            core::future::pending::<()>(),
+            "ba",
+            [&b, &a],
+        ));
+        assert!(ba.as_mut().poll(&mut cx).is_pending());
+        assert!(a.acquired_at() < b.acquired_at());
+
+        // per-requirement results keep the listed order
+        assert_eq!(
+            ba.displaced()
+                .map(|thief| thief.map(|thief| thief.name.as_str())),
+            [Some("ab"); 2]
+        );
+        assert_eq!(
+            ba.requirement_ids().collect::<std::vec::Vec<_>>(),
+            [b.id(), a.id()]
+        );
+    }
+
+    #[test]
+    fn preempt_only_check() {
+        let resource = RevocableCell::new(0, "test");
+        let polls = core::cell::Cell::new(0);
+        let mut cx = Context::from_waker(task::Waker::noop());
+
+        let mut victim = Box::pin(resource.run("victim", async |_| {
+            poll_fn(|_| {
+                polls.set(polls.get() + 1);
+                Poll::<()>::Pending
+            })
+            .await;
+        }));
+        assert_eq!(victim.as_mut().poll_preempt_only(), None);
+        assert!(victim.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(victim.as_mut().poll_preempt_only(), None);
+        assert_eq!(polls.get(), 1);
+
+        let mut thief = Box::pin(resource.run("thief", async |_| {}));
+        assert!(thief.as_mut().poll(&mut cx).is_ready());
+        let err = victim
+            .as_mut()
+            .poll_preempt_only()
+            .expect("victim should be preempted");
+        assert_eq!(
+            err.incoming().map(|thief| thief.name.as_str()),
+            Some("thief")
+        );
+        assert_eq!(victim.as_mut().poll_preempt_only(), Some(err.clone()));
+        assert_eq!(polls.get(), 1);
+
+        assert_eq!(victim.as_mut().poll(&mut cx), Poll::Ready(Err(err)));
+        assert_eq!(polls.get(), 1);
+    }
+
+    #[test]
+    fn yield_window() {
+        let arm = RevocableCell::new(1, "arm");
+        let mut cx = Context::from_waker(task::Waker::noop());
+
+        let mut settle = Box::pin(arm.run("settle", async |x| {
+            *x += 1;
+            yield_requirement(&arm, futures_lite::future::yield_now()).await;
+            *x += 1;
+        }));
+        assert!(settle.as_mut().poll(&mut cx).is_pending());
+        assert!(arm.current_owner().is_none());
+
+        // another task uses the cell during the window without preempting settle
+        let mut other = Box::pin(arm.run("other", async |x| *x *= 10));
+        assert_eq!(other.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(settle.as_mut().poll_preempt_only().is_none());
+        assert_eq!(settle.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        drop((settle, other));
+        assert_eq!(arm.into_inner(), 21);
+    }
+
+    #[test]
+    fn yield_window_contested() {
+        let arm = RevocableCell::new(1, "arm");
+        let mut cx = Context::from_waker(task::Waker::noop());
+
+        let mut settle = Box::pin(arm.run("settle", async |x| {
+            *x += 1;
+            yield_requirement(&arm, futures_lite::future::yield_now()).await;
+            *x += 1;
+        }));
+        let mut hold = Box::pin(arm.run("hold", async |_| {
+            core::future::pending::<()>().await;
+        }));
+        assert!(settle.as_mut().poll(&mut cx).is_pending());
+        assert!(hold.as_mut().poll(&mut cx).is_pending());
+
+        // the window ends while hold owns the cell, which is not stolen back
+        assert!(settle.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(
+            arm.current_owner().map(|thief| thief.name.as_str()),
+            Some("hold")
+        );
+        let Poll::Ready(Err(err)) = settle.as_mut().poll(&mut cx) else {
+            panic!("settle should lose the contested cell");
+        };
+        assert_eq!(
+            err.incoming().map(|thief| thief.name.as_str()),
+            Some("hold")
+        );
+        assert!(arm.yielded_by().is_none());
+        assert!(hold.as_mut().poll(&mut cx).is_pending());
+        drop((settle, hold));
+        assert_eq!(arm.into_inner(), 2);
+    }
+
+    #[test]
+    fn debug_output() {
+        let arm = RevocableCell::new(0, "arm");
+        let mut cx = Context::from_waker(task::Waker::noop());
+        let mut task = Box::pin(arm.run("task", async |_| {
+            core::future::pending::<()>().await;
+        }));
+        assert_eq!(
+            std::format!("{task:?}"),
+            r#"PreemptibleFuture { info: ThiefInfo { name: "task" }, requirements: ["arm"], started: false, displaced: [None], .. }"#
+        );
+        assert!(task.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(
+            std::format!("{task:?} {arm:?}"),
+            r#"PreemptibleFuture { info: ThiefInfo { name: "task" }, requirements: ["arm"], started: true, displaced: [None], .. } RevocableCell { name: "arm", owner: Some("task"), data: <claimed> }"#
+        );
+    }
+
+    #[test]
+    fn shared_readers() {
+        let sensor = RevocableCell::new(1, "sensor");
+        let mut cx = Context::from_waker(task::Waker::noop());
+        let read = async |x: &i32| {
+            let seen = *x;
+            futures_lite::future::yield_now().await;
+            seen
+        };
+        let mut a = Box::pin(sensor.run_shared("a", read));
+        let mut b = Box::pin(sensor.run_shared("b", read));
+        assert!(a.as_mut().poll(&mut cx).is_pending());
+        assert!(b.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(sensor.current_claims(), Claims::Shared { readers: 2 });
+
+        // readers coexist
+        assert_eq!(a.as_mut().poll(&mut cx), Poll::Ready(Ok(1)));
+        assert_eq!(sensor.current_claims(), Claims::Shared { readers: 1 });
+        assert_eq!(b.as_mut().poll(&mut cx), Poll::Ready(Ok(1)));
+        assert_eq!(sensor.current_claims(), Claims::Unclaimed);
+    }
+
+    #[test]
+    fn writer_preempts_readers() {
+        let sensor = RevocableCell::new(1, "sensor");
+        let mut cx = Context::from_waker(task::Waker::noop());
+        let read = async |_: &i32| core::future::pending::<()>().await;
+        let mut a = Box::pin(sensor.run_shared("a", read));
+        let mut b = Box::pin(sensor.run_shared("b", read));
+        let mut write = Box::pin(sensor.run("write", async |x| {
+            *x = 2;
+            futures_lite::future::yield_now().await;
+        }));
+        assert!(a.as_mut().poll(&mut cx).is_pending());
+        assert!(b.as_mut().poll(&mut cx).is_pending());
+        assert!(write.as_mut().poll(&mut cx).is_pending());
+        for reader in [a.as_mut(), b.as_mut()] {
+            let Poll::Ready(Err(err)) = reader.poll(&mut cx) else {
+                panic!("readers should be preempted by the writer");
+            };
+            assert!(err.shared());
+            assert_eq!(
+                err.incoming().map(|thief| thief.name.as_str()),
+                Some("write")
+            );
+            assert!(err.to_string().contains("its shared requirement"));
+        }
+
+        // a late reader preempts the writer
+        let mut c = Box::pin(sensor.run_shared("c", async |x| *x));
+        assert_eq!(c.as_mut().poll(&mut cx), Poll::Ready(Ok(2)));
+        let Poll::Ready(Err(err)) = write.as_mut().poll(&mut cx) else {
+            panic!("the writer should be preempted by the late reader");
+        };
+        assert!(!err.shared());
+        assert_eq!(err.incoming().map(|thief| thief.name.as_str()), Some("c"));
+        assert_eq!(sensor.current_claims(), Claims::Unclaimed);
+    }
+
+    #[test]
+    fn background_declines() {
+        let arm = RevocableCell::new(0, "arm");
+        let mut cx = Context::from_waker(task::Waker::noop());
+        let background = async |x: &mut i32| *x += 1;
+        {
+            let mut foreground = Box::pin(arm.run("foreground", async |x| {
+                *x = 10;
+                futures_lite::future::yield_now().await;
+            }));
+            assert!(foreground.as_mut().poll(&mut cx).is_pending());
+
+            let mut declined = Box::pin(arm.try_run("background", background));
+            let Poll::Ready(Err(err)) = declined.as_mut().poll(&mut cx) else {
+                panic!("background should decline while foreground owns arm");
+            };
+            assert_eq!(
+                err.incoming().map(|thief| thief.name.as_str()),
+                Some("foreground")
+            );
+            assert_eq!(err.outgoing().name, "background");
+
+            // nothing was stolen from the foreground task
+            assert_eq!(foreground.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        }
+
+        let retry = Box::pin(arm.try_run("background", background))
+            .as_mut()
+            .poll(&mut cx);
+        assert_eq!(retry, Poll::Ready(Ok(())));
+        assert_eq!(arm.into_inner(), 11);
+    }
+
+    #[test]
+    fn steal_wakes_outgoing_owner() {
+        use std::{
+            sync::{
+                Arc,
+                atomic::{AtomicUsize, Ordering},
+            },
+            task::Wake,
+        };
+
+        struct CountingWaker(AtomicUsize);
+
+        impl Wake for CountingWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let arm = RevocableCell::new(0, "arm");
+        let wakes = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = task::Waker::from(wakes.clone());
+        let mut cx = Context::from_waker(task::Waker::noop());
+        let mut parked_cx = Context::from_waker(&waker);
+
+        // the owner awaits something that never wakes it, so only the steal can
+        let mut parked = Box::pin(arm.run("parked", async |_| {
+            core::future::pending::<()>().await;
+        }));
+        assert!(parked.as_mut().poll(&mut parked_cx).is_pending());
+        assert!(parked.as_mut().poll(&mut parked_cx).is_pending());
+        assert_eq!(wakes.0.load(Ordering::Relaxed), 0);
+
+        let mut stealer = Box::pin(arm.run("stealer", async |x| *x = 1));
+        assert_eq!(stealer.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(wakes.0.load(Ordering::Relaxed), 1);
+        assert!(matches!(
+            parked.as_mut().poll(&mut parked_cx),
+            Poll::Ready(Err(_))
+        ));
+
+        // a released owner's waker is forgotten, so the next owner's steal wakes nobody
+        let mut next = Box::pin(arm.run("next", async |x| *x = 2));
+        assert_eq!(next.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(wakes.0.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn waits_for_release() {
+        use std::{
+            sync::{
+                Arc,
+                atomic::{AtomicUsize, Ordering},
+            },
+            task::Wake,
+        };
+
+        struct CountingWaker(AtomicUsize);
+
+        impl Wake for CountingWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let arm = RevocableCell::new(0, "arm");
+        let wakes = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = task::Waker::from(wakes.clone());
+        let mut cx = Context::from_waker(task::Waker::noop());
+        let mut waiting_cx = Context::from_waker(&waker);
+        let hold = async |x: &mut i32| {
+            *x += 1;
+            futures_lite::future::yield_now().await;
+        };
+
+        let mut queued = Box::pin(arm.run_when_free("queued", async |x| *x *= 10));
+        let mut foreground = Box::pin(arm.run("foreground", hold));
+        assert!(foreground.as_mut().poll(&mut cx).is_pending());
+        assert!(queued.as_mut().poll(&mut waiting_cx).is_pending());
+
+        // a regular steal preempts the foreground task, but the queued task keeps waiting
+        let mut stealer = Box::pin(arm.run("stealer", hold));
+        assert!(stealer.as_mut().poll(&mut cx).is_pending());
+        assert!(foreground.as_mut().poll(&mut cx).is_ready());
+        assert_eq!(wakes.0.load(Ordering::Relaxed), 0);
+        assert!(queued.as_mut().poll(&mut waiting_cx).is_pending());
+
+        assert_eq!(stealer.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(wakes.0.load(Ordering::Relaxed), 1);
+        assert_eq!(queued.as_mut().poll(&mut waiting_cx), Poll::Ready(Ok(())));
+        drop((queued, foreground, stealer));
+        assert_eq!(arm.into_inner(), 20);
+    }
+
+    #[test]
+    fn steal_history() {
+        let arm = RevocableCell::new(0, "arm").with_history::<2>();
+        let mut cx = Context::from_waker(task::Waker::noop());
+        let hold = async |_: &mut i32| futures_lite::future::pending::<()>().await;
+        let mut a = Box::pin(arm.run("a", hold));
+        let mut b = Box::pin(arm.run("b", hold));
+        let mut c = Box::pin(arm.run("c", hold));
+
+        assert!(a.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(arm.steal_history().count(), 0);
+        assert!(b.as_mut().poll(&mut cx).is_pending());
+        let Poll::Ready(Err(err)) = a.as_mut().poll(&mut cx) else {
+            panic!("a should be preempted by b");
+        };
+        let record = err.last_steal().expect("arm keeps a history");
+        assert_eq!(
+            (record.outgoing.name.as_str(), record.incoming.name.as_str()),
+            ("a", "b")
+        );
+        assert!(record.sequence < poll_sequence());
+        assert_eq!(
+            std::format!("{err:#}"),
+            std::format!(
+                "{err}, last steal: b stole from a at poll {}",
+                record.sequence
+            )
+        );
+
+        assert!(c.as_mut().poll(&mut cx).is_pending());
+        let steals = arm.steal_history().collect::<std::vec::Vec<_>>();
+        assert_eq!(steals.len(), 2);
+        assert_eq!(steals[0], record);
+        assert_eq!(
+            (
+                steals[1].outgoing.name.as_str(),
+                steals[1].incoming.name.as_str()
+            ),
+            ("b", "c")
+        );
+        assert!(steals[1].sequence > record.sequence);
+    }
+
+    #[test]
+    fn contention_stats() {
+        let arm = RevocableCell::new(0, "arm");
+        let mut cx = Context::from_waker(task::Waker::noop());
+        let hold = async |x: &mut i32| {
+            *x += 1;
+            futures_lite::future::yield_now().await;
+        };
+        let mut a = Box::pin(arm.run("a", hold));
+        let mut b = Box::pin(arm.run("b", hold));
+
+        assert!(a.as_mut().poll(&mut cx).is_pending());
+        assert!(b.as_mut().poll(&mut cx).is_pending());
+        assert!(a.as_mut().poll(&mut cx).is_ready());
+        assert_eq!(b.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+
+        let mut c = Box::pin(arm.run("c", hold));
+        while c.as_mut().poll(&mut cx).is_pending() {}
+        assert_eq!(
+            arm.stats(),
+            RequirementStats {
+                steals: 1,
+                acquisitions: 2,
+                releases: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn preemption_without_clock() {
+        let resource = RevocableCell::new(0, "test");
+        let mut cx = Context::from_waker(task::Waker::noop());
+
+        let mut victim = Box::pin(resource.run("victim", async |_| {
+            core::future::pending::<()>().await;
+        }));
+        let mut thief = Box::pin(resource.run("thief", async |_| {}));
+
+        assert!(victim.as_mut().poll(&mut cx).is_pending());
+        assert!(thief.as_mut().poll(&mut cx).is_ready());
+        let Poll::Ready(Err(err)) = victim.as_mut().poll(&mut cx) else {
+            panic!("victim should be preempted");
+        };
+        assert_eq!(err.acquired_at(), None);
+        assert_eq!(err.stolen_at(), None);
+        assert!(!err.to_string().contains("tick"));
+    }
+
+    #[test]
+    fn requirement_id_overlap() {
+        use std::collections::HashSet;
+
+        // identical names, distinct requirements
+        let left = RevocableCell::new(0, "motor");
+        let right = RevocableCell::new(0, "motor");
+        let gyro = RevocableCell::new(0, "gyro");
+
+        let drive = PreemptibleFuture::new(async {}, "drive", [&left, &right, &gyro]);
+        let turn = PreemptibleFuture::new(async {}, "turn", [&right, &gyro]);
+        let spin = PreemptibleFuture::new(async {}, "spin", [&left]);
+
+        let drive_ids: HashSet<_> = drive.requirement_ids().collect();
+        let turn_ids: HashSet<_> = turn.requirement_ids().collect();
+        let spin_ids: HashSet<_> = spin.requirement_ids().collect();
+        assert_eq!(drive_ids.len(), 3);
+
+        let overlap: HashSet<_> = drive_ids.intersection(&turn_ids).copied().collect();
+        assert_eq!(overlap, HashSet::from([right.id(), gyro.id()]));
+        assert!(turn_ids.is_disjoint(&spin_ids));
+    }
+
+    #[test]
+    fn indexed_requirement_errors() {
+        let modules = RevocableCell::<i32>::array::<4>(|_| 0, "swerve");
+        let mut cx = Context::from_waker(task::Waker::noop());
+
+        for (i, module) in modules.iter().enumerate() {
+            let mut victim = Box::pin(module.run("drive", async |_| {
+                core::future::pending::<()>().await;
+            }));
+            let mut thief = Box::pin(module.run("brake", async |x| *x = 0));
+            assert!(victim.as_mut().poll(&mut cx).is_pending());
+            assert!(thief.as_mut().poll(&mut cx).is_ready());
+
+            let Poll::Ready(Err(err)) = victim.as_mut().poll(&mut cx) else {
+                panic!("drive should be preempted");
+            };
+            assert_eq!(err.requirement().index, Some(i));
+            assert!(err.to_string().contains(&std::format!("swerve[{i}]")));
+        }
+    }
+
+    #[test]
+    fn async_cleanup_after_preemption() {
+        let resource = RevocableCell::new(0, "motor");
+        let mut cx = Context::from_waker(task::Waker::noop());
+        let cleanup_polls = core::cell::Cell::new(0);
+
+        let mut victim = Box::pin(
+            resource
+                .run("drive", async |_| core::future::pending::<()>().await)
+                .with_async_cleanup(|err| {
+                    assert_eq!(err.outgoing().name, "drive");
+                    let cleanup_polls = &cleanup_polls;
+                    async move {
+                        // wait for two frames confirming the motor stopped
+                        for _ in 0..2 {
+                            cleanup_polls.set(cleanup_polls.get() + 1);
+                            futures_lite::future::yield_now().await;
+                        }
+                    }
+                }),
+        );
+        assert!(victim.as_mut().poll(&mut cx).is_pending());
+
+        let mut thief = Box::pin(resource.run("stop", async |x| {
+            futures_lite::future::yield_now().await;
+            *x = 1;
+        }));
+        assert!(thief.as_mut().poll(&mut cx).is_pending());
+
+        // the cleanup runs across polls without blocking the thief
+        assert!(victim.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(cleanup_polls.get(), 1);
+        assert_eq!(thief.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(victim.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(cleanup_polls.get(), 2);
+
+        let Poll::Ready(Err(err)) = victim.as_mut().poll(&mut cx) else {
+            panic!("victim should surface its preemption after cleanup");
+        };
+        assert_eq!(err.incoming().map(|inc| inc.name.as_str()), Some("stop"));
+        assert_eq!(cleanup_polls.get(), 2);
+    }
+
+    #[test]
+    fn async_cleanup_drop_releases() {
+        let resource = RevocableCell::new(0, "motor");
+        let mut cx = Context::from_waker(task::Waker::noop());
+
+        let mut task = Box::pin(
+            resource
+                .run("drive", async |_| core::future::pending::<()>().await)
+                .with_async_cleanup(|_| async {}),
+        );
+        assert!(task.as_mut().poll(&mut cx).is_pending());
+        assert!(resource.current_owner().is_some());
+        drop(task);
+        assert!(resource.current_owner().is_none());
+        assert_eq!(
+            resource.last_owner().map(|last| last.ended),
+            Some(TenureEnd::Aborted)
+        );
+    }
+
+    #[test]
+    fn cleanup_skipped_on_completion() {
+        let motor = RevocableCell::new(0, "motor");
+        let mut cx = Context::from_waker(task::Waker::noop());
+        let mut task = Box::pin(motor.run_with_cleanup(
+            "drive",
+            async |speed| {
+                *speed = 10;
+                *speed
+            },
+            async |_| panic!("cleanup only runs after a preemption"),
+        ));
+        assert_eq!(task.as_mut().poll(&mut cx), Poll::Ready(Ok(10)));
+        drop(task);
+        assert!(motor.current_owner().is_none());
+        assert_eq!(motor.into_inner(), 10);
+    }
+
+    #[test]
+    fn cleanup_preempts_thief() {
+        let motor = RevocableCell::new(0, "motor");
+        let mut cx = Context::from_waker(task::Waker::noop());
+        let mut victim = Box::pin(motor.run_with_cleanup(
+            "drive",
+            async |speed| {
+                *speed = 10;
+                core::future::pending::<()>().await
+            },
+            async |speed| {
+                // ramp down over two frames
+                while *speed > 0 {
+                    *speed -= 5;
+                    futures_lite::future::yield_now().await;
+                }
+            },
+        ));
+        assert!(victim.as_mut().poll(&mut cx).is_pending());
+
+        let mut thief = Box::pin(motor.run("stop", async |speed| {
+            futures_lite::future::yield_now().await;
+            *speed = 20;
+        }));
+        assert!(thief.as_mut().poll(&mut cx).is_pending());
+
+        // the cleanup steals the motor back from the thief
+        assert!(victim.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(
+            motor.current_owner().map(|owner| owner.name.as_str()),
+            Some("drive")
+        );
+        assert_eq!(motor.peek(), 5);
+        assert!(victim.as_mut().poll(&mut cx).is_pending());
+        let Poll::Ready(Err(err)) = victim.as_mut().poll(&mut cx) else {
+            panic!("victim should surface its preemption after cleanup");
+        };
+        assert_eq!(err.incoming().map(|inc| inc.name.as_str()), Some("stop"));
+        assert_eq!(motor.peek(), 0);
+
+        // the thief never writes through its reference again
+        let Poll::Ready(Err(err)) = thief.as_mut().poll(&mut cx) else {
+            panic!("the thief should be preempted by the cleanup");
+        };
+        assert_eq!(err.incoming().map(|inc| inc.name.as_str()), Some("drive"));
+        assert_eq!(motor.peek(), 0);
+    }
+
+    #[test]
+    fn cleanup_skipped_for_higher_priority() {
+        let motor = RevocableCell::new(0, "motor");
+        let mut cx = Context::from_waker(task::Waker::noop());
+        let mut victim = Box::pin(motor.run_with_cleanup(
+            "drive",
+            async |_| core::future::pending::<()>().await,
+            async |_| panic!("cleanup cannot preempt a higher priority"),
+        ));
+        assert!(victim.as_mut().poll(&mut cx).is_pending());
+        let mut thief = Box::pin(
+            motor
+                .run("stop", async |speed| {
+                    futures_lite::future::yield_now().await;
+                    *speed = 1;
+                })
+                .with_priority(1),
+        );
+        assert!(thief.as_mut().poll(&mut cx).is_pending());
+        assert!(matches!(victim.as_mut().poll(&mut cx), Poll::Ready(Err(_))));
+        assert_eq!(thief.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(motor.peek(), 1);
+    }
+
+    #[test]
+    fn cleanup_abandoned_on_steal() {
+        let motor = RevocableCell::new(0, "motor");
+        let mut cx = Context::from_waker(task::Waker::noop());
+        let cleanup_polls = core::cell::Cell::new(0);
+        let mut victim = Box::pin(motor.run_with_cleanup(
+            "drive",
+            async |_| core::future::pending::<()>().await,
+            async |_| loop {
+                cleanup_polls.set(cleanup_polls.get() + 1);
+                futures_lite::future::yield_now().await;
+            },
+        ));
+        assert!(victim.as_mut().poll(&mut cx).is_pending());
+        let mut thief = Box::pin(motor.run("stop", async |_| core::future::pending::<()>().await));
+        assert!(thief.as_mut().poll(&mut cx).is_pending());
+        assert!(victim.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(cleanup_polls.get(), 1);
+
+        let mut third = Box::pin(motor.run("hold", async |_| ()));
+        assert_eq!(third.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(matches!(victim.as_mut().poll(&mut cx), Poll::Ready(Err(_))));
+        assert_eq!(cleanup_polls.get(), 1);
+        // the cleanup already preempted the thief
+        assert!(matches!(thief.as_mut().poll(&mut cx), Poll::Ready(Err(_))));
+    }
+
+    #[test]
+    fn lease_expires() {
+        let arm = RevocableCell::new(0, "arm");
+        let mut cx = Context::from_waker(task::Waker::noop());
+        let mut stuck = Box::pin(
+            arm.run("stuck", async |_| core::future::pending::<()>().await)
+                .with_lease(3),
+        );
+        for _ in 0..2 {
+            assert!(stuck.as_mut().poll(&mut cx).is_pending());
+            assert!(arm.current_owner().is_some());
+        }
+        let Poll::Ready(Err(err)) = stuck.as_mut().poll(&mut cx) else {
+            panic!("the lease should expire on the third poll");
+        };
+        assert!(err.lease_expired());
+        assert_eq!(err.kind(), PreemptionKind::LeaseExpired);
+        assert_eq!(err.incoming(), None);
+        assert_eq!(
+            err.to_string(),
+            "outgoing task Thief { name: stuck }  gave up its requirement Requirement { name: arm, type: i32 }  after its lease expired"
+        );
+        assert!(arm.current_owner().is_none());
+        assert_eq!(
+            arm.last_owner().map(|last| last.ended),
+            Some(TenureEnd::Expired)
+        );
+    }
+
+    #[test]
+    fn lease_renewed() {
+        let arm = RevocableCell::new(0, "arm");
+        let mut cx = Context::from_waker(task::Waker::noop());
+        let mut task = Box::pin(
+            arm.run("task", async |x| {
+                // each stage takes three polls, longer than the lease without renewals
+                for _ in 0..5 {
+                    futures_lite::future::yield_now().await;
+                    futures_lite::future::yield_now().await;
+                    *x += 1;
+                    renew_lease();
+                }
+            })
+            .with_lease(3),
+        );
+        let res = loop {
+            if let Poll::Ready(res) = task.as_mut().poll(&mut cx) {
+                break res;
+            }
+        };
+        assert_eq!(res, Ok(()));
+        drop(task);
+        assert_eq!(arm.into_inner(), 5);
+    }
+
+    #[test]
+    fn transactional_rollback() {
+        let pose = RevocableCell::new((0, 0), "pose");
+        let mut cx = Context::from_waker(task::Waker::noop());
+        let mut victim = Box::pin(pose.run_transactional("move", async |pose| {
+            pose.0 = 1;
+            futures_lite::future::yield_now().await;
+            pose.1 = 1;
+        }));
+        assert!(victim.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(pose.peek(), (1, 0));
+
+        // the thief sees the pose from before the interrupted update
+        let mut thief = Box::pin(pose.run("read", async |pose| *pose));
+        assert_eq!(thief.as_mut().poll(&mut cx), Poll::Ready(Ok((0, 0))));
+        assert!(matches!(victim.as_mut().poll(&mut cx), Poll::Ready(Err(_))));
+        drop((victim, thief));
+        assert_eq!(pose.into_inner(), (0, 0));
+    }
+
+    #[test]
+    fn transactional_commit() {
+        let pose = RevocableCell::new((0, 0), "pose");
+        let mut cx = Context::from_waker(task::Waker::noop());
+        let mut task = Box::pin(pose.run_transactional("move", async |pose| {
+            pose.0 = 1;
+            futures_lite::future::yield_now().await;
+            pose.1 = 1;
+        }));
+        assert!(task.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(task.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        drop(task);
+
+        // a completed transaction is not undone by the next steal
+        let mut next = Box::pin(pose.run("read", async |pose| *pose));
+        assert_eq!(next.as_mut().poll(&mut cx), Poll::Ready(Ok((1, 1))));
+    }
+
+    #[test]
+    fn staged_writes() {
+        let pose = RevocableCell::new((0, 0), "pose");
+        let mut cx = Context::from_waker(task::Waker::noop());
+        let stage = async |pose: &mut (i32, i32)| {
+            pose.0 = 1;
+            futures_lite::future::yield_now().await;
+            pose.1 = 1;
+        };
+
+        let mut preempted = Box::pin(pose.run_staged("move", stage));
+        assert!(preempted.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(pose.peek(), (0, 0));
+        let mut thief = Box::pin(pose.run("read", async |pose| *pose));
+        assert_eq!(thief.as_mut().poll(&mut cx), Poll::Ready(Ok((0, 0))));
+        assert!(matches!(
+            preempted.as_mut().poll(&mut cx),
+            Poll::Ready(Err(_))
+        ));
+        drop((preempted, thief));
+        assert_eq!(pose.peek(), (0, 0));
+
+        let mut completed = Box::pin(pose.run_staged("move", stage));
+        assert!(completed.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(completed.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        drop(completed);
+        assert_eq!(pose.into_inner(), (1, 1));
+    }
+
+    #[test]
+    fn default_task_resumes() {
+        let drive = RevocableCell::new(0, "drive");
+        let mut cx = Context::from_waker(task::Waker::noop());
+        let mut default = Box::pin(drive.run_default("hold", async |speed| {
+            *speed = 0;
+            core::future::pending::<()>().await
+        }));
+        assert!(default.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(
+            drive.current_owner().map(|owner| owner.name.as_str()),
+            Some("hold")
+        );
+
+        let mut forward = Box::pin(drive.run("forward", async |speed| {
+            *speed = 10;
+            futures_lite::future::yield_now().await;
+        }));
+        assert!(forward.as_mut().poll(&mut cx).is_pending());
+        // the preempted default waits for the cell instead of stealing it back
+        assert!(default.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(
+            drive.current_owner().map(|owner| owner.name.as_str()),
+            Some("forward")
+        );
+        assert_eq!(forward.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(drive.peek(), 10);
+
+        assert!(default.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(
+            drive.current_owner().map(|owner| owner.name.as_str()),
+            Some("hold")
+        );
+        assert_eq!(drive.peek(), 0);
+    }
+
+    #[test]
+    fn default_task_restarts() {
+        let drive = RevocableCell::new(0, "drive");
+        let mut cx = Context::from_waker(task::Waker::noop());
+        let mut default = Box::pin(drive.run_default("count", async |runs| *runs += 1));
+        for _ in 0..3 {
+            assert!(default.as_mut().poll(&mut cx).is_pending());
+        }
+        // a default that completes immediately runs once per poll
+        assert_eq!(drive.peek(), 3);
+    }
+
+    #[test]
+    fn current_thief_nesting() {
+        let outer_cell = RevocableCell::new(0, "outer");
+        let inner_cell = RevocableCell::new(0, "inner");
+        let mut cx = Context::from_waker(task::Waker::noop());
+        assert_eq!(current_thief(), None);
+
+        let mut outer = Box::pin(outer_cell.run("outer", async |_| {
+            let before = current_thief().map(|thief| thief.name.as_str());
+            let nested = inner_cell
+                .run("inner", async |_| {
+                    current_thief().map(|thief| thief.name.as_str())
+                })
+                .await;
+            let after = current_thief().map(|thief| thief.name.as_str());
+            (before, nested, after)
+        }));
+        let Poll::Ready(Ok((before, nested, after))) = outer.as_mut().poll(&mut cx) else {
+            panic!("outer should complete in one poll");
+        };
+        assert_eq!(before, Some("outer"));
+        assert_eq!(nested, Ok(Some("inner")));
+        assert_eq!(after, Some("outer"));
+        assert_eq!(current_thief(), None);
     }
 }
@@ -0,0 +1,288 @@
+//! A cell whose ownership is held through reference counted flags, so tasks using it can be `'static`.
+//!
+//! A [`RevocableCell`](crate::requirement::RevocableCell) points at the [`ThiefInfo`] of its owner,
+//! so every task borrows both the cell and its own pinned identity.
+//! A [`TokenCell`] instead hands each owner an [`OwnershipToken`], a shared flag that the cell clears when it is stolen,
+//! and only keeps a weak reference to it. A task owns its token, so nothing can dangle
+//! and a future from [`TokenCell::run()`] on an `Rc<TokenCell<T>>` is `'static`.
+//!
+//! Prefer [`RevocableCell`](crate::requirement::RevocableCell) when tasks can borrow their cells, as it never allocates and works with
+//! [`PreemptibleFuture`](crate::thief::PreemptibleFuture), `#[preemptible]`, and everything built on [`Requirement`](crate::requirement::Requirement).
+//! Prefer [`TokenCell`] when tasks have to be spawned onto an executor requiring `'static` futures.
+
+extern crate alloc;
+
+use alloc::rc::{Rc, Weak};
+use core::{
+    cell::{Cell, RefCell, UnsafeCell},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{
+    PreemptionError, PreemptionKind, Result,
+    name::Name,
+    notify::Version,
+    requirement::{RequirementId, RequirementInfo},
+    thief::ThiefInfo,
+};
+
+/// Proof of ownership over a [`TokenCell`], valid until the cell is stolen or released
+#[derive(Debug)]
+pub struct OwnershipToken {
+    valid: Rc<Cell<bool>>,
+    requirement: RequirementInfo,
+}
+
+impl OwnershipToken {
+    /// Returns `true` if the cell has not been stolen or released since this token was handed out.
+    pub fn is_valid(&self) -> bool {
+        self.valid.get()
+    }
+
+    /// Returns the requirement this token grants ownership of.
+    pub fn requirement(&self) -> RequirementInfo {
+        self.requirement
+    }
+}
+
+/// The owner of a [`TokenCell`], reachable only while its token exists
+struct Holder {
+    thief: ThiefInfo,
+    valid: Weak<Cell<bool>>,
+}
+
+/// A cell whose owners hold [`OwnershipToken`]s, see the [module-level documentation](self)
+pub struct TokenCell<T> {
+    data: UnsafeCell<T>,
+    holder: RefCell<Option<Holder>>,
+    generation: Cell<u64>,
+    name: Name,
+    id: RequirementId,
+}
+
+impl<T> TokenCell<T> {
+    /// Creates a new [`TokenCell`] with ownership of `data`.
+    ///
+    /// The cell will default having no owner.
+    pub fn new(data: T, name: impl Into<Name>) -> Self {
+        Self {
+            data: data.into(),
+            holder: RefCell::new(None),
+            generation: Cell::new(0),
+            name: name.into(),
+            id: RequirementId::next(),
+        }
+    }
+
+    /// Consumes the cell, returning the guarded value.
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+
+    /// Returns the metadata of this cell.
+    pub fn info(&self) -> RequirementInfo {
+        RequirementInfo {
+            name: self.name,
+            index: None,
+            id: self.id,
+            type_name: core::any::type_name::<T>(),
+            version: Version::default(),
+        }
+    }
+
+    /// Returns the number of times this cell has changed owners.
+    pub fn generation(&self) -> u64 {
+        self.generation.get()
+    }
+
+    /// Returns the thief whose token is still valid, if any.
+    ///
+    /// Dropping a token without releasing it leaves the cell without an owner.
+    pub fn current_owner(&self) -> Option<ThiefInfo> {
+        self.holder
+            .borrow()
+            .as_ref()
+            .filter(|holder| holder.valid.upgrade().is_some_and(|valid| valid.get()))
+            .map(|holder| holder.thief)
+    }
+
+    /// Takes ownership of this cell for `thief`, invalidating the token of the previous owner.
+    pub fn steal_ownership(&self, thief: ThiefInfo) -> OwnershipToken {
+        let valid = Rc::new(Cell::new(true));
+        let previous = self.holder.replace(Some(Holder {
+            thief,
+            valid: Rc::downgrade(&valid),
+        }));
+        if let Some(previous) = previous.and_then(|holder| holder.valid.upgrade()) {
+            previous.set(false);
+        }
+        self.generation.set(self.generation.get().wrapping_add(1));
+        OwnershipToken {
+            valid,
+            requirement: self.info(),
+        }
+    }
+
+    /// Gives up the ownership granted by `token`, leaving the cell unowned if it was not stolen in the meantime.
+    pub fn release_ownership(&self, token: OwnershipToken) {
+        if token.valid.replace(false) {
+            self.holder.replace(None);
+        }
+    }
+
+    /// Returns a pointer to the guarded data.
+    ///
+    /// # Safety
+    ///
+    /// The pointer may only be dereferenced while holding a valid [`OwnershipToken`] of this cell.
+    pub unsafe fn data_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+}
+
+impl<T: 'static> TokenCell<T> {
+    /// Same as [`RevocableCell::run`](crate::requirement::RevocableCell::run), but the future keeps the cell alive instead of borrowing it.
+    ///
+    /// # Errors
+    ///
+    /// If access to this cell has been stolen by a different future, the future returns `Err<PreemptionError>` when next polled.
+    pub fn run<Out>(
+        self: &Rc<Self>,
+        name: &'static str,
+        func: impl AsyncFnOnce(&mut T) -> Out + 'static,
+    ) -> TokenFuture<T, impl Future<Output = Out> + 'static> {
+        let data = unsafe { self.data_ptr() };
+        // the reference is only created once the future holds a token, and the future keeps the allocation alive
+        let inner = async move { func(unsafe { &mut *data }).await };
+        TokenFuture {
+            inner,
+            cell: Rc::clone(self),
+            info: ThiefInfo::new(name),
+            token: None,
+        }
+    }
+}
+
+/// Future returned by [`TokenCell::run()`]
+pub struct TokenFuture<T, Fut> {
+    inner: Fut,
+    cell: Rc<TokenCell<T>>,
+    info: ThiefInfo,
+    token: Option<OwnershipToken>,
+}
+
+impl<T, Fut: Future> Future for TokenFuture<T, Fut> {
+    type Output = Result<Fut::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // inner is structurally pinned, the other fields are not
+        let this = unsafe { self.get_unchecked_mut() };
+        let token = this
+            .token
+            .get_or_insert_with(|| this.cell.steal_ownership(this.info));
+        if !token.is_valid() {
+            return Poll::Ready(Err(PreemptionError {
+                incoming: this.cell.current_owner(),
+                ..PreemptionError::new(PreemptionKind::Stolen, this.info, token.requirement())
+            }));
+        }
+
+        let res = unsafe { Pin::new_unchecked(&mut this.inner) }.poll(cx);
+        if let Some(token) = this.token.take_if(|_| res.is_ready()) {
+            this.cell.release_ownership(token);
+        }
+        res.map(Ok)
+    }
+}
+
+impl<T, Fut> Drop for TokenFuture<T, Fut> {
+    fn drop(&mut self) {
+        // a dropped task gives the cell up, unlike a task that was only preempted
+        if let Some(token) = self.token.take() {
+            self.cell.release_ownership(token);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, vec::Vec};
+    use core::task::Waker;
+
+    use futures_lite::future::yield_now;
+
+    use super::*;
+
+    #[test]
+    fn flag_stealing() {
+        let data = Rc::new(TokenCell::new(0, "example"));
+        let mut increment = Box::pin(data.run("increment", async |x| {
+            loop {
+                *x += 1;
+                yield_now().await;
+            }
+        }));
+        let mut decrement = Box::pin(data.run("decrement", async |x| {
+            loop {
+                if *x == 0 {
+                    return;
+                }
+                *x -= 1;
+                yield_now().await;
+            }
+        }));
+        let mut cx = Context::from_waker(Waker::noop());
+
+        for _ in 0..5 {
+            assert!(increment.as_mut().poll(&mut cx).is_pending());
+        }
+        assert_eq!(
+            data.current_owner().map(|thief| thief.name.as_str()),
+            Some("increment")
+        );
+
+        // decrement steals the flag, so increment is cancelled next time it is polled
+        assert!(decrement.as_mut().poll(&mut cx).is_pending());
+        let Poll::Ready(Err(err)) = increment.as_mut().poll(&mut cx) else {
+            panic!("increment should be preempted");
+        };
+        assert_eq!(err.outgoing().name, "increment");
+        assert_eq!(
+            err.incoming().map(|thief| thief.name.as_str()),
+            Some("decrement")
+        );
+        assert_eq!(err.requirement(), data.info());
+
+        while decrement.as_mut().poll(&mut cx).is_pending() {}
+        assert!(data.current_owner().is_none());
+    }
+
+    type Spawned = Pin<Box<dyn Future<Output = Result<()>>>>;
+
+    fn spawn(tasks: &mut Vec<Spawned>, task: impl Future<Output = Result<()>> + 'static) {
+        tasks.push(Box::pin(task));
+    }
+
+    #[test]
+    fn static_spawn() {
+        let mut tasks = Vec::new();
+        let arm = {
+            // the spawned tasks outlive this scope's handle on the cell
+            let arm = Rc::new(TokenCell::new(0, "arm"));
+            spawn(&mut tasks, arm.run("raise", async |x| *x = 90));
+            spawn(&mut tasks, arm.run("lower", async |x| *x -= 45));
+            Rc::downgrade(&arm)
+        };
+        let mut cx = Context::from_waker(Waker::noop());
+        for task in &mut tasks {
+            assert_eq!(task.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        }
+
+        let arm = arm.upgrade().expect("the tasks keep the cell alive");
+        assert!(arm.current_owner().is_none());
+        drop(tasks);
+        let arm = Rc::into_inner(arm).expect("the tasks were the other handles on the cell");
+        assert_eq!(arm.into_inner(), 45);
+    }
+}
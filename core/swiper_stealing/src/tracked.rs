@@ -0,0 +1,129 @@
+//! Development-time detection of data access held across a revocation.
+//!
+//! A task can keep using its `&mut T` after an await during which a nested task stole the same requirement,
+//! which silently aliases the thief's access. [`RevocableCell::run_tracked()`] hands the task a [`TrackedMut`] instead.
+//!
+//! With the `debug-validate` feature, a [`TrackedMut`] remembers the requirement's generation when it was created
+//! and panics, naming both thieves, if it is dereferenced after the generation advanced.
+//! Without the feature it is a plain `&mut T`, so validation costs nothing in release builds.
+
+use core::any::Any;
+#[cfg(feature = "debug-validate")]
+use core::ops::{Deref, DerefMut};
+
+use crate::{guard::RevocableGuard, requirement::RevocableCell, thief::PreemptibleFuture};
+#[cfg(feature = "debug-validate")]
+use crate::{requirement::RequirementInfoSource, thief::ThiefInfo};
+
+/// A `&mut T` that panics on access after its requirement was stolen, see the [module-level documentation](self)
+#[cfg(feature = "debug-validate")]
+pub struct TrackedMut<'a, T> {
+    data: &'a mut T,
+    requirement: &'a dyn RequirementInfoSource,
+    generation: u64,
+    owner: Option<ThiefInfo>,
+}
+
+/// A `&mut T` that panics on access after its requirement was stolen, see the [module-level documentation](self)
+#[cfg(not(feature = "debug-validate"))]
+pub type TrackedMut<'a, T> = &'a mut T;
+
+#[cfg(feature = "debug-validate")]
+impl<'a, T> TrackedMut<'a, T> {
+    fn new(data: &'a mut T, requirement: &'a dyn RequirementInfoSource) -> Self {
+        Self {
+            data,
+            requirement,
+            generation: requirement.generation(),
+            owner: crate::current_thief(),
+        }
+    }
+
+    fn validate(&self) {
+        if self.requirement.generation() == self.generation {
+            return;
+        }
+        let name =
+            |thief: Option<ThiefInfo>| thief.map_or("an unknown task", |thief| thief.name.as_str());
+        panic!(
+            "{} was stolen by {} while {} still held a reference to its data",
+            self.requirement.info(),
+            name(
+                self.requirement
+                    .last_preemption()
+                    .map(|preemption| preemption.by)
+            ),
+            name(self.owner),
+        );
+    }
+}
+
+#[cfg(feature = "debug-validate")]
+impl<T> Deref for TrackedMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.validate();
+        self.data
+    }
+}
+
+#[cfg(feature = "debug-validate")]
+impl<T> DerefMut for TrackedMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.validate();
+        self.data
+    }
+}
+
+impl<T, M: Any, const H: usize> RevocableCell<T, M, H> {
+    /// Same as [`run()`](RevocableCell::run), but `func` receives a [`TrackedMut`] that detects use after a revocation.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`run()`](RevocableCell::run).
+    pub fn run_tracked<'a, Out>(
+        &'a self,
+        name: &'static str,
+        func: impl AsyncFnOnce(TrackedMut<'_, T>) -> Out + 'a,
+    ) -> PreemptibleFuture<'a, impl Future<Output = Out>, Out, 1> {
+        let inner = async move {
+            let mut guard = RevocableGuard::current(self).expect("run_tracked owns its cell");
+            let data = &mut *guard;
+            #[cfg(feature = "debug-validate")]
+            let data = TrackedMut::new(data, self);
+            func(data).await
+        };
+        PreemptibleFuture::new(inner, name, [self]).lending_data()
+    }
+}
+
+#[cfg(all(test, feature = "debug-validate"))]
+mod tests {
+    use super::*;
+    use crate::executor::block_on;
+
+    #[test]
+    #[should_panic(
+        expected = "Requirement { name: arm, type: i32 }  was stolen by thief while outer still held a reference to its data"
+    )]
+    fn use_across_revocation() {
+        let cell = RevocableCell::new(0, "arm");
+        let _ = block_on(cell.run_tracked("outer", async |mut x| {
+            *x += 1;
+            // a nested task steals the cell while outer still holds x
+            cell.run("thief", async |y| *y = 5).await.unwrap();
+            *x += 1;
+        }));
+    }
+
+    #[test]
+    fn use_without_revocation() {
+        let cell = RevocableCell::new(0, "arm");
+        let out = block_on(cell.run_tracked("outer", async |mut x| {
+            *x += 1;
+            *x
+        }));
+        assert_eq!(out, Ok(1));
+    }
+}
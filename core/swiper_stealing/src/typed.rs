@@ -0,0 +1,338 @@
+//! Opt-in typed layer that checks requirement disjointness at compile time.
+//!
+//! Each [`TypedCell`] carries a zero-sized [`Tag`] type, and futures built from typed cells carry the set of tags they require in their type.
+//! Composing two futures with [`parallel()`] fails to compile if both require the same tag, rather than having one preempt the other at runtime.
+//!
+//! ```
+//! # use swiper_stealing::{define_tag, typed::{self, TypedCell}};
+//! # use futures_lite::future;
+//! define_tag!(Drivetrain);
+//! define_tag!(Arm);
+//!
+//! let drivetrain = TypedCell::<_, Drivetrain>::new(0, "drivetrain");
+//! let arm = TypedCell::<_, Arm>::new(0, "arm");
+//!
+//! let both = typed::parallel(
+//!     drivetrain.run("drive", async |x| *x += 1),
+//!     arm.run("raise", async |x| *x += 2),
+//! );
+//! let (drive, raise) = future::block_on(both);
+//! assert!(drive.is_ok() && raise.is_ok());
+//! ```
+//!
+//! Overlapping requirements are rejected during compilation:
+//!
+//! ```compile_fail,E0080
+//! # use swiper_stealing::{define_tag, typed::{self, TypedCell}};
+//! # use futures_lite::future;
+//! define_tag!(Drivetrain);
+//!
+//! let drivetrain = TypedCell::<_, Drivetrain>::new(0, "drivetrain");
+//!
+//! let both = typed::parallel(
+//!     drivetrain.run("drive", async |x| *x += 1),
+//!     drivetrain.run("turn", async |x| *x -= 1),
+//! );
+//! future::block_on(both);
+//! ```
+//!
+//! Tags are compared by their [`NAME`](Tag::NAME), which [`define_tag!`](crate::define_tag) derives from the module path and type name.
+
+use core::{
+    marker::PhantomData,
+    ops::Deref,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{
+    Result,
+    combinator::Parallel,
+    requirement::{HasRequirements, Requirement, RevocableCell},
+};
+
+/// Maximum number of tags a single [`TagSet`] may contain
+pub const MAX_TAGS: usize = 16;
+
+/// A zero-sized marker type identifying a [`TypedCell`] at the type level.
+///
+/// Use [`define_tag!`](crate::define_tag) to declare tags with unique names.
+pub trait Tag {
+    /// Unique name of this tag, used for compile time comparisons
+    const NAME: &'static str;
+}
+
+/// Declares a zero-sized [`Tag`](crate::typed::Tag) type named after its module path.
+///
+/// ```
+/// swiper_stealing::define_tag!(pub Drivetrain);
+/// ```
+#[macro_export]
+macro_rules! define_tag {
+    ($vis:vis $name:ident) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        $vis struct $name;
+
+        impl $crate::typed::Tag for $name {
+            const NAME: &'static str = concat!(module_path!(), "::", stringify!($name));
+        }
+    };
+}
+
+/// A fixed capacity list of tag names that can be built and compared in const contexts
+#[derive(Debug, Clone, Copy)]
+pub struct TagList {
+    names: [&'static str; MAX_TAGS],
+    len: usize,
+}
+
+impl TagList {
+    /// The list without any tags
+    pub const EMPTY: Self = Self {
+        names: [""; MAX_TAGS],
+        len: 0,
+    };
+
+    /// Returns this list with `name` appended, failing const evaluation if it exceeds [`MAX_TAGS`].
+    pub const fn with(mut self, name: &'static str) -> Self {
+        assert!(self.len < MAX_TAGS, "too many tags in one tag set");
+        self.names[self.len] = name;
+        self.len += 1;
+        self
+    }
+
+    /// Returns the union of both lists, keeping duplicates.
+    pub const fn union(self, other: Self) -> Self {
+        let mut out = self;
+        let mut i = 0;
+        while i < other.len {
+            out = out.with(other.names[i]);
+            i += 1;
+        }
+        out
+    }
+
+    /// Returns `true` if `name` is in this list.
+    pub const fn contains(&self, name: &str) -> bool {
+        let mut i = 0;
+        while i < self.len {
+            if str_eq(self.names[i], name) {
+                return true;
+            }
+            i += 1;
+        }
+        false
+    }
+
+    /// Returns `true` if no tag is in both lists.
+    pub const fn is_disjoint(&self, other: &Self) -> bool {
+        let mut i = 0;
+        while i < other.len {
+            if self.contains(other.names[i]) {
+                return false;
+            }
+            i += 1;
+        }
+        true
+    }
+
+    /// Returns the number of tags in this list.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if this list has no tags.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// A type-level set of [`Tag`]s, implemented for tuples of up to 3 tags and for [`Union`]
+pub trait TagSet {
+    const TAGS: TagList;
+}
+
+impl TagSet for () {
+    const TAGS: TagList = TagList::EMPTY;
+}
+
+impl<A: Tag> TagSet for (A,) {
+    const TAGS: TagList = TagList::EMPTY.with(A::NAME);
+}
+
+impl<A: Tag, B: Tag> TagSet for (A, B) {
+    const TAGS: TagList = TagList::EMPTY.with(A::NAME).with(B::NAME);
+}
+
+impl<A: Tag, B: Tag, C: Tag> TagSet for (A, B, C) {
+    const TAGS: TagList = TagList::EMPTY.with(A::NAME).with(B::NAME).with(C::NAME);
+}
+
+/// The union of two [`TagSet`]s
+pub struct Union<A, B>(PhantomData<(A, B)>);
+
+impl<A: TagSet, B: TagSet> TagSet for Union<A, B> {
+    const TAGS: TagList = A::TAGS.union(B::TAGS);
+}
+
+/// A [`Future`] whose requirements are known at the type level
+pub trait TypedFuture: Future {
+    /// Tags of all requirements this future may steal
+    type Tags: TagSet;
+}
+
+/// Wraps a [`Future`] to attach the [`TagSet`] `S` to its type.
+pub struct Typed<Fut, S> {
+    inner: Fut,
+    _tags: PhantomData<S>,
+}
+
+impl<Fut: Future, S: TagSet> Typed<Fut, S> {
+    /// Declares that `inner` requires exactly the tags in `S`.
+    ///
+    /// This is not checked, so `S` must cover every requirement `inner` steals for the compile time guarantees to hold.
+    pub fn new(inner: Fut) -> Self {
+        Self {
+            inner,
+            _tags: PhantomData,
+        }
+    }
+}
+
+impl<Fut: Future, S> Future for Typed<Fut, S> {
+    type Output = Fut::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // inner is structurally pinned
+        unsafe { self.map_unchecked_mut(|typed| &mut typed.inner) }.poll(cx)
+    }
+}
+
+impl<Fut: Future, S: TagSet> TypedFuture for Typed<Fut, S> {
+    type Tags = S;
+}
+
+impl<Fut: HasRequirements, S> HasRequirements for Typed<Fut, S> {
+    fn visit_requirements(&self, visit: &mut dyn FnMut(&dyn Requirement)) {
+        self.inner.visit_requirements(visit);
+    }
+}
+
+/// A [`RevocableCell`] tagged with the zero-sized type `Tg`.
+///
+/// Dereferences to the underlying cell for untyped access.
+pub struct TypedCell<T, Tg: Tag> {
+    cell: RevocableCell<T>,
+    _tag: PhantomData<Tg>,
+}
+
+impl<T, Tg: Tag> TypedCell<T, Tg> {
+    /// Creates a new [`TypedCell`] with ownership of `data`.
+    pub fn new(data: T, name: &'static str) -> Self {
+        Self {
+            cell: RevocableCell::new(data, name),
+            _tag: PhantomData,
+        }
+    }
+
+    /// Same as [`RevocableCell::run`], but the returned future carries this cell's tag.
+    pub fn run<'a, Out>(
+        &'a self,
+        name: &'static str,
+        func: impl AsyncFnOnce(&mut T) -> Out + 'a,
+    ) -> Typed<impl Future<Output = Result<Out>> + HasRequirements, (Tg,)> {
+        Typed::new(self.cell.run(name, func))
+    }
+
+    /// Consumes the cell, returning the guarded value.
+    pub fn into_inner(self) -> T {
+        self.cell.into_inner()
+    }
+}
+
+impl<T, Tg: Tag> Deref for TypedCell<T, Tg> {
+    type Target = RevocableCell<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.cell
+    }
+}
+
+/// Runs both futures concurrently, failing to compile if their requirements overlap.
+///
+/// The result is itself a [`TypedFuture`] requiring the union of both tag sets, so nested compositions are checked as well.
+pub fn parallel<A, B>(a: A, b: B) -> Typed<Parallel<A, B>, Union<A::Tags, B::Tags>>
+where
+    A: TypedFuture,
+    B: TypedFuture,
+{
+    const {
+        assert!(
+            A::Tags::TAGS.is_disjoint(&B::Tags::TAGS),
+            "futures composed with `parallel` have overlapping requirements"
+        );
+    }
+    Typed::new(Parallel::new(a, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_lite::future;
+
+    use super::*;
+
+    crate::define_tag!(Left);
+    crate::define_tag!(Right);
+    crate::define_tag!(Gyro);
+
+    #[test]
+    fn tag_lists() {
+        let lr = <(Left, Right)>::TAGS;
+        let g = <(Gyro,)>::TAGS;
+        assert_eq!(lr.len(), 2);
+        assert!(lr.contains(Left::NAME));
+        assert!(!lr.contains(Gyro::NAME));
+        assert!(lr.is_disjoint(&g));
+        assert!(!lr.is_disjoint(&<(Right, Gyro)>::TAGS));
+
+        let all = <Union<(Left, Right), (Gyro,)>>::TAGS;
+        assert_eq!(all.len(), 3);
+        assert!(all.contains(Gyro::NAME));
+        assert!(<()>::TAGS.is_empty());
+    }
+
+    #[test]
+    fn nested_parallel() {
+        let left = TypedCell::<_, Left>::new(0, "left");
+        let right = TypedCell::<_, Right>::new(0, "right");
+        let gyro = TypedCell::<_, Gyro>::new(0, "gyro");
+
+        let drive = parallel(
+            left.run("left", async |x| {
+                future::yield_now().await;
+                *x += 1;
+            }),
+            right.run("right", async |x| *x += 2),
+        );
+        let ((l, r), g) = future::block_on(parallel(drive, gyro.run("gyro", async |x| *x += 3)));
+        assert!(l.is_ok() && r.is_ok() && g.is_ok());
+        assert_eq!(left.into_inner(), 1);
+        assert_eq!(right.into_inner(), 2);
+        assert_eq!(gyro.into_inner(), 3);
+    }
+}
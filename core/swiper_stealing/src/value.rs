@@ -0,0 +1,239 @@
+//! A cell for small `Copy` values that can be read and written without `unsafe`.
+//!
+//! A [`RevocableValue`] keeps its value in a [`Cell`], so [`get()`](RevocableValue::get) and [`set()`](RevocableValue::set)
+//! copy the whole value in and out and never hand out a reference that a preemption could invalidate.
+//! It is still a requirement with the same stealing and preemption rules as a [`RevocableCell`]:
+//! for `Copy` data, ownership decides which task gets cancelled, not whether an access is sound.
+//! Like any [`RevocableData`], it can be passed to `#[preemptible]` functions.
+
+use core::cell::Cell;
+
+use crate::{
+    guard::BorrowFlag,
+    history::StealRecord,
+    name::Name,
+    notify::Version,
+    requirement::{
+        Claims, LastOwner, LastPreemption, Requirement, RequirementId, RequirementInfo,
+        RequirementInfoSource, RevocableCell, RevocableData, StealOutcome, TenureEnd,
+    },
+    thief::ThiefInfo,
+};
+
+/// A `Copy` value guarded like a [`RevocableCell`], see the [module-level documentation](self)
+pub struct RevocableValue<T: Copy> {
+    value: Cell<T>,
+    /// Ownership bookkeeping, shared with the unchecked cell
+    cell: RevocableCell<()>,
+}
+
+impl<T: Copy> RevocableValue<T> {
+    /// Creates a new [`RevocableValue`] holding `value`.
+    ///
+    /// The value will default having no owner.
+    pub fn new(value: T, name: impl Into<Name>) -> Self {
+        Self {
+            value: Cell::new(value),
+            cell: RevocableCell::new((), name),
+        }
+    }
+
+    /// Returns a copy of the value, even while a task owns it.
+    pub fn get(&self) -> T {
+        self.value.get()
+    }
+
+    /// Replaces the value, even while a task owns it, and marks it as changed.
+    ///
+    /// The owner is not preempted, and sees the new value the next time it reads.
+    pub fn set(&self, value: T) {
+        self.value.set(value);
+        self.cell.mark_changed();
+    }
+
+    /// Returns the current version of the value, see [`RevocableCell::version()`].
+    pub fn version(&self) -> Version {
+        self.cell.version()
+    }
+
+    /// Consumes the cell, returning the value.
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: Copy> Requirement for RevocableValue<T> {
+    fn steal_ownership(&self, thief: &ThiefInfo) {
+        self.cell.steal_ownership(thief);
+    }
+
+    fn try_steal_ownership(&self, thief: &ThiefInfo) -> StealOutcome {
+        self.cell.try_steal_ownership(thief)
+    }
+
+    fn claim_shared(&self, thief: &ThiefInfo) -> StealOutcome {
+        self.cell.claim_shared(thief)
+    }
+
+    fn release_shared(&self, generation: u64) {
+        self.cell.release_shared(generation);
+    }
+
+    fn release_ownership(&self) {
+        self.cell.release_ownership();
+    }
+
+    fn end_ownership(&self, ended: TenureEnd) {
+        self.cell.end_ownership(ended);
+    }
+
+    fn revoke(&self, by: &ThiefInfo) {
+        self.cell.revoke(by);
+    }
+
+    fn poison(&self) {
+        self.cell.poison();
+    }
+
+    fn is_owned_by(&self, thief: &ThiefInfo) -> bool {
+        self.cell.is_owned_by(thief)
+    }
+
+    fn mark_polled(&self) {
+        self.cell.mark_polled();
+    }
+
+    fn register_owner_waker(&self, waker: &core::task::Waker) {
+        self.cell.register_owner_waker(waker);
+    }
+
+    fn register_release_waker(&self, waker: &core::task::Waker) {
+        self.cell.register_release_waker(waker);
+    }
+
+    fn meta_any(&self) -> Option<&dyn core::any::Any> {
+        self.cell.meta_any()
+    }
+}
+
+impl<T: Copy> RequirementInfoSource for RevocableValue<T> {
+    fn current_owner(&self) -> Option<ThiefInfo> {
+        self.cell.current_owner()
+    }
+
+    fn current_claims(&self) -> Claims {
+        self.cell.current_claims()
+    }
+
+    fn info(&self) -> RequirementInfo {
+        RequirementInfo {
+            type_name: core::any::type_name::<T>(),
+            ..self.cell.info()
+        }
+    }
+
+    fn id(&self) -> RequirementId {
+        self.cell.id()
+    }
+
+    fn generation(&self) -> u64 {
+        self.cell.generation()
+    }
+
+    fn last_preemption(&self) -> Option<LastPreemption> {
+        self.cell.last_preemption()
+    }
+
+    fn last_owner(&self) -> Option<LastOwner> {
+        self.cell.last_owner()
+    }
+
+    fn last_steal(&self) -> Option<StealRecord> {
+        self.cell.last_steal()
+    }
+
+    fn yielded_by(&self) -> Option<ThiefInfo> {
+        self.cell.yielded_by()
+    }
+
+    fn is_poisoned(&self) -> bool {
+        self.cell.is_poisoned()
+    }
+
+    fn is_frozen(&self) -> bool {
+        self.cell.is_frozen()
+    }
+
+    fn min_steal_priority(&self) -> u8 {
+        self.cell.min_steal_priority()
+    }
+
+    fn may_steal(&self, incoming: &ThiefInfo) -> bool {
+        self.cell.may_steal(incoming)
+    }
+}
+
+impl<T: Copy> RevocableData<T> for RevocableValue<T> {
+    unsafe fn data_ptr(&self) -> *mut T {
+        self.value.as_ptr()
+    }
+
+    fn borrow_flag(&self) -> Option<&BorrowFlag> {
+        self.cell.borrow_flag()
+    }
+
+    fn mark_changed(&self) {
+        self.cell.mark_changed();
+    }
+
+    #[cfg(feature = "debug-audit")]
+    fn record_access(&self, thief: &ThiefInfo, write: bool) {
+        self.cell.record_access(thief, write);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use futures_lite::future::yield_now;
+
+    use super::*;
+    use crate::{guard::RevocableGuard, thief::PreemptibleFuture};
+
+    #[test]
+    fn preempts_like_a_cell() {
+        let setpoint = RevocableValue::new(0, "setpoint");
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut raise = pin!(PreemptibleFuture::new(
+            async {
+                loop {
+                    setpoint.set(setpoint.get() + 1);
+                    yield_now().await;
+                }
+            },
+            "raise",
+            [&setpoint],
+        ));
+        assert!(raise.as_mut().poll(&mut cx).is_pending());
+        assert!(raise.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(setpoint.get(), 2);
+
+        // guards work as with any other data
+        let mut hold = pin!(PreemptibleFuture::new(
+            async {
+                *RevocableGuard::current(&setpoint).expect("hold owns setpoint") = 10;
+            },
+            "hold",
+            [&setpoint],
+        ));
+        assert_eq!(hold.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(matches!(raise.as_mut().poll(&mut cx), Poll::Ready(Err(_))));
+        assert_eq!(setpoint.get(), 10);
+        assert_eq!(setpoint.info().type_name, "i32");
+    }
+}